@@ -0,0 +1,321 @@
+//! Compile-time image conversion for `epd-waveshare-async`.
+//!
+//! Use these macros through `epd-waveshare-async`'s `macros` feature, rather than depending on this
+//! crate directly.
+use std::{fs::File, path::PathBuf};
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input, Ident, LitStr, Token,
+};
+
+/// Converts a PNG image into packed buffer data at compile time, so that it can be stored in flash
+/// and written to a display without decoding it at runtime.
+///
+/// ```text
+/// static LOGO: Bitmap = epd_image!("assets/logo.png", Binary, dither = "floyd");
+///
+/// buffer.blit(&LOGO, Point::new(8, 8));
+/// ```
+///
+/// The path is relative to the crate's `Cargo.toml`. The second argument picks the format:
+///
+/// * `Binary`: a `buffer::Bitmap`, where white pixels are set, as in a `BinaryBuffer`.
+/// * `Gray2`: a `buffer::Gray2SplitBitmap`, with the same bit planes as a `Gray2SplitBuffer`.
+///
+/// The optional `dither` argument is one of `"threshold"` (the default), `"ordered"` or `"floyd"`
+/// (Floyd-Steinberg error diffusion). Transparent pixels are drawn over white.
+#[proc_macro]
+pub fn epd_image(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input as ImageArgs);
+    match expand(&args) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// The buffer layout to convert an image to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Binary,
+    Gray2,
+}
+
+impl Format {
+    fn levels(self) -> u8 {
+        match self {
+            Format::Binary => 2,
+            Format::Gray2 => 4,
+        }
+    }
+}
+
+/// How to convert each pixel's brightness to the format's levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dither {
+    Threshold,
+    Ordered,
+    Floyd,
+}
+
+struct ImageArgs {
+    path: LitStr,
+    format: Format,
+    dither: Dither,
+}
+
+impl Parse for ImageArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path: LitStr = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let format: Ident = input.parse()?;
+        let format = match format.to_string().as_str() {
+            "Binary" => Format::Binary,
+            "Gray2" => Format::Gray2,
+            _ => {
+                return Err(syn::Error::new(
+                    format.span(),
+                    "expected `Binary` or `Gray2`",
+                ))
+            }
+        };
+        let mut dither = Dither::Threshold;
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let key: Ident = input.parse()?;
+            if key != "dither" {
+                return Err(syn::Error::new(key.span(), "expected `dither`"));
+            }
+            input.parse::<Token![=]>()?;
+            let value: LitStr = input.parse()?;
+            dither = match value.value().as_str() {
+                "threshold" => Dither::Threshold,
+                "ordered" => Dither::Ordered,
+                "floyd" => Dither::Floyd,
+                _ => {
+                    return Err(syn::Error::new(
+                        value.span(),
+                        "expected \"threshold\", \"ordered\" or \"floyd\"",
+                    ))
+                }
+            };
+            input.parse::<Option<Token![,]>>()?;
+        }
+        Ok(ImageArgs {
+            path,
+            format,
+            dither,
+        })
+    }
+}
+
+fn expand(args: &ImageArgs) -> syn::Result<TokenStream2> {
+    let root = std::env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let path = PathBuf::from(root).join(args.path.value());
+    let image = decode(&path).map_err(|error| {
+        syn::Error::new(
+            args.path.span(),
+            format!("failed to read {}: {error}", path.display()),
+        )
+    })?;
+    let levels = quantize(&image, args.format.levels(), args.dither);
+    let (width, height) = (image.width, image.height);
+    let size = quote! { ::epd_waveshare_async::__private::Size::new(#width, #height) };
+    let image = match args.format {
+        Format::Binary => {
+            let data = pack(&levels, width, |level| level != 0);
+            quote! { ::epd_waveshare_async::buffer::Bitmap::new(&[#(#data),*], #size) }
+        }
+        Format::Gray2 => {
+            let low = pack(&levels, width, |level| level & 1 != 0);
+            let high = pack(&levels, width, |level| level & 2 != 0);
+            quote! {
+                ::epd_waveshare_async::buffer::Gray2SplitBitmap::new(
+                    &[#(#low),*],
+                    &[#(#high),*],
+                    #size,
+                )
+            }
+        }
+    };
+    let path = path.to_string_lossy();
+    Ok(quote! {
+        {
+            // Rebuilds when the image changes.
+            const _: &[u8] = include_bytes!(#path);
+            // The data is packed for the size, so this can't fail.
+            match #image {
+                ::core::option::Option::Some(image) => image,
+                ::core::option::Option::None => ::core::unreachable!(),
+            }
+        }
+    })
+}
+
+/// An image's brightness, from 0 (black) to 255 (white), row by row.
+struct Image {
+    width: u32,
+    height: u32,
+    luma: Vec<u8>,
+}
+
+fn decode(path: &std::path::Path) -> Result<Image, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => return Err("indexed images weren't expanded".into()),
+    };
+    let luma = buf[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|pixel| {
+            let (luma, alpha) = match pixel {
+                [l] => (u32::from(*l), 255),
+                [l, a] => (u32::from(*l), u32::from(*a)),
+                [r, g, b] | [r, g, b, _] => {
+                    // Rec. 601 luma weights, scaled to sum to 256, as in the `dither` module.
+                    let luma = (77 * u32::from(*r) + 150 * u32::from(*g) + 29 * u32::from(*b)) >> 8;
+                    (luma, pixel.get(3).copied().map_or(255, u32::from))
+                }
+                _ => (255, 255),
+            };
+            // Draw transparent pixels over white.
+            ((luma * alpha + 255 * (255 - alpha)) / 255) as u8
+        })
+        .collect();
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        luma,
+    })
+}
+
+/// A 4x4 Bayer matrix, with thresholds from 0 to 15.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Converts each pixel to a level from 0 (black) to `levels - 1` (white).
+fn quantize(image: &Image, levels: u8, dither: Dither) -> Vec<u8> {
+    let max_level = i32::from(levels - 1);
+    let width = image.width as usize;
+    match dither {
+        Dither::Threshold | Dither::Ordered => image
+            .luma
+            .iter()
+            .enumerate()
+            .map(|(i, luma)| {
+                let bias = match dither {
+                    Dither::Ordered => {
+                        let threshold = BAYER[(i / width) % 4][(i % width) % 4];
+                        (i32::from(threshold) * 2 + 1) * 8
+                    }
+                    _ => 128,
+                };
+                ((i32::from(*luma) * max_level + bias) / 256).min(max_level) as u8
+            })
+            .collect(),
+        Dither::Floyd => {
+            let mut values: Vec<i32> = image.luma.iter().map(|l| i32::from(*l)).collect();
+            let mut levels = Vec::with_capacity(values.len());
+            for i in 0..values.len() {
+                let (x, value) = (i % width, values[i]);
+                let level = ((value * max_level + 127) / 255).clamp(0, max_level);
+                let error = value - level * 255 / max_level;
+                levels.push(level as u8);
+                let mut spread = |index: usize, weight: i32| {
+                    if let Some(value) = values.get_mut(index) {
+                        *value += error * weight / 16;
+                    }
+                };
+                if x + 1 < width {
+                    spread(i + 1, 7);
+                    spread(i + width + 1, 1);
+                }
+                if x > 0 {
+                    spread(i + width - 1, 3);
+                }
+                spread(i + width, 5);
+            }
+            levels
+        }
+    }
+}
+
+/// Packs one bit per pixel, with each row rounded up to a whole byte and the leftmost pixel in the
+/// most significant bit.
+fn pack(levels: &[u8], width: u32, is_set: impl Fn(u8) -> bool) -> Vec<u8> {
+    let width = width as usize;
+    levels
+        .chunks(width.max(1))
+        .flat_map(|row| {
+            row.chunks(8).map(|pixels| {
+                pixels
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, level)| is_set(**level))
+                    .fold(0u8, |byte, (x, _)| byte | (0x80 >> x))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, luma: &[u8]) -> Image {
+        Image {
+            width,
+            height: luma.len() as u32 / width,
+            luma: luma.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_pack_rounds_rows_to_bytes() {
+        let levels = [1, 0, 1, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        assert_eq!(
+            pack(&levels, 10, |level| level != 0),
+            [0b1010_0000, 0b1100_0000, 0b0000_0000, 0b0100_0000]
+        );
+    }
+
+    #[test]
+    fn test_threshold_rounds_to_nearest_level() {
+        let image = image(4, &[0, 80, 180, 255]);
+        assert_eq!(quantize(&image, 4, Dither::Threshold), [0, 1, 2, 3]);
+        assert_eq!(quantize(&image, 2, Dither::Threshold), [0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_dithering_mixes_levels_for_mid_gray() {
+        let image = image(4, &[128; 16]);
+        for dither in [Dither::Ordered, Dither::Floyd] {
+            let white = quantize(&image, 2, dither)
+                .iter()
+                .filter(|l| **l == 1)
+                .count();
+            assert_eq!(white, 8, "{dither:?}");
+        }
+    }
+
+    #[test]
+    fn test_parses_arguments() {
+        let args: ImageArgs = syn::parse_str(r#""logo.png", Gray2, dither = "floyd""#).unwrap();
+        assert_eq!(args.path.value(), "logo.png");
+        assert_eq!(args.format, Format::Gray2);
+        assert_eq!(args.dither, Dither::Floyd);
+
+        let args: ImageArgs = syn::parse_str(r#""logo.png", Binary"#).unwrap();
+        assert_eq!(args.dither, Dither::Threshold);
+        assert!(syn::parse_str::<ImageArgs>(r#""logo.png", Rgb"#).is_err());
+    }
+}
@@ -0,0 +1,397 @@
+//! Plays simple animations, such as spinners and progress indicators, in a small window of the
+//! display using partial refreshes. [Ticker] scrolls long text through such a window.
+//!
+//! Each frame is drawn into a buffer the size of the window, rather than a full-screen buffer, so
+//! only the window's data is sent for each frame.
+//!
+//! ```text
+//! const SIZE: Size = Size::new(32, 32);
+//! let mut spinner = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+//! let animation = Animation::new(Point::new(48, 132), Duration::from_millis(500));
+//!
+//! // Spin until the long operation is done.
+//! select(
+//!     long_operation(),
+//!     animation.play(&mut epd, &mut spi, &mut delay, &mut spinner, |buffer, tick| {
+//!         draw_spinner(buffer, tick);
+//!         true
+//!     }),
+//! )
+//! .await;
+//! ```
+use core::{convert::Infallible, time::Duration};
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    mono_font::MonoTextStyle,
+    prelude::{PixelColor, Point},
+    primitives::Rectangle,
+    text::{renderer::TextRenderer, Baseline, Text},
+    Drawable,
+};
+use embedded_hal_async::{delay::DelayNs, spi::SpiDevice};
+
+use crate::{buffer::BufferView, log::trace, DisplayPartial, WaitIdle};
+
+/// Plays frames into a fixed window of the display, one partial refresh per frame.
+///
+/// The display should already be in a partial refresh mode, and showing the content around the
+/// window. Before each frame after the first, the previous frame is written as the diff base, so
+/// only the pixels that change between frames are refreshed, even on displays that don't keep
+/// the last frame as the base themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Animation {
+    top_left: Point,
+    interval: Duration,
+}
+
+impl Animation {
+    /// Creates an animation that's shown with its top left at `top_left`, pausing for `interval`
+    /// after each frame is displayed.
+    ///
+    /// `top_left.x` should be a multiple of 8, as most displays can only address whole bytes.
+    pub const fn new(top_left: Point, interval: Duration) -> Self {
+        Animation { top_left, interval }
+    }
+
+    /// Displays each of the pre-rendered `frames` in turn.
+    pub async fn play_frames<const BITS: usize, const FRAMES: usize, V, D, SPI, DELAY, ERROR>(
+        &self,
+        epd: &mut D,
+        spi: &mut SPI,
+        delay: &mut DELAY,
+        frames: &[V],
+    ) -> Result<(), ERROR>
+    where
+        V: BufferView<BITS, FRAMES>,
+        D: DisplayPartial<BITS, FRAMES, SPI, ERROR> + WaitIdle<ERROR>,
+        SPI: SpiDevice,
+        DELAY: DelayNs,
+    {
+        let mut previous = None;
+        for frame in frames {
+            self.show(epd, spi, delay, previous, frame).await?;
+            previous = Some(frame);
+        }
+        Ok(())
+    }
+
+    /// Calls `draw` to update `buffer` for each tick, starting from 0, and displays the result.
+    ///
+    /// Stops when `draw` returns `false`, without displaying that tick.
+    pub async fn play<const BITS: usize, const FRAMES: usize, B, D, SPI, DELAY, ERROR>(
+        &self,
+        epd: &mut D,
+        spi: &mut SPI,
+        delay: &mut DELAY,
+        buffer: &mut B,
+        mut draw: impl FnMut(&mut B, u32) -> bool,
+    ) -> Result<(), ERROR>
+    where
+        B: BufferView<BITS, FRAMES>,
+        D: DisplayPartial<BITS, FRAMES, SPI, ERROR> + WaitIdle<ERROR>,
+        SPI: SpiDevice,
+        DELAY: DelayNs,
+    {
+        let mut tick = 0;
+        loop {
+            if tick > 0 {
+                // Write the last frame as the base before it's overwritten.
+                epd.write_base_framebuffer(spi, &self.placed(buffer))
+                    .await?;
+            }
+            if !draw(buffer, tick) {
+                return Ok(());
+            }
+            self.show(epd, spi, delay, None, buffer).await?;
+            tick = tick.wrapping_add(1);
+        }
+    }
+
+    async fn show<const BITS: usize, const FRAMES: usize, V, D, SPI, DELAY, ERROR>(
+        &self,
+        epd: &mut D,
+        spi: &mut SPI,
+        delay: &mut DELAY,
+        base: Option<&V>,
+        frame: &V,
+    ) -> Result<(), ERROR>
+    where
+        V: BufferView<BITS, FRAMES>,
+        D: DisplayPartial<BITS, FRAMES, SPI, ERROR> + WaitIdle<ERROR>,
+        SPI: SpiDevice,
+        DELAY: DelayNs,
+    {
+        trace!("Showing animation frame");
+        if let Some(base) = base {
+            epd.write_base_framebuffer(spi, &self.placed(base)).await?;
+        }
+        epd.write_framebuffer(spi, &self.placed(frame)).await?;
+        epd.update_display(spi).await?;
+        epd.wait_until_idle().await?;
+        let ms = u32::try_from(self.interval.as_millis()).unwrap_or(u32::MAX);
+        delay.delay_ms(ms).await;
+        Ok(())
+    }
+
+    fn placed<'a, V>(&self, view: &'a V) -> Placed<'a, V> {
+        Placed {
+            view,
+            top_left: self.top_left,
+        }
+    }
+}
+
+/// Scrolls a line of text from right to left within an animation's window, e.g. for showing long
+/// messages on narrow displays.
+///
+/// Each frame moves the text left by the step size. The text repeats, with a gap between each
+/// repeat, so the ticker loops forever unless stopped.
+///
+/// ```text
+/// const SIZE: Size = Size::new(128, 16);
+/// let mut window = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+/// let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::Off);
+/// let ticker = Ticker::new(
+///     Animation::new(Point::new(0, 100), Duration::from_millis(200)),
+///     "Next train: 12:34 to Central",
+///     style,
+///     BinaryColor::On,
+/// )
+/// .with_step(8);
+///
+/// // Scroll the message through twice.
+/// let ticks = 2 * ticker.period() / 8;
+/// ticker.play(&mut epd, &mut spi, &mut delay, &mut window, |tick| tick < ticks).await?;
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Ticker<'a, C> {
+    animation: Animation,
+    text: &'a str,
+    style: MonoTextStyle<'a, C>,
+    background: C,
+    step: u32,
+    gap: u32,
+}
+
+impl<'a, C: PixelColor> Ticker<'a, C> {
+    /// Creates a ticker that draws `text` with `style`, over `background`.
+    ///
+    /// By default, the text moves 4 pixels per frame, with a 16 pixel gap between repeats.
+    pub const fn new(
+        animation: Animation,
+        text: &'a str,
+        style: MonoTextStyle<'a, C>,
+        background: C,
+    ) -> Self {
+        Ticker {
+            animation,
+            text,
+            style,
+            background,
+            step: 4,
+            gap: 16,
+        }
+    }
+
+    /// Sets how many pixels the text moves per frame.
+    ///
+    /// Larger steps scroll faster for the same refresh rate, but look jumpier.
+    pub const fn with_step(mut self, step: u32) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Sets the blank space between the end of the text and its next repeat.
+    pub const fn with_gap(mut self, gap: u32) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    /// The distance that the text moves before it repeats, i.e. its width plus the gap.
+    pub fn period(&self) -> u32 {
+        let width = self
+            .style
+            .measure_string(self.text, Point::zero(), Baseline::Middle)
+            .bounding_box
+            .size
+            .width;
+        (width + self.gap).max(1)
+    }
+
+    /// Returns how far the text has scrolled left after `tick` frames.
+    pub fn offset(&self, tick: u32) -> u32 {
+        ((tick as u64 * self.step as u64) % self.period() as u64) as u32
+    }
+
+    /// Clears `buffer` and draws the text as it's shown for `tick`, vertically centered.
+    pub fn draw_tick<B: DrawTarget<Color = C>>(
+        &self,
+        buffer: &mut B,
+        tick: u32,
+    ) -> Result<(), B::Error> {
+        buffer.clear(self.background)?;
+        let bounds = buffer.bounding_box();
+        let y = bounds.center().y;
+        let period = self.period() as i32;
+        // Draw repeats until the window is covered.
+        let mut x = bounds.top_left.x - self.offset(tick) as i32;
+        while x < bounds.top_left.x + bounds.size.width as i32 {
+            Text::with_baseline(self.text, Point::new(x, y), self.style, Baseline::Middle)
+                .draw(buffer)?;
+            x += period;
+        }
+        Ok(())
+    }
+
+    /// Scrolls the text, one frame per tick, while `keep_going` returns `true` for the tick.
+    ///
+    /// The animation's interval sets the time between frames, and the previous frame is used as
+    /// the diff base for the next, as with [Animation::play].
+    pub async fn play<const BITS: usize, const FRAMES: usize, B, D, SPI, DELAY, ERROR>(
+        &self,
+        epd: &mut D,
+        spi: &mut SPI,
+        delay: &mut DELAY,
+        buffer: &mut B,
+        mut keep_going: impl FnMut(u32) -> bool,
+    ) -> Result<(), ERROR>
+    where
+        B: BufferView<BITS, FRAMES> + DrawTarget<Color = C, Error = Infallible>,
+        D: DisplayPartial<BITS, FRAMES, SPI, ERROR> + WaitIdle<ERROR>,
+        SPI: SpiDevice,
+        DELAY: DelayNs,
+    {
+        self.animation
+            .play(epd, spi, delay, buffer, |buffer, tick| {
+                if !keep_going(tick) {
+                    return false;
+                }
+                let Ok(()) = self.draw_tick(buffer, tick);
+                true
+            })
+            .await
+    }
+}
+
+/// A buffer view, moved to the animation's window.
+struct Placed<'a, V> {
+    view: &'a V,
+    top_left: Point,
+}
+
+impl<const BITS: usize, const FRAMES: usize, V> BufferView<BITS, FRAMES> for Placed<'_, V>
+where
+    V: BufferView<BITS, FRAMES>,
+{
+    fn window(&self) -> Rectangle {
+        let window = self.view.window();
+        Rectangle::new(self.top_left + window.top_left, window.size)
+    }
+
+    fn data(&self) -> [&[u8]; FRAMES] {
+        self.view.data()
+    }
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use embedded_graphics::{
+        pixelcolor::BinaryColor,
+        prelude::{DrawTarget, Size},
+    };
+
+    use super::*;
+    use crate::{
+        buffer::{binary_buffer_length, BinaryBuffer},
+        epd2in9_v2::{self, Command, Epd2In9V2, RefreshMode},
+        testing::{block_on, Event, MockHw},
+    };
+
+    struct CountingDelay(u32);
+
+    impl DelayNs for CountingDelay {
+        async fn delay_ns(&mut self, _ns: u32) {
+            self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn test_play_writes_previous_frame_as_base() {
+        const SIZE: Size = Size::new(8, 2);
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        log.take_events();
+
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let mut delay = CountingDelay(0);
+        let animation = Animation::new(Point::new(8, 4), Duration::from_millis(100));
+        block_on(animation.play(
+            &mut epd,
+            &mut spi,
+            &mut delay,
+            &mut buffer,
+            |buffer, tick| {
+                let color = BinaryColor::from(tick % 2 == 1);
+                buffer.clear(color).unwrap();
+                tick < 2
+            },
+        ))
+        .unwrap();
+        assert_eq!(delay.0, 2);
+
+        // Each frame's data is only written once as the frame, and once as the next frame's base.
+        let events = log.take_events();
+        let written = |command: Command| {
+            events
+                .windows(2)
+                .filter(|pair| pair[0] == Event::Command(command as u8))
+                .map(|pair| pair[1].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            written(Command::WriteLowRam),
+            [Event::Data(vec![0x00, 0x00]), Event::Data(vec![0xFF, 0xFF])]
+        );
+        assert_eq!(
+            written(Command::WriteHighRam),
+            [Event::Data(vec![0x00, 0x00]), Event::Data(vec![0xFF, 0xFF])]
+        );
+        assert!(events.contains(&Event::Command(Command::SetRamXStartEnd as u8)));
+    }
+
+    #[test]
+    fn test_ticker_scrolls_and_repeats() {
+        use embedded_graphics::mono_font::ascii::FONT_6X10;
+
+        const SIZE: Size = Size::new(32, 10);
+        let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+        let animation = Animation::new(Point::zero(), Duration::from_millis(100));
+        let ticker = Ticker::new(animation, "ab", style, BinaryColor::Off)
+            .with_step(5)
+            .with_gap(8);
+        assert_eq!(ticker.period(), 20);
+        assert_eq!(ticker.offset(3), 15);
+        assert_eq!(ticker.offset(4), 0);
+
+        // The text repeats every period.
+        let mut shown = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        ticker.draw_tick(&mut shown, 1).unwrap();
+        let mut expected = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        for x in [-5, 15] {
+            Text::with_baseline("ab", Point::new(x, 4), style, Baseline::Middle)
+                .draw(&mut expected)
+                .unwrap();
+        }
+        assert_eq!(shown.data(), expected.data());
+
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        let mut delay = CountingDelay(0);
+        block_on(ticker.play(&mut epd, &mut spi, &mut delay, &mut shown, |tick| tick < 3)).unwrap();
+        assert_eq!(delay.0, 3);
+    }
+}
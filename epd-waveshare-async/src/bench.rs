@@ -0,0 +1,141 @@
+//! Measures where the time goes in each display update: drawing into the buffer, transferring it
+//! over SPI, and waiting for the panel to refresh.
+//!
+//! The SPI and busy times come from the display's [Timings], so its hardware must override
+//! [BusyHw::timings](crate::hw::BusyHw::timings) to return them.
+//!
+//! ```text
+//! let mut bench = Benchmark::start(epd.timings().unwrap());
+//! bench.render(epd.timings().unwrap(), || draw_dashboard(&mut buffer, &readings));
+//! epd.display_framebuffer(&mut spi, &buffer).await?;
+//! epd.wait_until_idle().await?;
+//! let phases = bench.finish(epd.timings().unwrap());
+//! defmt::info!("{}", phases);
+//! ```
+use crate::hw::Timings;
+
+/// The time spent in each phase of an operation, measured by a [Benchmark].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PhaseTimings {
+    /// Time spent in [Benchmark::render].
+    pub render_us: u64,
+    /// The number of commands sent to (or read from) the display.
+    pub transfer_count: u32,
+    /// The number of bytes transferred, including command bytes.
+    pub transfer_bytes: u64,
+    /// Time spent transferring data over SPI.
+    pub transfer_us: u64,
+    /// The number of times the driver waited for the display to stop being busy.
+    pub busy_wait_count: u32,
+    /// Time spent waiting for the display to stop being busy.
+    pub busy_us: u64,
+    /// The time from [Benchmark::start] to [Benchmark::finish].
+    pub total_us: u64,
+}
+
+impl PhaseTimings {
+    /// The time that wasn't spent rendering, transferring or waiting, e.g. in other tasks.
+    pub fn other_us(&self) -> u64 {
+        self.total_us
+            .saturating_sub(self.render_us + self.transfer_us + self.busy_us)
+    }
+
+    /// The average SPI throughput while transferring, in bytes per second, if anything was
+    /// transferred.
+    ///
+    /// Compare this with the SPI clock rate to see how much time is lost between transfers.
+    pub fn transfer_bytes_per_second(&self) -> Option<u64> {
+        if self.transfer_us == 0 {
+            return None;
+        }
+        Some(self.transfer_bytes * 1_000_000 / self.transfer_us)
+    }
+}
+
+/// Times one operation, split into [PhaseTimings].
+#[derive(Debug, Clone)]
+pub struct Benchmark {
+    now_us: u64,
+    start: PhaseTimings,
+    render_us: u64,
+}
+
+impl Benchmark {
+    /// Starts timing, using the clock of the display's `timings`.
+    pub fn start(timings: &Timings) -> Self {
+        Benchmark {
+            now_us: timings.now(),
+            start: snapshot(timings),
+            render_us: 0,
+        }
+    }
+
+    /// Runs `render`, adding the time it takes to the render phase.
+    pub fn render<R>(&mut self, timings: &Timings, render: impl FnOnce() -> R) -> R {
+        let start = timings.now();
+        let result = render();
+        self.render_us += timings.now().saturating_sub(start);
+        result
+    }
+
+    /// Stops timing, and returns the time spent in each phase since [Benchmark::start].
+    pub fn finish(self, timings: &Timings) -> PhaseTimings {
+        let end = snapshot(timings);
+        PhaseTimings {
+            render_us: self.render_us,
+            transfer_count: end.transfer_count.saturating_sub(self.start.transfer_count),
+            transfer_bytes: end.transfer_bytes.saturating_sub(self.start.transfer_bytes),
+            transfer_us: end.transfer_us.saturating_sub(self.start.transfer_us),
+            busy_wait_count: end
+                .busy_wait_count
+                .saturating_sub(self.start.busy_wait_count),
+            busy_us: end.busy_us.saturating_sub(self.start.busy_us),
+            total_us: timings.now().saturating_sub(self.now_us),
+        }
+    }
+}
+
+/// The running totals from `timings`.
+fn snapshot(timings: &Timings) -> PhaseTimings {
+    PhaseTimings {
+        render_us: 0,
+        transfer_count: timings.transfer_count(),
+        transfer_bytes: timings.transfer_bytes(),
+        transfer_us: timings.transfer_us(),
+        busy_wait_count: timings.busy_wait_count(),
+        busy_us: timings.busy_wait_us(),
+        total_us: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    static NOW_US: AtomicU64 = AtomicU64::new(0);
+
+    fn now_us() -> u64 {
+        NOW_US.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn test_render_time_is_separate_from_the_rest() {
+        let timings = Timings::new(now_us);
+        let mut bench = Benchmark::start(&timings);
+        let result = bench.render(&timings, || {
+            NOW_US.fetch_add(250, Ordering::Relaxed);
+            7
+        });
+        assert_eq!(result, 7);
+        NOW_US.fetch_add(100, Ordering::Relaxed);
+
+        let phases = bench.finish(&timings);
+        assert_eq!(phases.render_us, 250);
+        assert_eq!(phases.total_us, 350);
+        assert_eq!(phases.other_us(), 100);
+        assert_eq!(phases.transfer_bytes_per_second(), None);
+    }
+}
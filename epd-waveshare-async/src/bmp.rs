@@ -0,0 +1,139 @@
+//! Draws BMP images into display buffers, converting their colors with [crate::dither].
+//!
+//! 1-bit, grayscale and RGB BMPs are supported, via `tinybmp`.
+//!
+//! ```text
+//! static LOGO: &[u8] = include_bytes!("logo.bmp");
+//!
+//! let logo = BmpImage::from_slice(LOGO)?.with_fit(Fit::Contain);
+//! let changed = logo.draw(&mut buffer, buffer.bounding_box())?;
+//! ```
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    image::GetPixel,
+    pixelcolor::Rgb888,
+    prelude::{OriginDimensions, RgbColor, Size},
+    primitives::Rectangle,
+};
+use tinybmp::Bmp;
+
+use crate::dither::{draw_image, Dither, Fit, Quantize};
+
+pub use tinybmp::ParseError;
+
+/// A BMP image that can be drawn into any buffer with [Quantize]d colors.
+#[derive(Debug, Clone, Copy)]
+pub struct BmpImage<'a> {
+    bmp: Bmp<'a, Rgb888>,
+    dither: Dither,
+    fit: Fit,
+    centered: bool,
+}
+
+impl<'a> BmpImage<'a> {
+    /// Parses a BMP file. The image is drawn centered, at its original size, with ordered
+    /// dithering.
+    pub fn from_slice(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        Ok(BmpImage {
+            bmp: Bmp::from_slice(bytes)?,
+            dither: Dither::default(),
+            fit: Fit::default(),
+            centered: true,
+        })
+    }
+
+    /// Sets how colors are converted for the target buffer.
+    pub fn with_dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// Sets how the image is sized within the area it's drawn to.
+    pub fn with_fit(mut self, fit: Fit) -> Self {
+        self.fit = fit;
+        self
+    }
+
+    /// Sets whether the image is centered in the area it's drawn to, or drawn at its top left.
+    pub fn with_centering(mut self, centered: bool) -> Self {
+        self.centered = centered;
+        self
+    }
+
+    /// The image's original size.
+    pub fn size(&self) -> Size {
+        self.bmp.size()
+    }
+
+    /// Draws the image into `area`, and returns the area that changed.
+    pub fn draw<D>(&self, target: &mut D, area: Rectangle) -> Result<Rectangle, D::Error>
+    where
+        D: DrawTarget,
+        D::Color: Quantize,
+    {
+        draw_image(
+            target,
+            area,
+            self.size(),
+            self.fit,
+            self.centered,
+            self.dither,
+            |point| self.bmp.pixel(point).unwrap_or(Rgb888::WHITE),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{pixelcolor::BinaryColor, prelude::Point};
+
+    use super::*;
+    use crate::buffer::{binary_buffer_length, BinaryBuffer};
+
+    /// A 2x2 24-bit BMP, with black in the top left and bottom right.
+    fn checkerboard_bmp() -> Vec<u8> {
+        let mut bmp = Vec::new();
+        // File header.
+        bmp.extend_from_slice(b"BM");
+        bmp.extend_from_slice(&70u32.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&54u32.to_le_bytes());
+        // Info header.
+        for value in [40u32, 2, 2] {
+            bmp.extend_from_slice(&value.to_le_bytes());
+        }
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&24u16.to_le_bytes());
+        for value in [0u32, 16, 0, 0, 0, 0] {
+            bmp.extend_from_slice(&value.to_le_bytes());
+        }
+        // Rows are stored bottom up, and padded to 4 bytes.
+        bmp.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0]);
+        bmp.extend_from_slice(&[0, 0, 0, 0xFF, 0xFF, 0xFF, 0, 0]);
+        bmp
+    }
+
+    #[test]
+    fn test_draw_scales_and_centers() {
+        const SIZE: Size = Size::new(16, 8);
+        let bytes = checkerboard_bmp();
+        let image = BmpImage::from_slice(&bytes)
+            .unwrap()
+            .with_dither(Dither::Threshold);
+        assert_eq!(image.size(), Size::new(2, 2));
+
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        buffer.clear(BinaryColor::On).unwrap();
+        let area = Rectangle::new(Point::zero(), Size::new(16, 4));
+        let changed = image.with_fit(Fit::Contain).draw(&mut buffer, area);
+
+        // Each source pixel becomes 2x2, and the image is centered horizontally.
+        assert_eq!(
+            changed,
+            Ok(Rectangle::new(Point::new(6, 0), Size::new(4, 4)))
+        );
+        assert_eq!(buffer.data()[0..2], [0b1111_1100, 0b1111_1111]);
+        assert_eq!(buffer.data()[4..6], [0b1111_1111, 0b0011_1111]);
+        assert_eq!(buffer.data()[8], 0xFF);
+    }
+}
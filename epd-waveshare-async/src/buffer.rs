@@ -1,18 +1,22 @@
 use core::{
     cmp::{max, min},
     convert::Infallible,
+    ops::Range,
 };
 
 use embedded_graphics::{
+    image::ImageDrawable,
     pixelcolor::{BinaryColor, Gray2},
-    prelude::{Dimensions, DrawTarget, GrayColor, Point, Size},
+    prelude::{Dimensions, DrawTarget, DrawTargetExt, GrayColor, OriginDimensions, Point, Size},
     primitives::Rectangle,
     Pixel,
 };
 use heapless::Vec;
 
 /// Provides a view into a display buffer's data. This buffer is encoded into a set number of frames and bits per pixel.
-pub trait BufferView<const BITS: usize, const FRAMES: usize> {
+///
+/// Views must be [Sync] so that display futures holding a `&dyn BufferView` remain [Send].
+pub trait BufferView<const BITS: usize, const FRAMES: usize>: Sync {
     /// Returns the display window covered by this buffer.
     fn window(&self) -> Rectangle;
 
@@ -20,6 +24,101 @@ pub trait BufferView<const BITS: usize, const FRAMES: usize> {
     fn data(&self) -> [&[u8]; FRAMES];
 }
 
+/// Why a buffer's window can't be written to a display.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowError {
+    /// The window has no width or no height.
+    Empty,
+    /// Part of the window is outside the display.
+    OutOfBounds,
+}
+
+impl core::fmt::Display for WindowError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WindowError::Empty => f.write_str("the window is empty"),
+            WindowError::OutOfBounds => f.write_str("the window is outside the display"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for WindowError {}
+
+/// Checks that `window` can be written to a display of size `display`: it must cover at least one
+/// pixel, and lie entirely within the display.
+///
+/// Windows that are partly outside the display are rejected rather than clamped, as the buffer's
+/// data is laid out for the whole window. Drivers return this error, converted into
+/// [ErrorHw::Error](crate::hw::ErrorHw::Error), for buffers whose window fails this check, without
+/// writing them.
+///
+/// ```
+/// use embedded_graphics::{prelude::*, primitives::Rectangle};
+/// use epd_waveshare_async::buffer::{check_window, WindowError};
+///
+/// let display = Size::new(128, 296);
+/// let window = Rectangle::new(Point::new(120, 0), Size::new(16, 8));
+/// assert_eq!(check_window(window, display), Err(WindowError::OutOfBounds));
+/// ```
+pub fn check_window(window: Rectangle, display: Size) -> Result<(), WindowError> {
+    let Some(bottom_right) = window.bottom_right() else {
+        return Err(WindowError::Empty);
+    };
+    let bounds = Rectangle::new(Point::zero(), display);
+    if bounds.contains(window.top_left) && bounds.contains(bottom_right) {
+        Ok(())
+    } else {
+        Err(WindowError::OutOfBounds)
+    }
+}
+
+/// An area rounded out to whole bytes on the x-axis, and where it lies in a buffer's packed data.
+/// See [align_window].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignedWindow {
+    /// The area, rounded out to whole bytes, in display coordinates.
+    pub window: Rectangle,
+    /// The rows of the window, counted from the top of the buffer.
+    pub rows: Range<usize>,
+    /// The bytes of the window within each row of the buffer.
+    pub row_bytes: Range<usize>,
+}
+
+/// Rounds `area` out to whole bytes on the x-axis, as the displays address their RAM one byte (8
+/// pixels) at a time, and finds its data in a buffer covering `buffer`.
+///
+/// `area` is first clipped to `buffer`, whose left edge must be a multiple of 8, as it is for all
+/// of this crate's buffers. Returns `None` if they don't overlap.
+///
+/// ```
+/// use embedded_graphics::{prelude::*, primitives::Rectangle};
+/// use epd_waveshare_async::buffer::align_window;
+///
+/// let buffer = Rectangle::new(Point::zero(), Size::new(128, 296));
+/// let aligned = align_window(Rectangle::new(Point::new(10, 4), Size::new(20, 2)), buffer).unwrap();
+/// assert_eq!(aligned.window, Rectangle::new(Point::new(8, 4), Size::new(24, 2)));
+/// assert_eq!(aligned.rows, 4..6);
+/// assert_eq!(aligned.row_bytes, 1..4);
+/// ```
+pub fn align_window(area: Rectangle, buffer: Rectangle) -> Option<AlignedWindow> {
+    let area = area.intersection(&buffer);
+    let bottom_right = area.bottom_right()?;
+    let x_start = area.top_left.x.div_euclid(8) * 8;
+    let x_end = (bottom_right.x.div_euclid(8) + 1) * 8;
+    let byte = |x: i32| ((x - buffer.top_left.x).max(0) / 8) as usize;
+    let row = |y: i32| (y - buffer.top_left.y) as usize;
+    Some(AlignedWindow {
+        window: Rectangle::with_corners(
+            Point::new(x_start, area.top_left.y),
+            Point::new(x_end - 1, bottom_right.y),
+        ),
+        rows: row(area.top_left.y)..row(bottom_right.y) + 1,
+        row_bytes: byte(x_start)..byte(x_end),
+    })
+}
+
 /// A compact buffer for storing binary coloured display data.
 ///
 /// This buffer packs the data such that each byte represents 8 pixels.
@@ -31,6 +130,19 @@ pub struct BinaryBuffer<const L: usize> {
     data: [u8; L],
 }
 
+#[cfg(feature = "defmt")]
+impl<const L: usize> defmt::Format for BinaryBuffer<L> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "BinaryBuffer {{ size: {}x{}, len: {} }}",
+            self.size.width,
+            self.size.height,
+            L
+        )
+    }
+}
+
 /// Computes the correct size for the binary buffer based on the given dimensions.
 pub const fn binary_buffer_length(size: Size) -> usize {
     (size.width as usize / 8) * size.height as usize
@@ -71,6 +183,112 @@ impl<const L: usize> BinaryBuffer<L> {
     pub fn data(&self) -> &[u8] {
         &self.data
     }
+
+    /// Mutably access the packed buffer data.
+    pub(crate) fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Copies all of `image`'s pixels into this buffer, with its top left at `top_left`, and
+    /// returns the area that it covers.
+    ///
+    /// Pixels that fall outside this buffer are skipped. `image` is copied a byte at a time,
+    /// shifting the bits when `top_left.x` isn't a multiple of 8, so this is much faster than
+    /// drawing it pixel by pixel.
+    ///
+    #[cfg_attr(feature = "epd2in9_v2", doc = "```")]
+    #[cfg_attr(not(feature = "epd2in9_v2"), doc = "```ignore")]
+    /// use embedded_graphics::prelude::{Point, Size};
+    /// use epd_waveshare_async::{buffer::Bitmap, epd2in9_v2};
+    ///
+    /// // Stored in flash, with set bits shown as white.
+    /// static ARROW: [u8; 4] = [0x18, 0x3C, 0x7E, 0xFF];
+    ///
+    /// let mut buffer = epd2in9_v2::new_binary_buffer();
+    /// let arrow = Bitmap::new(&ARROW, Size::new(8, 4)).unwrap();
+    /// buffer.blit(&arrow, Point::new(13, 40));
+    /// ```
+    pub fn blit(&mut self, image: &dyn BufferView<1, 1>, top_left: Point) -> Rectangle {
+        self.blit_with(image, top_left, |byte, bits, mask| {
+            *byte = (*byte & !mask) | bits
+        })
+    }
+
+    /// Sets the pixels that are set in `image` to `color`, with its top left at `top_left`,
+    /// leaving the other pixels unchanged. Returns the area that `image` covers.
+    ///
+    /// This suits compositing icons and glyphs over existing content. Like [BinaryBuffer::blit],
+    /// `image` is copied a byte at a time.
+    pub fn stamp(
+        &mut self,
+        image: &dyn BufferView<1, 1>,
+        top_left: Point,
+        color: BinaryColor,
+    ) -> Rectangle {
+        self.blit_with(image, top_left, |byte, bits, _mask| {
+            if color == BinaryColor::On {
+                *byte |= bits;
+            } else {
+                *byte &= !bits;
+            }
+        })
+    }
+
+    /// Calls `apply` with each byte of this buffer that `image` covers, the image's bits for that
+    /// byte, and a mask of which of the byte's pixels the image covers.
+    fn blit_with(
+        &mut self,
+        image: &dyn BufferView<1, 1>,
+        top_left: Point,
+        mut apply: impl FnMut(&mut u8, u8, u8),
+    ) -> Rectangle {
+        let size = image.window().size;
+        let area = Rectangle::new(top_left, size);
+        if size.width == 0 || size.height == 0 {
+            return area;
+        }
+        let source_bytes_per_row = (size.width as usize).div_ceil(8);
+        let [source] = image.data();
+        let Some(source) = source.get(..source_bytes_per_row * size.height as usize) else {
+            return area;
+        };
+        // The mask of pixels used in the last byte of each row.
+        let last_mask = match size.width % 8 {
+            0 => 0xFF,
+            used => 0xFFu8 << (8 - used),
+        };
+        let shift = top_left.x.rem_euclid(8) as u32;
+        let first_column = top_left.x.div_euclid(8);
+        for (row, bytes) in source.chunks_exact(source_bytes_per_row).enumerate() {
+            let y = top_left.y + row as i32;
+            if y < 0 || y >= self.size.height as i32 {
+                continue;
+            }
+            let row_start = y as usize * self.bytes_per_row;
+            let mut write = |column: i32, bits: u8, mask: u8| {
+                if mask == 0 || column < 0 || column >= self.bytes_per_row as i32 {
+                    return;
+                }
+                apply(&mut self.data[row_start + column as usize], bits, mask);
+            };
+            for (i, byte) in bytes.iter().enumerate() {
+                let mask = if i + 1 == bytes.len() {
+                    last_mask
+                } else {
+                    0xFF
+                };
+                let bits = byte & mask;
+                let column = first_column + i as i32;
+                if shift == 0 {
+                    write(column, bits, mask);
+                } else {
+                    write(column, bits >> shift, mask >> shift);
+                    write(column + 1, bits << (8 - shift), mask << (8 - shift));
+                }
+            }
+        }
+        area
+    }
 }
 
 impl<const L: usize> BufferView<1, 1> for BinaryBuffer<L> {
@@ -83,9 +301,32 @@ impl<const L: usize> BufferView<1, 1> for BinaryBuffer<L> {
     }
 }
 
-impl<const L: usize> Dimensions for BinaryBuffer<L> {
-    fn bounding_box(&self) -> Rectangle {
-        Rectangle::new(Point::zero(), self.size)
+impl<const L: usize> OriginDimensions for BinaryBuffer<L> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+/// Draws the buffer into another [DrawTarget], such as a simulator window or a larger canvas, with
+/// [embedded_graphics::image::Image].
+impl<const L: usize> ImageDrawable for BinaryBuffer<L> {
+    type Color = BinaryColor;
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        target.fill_contiguous(
+            &self.bounding_box(),
+            packed_bits(&self.data, self.size).map(BinaryColor::from),
+        )
+    }
+
+    fn draw_sub_image<D>(&self, target: &mut D, area: &Rectangle) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        self.draw(&mut target.translated(-area.top_left).clipped(area))
     }
 }
 
@@ -199,7 +440,8 @@ impl<const L: usize> DrawTarget for BinaryBuffer<L> {
         let x_start = drawable_area.top_left.x;
         let x_end = drawable_area.top_left.x + drawable_area.size.width as i32;
 
-        let x_full_bytes_start = min(x_start + x_start % 8, x_end);
+        // Round the start up to the next whole byte.
+        let x_full_bytes_start = min((x_start + 7) / 8 * 8, x_end);
         let x_full_bytes_end = max(x_end - (x_end % 8), x_start);
         let num_full_bytes_per_row = (x_full_bytes_end - x_full_bytes_start) / 8;
 
@@ -263,6 +505,194 @@ impl<const L: usize> DrawTarget for BinaryBuffer<L> {
     }
 }
 
+/// Packed 1-bit image data, with each row rounded up to a whole byte and the leftmost pixel of
+/// each byte in the most significant bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bitmap<'a> {
+    data: &'a [u8],
+    size: Size,
+}
+
+impl<'a> Bitmap<'a> {
+    /// Wraps `data` as an image of the given `size`, or returns `None` if the length of `data`
+    /// doesn't match the size.
+    pub const fn new(data: &'a [u8], size: Size) -> Option<Self> {
+        if data.len() != (size.width as usize).div_ceil(8) * size.height as usize {
+            return None;
+        }
+        Some(Bitmap { data, size })
+    }
+}
+
+impl BufferView<1, 1> for Bitmap<'_> {
+    fn window(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.size)
+    }
+
+    fn data(&self) -> [&[u8]; 1] {
+        [self.data]
+    }
+}
+
+impl OriginDimensions for Bitmap<'_> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl ImageDrawable for Bitmap<'_> {
+    type Color = BinaryColor;
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        target.fill_contiguous(
+            &self.bounding_box(),
+            packed_bits(self.data, self.size).map(BinaryColor::from),
+        )
+    }
+
+    fn draw_sub_image<D>(&self, target: &mut D, area: &Rectangle) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        self.draw(&mut target.translated(-area.top_left).clipped(area))
+    }
+}
+
+/// Returns each pixel of packed 1-bit data, row by row, as whether its bit is set. Each row is
+/// rounded up to a whole byte.
+fn packed_bits(data: &[u8], size: Size) -> impl Iterator<Item = bool> + '_ {
+    let width = size.width as usize;
+    let bytes_per_row = width.div_ceil(8);
+    data.chunks_exact(bytes_per_row.max(1))
+        .take(size.height as usize)
+        .flat_map(move |row| (0..width).map(move |x| row[x / 8] & (0x80 >> (x % 8)) != 0))
+}
+
+/// Packed 2-bit image data, split into low and high bit planes like a [Gray2SplitBuffer]. Each
+/// plane is laid out like a [Bitmap].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gray2SplitBitmap<'a> {
+    low: &'a [u8],
+    high: &'a [u8],
+    size: Size,
+}
+
+impl<'a> Gray2SplitBitmap<'a> {
+    /// Wraps the `low` and `high` bit planes as an image of the given `size`, or returns `None` if
+    /// the length of either plane doesn't match the size.
+    pub const fn new(low: &'a [u8], high: &'a [u8], size: Size) -> Option<Self> {
+        if low.len() != (size.width as usize).div_ceil(8) * size.height as usize
+            || high.len() != low.len()
+        {
+            return None;
+        }
+        Some(Gray2SplitBitmap { low, high, size })
+    }
+}
+
+impl BufferView<1, 2> for Gray2SplitBitmap<'_> {
+    fn window(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.size)
+    }
+
+    fn data(&self) -> [&[u8]; 2] {
+        [self.low, self.high]
+    }
+}
+
+impl OriginDimensions for Gray2SplitBitmap<'_> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl ImageDrawable for Gray2SplitBitmap<'_> {
+    type Color = Gray2;
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        target.fill_contiguous(
+            &self.bounding_box(),
+            packed_gray2(self.low, self.high, self.size),
+        )
+    }
+
+    fn draw_sub_image<D>(&self, target: &mut D, area: &Rectangle) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        self.draw(&mut target.translated(-area.top_left).clipped(area))
+    }
+}
+
+/// Returns each pixel of packed low and high bit planes, row by row, like [packed_bits].
+fn packed_gray2<'a>(low: &'a [u8], high: &'a [u8], size: Size) -> impl Iterator<Item = Gray2> + 'a {
+    packed_bits(low, size)
+        .zip(packed_bits(high, size))
+        .map(|(low, high)| Gray2::new(u8::from(low) | (u8::from(high) << 1)))
+}
+
+/// A view of packed buffer data that's already laid out for a window of the display, e.g. a
+/// full-screen image baked into flash, which can be written with
+/// [DisplaySimple::write_framebuffer](crate::DisplaySimple::write_framebuffer) without copying it
+/// into a buffer first.
+///
+/// Each frame is laid out like the frames of this crate's buffers: row by row, with each row
+/// rounded up to a whole byte. Multi-frame formats, such as the low and high planes of a
+/// [Gray2SplitBuffer], take one slice per frame.
+///
+/// ```
+/// use embedded_graphics::{prelude::*, primitives::Rectangle};
+/// use epd_waveshare_async::buffer::{BufferView, RawBufferView};
+///
+/// const SPLASH: [u8; 16 * 4] = [0xFF; 16 * 4];
+/// static SPLASH_VIEW: RawBufferView<'static, 1, 1> = RawBufferView::new(
+///     Rectangle::new(Point::new(0, 8), Size::new(128, 4)),
+///     [&SPLASH],
+/// );
+///
+/// assert_eq!(SPLASH_VIEW.window().top_left, Point::new(0, 8));
+/// assert_eq!(SPLASH_VIEW.data()[0].len(), 64);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawBufferView<'a, const BITS: usize, const FRAMES: usize> {
+    window: Rectangle,
+    data: [&'a [u8]; FRAMES],
+}
+
+impl<'a, const BITS: usize, const FRAMES: usize> RawBufferView<'a, BITS, FRAMES> {
+    /// Wraps the frames in `data` as covering `window` of the display.
+    pub const fn new(window: Rectangle, data: [&'a [u8]; FRAMES]) -> Self {
+        let len = (window.size.width as usize * BITS).div_ceil(8) * window.size.height as usize;
+        let mut frame = 0;
+        while frame < FRAMES {
+            debug_assert!(
+                data[frame].len() == len,
+                "Data length must match the window size."
+            );
+            frame += 1;
+        }
+        RawBufferView { window, data }
+    }
+}
+
+impl<const BITS: usize, const FRAMES: usize> BufferView<BITS, FRAMES>
+    for RawBufferView<'_, BITS, FRAMES>
+{
+    fn window(&self) -> Rectangle {
+        self.window
+    }
+
+    fn data(&self) -> [&[u8]; FRAMES] {
+        self.data
+    }
+}
+
 /// A buffer supporting 2-bit grayscale colours. This buffer splits the 2 bits into two separate single-bit framebuffers.
 #[derive(Clone)]
 pub struct Gray2SplitBuffer<const L: usize> {
@@ -270,6 +700,19 @@ pub struct Gray2SplitBuffer<const L: usize> {
     pub high: BinaryBuffer<L>,
 }
 
+#[cfg(feature = "defmt")]
+impl<const L: usize> defmt::Format for Gray2SplitBuffer<L> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Gray2SplitBuffer {{ size: {}x{}, len: {} }}",
+            self.low.size.width,
+            self.low.size.height,
+            L
+        )
+    }
+}
+
 /// Computes the correct size for the [Gray2SplitBuffer] based on the given dimensions.
 pub const fn gray2_split_buffer_length(size: Size) -> usize {
     binary_buffer_length(size)
@@ -305,9 +748,32 @@ impl<const L: usize> BufferView<1, 2> for Gray2SplitBuffer<L> {
     }
 }
 
-impl<const L: usize> Dimensions for Gray2SplitBuffer<L> {
-    fn bounding_box(&self) -> Rectangle {
-        Rectangle::new(Point::zero(), self.low.size)
+impl<const L: usize> OriginDimensions for Gray2SplitBuffer<L> {
+    fn size(&self) -> Size {
+        self.low.size
+    }
+}
+
+/// Draws the buffer into another [DrawTarget], such as a simulator window or a larger canvas, with
+/// [embedded_graphics::image::Image].
+impl<const L: usize> ImageDrawable for Gray2SplitBuffer<L> {
+    type Color = Gray2;
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        target.fill_contiguous(
+            &self.bounding_box(),
+            packed_gray2(&self.low.data, &self.high.data, self.low.size),
+        )
+    }
+
+    fn draw_sub_image<D>(&self, target: &mut D, area: &Rectangle) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        self.draw(&mut target.translated(-area.top_left).clipped(area))
     }
 }
 
@@ -396,6 +862,7 @@ pub trait Rotation {
 }
 
 /// Represents a 90, 180, or 270 degree clockwise rotation of a point within a given size.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Rotate {
     Degrees90,
@@ -494,6 +961,22 @@ impl<B: DrawTarget, R: Rotation> RotatedBuffer<B, R> {
     }
 }
 
+#[cfg(feature = "defmt")]
+impl<B, R> defmt::Format for RotatedBuffer<B, R>
+where
+    B: DrawTarget + defmt::Format,
+    R: Rotation + defmt::Format,
+{
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "RotatedBuffer {{ buffer: {}, rotation: {} }}",
+            self.buffer,
+            self.rotation
+        )
+    }
+}
+
 impl<B: DrawTarget, R: Rotation> Dimensions for RotatedBuffer<B, R> {
     fn bounding_box(&self) -> Rectangle {
         self.bounds
@@ -516,6 +999,7 @@ impl<B: DrawTarget, R: Rotation> DrawTarget for RotatedBuffer<B, R> {
     }
 }
 
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
 #[inline(always)]
 /// Splits a 16-bit value into the two 8-bit values representing the low and high bytes.
 pub(crate) fn split_low_and_high(value: u16) -> (u8, u8) {
@@ -524,11 +1008,131 @@ pub(crate) fn split_low_and_high(value: u16) -> (u8, u8) {
     (low, high)
 }
 
+#[cfg(test)]
+mod proptests;
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use embedded_graphics::pixelcolor::BinaryColor;
 
+    #[test]
+    fn test_check_window_rejects_empty_and_out_of_bounds() {
+        let display = Size::new(16, 8);
+        let window =
+            |x, y, width, height| Rectangle::new(Point::new(x, y), Size::new(width, height));
+        assert_eq!(check_window(window(0, 0, 16, 8), display), Ok(()));
+        assert_eq!(check_window(window(8, 4, 8, 4), display), Ok(()));
+        assert_eq!(
+            check_window(window(8, 4, 0, 4), display),
+            Err(WindowError::Empty)
+        );
+        assert_eq!(
+            check_window(window(-8, 0, 8, 8), display),
+            Err(WindowError::OutOfBounds)
+        );
+        assert_eq!(
+            check_window(window(8, 4, 16, 4), display),
+            Err(WindowError::OutOfBounds)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_window_error_is_a_std_error() {
+        let error: std::boxed::Box<dyn std::error::Error> =
+            std::boxed::Box::new(WindowError::Empty);
+        assert_eq!(error.to_string(), "the window is empty");
+    }
+
+    #[test]
+    fn test_align_window_clips_to_the_buffer() {
+        let buffer = Rectangle::new(Point::new(16, 8), Size::new(32, 8));
+        let aligned = align_window(Rectangle::new(Point::new(0, 0), Size::new(20, 10)), buffer);
+        assert_eq!(
+            aligned,
+            Some(AlignedWindow {
+                window: Rectangle::new(Point::new(16, 8), Size::new(8, 2)),
+                rows: 0..2,
+                row_bytes: 0..1,
+            })
+        );
+        assert_eq!(
+            align_window(Rectangle::new(Point::new(47, 15), Size::new(1, 1)), buffer)
+                .map(|aligned| aligned.row_bytes),
+            Some(3..4)
+        );
+        assert_eq!(
+            align_window(Rectangle::new(Point::new(48, 8), Size::new(8, 8)), buffer),
+            None
+        );
+    }
+
+    #[test]
+    fn test_binary_buffer_blit_unaligned() {
+        const SIZE: Size = Size::new(16, 2);
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        buffer
+            .fill_solid(&buffer.bounding_box(), BinaryColor::On)
+            .unwrap();
+        // A 6x2 image, whose unused low bits must be ignored.
+        let image = Bitmap::new(&[0b1010_1011, 0b0000_0011], Size::new(6, 2)).unwrap();
+
+        let area = buffer.blit(&image, Point::new(5, 1));
+        assert_eq!(area, Rectangle::new(Point::new(5, 1), Size::new(6, 2)));
+        assert_eq!(buffer.data(), [0xFF, 0xFF, 0b1111_1101, 0b0101_1111]);
+
+        // Pixels outside the buffer are skipped.
+        buffer.blit(&image, Point::new(-3, 0));
+        assert_eq!(buffer.data(), [0b0101_1111, 0xFF, 0b0001_1101, 0b0101_1111]);
+    }
+
+    #[test]
+    fn test_bitmaps_reject_data_of_the_wrong_length() {
+        assert_eq!(Bitmap::new(&[0; 3], Size::new(9, 2)), None);
+        assert!(Bitmap::new(&[0; 4], Size::new(9, 2)).is_some());
+        assert_eq!(
+            Gray2SplitBitmap::new(&[0; 2], &[0; 1], Size::new(8, 2)),
+            None
+        );
+        assert!(Gray2SplitBitmap::new(&[0; 2], &[0; 2], Size::new(8, 2)).is_some());
+    }
+
+    #[test]
+    fn test_binary_buffer_blit_skips_short_images() {
+        /// A view whose data is too short for its window.
+        struct Short;
+
+        impl BufferView<1, 1> for Short {
+            fn window(&self) -> Rectangle {
+                Rectangle::new(Point::zero(), Size::new(12, 3))
+            }
+
+            fn data(&self) -> [&[u8]; 1] {
+                [&[0xFF]]
+            }
+        }
+
+        const SIZE: Size = Size::new(16, 4);
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        buffer.blit(&Short, Point::zero());
+        assert!(buffer.data().iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_binary_buffer_stamp_keeps_unset_pixels() {
+        const SIZE: Size = Size::new(16, 1);
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let image = Bitmap::new(&[0b1001_0000], Size::new(4, 1)).unwrap();
+
+        buffer.stamp(&image, Point::new(6, 0), BinaryColor::On);
+        assert_eq!(buffer.data(), [0b0000_0010, 0b0100_0000]);
+        buffer.stamp(&image, Point::new(6, 0), BinaryColor::Off);
+        assert_eq!(buffer.data(), [0, 0]);
+        buffer.stamp(&image, Point::new(8, 0), BinaryColor::On);
+        assert_eq!(buffer.data(), [0, 0b1001_0000]);
+    }
+
     #[test]
     fn test_binary_buffer_draw_iter_singles() {
         const SIZE: Size = Size::new(16, 4);
@@ -842,6 +1446,43 @@ mod tests {
         assert_eq!(buffer.data()[1], &expected_high);
     }
 
+    #[test]
+    fn test_buffers_draw_as_images() {
+        use embedded_graphics::{
+            image::{Image, ImageDrawableExt},
+            prelude::Drawable,
+        };
+
+        const SIZE: Size = Size::new(16, 2);
+        let mut canvas = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let arrow = Bitmap::new(&[0b0110_0000, 0b1111_0000], Size::new(4, 2)).unwrap();
+        Image::new(&arrow, Point::new(5, 0))
+            .draw(&mut canvas)
+            .unwrap();
+        assert_eq!(canvas.data(), &[0b0000_0011, 0, 0b0000_0111, 0b1000_0000]);
+
+        // Draw the canvas into another buffer, offset by 4 pixels.
+        let mut copy = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        Image::new(&canvas, Point::new(4, 0))
+            .draw(&mut copy)
+            .unwrap();
+        assert_eq!(copy.data(), &[0, 0b0011_0000, 0, 0b0111_1000]);
+
+        let mut gray = Gray2SplitBuffer::<{ gray2_split_buffer_length(SIZE) }>::new(SIZE);
+        gray.fill_solid(
+            &Rectangle::new(Point::new(8, 1), Size::new(2, 1)),
+            Gray2::new(0b10),
+        )
+        .unwrap();
+        let mut gray_copy = Gray2SplitBuffer::<{ gray2_split_buffer_length(SIZE) }>::new(SIZE);
+        let sub_image = gray.sub_image(&Rectangle::new(Point::new(8, 0), Size::new(8, 2)));
+        Image::new(&sub_image, Point::zero())
+            .draw(&mut gray_copy)
+            .unwrap();
+        assert_eq!(gray_copy.low.data(), &[0; 4]);
+        assert_eq!(gray_copy.high.data(), &[0, 0, 0b1100_0000, 0]);
+    }
+
     #[test]
     fn test_rotated_buffer_bounds() {
         const SIZE: Size = Size::new(8, 24);
@@ -0,0 +1,213 @@
+//! Property tests that draw random shapes into the buffers, and check them against a naive
+//! reference buffer with one value per pixel.
+//!
+//! To cover a new buffer type, implement [ImageDrawable] for it (so that it can be read back), and
+//! call [check_ops] with a strategy for its colour.
+use std::vec::Vec;
+
+use embedded_graphics::{
+    image::Image,
+    prelude::{Drawable, PixelColor},
+};
+use proptest::{collection::vec, prelude::*};
+
+use super::*;
+
+/// A buffer with one colour per pixel, using only the default [DrawTarget] methods.
+#[derive(Debug, Clone, PartialEq)]
+struct Reference<C> {
+    size: Size,
+    pixels: Vec<C>,
+}
+
+impl<C: PixelColor + Default> Reference<C> {
+    fn new(size: Size) -> Self {
+        Reference {
+            size,
+            pixels: std::vec![C::default(); (size.width * size.height) as usize],
+        }
+    }
+
+    /// Returns a copy of this buffer, rotated clockwise by `rotation`.
+    fn rotated(&self, rotation: Rotate) -> Self {
+        let quarter_turns = match rotation {
+            Rotate::Degrees90 => 1,
+            Rotate::Degrees180 => 2,
+            Rotate::Degrees270 => 3,
+        };
+        let mut rotated = self.clone();
+        for _ in 0..quarter_turns {
+            let (w, h) = (rotated.size.width as usize, rotated.size.height as usize);
+            let mut pixels = Vec::with_capacity(w * h);
+            // The new row `y` is the old column `y`, read from the bottom up.
+            for y in 0..w {
+                for x in 0..h {
+                    pixels.push(rotated.pixels[(h - 1 - x) * w + y]);
+                }
+            }
+            rotated = Reference {
+                size: Size::new(h as u32, w as u32),
+                pixels,
+            };
+        }
+        rotated
+    }
+}
+
+impl<C> OriginDimensions for Reference<C> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl<C: PixelColor> DrawTarget for Reference<C> {
+    type Color = C;
+
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if self.bounding_box().contains(point) {
+                self.pixels[point.y as usize * self.size.width as usize + point.x as usize] = color;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A drawing operation, which may reach outside the buffer.
+#[derive(Debug, Clone)]
+enum Op<C> {
+    Pixels(Vec<(Point, C)>),
+    FillSolid(Rectangle, C),
+    /// Fills the area with the colours repeated.
+    FillContiguous(Rectangle, Vec<C>),
+}
+
+impl<C: PixelColor> Op<C> {
+    fn apply<D: DrawTarget<Color = C, Error = Infallible>>(&self, target: &mut D) {
+        let Ok(()) = match self {
+            Op::Pixels(pixels) => {
+                target.draw_iter(pixels.iter().map(|&(point, color)| Pixel(point, color)))
+            }
+            Op::FillSolid(area, color) => target.fill_solid(area, *color),
+            Op::FillContiguous(area, colors) => {
+                target.fill_contiguous(area, colors.iter().copied().cycle())
+            }
+        };
+    }
+}
+
+/// Buffer sizes, whose width is a multiple of 8.
+fn sizes() -> impl Strategy<Value = Size> {
+    (1..=4u32, 1..=12u32).prop_map(|(bytes, height)| Size::new(bytes * 8, height))
+}
+
+fn points() -> impl Strategy<Value = Point> {
+    (-12..44, -12..24).prop_map(|(x, y)| Point::new(x, y))
+}
+
+fn rectangles() -> impl Strategy<Value = Rectangle> {
+    (points(), 0..40u32, 0..20u32)
+        .prop_map(|(top_left, width, height)| Rectangle::new(top_left, Size::new(width, height)))
+}
+
+fn ops<C: Clone + core::fmt::Debug>(
+    color: impl Strategy<Value = C> + Clone,
+) -> impl Strategy<Value = Vec<Op<C>>> {
+    let op = prop_oneof![
+        vec((points(), color.clone()), 0..16).prop_map(Op::Pixels),
+        (rectangles(), color.clone()).prop_map(|(area, color)| Op::FillSolid(area, color)),
+        (rectangles(), vec(color, 1..12))
+            .prop_map(|(area, colors)| Op::FillContiguous(area, colors)),
+    ];
+    vec(op, 1..8)
+}
+
+fn binary_colors() -> impl Strategy<Value = BinaryColor> + Clone {
+    any::<bool>().prop_map(BinaryColor::from)
+}
+
+fn gray2_colors() -> impl Strategy<Value = Gray2> + Clone {
+    (0..4u8).prop_map(Gray2::new)
+}
+
+fn rotations() -> impl Strategy<Value = Rotate> {
+    prop_oneof![
+        Just(Rotate::Degrees90),
+        Just(Rotate::Degrees180),
+        Just(Rotate::Degrees270)
+    ]
+}
+
+/// Reads all of `buffer`'s pixels back into a [Reference].
+fn read_back<B, C>(buffer: &B) -> Reference<C>
+where
+    B: ImageDrawable<Color = C>,
+    C: PixelColor + Default,
+{
+    let mut reference = Reference::new(buffer.size());
+    let Ok(()) = Image::new(buffer, Point::zero()).draw(&mut reference);
+    reference
+}
+
+/// Applies `ops` to both `buffer` and a [Reference], and checks that they match.
+fn check_ops<B, C>(mut buffer: B, ops: &[Op<C>]) -> Result<(), TestCaseError>
+where
+    B: DrawTarget<Color = C, Error = Infallible> + ImageDrawable<Color = C>,
+    C: PixelColor + Default + core::fmt::Debug,
+{
+    let mut reference = Reference::new(buffer.bounding_box().size);
+    for op in ops {
+        op.apply(&mut buffer);
+        op.apply(&mut reference);
+    }
+    prop_assert_eq!(read_back(&buffer), reference);
+    Ok(())
+}
+
+/// The length of the largest buffer from [sizes].
+const MAX_LEN: usize = 4 * 12;
+
+/// Creates a [BinaryBuffer] of `size`, with [MAX_LEN] bytes of data, as the length of a buffer must
+/// be constant.
+fn binary_buffer(size: Size) -> BinaryBuffer<MAX_LEN> {
+    let mut buffer = BinaryBuffer::new(Size::new(32, 12));
+    buffer.size = size;
+    buffer.bytes_per_row = size.width as usize / 8;
+    buffer
+}
+
+proptest! {
+    #[test]
+    fn binary_buffer_matches_reference(size in sizes(), ops in ops(binary_colors())) {
+        check_ops(binary_buffer(size), &ops)?;
+    }
+
+    #[test]
+    fn gray2_split_buffer_matches_reference(size in sizes(), ops in ops(gray2_colors())) {
+        let buffer = Gray2SplitBuffer {
+            low: binary_buffer(size),
+            high: binary_buffer(size),
+        };
+        check_ops(buffer, &ops)?;
+    }
+
+    #[test]
+    fn rotated_buffer_matches_rotated_reference(
+        size in sizes(),
+        rotation in rotations(),
+        ops in ops(binary_colors()),
+    ) {
+        let mut buffer = RotatedBuffer::new(binary_buffer(size), rotation);
+        let mut reference = Reference::new(buffer.bounding_box().size);
+        for op in &ops {
+            op.apply(&mut buffer);
+            op.apply(&mut reference);
+        }
+        prop_assert_eq!(read_back(&buffer.take_inner()), reference.rotated(rotation));
+    }
+}
@@ -0,0 +1,488 @@
+//! Line charts, bar charts and sparklines for plotting sensor readings in 1-bit color.
+//!
+//! The charts are [Widget]s, so they clear their bounds before drawing and return the area they
+//! changed, ready to be added to a [crate::widgets::DirtyRect] for a partial refresh.
+//!
+//! ```
+//! use embedded_graphics::{
+//!     mono_font::ascii::FONT_6X10,
+//!     pixelcolor::BinaryColor,
+//!     prelude::{Point, Size},
+//!     primitives::Rectangle,
+//! };
+//! use epd_waveshare_async::{
+//!     chart::{Chart, ChartKind, Scale, Sparkline},
+//!     epd2in9_v2,
+//!     widgets::{Widget, WidgetStyle},
+//! };
+//!
+//! let style = WidgetStyle::new(&FONT_6X10, BinaryColor::Off, BinaryColor::On);
+//! let mut buffer = epd2in9_v2::new_binary_buffer();
+//! let temperatures = [18.5, 19.0, 20.5, 21.0, 20.0, 19.5];
+//!
+//! let trend = Sparkline::new(Rectangle::new(Point::zero(), Size::new(48, 12)), &temperatures);
+//! trend.draw(&mut buffer, &style).unwrap();
+//!
+//! let area = Rectangle::new(Point::new(0, 16), Size::new(128, 64));
+//! let chart = Chart::new(area, &temperatures, ChartKind::Bar)
+//!     .with_scale(Scale::new(15.0, 25.0))
+//!     .with_ticks(3, Some(0));
+//! assert_eq!(chart.draw(&mut buffer, &style), Ok(area));
+//! ```
+use core::fmt::Write as _;
+
+use embedded_graphics::{
+    draw_target::{DrawTarget, DrawTargetExt},
+    mono_font::MonoTextStyle,
+    prelude::{PixelColor, Point, Size},
+    primitives::{Line, Primitive, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable,
+};
+
+use crate::widgets::{Widget, WidgetStyle};
+
+/// The length of the tick marks on a chart's value axis.
+const TICK_LENGTH: u32 = 2;
+/// The gap between a tick label and its tick mark.
+const LABEL_GAP: u32 = 1;
+
+/// Maps values onto pixel positions.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale {
+    min: f32,
+    max: f32,
+}
+
+impl Scale {
+    /// Creates a scale from `min` to `max`. Values outside the scale are clamped to it.
+    pub const fn new(min: f32, max: f32) -> Self {
+        Scale { min, max }
+    }
+
+    /// Creates the smallest scale that fits all the finite `values`.
+    ///
+    /// If all the values are equal, the scale extends 1 either side of them, so that they're
+    /// plotted in the middle. If there are no finite values, the scale is from 0 to 1.
+    pub fn fit(values: &[f32]) -> Self {
+        let mut finite = values.iter().copied().filter(|v| v.is_finite());
+        let Some(first) = finite.next() else {
+            return Scale::new(0.0, 1.0);
+        };
+        let (min, max) = finite.fold((first, first), |(min, max), v| (min.min(v), max.max(v)));
+        if min == max {
+            Scale::new(min - 1.0, max + 1.0)
+        } else {
+            Scale::new(min, max)
+        }
+    }
+
+    pub fn min(&self) -> f32 {
+        self.min
+    }
+
+    pub fn max(&self) -> f32 {
+        self.max
+    }
+
+    /// Returns the `index`th of `count` evenly spaced values, from the minimum to the maximum.
+    pub fn tick(&self, index: u32, count: u32) -> f32 {
+        if count < 2 {
+            return self.min;
+        }
+        self.min + (self.max - self.min) * index as f32 / (count - 1) as f32
+    }
+
+    /// Returns how many pixels `value` is from the minimum, along an axis `length` pixels long.
+    ///
+    /// The minimum maps to 0, and the maximum maps to `length - 1`.
+    pub fn position(&self, value: f32, length: u32) -> u32 {
+        let range = self.max - self.min;
+        let fraction = if range == 0.0 || value.is_nan() {
+            0.0
+        } else {
+            ((value - self.min) / range).clamp(0.0, 1.0)
+        };
+        (fraction * length.saturating_sub(1) as f32 + 0.5) as u32
+    }
+}
+
+/// A chart's plot, without any axes.
+#[derive(Debug, Clone, Copy)]
+struct Plot<'a> {
+    area: Rectangle,
+    values: &'a [f32],
+    scale: Scale,
+    kind: ChartKind,
+}
+
+impl Plot<'_> {
+    /// The y coordinate of `value`, or `None` if it can't be plotted.
+    fn y(&self, value: f32) -> Option<i32> {
+        if !value.is_finite() {
+            return None;
+        }
+        let bottom = self.area.top_left.y + self.area.size.height as i32 - 1;
+        Some(bottom - self.scale.position(value, self.area.size.height) as i32)
+    }
+
+    fn draw<D: DrawTarget>(&self, target: &mut D, color: D::Color) -> Result<(), D::Error> {
+        if self.area.is_zero_sized() || self.values.is_empty() {
+            return Ok(());
+        }
+        match self.kind {
+            ChartKind::Line => self.draw_line(target, color),
+            ChartKind::Bar => self.draw_bars(target, color),
+        }
+    }
+
+    /// Spreads the values across the full width, joining consecutive values with lines. Values
+    /// that aren't finite leave a gap.
+    fn draw_line<D: DrawTarget>(&self, target: &mut D, color: D::Color) -> Result<(), D::Error> {
+        let width = self.area.size.width;
+        let last = self.values.len() as u32 - 1;
+        let x = |i: u32| {
+            let offset = if last == 0 {
+                0
+            } else {
+                u64::from(i) * u64::from(width - 1) / u64::from(last)
+            };
+            self.area.top_left.x + offset as i32
+        };
+        let style = PrimitiveStyle::with_stroke(color, 1);
+        let mut previous: Option<Point> = None;
+        for (i, value) in self.values.iter().enumerate() {
+            let point = self.y(*value).map(|y| Point::new(x(i as u32), y));
+            match (previous, point) {
+                (Some(start), Some(end)) => {
+                    Line::new(start, end).into_styled(style).draw(target)?
+                }
+                (None, Some(point)) => {
+                    target.fill_solid(&Rectangle::new(point, Size::new(1, 1)), color)?
+                }
+                _ => {}
+            }
+            previous = point;
+        }
+        Ok(())
+    }
+
+    /// Draws a bar for each value, rising from the bottom, with a 1 pixel gap between bars. If
+    /// there isn't room for every value, only the last ones are shown.
+    fn draw_bars<D: DrawTarget>(&self, target: &mut D, color: D::Color) -> Result<(), D::Error> {
+        let width = self.area.size.width;
+        let slot = (width / self.values.len() as u32).max(1);
+        let shown = (width / slot) as usize;
+        let values = &self.values[self.values.len().saturating_sub(shown)..];
+        let bottom = self.area.top_left.y + self.area.size.height as i32;
+        for (i, value) in values.iter().enumerate() {
+            let Some(top) = self.y(*value) else {
+                continue;
+            };
+            let bar = Rectangle::new(
+                Point::new(self.area.top_left.x + (i as u32 * slot) as i32, top),
+                Size::new(slot.saturating_sub(1).max(1), (bottom - top) as u32),
+            );
+            target.fill_solid(&bar, color)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a [Chart] plots its values.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartKind {
+    /// Joins the values with a line.
+    Line,
+    /// Draws a bar for each value.
+    Bar,
+}
+
+/// A small line chart without axes, to show a trend alongside a reading.
+#[derive(Debug, Clone, Copy)]
+pub struct Sparkline<'a> {
+    area: Rectangle,
+    values: &'a [f32],
+    scale: Option<Scale>,
+}
+
+impl<'a> Sparkline<'a> {
+    /// Creates a sparkline that spreads `values` across `area`, scaled to fit.
+    pub fn new(area: Rectangle, values: &'a [f32]) -> Self {
+        Sparkline {
+            area,
+            values,
+            scale: None,
+        }
+    }
+
+    /// Uses a fixed scale, instead of fitting the scale to the values.
+    pub fn with_scale(mut self, scale: Scale) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+}
+
+impl<C: PixelColor> Widget<C> for Sparkline<'_> {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error> {
+        let mut target = target.clipped(&self.area);
+        target.fill_solid(&self.area, style.background)?;
+        Plot {
+            area: self.area,
+            values: self.values,
+            scale: self.scale.unwrap_or_else(|| Scale::fit(self.values)),
+            kind: ChartKind::Line,
+        }
+        .draw(&mut target, style.foreground)?;
+        Ok(self.area)
+    }
+}
+
+/// A line or bar chart, with a value axis on the left and a baseline along the bottom.
+///
+/// The value axis can have evenly spaced tick marks, optionally labelled with their values.
+#[derive(Debug, Clone, Copy)]
+pub struct Chart<'a> {
+    area: Rectangle,
+    values: &'a [f32],
+    kind: ChartKind,
+    scale: Option<Scale>,
+    ticks: u32,
+    label_decimals: Option<usize>,
+}
+
+impl<'a> Chart<'a> {
+    /// Creates a chart of `values`, scaled to fit, with no ticks.
+    pub fn new(area: Rectangle, values: &'a [f32], kind: ChartKind) -> Self {
+        Chart {
+            area,
+            values,
+            kind,
+            scale: None,
+            ticks: 0,
+            label_decimals: None,
+        }
+    }
+
+    /// Uses a fixed scale, instead of fitting the scale to the values.
+    pub fn with_scale(mut self, scale: Scale) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    /// Adds `count` evenly spaced ticks to the value axis, from the minimum to the maximum of the
+    /// scale. If `label_decimals` is set, each tick is labelled with its value to that many
+    /// decimal places.
+    pub fn with_ticks(mut self, count: u32, label_decimals: Option<usize>) -> Self {
+        self.ticks = count;
+        self.label_decimals = label_decimals;
+        self
+    }
+
+    fn scale(&self) -> Scale {
+        self.scale.unwrap_or_else(|| Scale::fit(self.values))
+    }
+
+    /// Formats the label for the `index`th tick, if labels are shown.
+    fn label(&self, index: u32) -> Option<heapless::String<16>> {
+        let decimals = self.label_decimals?;
+        let mut text = heapless::String::new();
+        // Labels that don't fit would be clipped anyway, so it's fine to truncate them.
+        let _ = write!(
+            text,
+            "{:.*}",
+            decimals,
+            self.scale().tick(index, self.ticks)
+        );
+        Some(text)
+    }
+
+    /// The width of the widest tick label, in pixels.
+    fn label_width<C>(&self, style: &WidgetStyle<'_, C>) -> u32 {
+        let font = style.font;
+        let char_width = font.character_size.width + font.character_spacing;
+        (0..self.ticks)
+            .filter_map(|i| self.label(i))
+            .map(|label| label.chars().count() as u32 * char_width)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl<C: PixelColor> Widget<C> for Chart<'_> {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error> {
+        let mut target = target.clipped(&self.area);
+        target.fill_solid(&self.area, style.background)?;
+
+        // Labels are centred on their ticks, so leave room for half a label above and below.
+        let labelled = self.ticks > 0 && self.label_decimals.is_some();
+        let inset = if labelled {
+            style.font.character_size.height / 2
+        } else {
+            0
+        };
+        let label_width = self.label_width(style);
+        let tick_length = if self.ticks > 0 { TICK_LENGTH } else { 0 };
+        let label_space = if label_width > 0 {
+            label_width + LABEL_GAP
+        } else {
+            0
+        };
+        let top_left = self.area.top_left;
+        let axis_x = top_left.x + (label_space + tick_length) as i32;
+        let axis_y = top_left.y + self.area.size.height as i32 - 1 - inset as i32;
+
+        let axis_style = PrimitiveStyle::with_stroke(style.foreground, 1);
+        let right = top_left.x + self.area.size.width as i32 - 1;
+        Line::new(
+            Point::new(axis_x, top_left.y + inset as i32),
+            Point::new(axis_x, axis_y),
+        )
+        .into_styled(axis_style)
+        .draw(&mut target)?;
+        Line::new(Point::new(axis_x, axis_y), Point::new(right, axis_y))
+            .into_styled(axis_style)
+            .draw(&mut target)?;
+
+        let plot = Plot {
+            area: Rectangle::with_corners(
+                Point::new(axis_x + 1, top_left.y + inset as i32),
+                Point::new(right, axis_y - 1),
+            ),
+            values: self.values,
+            scale: self.scale(),
+            kind: self.kind,
+        };
+        if axis_x < right && axis_y > top_left.y + inset as i32 {
+            plot.draw(&mut target, style.foreground)?;
+        }
+
+        let text_style = TextStyleBuilder::new()
+            .alignment(Alignment::Right)
+            .baseline(Baseline::Middle)
+            .build();
+        for i in 0..self.ticks {
+            let Some(y) = plot.y(plot.scale.tick(i, self.ticks)) else {
+                continue;
+            };
+            Line::new(
+                Point::new(axis_x - tick_length as i32, y),
+                Point::new(axis_x, y),
+            )
+            .into_styled(axis_style)
+            .draw(&mut target)?;
+            if let Some(label) = self.label(i) {
+                Text::with_text_style(
+                    &label,
+                    Point::new(top_left.x + label_width as i32 - 1, y),
+                    MonoTextStyle::new(style.font, style.foreground),
+                    text_style,
+                )
+                .draw(&mut target)?;
+            }
+        }
+        Ok(self.area)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{mono_font::ascii::FONT_6X10, pixelcolor::BinaryColor};
+
+    use super::*;
+    use crate::buffer::{binary_buffer_length, BinaryBuffer};
+
+    const SIZE: Size = Size::new(16, 8);
+
+    fn style() -> WidgetStyle<'static, BinaryColor> {
+        WidgetStyle::new(&FONT_6X10, BinaryColor::On, BinaryColor::Off)
+    }
+
+    fn buffer() -> BinaryBuffer<{ binary_buffer_length(SIZE) }> {
+        BinaryBuffer::new(SIZE)
+    }
+
+    /// Returns the rows of `buffer` as bit strings, with `#` for the foreground.
+    fn rows(buffer: &BinaryBuffer<{ binary_buffer_length(SIZE) }>) -> std::vec::Vec<String> {
+        buffer
+            .data()
+            .chunks(2)
+            .map(|row| {
+                row.iter()
+                    .flat_map(|byte| (0..8).rev().map(move |bit| byte >> bit & 1 == 1))
+                    .map(|on| if on { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_scale_maps_values_to_pixels() {
+        let scale = Scale::fit(&[2.0, f32::NAN, 6.0, 4.0]);
+        assert_eq!(scale, Scale::new(2.0, 6.0));
+        assert_eq!(scale.position(2.0, 9), 0);
+        assert_eq!(scale.position(4.0, 9), 4);
+        assert_eq!(scale.position(10.0, 9), 8);
+        assert_eq!(scale.tick(1, 3), 4.0);
+        assert_eq!(Scale::fit(&[5.0, 5.0]), Scale::new(4.0, 6.0));
+    }
+
+    #[test]
+    fn test_sparkline_spans_its_area() {
+        let mut buffer = buffer();
+        let area = Rectangle::new(Point::zero(), SIZE);
+        let sparkline = Sparkline::new(area, &[0.0, 7.0, f32::NAN, 0.0]);
+        assert_eq!(sparkline.draw(&mut buffer, &style()), Ok(area));
+
+        let rows = rows(&buffer);
+        // The first value is bottom left, and the second top middle.
+        assert_eq!(&rows[7][..1], "#");
+        assert_eq!(&rows[0][..6], ".....#");
+        // The NaN leaves a gap, so the last value is a lone dot.
+        assert_eq!(&rows[7][6..], ".........#");
+    }
+
+    #[test]
+    fn test_bar_chart_draws_axes_and_bars() {
+        let mut buffer = buffer();
+        let area = Rectangle::new(Point::zero(), SIZE);
+        let chart = Chart::new(area, &[1.0, 3.0, 2.0], ChartKind::Bar)
+            .with_scale(Scale::new(0.0, 6.0))
+            .with_ticks(2, None);
+        assert_eq!(chart.draw(&mut buffer, &style()), Ok(area));
+
+        // The plot is 13x7 pixels, right of the axis and above the baseline, with 4 pixel slots.
+        // The lowest tick is on the bottom row of the plot, just above the baseline.
+        assert_eq!(
+            rows(&buffer),
+            [
+                "###.............",
+                "..#.............",
+                "..#.............",
+                "..#....###......",
+                "..#....###.###..",
+                "..####.###.###..",
+                "######.###.###..",
+                "..##############",
+            ]
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! Declares the `Command` enums of the SPI display drivers, and their `send` methods.
+//!
+//! ```text
+//! commands! {
+//!     /// Low-level commands for the EpdXinY.
+//!     pub enum Command {
+//!         /// Resets all commands and parameters to default values.
+//!         SwReset = 0x12,
+//!     }
+//! }
+//!
+//! impl<HW, STATE> EpdXinY<HW, STATE>
+//! where
+//!     HW: DcHw + BusyHw + ErrorHw + SpiHw,
+//!     ...
+//! {
+//!     command_methods!(Command);
+//! }
+//! ```
+
+/// Declares a `Command` enum, where each variant's discriminant is its register address, and a
+/// private `register` method to read it. A driver can reuse a shared register, such as those in
+/// `ssd16xx::Command`, by casting it: `SetRamX = ssd16xx::Command::SetRamX as isize`.
+macro_rules! commands {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident = $register:expr,
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        $vis enum $name {
+            $(
+                $(#[$variant_meta])*
+                $variant = $register,
+            )*
+        }
+
+        impl $name {
+            /// Returns the register address for this command.
+            fn register(&self) -> u8 {
+                *self as u8
+            }
+        }
+    };
+}
+
+/// Adds `send` (and with `read`, `send_read`) methods for the given command type to a driver,
+/// inside an `impl` block whose `HW` implements [crate::hw::CommandDataSend].
+macro_rules! command_methods {
+    ($command:ty) => {
+        /// Send the following command and data to the display. Waits until the display is no longer busy before sending.
+        pub async fn send(
+            &mut self,
+            spi: &mut HW::Spi,
+            command: $command,
+            data: &[u8],
+        ) -> Result<(), HW::Error> {
+            $crate::hw::CommandDataSend::send(&mut self.hw, spi, command.register(), data).await
+        }
+    };
+    ($command:ty, read) => {
+        $crate::command::command_methods!($command);
+
+        /// Send the following command and data to the display, then read its response into `buf`.
+        /// Waits until the display is no longer busy before sending.
+        ///
+        /// This requires the display's data line to be readable by your SPI device. Any dummy bytes
+        /// that the controller sends first are left in `buf`.
+        pub async fn send_read(
+            &mut self,
+            spi: &mut HW::Spi,
+            command: $command,
+            data: &[u8],
+            buf: &mut [u8],
+        ) -> Result<(), HW::Error> {
+            $crate::hw::CommandDataSend::send_read(&mut self.hw, spi, command.register(), data, buf)
+                .await
+        }
+    };
+}
+
+pub(crate) use {command_methods, commands};
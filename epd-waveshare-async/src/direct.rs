@@ -0,0 +1,341 @@
+//! Draws straight into the display's RAM, without a framebuffer, for microcontrollers that don't
+//! have the RAM for one.
+//!
+//! [DrawTarget] methods can't wait for SPI transfers, so [DirectDrawTarget] draws the screen in
+//! bands of rows instead: your drawing code is run once per band, only the pixels inside the band
+//! are kept, and each band is written to the display before the next one is drawn. A 2.9" display
+//! needs a 4,736 byte framebuffer, but only a 256 byte band by default. The trade-off is that the
+//! drawing code runs once for each band, so this suits simple screens of text and shapes.
+//!
+//! [PipelinedDrawTarget] uses two bands, drawing the next band while the previous one is being
+//! written, which speeds up updates when SPI transfers run in the background with DMA.
+//!
+//! ```text
+//! let mut target = DirectDrawTarget::<256>::new(Size::new(
+//!     epd2in9_v2::DISPLAY_WIDTH.into(),
+//!     epd2in9_v2::DISPLAY_HEIGHT.into(),
+//! ));
+//! target
+//!     .draw(&mut epd, &mut spi, |target| {
+//!         let _ = Text::new("Hello", Point::new(8, 20), style).draw(target);
+//!     })
+//!     .await?;
+//! epd.update_display(&mut spi).await?;
+//! ```
+use core::{
+    convert::Infallible,
+    future::{poll_fn, Future},
+    pin::pin,
+    task::Poll,
+};
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    pixelcolor::BinaryColor,
+    prelude::{Dimensions, Point, PointsIter, Size},
+    primitives::Rectangle,
+    Pixel,
+};
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::{buffer::BufferView, log::trace, DisplaySimple};
+
+/// Draws the whole display, one band of at most `L` bytes at a time. See the [module](self) docs.
+///
+/// The target reports the whole display as its bounding box, so layouts are computed as if
+/// drawing into a full-screen buffer. Like [BinaryBuffer](crate::buffer::BinaryBuffer), pixels
+/// are [BinaryColor::Off] until drawn.
+#[derive(Clone)]
+pub struct DirectDrawTarget<const L: usize = 256> {
+    display: Size,
+    bytes_per_row: usize,
+    /// The rows of the display that are currently being drawn.
+    band: Rectangle,
+    data: [u8; L],
+}
+
+impl<const L: usize> DirectDrawTarget<L> {
+    /// Creates a target for a display of size `display`, whose width must be a multiple of 8.
+    ///
+    /// `L` must hold at least one row of the display.
+    pub fn new(display: Size) -> Self {
+        let bytes_per_row = display.width as usize / 8;
+        debug_assert!(
+            bytes_per_row <= L,
+            "The band must hold at least one row of the display."
+        );
+        DirectDrawTarget {
+            display,
+            bytes_per_row,
+            band: Rectangle::zero(),
+            data: [0; L],
+        }
+    }
+
+    /// Returns the rows of the display that are currently being drawn.
+    pub fn band(&self) -> Rectangle {
+        self.band
+    }
+
+    /// Writes the whole display's framebuffer by calling `draw` once for each band, and writing
+    /// the band to the display. This doesn't refresh the display.
+    pub async fn draw<D, SPI, ERROR>(
+        &mut self,
+        epd: &mut D,
+        spi: &mut SPI,
+        mut draw: impl FnMut(&mut Self),
+    ) -> Result<(), ERROR>
+    where
+        D: DisplaySimple<1, 1, SPI, ERROR>,
+        SPI: SpiDevice,
+    {
+        let mut top = 0;
+        while top < self.display.height {
+            self.start_band(top);
+            draw(self);
+            epd.write_framebuffer(spi, &*self).await?;
+            top += self.band.size.height;
+        }
+        Ok(())
+    }
+
+    /// Moves to the band of rows starting at `top`, and clears it.
+    fn start_band(&mut self, top: u32) {
+        let rows_per_band = (L / self.bytes_per_row.max(1)).max(1) as u32;
+        let height = rows_per_band.min(self.display.height - top);
+        self.band = Rectangle::new(
+            Point::new(0, top as i32),
+            Size::new(self.display.width, height),
+        );
+        trace!("Drawing rows {} to {}", top, top + height);
+        self.data.fill(0);
+    }
+
+    /// The first row after the current band.
+    fn band_end(&self) -> u32 {
+        self.band.top_left.y as u32 + self.band.size.height
+    }
+}
+
+/// Like [DirectDrawTarget], but with two bands, so that the next band is drawn while the previous
+/// one is being written to the display.
+///
+/// This helps when the SPI device writes in the background, e.g. with DMA, and the drawing code
+/// takes a while: each band's drawing then overlaps the previous band's transfer, rather than
+/// waiting for it. It needs twice the RAM of a [DirectDrawTarget] with the same band size.
+#[derive(Clone)]
+pub struct PipelinedDrawTarget<const L: usize = 256> {
+    targets: [DirectDrawTarget<L>; 2],
+}
+
+impl<const L: usize> PipelinedDrawTarget<L> {
+    /// Creates a target for a display of size `display`. See [DirectDrawTarget::new].
+    pub fn new(display: Size) -> Self {
+        PipelinedDrawTarget {
+            targets: [
+                DirectDrawTarget::new(display),
+                DirectDrawTarget::new(display),
+            ],
+        }
+    }
+
+    /// Writes the whole display's framebuffer by calling `draw` once for each band, drawing each
+    /// band while the one before it is written to the display. This doesn't refresh the display.
+    pub async fn draw<D, SPI, ERROR>(
+        &mut self,
+        epd: &mut D,
+        spi: &mut SPI,
+        mut draw: impl FnMut(&mut DirectDrawTarget<L>),
+    ) -> Result<(), ERROR>
+    where
+        D: DisplaySimple<1, 1, SPI, ERROR>,
+        SPI: SpiDevice,
+    {
+        let [mut front, mut back] = self.targets.each_mut();
+        let height = front.display.height;
+        if height == 0 {
+            return Ok(());
+        }
+        front.start_band(0);
+        draw(front);
+        loop {
+            let next = front.band_end();
+            if next >= height {
+                return epd.write_framebuffer(spi, &*front).await;
+            }
+            let (written, ()) = join(epd.write_framebuffer(spi, &*front), async {
+                back.start_band(next);
+                draw(back);
+            })
+            .await;
+            written?;
+            core::mem::swap(&mut front, &mut back);
+        }
+    }
+}
+
+/// Runs both futures until they're done, so that `b` runs while `a` is waiting, e.g. for DMA.
+async fn join<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+    let (mut a, mut b) = (pin!(a), pin!(b));
+    let (mut a_out, mut b_out) = (None, None);
+    poll_fn(|cx| {
+        if a_out.is_none() {
+            if let Poll::Ready(out) = a.as_mut().poll(cx) {
+                a_out = Some(out);
+            }
+        }
+        if b_out.is_none() {
+            if let Poll::Ready(out) = b.as_mut().poll(cx) {
+                b_out = Some(out);
+            }
+        }
+        match (a_out.take(), b_out.take()) {
+            (Some(a), Some(b)) => Poll::Ready((a, b)),
+            (a, b) => {
+                (a_out, b_out) = (a, b);
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
+
+impl<const L: usize> BufferView<1, 1> for DirectDrawTarget<L> {
+    fn window(&self) -> Rectangle {
+        self.band
+    }
+
+    fn data(&self) -> [&[u8]; 1] {
+        [&self.data[..self.bytes_per_row * self.band.size.height as usize]]
+    }
+}
+
+impl<const L: usize> Dimensions for DirectDrawTarget<L> {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.display)
+    }
+}
+
+impl<const L: usize> DrawTarget for DirectDrawTarget<L> {
+    type Color = BinaryColor;
+
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if !self.band.contains(point) {
+                continue;
+            }
+            let row = (point.y - self.band.top_left.y) as usize;
+            let byte_index = row * self.bytes_per_row + point.x as usize / 8;
+            let mask = 0x80 >> (point.x as usize % 8);
+            if color == BinaryColor::On {
+                self.data[byte_index] |= mask;
+            } else {
+                self.data[byte_index] &= !mask;
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        // Only visit the pixels in this band, as the rest of the area is drawn with other bands.
+        let area = area.intersection(&self.band);
+        self.draw_iter(area.points().map(|point| Pixel(point, color)))
+    }
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use embedded_graphics::{prelude::Primitive, primitives::PrimitiveStyle, Drawable};
+
+    use super::*;
+    use crate::{
+        epd2in9_v2::{self, Command, Epd2In9V2, RefreshMode},
+        testing::{block_on, Event, MockHw},
+    };
+
+    #[test]
+    fn test_draws_each_band_to_the_display() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        log.take_events();
+
+        let display = Size::new(
+            epd2in9_v2::DISPLAY_WIDTH.into(),
+            epd2in9_v2::DISPLAY_HEIGHT.into(),
+        );
+        let mut target = DirectDrawTarget::<256>::new(display);
+        let mut bands = 0;
+        block_on(target.draw(&mut epd, &mut spi, |target| {
+            bands += 1;
+            assert_eq!(target.bounding_box().size, display);
+            Rectangle::new(Point::new(8, 10), Size::new(8, 2))
+                .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+                .draw(target)
+                .unwrap();
+        }))
+        .unwrap();
+
+        // 16 bytes per row, so 16 rows per band.
+        assert_eq!(bands, 296_usize.div_ceil(16));
+        let events = log.take_events();
+        let writes: std::vec::Vec<_> = events
+            .windows(2)
+            .filter(|pair| pair[0] == Event::Command(Command::WriteLowRam as u8))
+            .map(|pair| match &pair[1] {
+                Event::Data(data) => data.clone(),
+                _ => panic!("RAM write without data"),
+            })
+            .collect();
+        assert_eq!(writes.len(), bands);
+        assert_eq!(writes[0].len(), 256);
+        assert_eq!(writes[bands - 1].len(), 8 * 16);
+        let mut first_band = [0u8; 256];
+        first_band[10 * 16 + 1] = 0xFF;
+        first_band[11 * 16 + 1] = 0xFF;
+        assert_eq!(writes[0], first_band);
+        assert!(writes[1..].iter().flatten().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_pipelined_target_writes_the_same_bands() {
+        let display = Size::new(
+            epd2in9_v2::DISPLAY_WIDTH.into(),
+            epd2in9_v2::DISPLAY_HEIGHT.into(),
+        );
+        let draw = |target: &mut DirectDrawTarget<256>| {
+            Rectangle::new(Point::new(8, 10), Size::new(64, 40))
+                .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+                .draw(target)
+                .unwrap();
+        };
+        let write = |pipelined: bool| {
+            let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+            let log = hw.log();
+            let mut spi = hw.spi();
+            let mut epd = Epd2In9V2::attach(hw, RefreshMode::Full);
+            if pipelined {
+                let mut target = PipelinedDrawTarget::<256>::new(display);
+                block_on(target.draw(&mut epd, &mut spi, draw)).unwrap();
+            } else {
+                let mut target = DirectDrawTarget::<256>::new(display);
+                block_on(target.draw(&mut epd, &mut spi, draw)).unwrap();
+            }
+            log.take_events()
+        };
+
+        let events = write(true);
+        assert_eq!(events, write(false));
+        let bands = events
+            .iter()
+            .filter(|e| **e == Event::Command(Command::WriteLowRam as u8))
+            .count();
+        assert_eq!(bands, 296_usize.div_ceil(16));
+    }
+}
@@ -0,0 +1,858 @@
+//! Records which parts of a buffer have been drawn to, so that only those parts are written to the
+//! display on the next partial refresh.
+//!
+//! [write_changed_rows] instead compares two buffers, and writes only the rows that differ.
+//! [diff_rows] and [write_rows] do the same in two steps, via a bitmap of the changed rows.
+//! [write_area] writes any area of a buffer, e.g. for each widget that changed.
+//! [DirtyRegions] merges many small changed areas into a few larger ones, for when each area is
+//! refreshed separately.
+//!
+//! ```text
+//! let mut buffer = DirtyTracker::<_>::new(epd2in9_v2::new_binary_buffer());
+//! epd.write_base_framebuffer(&mut spi, &buffer).await?;
+//!
+//! loop {
+//!     Text::new(&time, Point::new(0, 10), style).draw(&mut buffer)?;
+//!     // Only the rows that the text touched are sent to the display.
+//!     buffer.flush_partial(&mut epd, &mut spi).await?;
+//! }
+//! ```
+use core::ops::Range;
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    prelude::{Dimensions, Point, Size},
+    primitives::Rectangle,
+    Pixel,
+};
+use embedded_hal_async::spi::SpiDevice;
+use heapless::Vec;
+
+use crate::{
+    buffer::{align_window, BufferView},
+    log::trace,
+    DisplayPartial, DisplaySimple,
+};
+
+/// A bounded set of changed areas, which merges areas that overlap or are close together.
+///
+/// Refreshing a few merged areas is usually faster than refreshing many small ones, as each
+/// refresh has a fixed cost. Areas are merged when they overlap, or when the gap between them is
+/// at most the merge gap (0 by default, i.e. only touching areas). When all `N` slots are in use, a
+/// new area is merged with the existing area that wastes the fewest pixels.
+///
+/// ```
+/// use embedded_graphics::{prelude::{Point, Size}, primitives::Rectangle};
+/// use epd_waveshare_async::dirty::DirtyRegions;
+///
+/// let mut regions = DirtyRegions::<4>::new().with_merge_gap(8);
+/// regions.add(Rectangle::new(Point::new(0, 0), Size::new(16, 16)));
+/// regions.add(Rectangle::new(Point::new(20, 0), Size::new(16, 16)));
+/// regions.add(Rectangle::new(Point::new(0, 100), Size::new(16, 16)));
+///
+/// assert_eq!(regions.len(), 2);
+/// assert_eq!(
+///     regions.iter().next(),
+///     Some(Rectangle::new(Point::new(0, 0), Size::new(36, 16)))
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct DirtyRegions<const N: usize = 4> {
+    /// Disjoint areas, sorted from top to bottom, then left to right.
+    areas: Vec<Rectangle, N>,
+    merge_gap: u32,
+}
+
+impl<const N: usize> Default for DirtyRegions<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> DirtyRegions<N> {
+    /// Creates an empty set, which only merges overlapping or touching areas.
+    pub const fn new() -> Self {
+        Self {
+            areas: Vec::new(),
+            merge_gap: 0,
+        }
+    }
+
+    /// Also merges areas that are at most `gap` pixels apart, horizontally or vertically.
+    pub const fn with_merge_gap(mut self, gap: u32) -> Self {
+        self.merge_gap = gap;
+        self
+    }
+
+    /// Whether no areas have been added since the set was last cleared.
+    pub fn is_empty(&self) -> bool {
+        self.areas.is_empty()
+    }
+
+    /// The number of separate areas.
+    pub fn len(&self) -> usize {
+        self.areas.len()
+    }
+
+    /// Returns the areas, from top to bottom, then left to right.
+    pub fn iter(&self) -> impl Iterator<Item = Rectangle> + '_ {
+        self.areas.iter().copied()
+    }
+
+    /// Returns the smallest rectangle that covers every area, if any.
+    pub fn bounding_box(&self) -> Option<Rectangle> {
+        self.areas.iter().copied().reduce(|a, b| union(&a, &b))
+    }
+
+    /// Removes all the areas.
+    pub fn clear(&mut self) {
+        self.areas.clear();
+    }
+
+    /// Adds a changed area, merging it with any nearby areas. Empty areas are ignored.
+    pub fn add(&mut self, mut area: Rectangle) {
+        if area.is_zero_sized() || N == 0 {
+            return;
+        }
+        let merge_gap = self.merge_gap as i32;
+        loop {
+            // Absorb any areas that overlap or are close to the new area.
+            self.areas.retain(|existing| {
+                let close = gap(existing, &area) <= merge_gap;
+                if close {
+                    area = union(existing, &area);
+                }
+                !close
+            });
+            if !self.areas.is_full() {
+                break;
+            }
+            // Out of slots, so merge with the area that wastes the fewest pixels.
+            let nearest = (0..self.areas.len()).min_by_key(|i| wasted(&self.areas[*i], &area));
+            let Some(nearest) = nearest else {
+                return;
+            };
+            let existing = self.areas.remove(nearest);
+            area = union(&existing, &area);
+        }
+        let key = |r: &Rectangle| (r.top_left.y, r.top_left.x);
+        let index = self
+            .areas
+            .iter()
+            .position(|existing| key(existing) > key(&area))
+            .unwrap_or(self.areas.len());
+        // This can't fail, as the loop only exits early when there's space.
+        let _ = self.areas.insert(index, area);
+    }
+}
+
+/// The smallest rectangle that covers both `a` and `b`.
+fn union(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    let top_left = a.top_left.component_min(b.top_left);
+    let bottom_right = (a.top_left + a.size).component_max(b.top_left + b.size);
+    let size = bottom_right - top_left;
+    Rectangle::new(top_left, Size::new(size.x as u32, size.y as u32))
+}
+
+/// The larger of the horizontal and vertical gaps between `a` and `b`. This is 0 when they touch,
+/// and negative when they overlap.
+fn gap(a: &Rectangle, b: &Rectangle) -> i32 {
+    let (a_end, b_end) = (a.top_left + a.size, b.top_left + b.size);
+    let x = (b.top_left.x - a_end.x).max(a.top_left.x - b_end.x);
+    let y = (b.top_left.y - a_end.y).max(a.top_left.y - b_end.y);
+    x.max(y)
+}
+
+/// The number of unchanged pixels that merging `a` and `b` would add.
+fn wasted(a: &Rectangle, b: &Rectangle) -> u64 {
+    let area = |r: &Rectangle| r.size.width as u64 * r.size.height as u64;
+    area(&union(a, b)).saturating_sub(area(a) + area(b))
+}
+
+/// Wraps a buffer, and records the rows that are drawn to since the last flush.
+///
+/// Changed rows are kept as up to `N` separate bands in a [DirtyRegions]. When a new band doesn't
+/// fit, it's merged with the nearest existing band. Bands always span the buffer's full width,
+/// because each band is written to the display as one contiguous slice of the buffer's data.
+#[derive(Clone)]
+pub struct DirtyTracker<B, const N: usize = 4> {
+    buffer: B,
+    /// Full-width bands of rows, in the buffer's coordinates.
+    bands: DirtyRegions<N>,
+}
+
+impl<B, const N: usize> DirtyTracker<B, N> {
+    /// Tracks changes to `buffer`, starting with nothing changed.
+    pub fn new(buffer: B) -> Self {
+        debug_assert!(N > 0, "At least one band is needed to track changes.");
+        Self {
+            buffer,
+            bands: DirtyRegions::new(),
+        }
+    }
+
+    /// Provides read-only access to the inner buffer.
+    pub fn inner(&self) -> &B {
+        &self.buffer
+    }
+
+    /// Drops the tracked changes and returns the inner buffer.
+    pub fn into_inner(self) -> B {
+        self.buffer
+    }
+
+    /// Whether anything has been drawn since the last flush.
+    pub fn is_dirty(&self) -> bool {
+        !self.bands.is_empty()
+    }
+
+    /// Returns the bands of rows that have changed since the last flush, from top to bottom.
+    pub fn dirty_bands(&self) -> impl Iterator<Item = Rectangle> + '_ {
+        self.bands.iter()
+    }
+
+    /// Marks `area` as changed, e.g. after drawing to the buffer without going through this
+    /// tracker.
+    pub fn mark_dirty(&mut self, area: Rectangle)
+    where
+        B: Dimensions,
+    {
+        let area = area.intersection(&self.buffer.bounding_box());
+        let start = area.top_left.y;
+        self.add_rows(start..start + area.size.height as i32);
+    }
+
+    /// Forgets all the changes, e.g. after writing the whole buffer to the display.
+    pub fn mark_clean(&mut self) {
+        self.bands.clear();
+    }
+
+    fn add_rows(&mut self, rows: Range<i32>)
+    where
+        B: Dimensions,
+    {
+        if rows.is_empty() {
+            return;
+        }
+        let bounds = self.buffer.bounding_box();
+        self.bands.add(Rectangle::new(
+            Point::new(bounds.top_left.x, rows.start),
+            Size::new(bounds.size.width, rows.len() as u32),
+        ));
+    }
+
+    /// Writes the changed bands to the display's framebuffer, then refreshes the display.
+    ///
+    /// The display should be in a partial refresh mode, with its base framebuffer already
+    /// written, so that only the changed pixels are refreshed. Does nothing if nothing has
+    /// changed.
+    pub async fn flush_partial<const BITS: usize, const FRAMES: usize, D, SPI, ERROR>(
+        &mut self,
+        epd: &mut D,
+        spi: &mut SPI,
+    ) -> Result<(), ERROR>
+    where
+        B: BufferView<BITS, FRAMES> + Dimensions,
+        D: DisplayPartial<BITS, FRAMES, SPI, ERROR>,
+        SPI: SpiDevice,
+    {
+        if self.bands.is_empty() {
+            return Ok(());
+        }
+        let bounds = self.buffer.bounding_box();
+        for rows in self.bands.iter().map(|band| band.rows()) {
+            trace!("Flushing rows {}..{}", rows.start, rows.end);
+            let start = (rows.start - bounds.top_left.y) as usize;
+            let end = (rows.end - bounds.top_left.y) as usize;
+            let band = Band::new(&self.buffer, start..end);
+            epd.write_framebuffer(spi, &band).await?;
+        }
+        epd.update_display(spi).await?;
+        self.bands.clear();
+        Ok(())
+    }
+}
+
+impl<B: Dimensions, const N: usize> Dimensions for DirtyTracker<B, N> {
+    fn bounding_box(&self) -> Rectangle {
+        self.buffer.bounding_box()
+    }
+}
+
+impl<B: DrawTarget, const N: usize> DrawTarget for DirtyTracker<B, N> {
+    type Color = B::Color;
+    type Error = B::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let bounds = self.buffer.bounding_box();
+        let mut rows: Option<Range<i32>> = None;
+        let pixels = pixels.into_iter().inspect(|Pixel(point, _)| {
+            if bounds.contains(*point) {
+                rows = Some(match rows.take() {
+                    Some(rows) => rows.start.min(point.y)..rows.end.max(point.y + 1),
+                    None => point.y..point.y + 1,
+                });
+            }
+        });
+        let result = self.buffer.draw_iter(pixels);
+        if let Some(rows) = rows {
+            self.add_rows(rows);
+        }
+        result
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.mark_dirty(*area);
+        self.buffer.fill_contiguous(area, colors)
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.mark_dirty(*area);
+        self.buffer.fill_solid(area, color)
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.mark_dirty(self.buffer.bounding_box());
+        self.buffer.clear(color)
+    }
+}
+
+impl<B, const BITS: usize, const FRAMES: usize, const N: usize> BufferView<BITS, FRAMES>
+    for DirtyTracker<B, N>
+where
+    B: BufferView<BITS, FRAMES>,
+{
+    fn window(&self) -> Rectangle {
+        self.buffer.window()
+    }
+
+    fn data(&self) -> [&[u8]; FRAMES] {
+        self.buffer.data()
+    }
+}
+
+/// Writes only the runs of rows that differ between `previous` and `next` to the display's
+/// framebuffer, and returns the number of rows written.
+///
+/// `previous` must hold what is already in the display's framebuffer, and both buffers should cover
+/// the same window; if they don't, every row of `next` is written. Each run of changed rows is
+/// written to its own window, so this can cut the SPI traffic of a mostly unchanged frame by a lot,
+/// even when the frame is then shown with a full refresh. Call
+/// [crate::Displayable::update_display] afterwards to show the changes.
+///
+/// ```text
+/// epd.display_framebuffer(&mut spi, &previous).await?;
+/// loop {
+///     draw_clock(&mut next)?;
+///     write_changed_rows(&mut epd, &mut spi, &previous, &next).await?;
+///     epd.update_display(&mut spi).await?;
+///     previous.clone_from(&next);
+/// }
+/// ```
+pub async fn write_changed_rows<const BITS: usize, const FRAMES: usize, D, SPI, ERROR>(
+    epd: &mut D,
+    spi: &mut SPI,
+    previous: &dyn BufferView<BITS, FRAMES>,
+    next: &dyn BufferView<BITS, FRAMES>,
+) -> Result<usize, ERROR>
+where
+    D: DisplaySimple<BITS, FRAMES, SPI, ERROR>,
+    SPI: SpiDevice,
+{
+    let same_window = previous.window() == next.window();
+    let previous_data = previous.data();
+    let next_data = next.data();
+    let height = next.window().size.height as usize;
+    write_runs(epd, spi, next, |row| {
+        !same_window || row_changed(&previous_data, &next_data, height, row)
+    })
+    .await
+}
+
+/// Returns the number of bytes needed for a row bitmap of `height` rows. See [diff_rows].
+pub const fn row_bitmap_length(height: u32) -> usize {
+    (height as usize).div_ceil(8)
+}
+
+/// Marks the rows that differ between `previous` and `next` in `changed`, and returns the number
+/// of changed rows, or `None` if `changed` is shorter than [row_bitmap_length].
+///
+/// `changed` has one bit per row of the buffers' window, starting with the most significant bit of
+/// its first byte. If the buffers cover different windows, every row is marked. Pass it to
+/// [write_rows] to write just those rows, e.g. after merging the bitmaps of several frames.
+pub fn diff_rows<const BITS: usize, const FRAMES: usize>(
+    previous: &dyn BufferView<BITS, FRAMES>,
+    next: &dyn BufferView<BITS, FRAMES>,
+    changed: &mut [u8],
+) -> Option<usize> {
+    let height = next.window().size.height;
+    if changed.len() < row_bitmap_length(height) {
+        return None;
+    }
+    let same_window = previous.window() == next.window();
+    let previous_data = previous.data();
+    let next_data = next.data();
+    let height = height as usize;
+    changed.fill(0);
+    let mut count = 0;
+    for row in 0..height {
+        if !same_window || row_changed(&previous_data, &next_data, height, row) {
+            changed[row / 8] |= 0x80 >> (row % 8);
+            count += 1;
+        }
+    }
+    Some(count)
+}
+
+/// Writes only the rows of `buffer` marked in `changed`, a row bitmap from [diff_rows], to the
+/// display's framebuffer, and returns the number of rows written.
+///
+/// The window and cursor are set once for each run of marked rows, and untouched rows are skipped,
+/// so a dashboard where only a couple of lines change sends just those lines. Call
+/// [crate::Displayable::update_display] afterwards to show the changes.
+pub async fn write_rows<const BITS: usize, const FRAMES: usize, D, SPI, ERROR>(
+    epd: &mut D,
+    spi: &mut SPI,
+    buffer: &dyn BufferView<BITS, FRAMES>,
+    changed: &[u8],
+) -> Result<usize, ERROR>
+where
+    D: DisplaySimple<BITS, FRAMES, SPI, ERROR>,
+    SPI: SpiDevice,
+{
+    write_runs(epd, spi, buffer, |row| {
+        changed
+            .get(row / 8)
+            .is_some_and(|byte| byte & (0x80 >> (row % 8)) != 0)
+    })
+    .await
+}
+
+/// Returns whether `row` differs between the frames of `previous` and `next`, counting rows that
+/// are missing from either as changed.
+fn row_changed<const FRAMES: usize>(
+    previous: &[&[u8]; FRAMES],
+    next: &[&[u8]; FRAMES],
+    height: usize,
+    row: usize,
+) -> bool {
+    previous.iter().zip(next.iter()).any(|(a, b)| {
+        let bytes_per_row = b.len() / height.max(1);
+        let bytes = row * bytes_per_row..(row + 1) * bytes_per_row;
+        a.get(bytes.clone()) != b.get(bytes)
+    })
+}
+
+/// Writes each run of rows of `buffer` for which `selected` is true, in its own window.
+async fn write_runs<const BITS: usize, const FRAMES: usize, D, SPI, ERROR>(
+    epd: &mut D,
+    spi: &mut SPI,
+    buffer: &dyn BufferView<BITS, FRAMES>,
+    selected: impl Fn(usize) -> bool,
+) -> Result<usize, ERROR>
+where
+    D: DisplaySimple<BITS, FRAMES, SPI, ERROR>,
+    SPI: SpiDevice,
+{
+    let height = buffer.window().size.height as usize;
+    let mut written = 0;
+    let mut row = 0;
+    while row < height {
+        if !selected(row) {
+            row += 1;
+            continue;
+        }
+        let start = row;
+        while row < height && selected(row) {
+            row += 1;
+        }
+        trace!("Writing changed rows {}..{}", start, row);
+        epd.write_framebuffer(spi, &Band::new(buffer, start..row))
+            .await?;
+        written += row - start;
+    }
+    Ok(written)
+}
+
+/// Writes the part of `buffer` that covers `area` to the display's framebuffer, without
+/// refreshing it. See [DisplayPartial::display_partial_regions] to refresh several areas at once.
+///
+/// `area` is clipped to the buffer, and rounded out to whole bytes on the x-axis. Areas narrower
+/// than the buffer are written one row at a time, as their rows aren't next to each other in the
+/// buffer's data.
+pub async fn write_area<const BITS: usize, const FRAMES: usize, D, SPI, ERROR>(
+    epd: &mut D,
+    spi: &mut SPI,
+    buffer: &dyn BufferView<BITS, FRAMES>,
+    area: Rectangle,
+) -> Result<(), ERROR>
+where
+    D: DisplaySimple<BITS, FRAMES, SPI, ERROR> + ?Sized,
+    SPI: SpiDevice,
+{
+    let bounds = buffer.window();
+    let Some(aligned) = align_window(area, bounds) else {
+        return Ok(());
+    };
+    if aligned.window.size.width == bounds.size.width {
+        return epd
+            .write_framebuffer(spi, &Band::new(buffer, aligned.rows))
+            .await;
+    }
+    trace!(
+        "Writing rows {}..{} of a narrow area",
+        aligned.rows.start,
+        aligned.rows.end
+    );
+    let bytes = aligned.row_bytes.start * BITS..aligned.row_bytes.end * BITS;
+    for row in aligned.rows {
+        let band = Band::new(buffer, row..row + 1);
+        let column = Band {
+            window: Rectangle::new(
+                Point::new(aligned.window.top_left.x, band.window.top_left.y),
+                Size::new(aligned.window.size.width, 1),
+            ),
+            data: band.data.map(|frame| &frame[bytes.clone()]),
+        };
+        epd.write_framebuffer(spi, &column).await?;
+    }
+    Ok(())
+}
+
+/// A full-width band of rows from a buffer.
+pub(crate) struct Band<'a, const FRAMES: usize> {
+    window: Rectangle,
+    data: [&'a [u8]; FRAMES],
+}
+
+impl<'a, const FRAMES: usize> Band<'a, FRAMES> {
+    /// Takes `rows` from `buffer`, counted from the top of the buffer's window.
+    pub(crate) fn new<const BITS: usize, B: BufferView<BITS, FRAMES> + ?Sized>(
+        buffer: &'a B,
+        rows: Range<usize>,
+    ) -> Self {
+        let window = buffer.window();
+        Self {
+            window: Rectangle::new(
+                window.top_left + Point::new(0, rows.start as i32),
+                Size::new(window.size.width, rows.len() as u32),
+            ),
+            data: buffer.data().map(|frame| {
+                let bytes_per_row = frame.len() / window.size.height.max(1) as usize;
+                &frame[rows.start * bytes_per_row..rows.end * bytes_per_row]
+            }),
+        }
+    }
+}
+
+impl<const BITS: usize, const FRAMES: usize> BufferView<BITS, FRAMES> for Band<'_, FRAMES> {
+    fn window(&self) -> Rectangle {
+        self.window
+    }
+
+    fn data(&self) -> [&[u8]; FRAMES] {
+        self.data
+    }
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use embedded_graphics::{
+        pixelcolor::BinaryColor,
+        primitives::{Line, Primitive, PrimitiveStyle},
+        Drawable,
+    };
+
+    use super::*;
+    use crate::{
+        buffer::{binary_buffer_length, BinaryBuffer},
+        epd2in9_v2::{self, Command, Epd2In9V2, RefreshMode},
+        testing::{block_on, Event, MockHw},
+    };
+
+    const SIZE: Size = Size::new(16, 32);
+
+    fn tracker<const N: usize>() -> DirtyTracker<BinaryBuffer<{ binary_buffer_length(SIZE) }>, N> {
+        DirtyTracker::new(BinaryBuffer::new(SIZE))
+    }
+
+    fn band(start: i32, end: i32) -> Rectangle {
+        Rectangle::new(Point::new(0, start), Size::new(16, (end - start) as u32))
+    }
+
+    #[test]
+    fn test_records_touched_rows() {
+        let mut buffer = tracker::<4>();
+        Line::new(Point::new(0, 4), Point::new(15, 6))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(&mut buffer)
+            .unwrap();
+        buffer
+            .fill_solid(
+                &Rectangle::new(Point::new(2, 20), Size::new(4, 2)),
+                BinaryColor::On,
+            )
+            .unwrap();
+        // Touching bands are merged, and pixels outside the buffer are ignored.
+        buffer
+            .draw_iter([
+                Pixel(Point::new(0, 7), BinaryColor::On),
+                Pixel(Point::new(0, 40), BinaryColor::On),
+            ])
+            .unwrap();
+
+        let bands: std::vec::Vec<_> = buffer.dirty_bands().collect();
+        assert_eq!(bands, [band(4, 8), band(20, 22)]);
+    }
+
+    #[test]
+    fn test_merges_nearest_bands_when_full() {
+        let mut buffer = tracker::<2>();
+        buffer.mark_dirty(band(0, 1));
+        buffer.mark_dirty(band(20, 21));
+        buffer.mark_dirty(band(10, 11));
+        buffer.mark_dirty(band(30, 31));
+
+        let bands: std::vec::Vec<_> = buffer.dirty_bands().collect();
+        assert_eq!(bands, [band(0, 11), band(20, 31)]);
+    }
+
+    #[test]
+    fn test_flush_partial_writes_only_dirty_rows() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        let mut buffer = DirtyTracker::<_>::new(epd2in9_v2::new_binary_buffer());
+
+        buffer
+            .fill_solid(
+                &Rectangle::new(Point::new(8, 2), Size::new(8, 2)),
+                BinaryColor::On,
+            )
+            .unwrap();
+        log.take_events();
+        block_on(buffer.flush_partial(&mut epd, &mut spi)).unwrap();
+        assert!(!buffer.is_dirty());
+
+        let events = log.take_events();
+        let bytes_per_row = epd2in9_v2::DISPLAY_WIDTH as usize / 8;
+        let write = events
+            .iter()
+            .position(|e| *e == Event::Command(Command::WriteLowRam as u8))
+            .unwrap();
+        let Event::Data(data) = &events[write + 1] else {
+            panic!("Expected data after the write command");
+        };
+        assert_eq!(data.len(), 2 * bytes_per_row);
+        assert_eq!(data[1], 0xFF);
+
+        // Nothing changed, so nothing is sent.
+        block_on(buffer.flush_partial(&mut epd, &mut spi)).unwrap();
+        assert_eq!(log.take_events(), []);
+    }
+
+    #[test]
+    fn test_write_changed_rows_writes_each_run() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        let previous = epd2in9_v2::new_binary_buffer();
+        let mut next = previous.clone();
+        next.fill_solid(
+            &Rectangle::new(Point::new(0, 3), Size::new(8, 2)),
+            BinaryColor::On,
+        )
+        .unwrap();
+        next.fill_solid(
+            &Rectangle::new(Point::new(64, 100), Size::new(8, 1)),
+            BinaryColor::On,
+        )
+        .unwrap();
+
+        log.take_events();
+        let written = block_on(write_changed_rows(&mut epd, &mut spi, &previous, &next)).unwrap();
+        assert_eq!(written, 3);
+
+        let bytes_per_row = epd2in9_v2::DISPLAY_WIDTH as usize / 8;
+        let writes: std::vec::Vec<_> = log
+            .take_events()
+            .windows(2)
+            .filter_map(|events| match events {
+                [Event::Command(c), Event::Data(data)] if *c == Command::WriteLowRam as u8 => {
+                    Some(data.len())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(writes, [2 * bytes_per_row, bytes_per_row]);
+
+        // Nothing changed, so nothing is sent.
+        let written = block_on(write_changed_rows(&mut epd, &mut spi, &next, &next)).unwrap();
+        assert_eq!(written, 0);
+        assert_eq!(log.take_events(), []);
+    }
+
+    #[test]
+    fn test_write_changed_rows_writes_all_rows_of_mismatched_buffers() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let mut spi = hw.spi();
+        let mut epd = Epd2In9V2::attach(hw, RefreshMode::Full);
+        const SMALL: Size = Size::new(8, 4);
+        let previous = BinaryBuffer::<{ binary_buffer_length(SMALL) }>::new(SMALL);
+        let next = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+
+        let written = block_on(write_changed_rows(&mut epd, &mut spi, &previous, &next)).unwrap();
+        assert_eq!(written, SIZE.height as usize);
+    }
+
+    #[test]
+    fn test_write_rows_skips_unmarked_rows() {
+        let mut previous = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let mut next = previous.clone();
+        next.fill_solid(&band(1, 3), BinaryColor::On).unwrap();
+        next.fill_solid(&band(9, 10), BinaryColor::On).unwrap();
+        let mut changed = [0xFF; row_bitmap_length(SIZE.height)];
+        assert_eq!(diff_rows(&previous, &next, &mut changed), Some(3));
+        assert_eq!(changed, [0b0110_0000, 0b0100_0000, 0, 0]);
+
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = Epd2In9V2::attach(hw, RefreshMode::Full);
+        let written = block_on(write_rows(&mut epd, &mut spi, &next, &changed)).unwrap();
+        assert_eq!(written, 3);
+        let writes: std::vec::Vec<_> = log
+            .take_events()
+            .windows(2)
+            .filter_map(|events| match events {
+                [Event::Command(c), Event::Data(data)] if *c == Command::WriteLowRam as u8 => {
+                    Some(data.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(writes, [std::vec![0xFF; 4], std::vec![0xFF; 2]]);
+
+        previous.clone_from(&next);
+        assert_eq!(diff_rows(&previous, &next, &mut changed), Some(0));
+        assert_eq!(changed, [0; 4]);
+    }
+
+    #[test]
+    fn test_diff_rows_handles_mismatched_buffers() {
+        let next = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let mut short = [0; row_bitmap_length(SIZE.height) - 1];
+        assert_eq!(diff_rows(&next, &next, &mut short), None);
+
+        // Buffers of different windows can't be compared, so every row is marked.
+        const SMALL: Size = Size::new(8, 4);
+        let previous = BinaryBuffer::<{ binary_buffer_length(SMALL) }>::new(SMALL);
+        let mut changed = [0; row_bitmap_length(SIZE.height)];
+        assert_eq!(diff_rows(&previous, &next, &mut changed), Some(32));
+        assert_eq!(changed, [0xFF; 4]);
+    }
+
+    #[test]
+    fn test_dirty_regions_merge_close_areas() {
+        let mut regions = DirtyRegions::<4>::new().with_merge_gap(2);
+        regions.add(Rectangle::new(Point::new(0, 0), Size::new(4, 4)));
+        // Within the merge gap.
+        regions.add(Rectangle::new(Point::new(6, 0), Size::new(4, 4)));
+        // Too far away.
+        regions.add(Rectangle::new(Point::new(0, 20), Size::new(4, 4)));
+        regions.add(Rectangle::new(Point::new(0, 20), Size::zero()));
+
+        let areas: std::vec::Vec<_> = regions.iter().collect();
+        assert_eq!(
+            areas,
+            [
+                Rectangle::new(Point::new(0, 0), Size::new(10, 4)),
+                Rectangle::new(Point::new(0, 20), Size::new(4, 4)),
+            ]
+        );
+        assert_eq!(
+            regions.bounding_box(),
+            Some(Rectangle::new(Point::new(0, 0), Size::new(10, 24)))
+        );
+    }
+
+    #[test]
+    fn test_dirty_regions_merge_least_wasteful_when_full() {
+        let mut regions = DirtyRegions::<2>::new();
+        regions.add(Rectangle::new(Point::new(0, 0), Size::new(4, 4)));
+        regions.add(Rectangle::new(Point::new(100, 100), Size::new(4, 4)));
+        regions.add(Rectangle::new(Point::new(90, 100), Size::new(4, 4)));
+
+        let areas: std::vec::Vec<_> = regions.iter().collect();
+        assert_eq!(
+            areas,
+            [
+                Rectangle::new(Point::new(0, 0), Size::new(4, 4)),
+                Rectangle::new(Point::new(90, 100), Size::new(14, 4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display_partial_regions_refreshes_once() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        let mut buffer = epd2in9_v2::new_binary_buffer();
+        buffer
+            .fill_solid(
+                &Rectangle::new(Point::new(8, 2), Size::new(8, 2)),
+                BinaryColor::On,
+            )
+            .unwrap();
+        let width = epd2in9_v2::DISPLAY_WIDTH as u32;
+
+        log.take_events();
+        block_on(epd.display_partial_regions(
+            &mut spi,
+            &[
+                // Rounded out to the second byte of rows 2 and 3.
+                (&buffer, Rectangle::new(Point::new(10, 2), Size::new(4, 2))),
+                (
+                    &buffer,
+                    Rectangle::new(Point::new(0, 50), Size::new(width, 3)),
+                ),
+            ],
+        ))
+        .unwrap();
+
+        let events = log.take_events();
+        let writes: std::vec::Vec<_> = events
+            .windows(2)
+            .filter_map(|events| match events {
+                [Event::Command(c), Event::Data(data)] if *c == Command::WriteLowRam as u8 => {
+                    Some(data.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        let bytes_per_row = width as usize / 8;
+        assert_eq!(
+            writes,
+            [
+                std::vec![0xFF],
+                std::vec![0xFF],
+                std::vec![0; 3 * bytes_per_row]
+            ]
+        );
+        let refreshes = events
+            .iter()
+            .filter(|e| **e == Event::Command(Command::MasterActivation as u8))
+            .count();
+        assert_eq!(refreshes, 1);
+    }
+}
@@ -0,0 +1,418 @@
+//! Converts full-color images into the few gray levels that e-paper displays support.
+//!
+//! Use [quantize] to convert each pixel, with either a plain [Dither::Threshold], or
+//! [Dither::Ordered] dithering to approximate intermediate shades with a pattern.
+//!
+//! For color panels, [nearest] picks the closest color of a [Palette], such as [TriColor] or
+//! [SevenColor], and [PaletteTarget] converts everything drawn to a buffer this way.
+use core::marker::PhantomData;
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    pixelcolor::{BinaryColor, Gray2, Rgb888},
+    prelude::{Dimensions, PixelColor, Point, RgbColor},
+    primitives::{PointsIter, Rectangle},
+    Pixel,
+};
+#[cfg(any(feature = "tinybmp", feature = "png"))]
+use embedded_graphics::{draw_target::DrawTargetExt, prelude::Size};
+
+/// How to convert each pixel's brightness to the nearest available level.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// Rounds each pixel to the nearest level. This suits line art and text.
+    Threshold,
+    /// Uses a 4x4 Bayer matrix to approximate intermediate shades. This suits photos and
+    /// gradients.
+    #[default]
+    Ordered,
+}
+
+/// Colors that images can be quantized to.
+pub trait Quantize: PixelColor {
+    /// The number of brightness levels.
+    const LEVELS: u8;
+
+    /// Returns the color for the given level, from 0 (black) to `LEVELS - 1` (white).
+    fn from_level(level: u8) -> Self;
+}
+
+impl Quantize for BinaryColor {
+    const LEVELS: u8 = 2;
+
+    /// Returns [BinaryColor::Off] for black, as the crate's displays show `On` as white.
+    fn from_level(level: u8) -> Self {
+        BinaryColor::from(level != 0)
+    }
+}
+
+impl Quantize for Gray2 {
+    const LEVELS: u8 = 4;
+
+    fn from_level(level: u8) -> Self {
+        Gray2::new(level)
+    }
+}
+
+/// A 4x4 Bayer matrix, with thresholds from 0 to 15.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Converts `color` to the nearest level of `C`. `point` is the pixel's position in the target,
+/// which sets the dither pattern.
+pub fn quantize<C: Quantize>(color: Rgb888, point: Point, dither: Dither) -> C {
+    // Rec. 601 luma weights, scaled to sum to 256.
+    let luma =
+        (77 * u32::from(color.r()) + 150 * u32::from(color.g()) + 29 * u32::from(color.b())) >> 8;
+    let bias = match dither {
+        Dither::Threshold => 128,
+        Dither::Ordered => {
+            let threshold = BAYER[point.y.rem_euclid(4) as usize][point.x.rem_euclid(4) as usize];
+            (u32::from(threshold) * 2 + 1) * 8
+        }
+    };
+    let max_level = u32::from(C::LEVELS.saturating_sub(1));
+    let level = ((luma * max_level + bias) / 256).min(max_level);
+    C::from_level(level as u8)
+}
+
+/// Colors of panels with a fixed palette, such as tri-color and 7-color panels, that full-color
+/// artwork can be mapped to with [nearest].
+pub trait Palette: PixelColor + 'static {
+    /// Each of the panel's colors, with how it looks on the display.
+    const PALETTE: &'static [(Self, Rgb888)];
+}
+
+impl Palette for BinaryColor {
+    const PALETTE: &'static [(Self, Rgb888)] = &[
+        (BinaryColor::Off, Rgb888::BLACK),
+        (BinaryColor::On, Rgb888::WHITE),
+    ];
+}
+
+impl Palette for Gray2 {
+    const PALETTE: &'static [(Self, Rgb888)] = &[
+        (Gray2::new(0), Rgb888::new(0, 0, 0)),
+        (Gray2::new(1), Rgb888::new(85, 85, 85)),
+        (Gray2::new(2), Rgb888::new(170, 170, 170)),
+        (Gray2::new(3), Rgb888::new(255, 255, 255)),
+    ];
+}
+
+/// The colors of black, white and red panels.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TriColor {
+    Black,
+    White,
+    Red,
+}
+
+impl PixelColor for TriColor {
+    type Raw = ();
+}
+
+impl Palette for TriColor {
+    const PALETTE: &'static [(Self, Rgb888)] = &[
+        (TriColor::Black, Rgb888::BLACK),
+        (TriColor::White, Rgb888::WHITE),
+        (TriColor::Red, Rgb888::new(255, 0, 0)),
+    ];
+}
+
+/// The colors of 7-color (ACeP) panels, in the order of their 4-bit codes.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum SevenColor {
+    Black = 0,
+    White = 1,
+    Green = 2,
+    Blue = 3,
+    Red = 4,
+    Yellow = 5,
+    Orange = 6,
+}
+
+impl PixelColor for SevenColor {
+    type Raw = ();
+}
+
+impl Palette for SevenColor {
+    // These approximate how the inks look, rather than pure primaries, which matches images better.
+    const PALETTE: &'static [(Self, Rgb888)] = &[
+        (SevenColor::Black, Rgb888::new(0, 0, 0)),
+        (SevenColor::White, Rgb888::new(255, 255, 255)),
+        (SevenColor::Green, Rgb888::new(0, 160, 0)),
+        (SevenColor::Blue, Rgb888::new(0, 0, 220)),
+        (SevenColor::Red, Rgb888::new(220, 0, 0)),
+        (SevenColor::Yellow, Rgb888::new(255, 230, 0)),
+        (SevenColor::Orange, Rgb888::new(255, 128, 0)),
+    ];
+}
+
+/// Converts `color` to the closest color in `C`'s palette. `point` is the pixel's position in the
+/// target, which sets the dither pattern.
+pub fn nearest<C: Palette>(color: Rgb888, point: Point, dither: Dither) -> C {
+    let offset = match dither {
+        Dither::Threshold => 0,
+        Dither::Ordered => {
+            let threshold = BAYER[point.y.rem_euclid(4) as usize][point.x.rem_euclid(4) as usize];
+            (i32::from(threshold) * 2 + 1) * 8 - 128
+        }
+    };
+    let channel = |value: u8| (i32::from(value) + offset).clamp(0, 255);
+    let (r, g, b) = (channel(color.r()), channel(color.g()), channel(color.b()));
+    // Weights green highest and blue lowest, roughly as the eye does.
+    let distance = |candidate: &Rgb888| {
+        let dr = r - i32::from(candidate.r());
+        let dg = g - i32::from(candidate.g());
+        let db = b - i32::from(candidate.b());
+        2 * dr * dr + 4 * dg * dg + 3 * db * db
+    };
+    C::PALETTE
+        .iter()
+        .min_by_key(|(_, rgb)| distance(rgb))
+        .map(|(color, _)| *color)
+        .expect("palettes must not be empty")
+}
+
+/// Wraps a draw target of palette colors, so that `Rgb888` or `Rgb565` artwork can be drawn to it.
+///
+/// Each pixel is converted with [nearest].
+///
+#[cfg_attr(feature = "epd2in9_v2", doc = "```")]
+#[cfg_attr(not(feature = "epd2in9_v2"), doc = "```ignore")]
+/// use embedded_graphics::{
+///     pixelcolor::{BinaryColor, Rgb565},
+///     prelude::{Point, Primitive, RgbColor, Size},
+///     primitives::{PrimitiveStyle, Rectangle},
+///     Drawable,
+/// };
+/// use epd_waveshare_async::{
+///     dither::{Dither, PaletteTarget},
+///     epd2in9_v2,
+/// };
+///
+/// let mut buffer = epd2in9_v2::new_binary_buffer();
+/// let mut target = PaletteTarget::<_, Rgb565>::new(&mut buffer, Dither::Ordered);
+/// Rectangle::new(Point::zero(), Size::new(16, 16))
+///     .into_styled(PrimitiveStyle::with_fill(Rgb565::YELLOW))
+///     .draw(&mut target)
+///     .unwrap();
+/// ```
+pub struct PaletteTarget<'a, D, C = Rgb888> {
+    target: &'a mut D,
+    dither: Dither,
+    _color: PhantomData<C>,
+}
+
+impl<'a, D, C> PaletteTarget<'a, D, C> {
+    /// Converts colors drawn to `target` with `dither`.
+    pub fn new(target: &'a mut D, dither: Dither) -> Self {
+        PaletteTarget {
+            target,
+            dither,
+            _color: PhantomData,
+        }
+    }
+}
+
+impl<D: Dimensions, C> Dimensions for PaletteTarget<'_, D, C> {
+    fn bounding_box(&self) -> Rectangle {
+        self.target.bounding_box()
+    }
+}
+
+impl<D, C> DrawTarget for PaletteTarget<'_, D, C>
+where
+    D: DrawTarget,
+    D::Color: Palette,
+    C: PixelColor + Into<Rgb888>,
+{
+    type Color = C;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let dither = self.dither;
+        self.target.draw_iter(
+            pixels
+                .into_iter()
+                .map(|Pixel(point, color)| Pixel(point, nearest(color.into(), point, dither))),
+        )
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let dither = self.dither;
+        let colors = area
+            .points()
+            .zip(colors)
+            .map(|(point, color)| nearest(color.into(), point, dither));
+        self.target.fill_contiguous(area, colors)
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        match self.dither {
+            Dither::Threshold => self.target.fill_solid(
+                area,
+                nearest(color.into(), Point::zero(), Dither::Threshold),
+            ),
+            Dither::Ordered => self.fill_contiguous(area, core::iter::repeat(color)),
+        }
+    }
+}
+
+/// How to size an image within the area it's drawn to.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    /// Draws the image at its original size, clipped to the area.
+    #[default]
+    Original,
+    /// Scales the image to the largest size that fits the area, keeping its aspect ratio.
+    Contain,
+    /// Scales the image to exactly fill the area.
+    Stretch,
+}
+
+/// Draws an image of the given `source` size into `area`, reading each source pixel with
+/// `pixel`, and returns the area that changed.
+#[cfg(any(feature = "tinybmp", feature = "png"))]
+pub(crate) fn draw_image<D>(
+    target: &mut D,
+    area: Rectangle,
+    source: Size,
+    fit: Fit,
+    centered: bool,
+    dither: Dither,
+    pixel: impl Fn(Point) -> Rgb888,
+) -> Result<Rectangle, D::Error>
+where
+    D: DrawTarget,
+    D::Color: Quantize,
+{
+    let size = match fit {
+        Fit::Original => source,
+        Fit::Stretch => area.size,
+        Fit::Contain if source.width == 0 || source.height == 0 => Size::zero(),
+        Fit::Contain => {
+            let (width, height) = (u64::from(source.width), u64::from(source.height));
+            let (max_width, max_height) = (u64::from(area.size.width), u64::from(area.size.height));
+            if width * max_height <= height * max_width {
+                Size::new((width * max_height / height) as u32, area.size.height)
+            } else {
+                Size::new(area.size.width, (height * max_width / width) as u32)
+            }
+        }
+    };
+    let top_left = if centered {
+        let free = Size::new(
+            area.size.width.saturating_sub(size.width),
+            area.size.height.saturating_sub(size.height),
+        );
+        area.top_left + free / 2
+    } else {
+        area.top_left
+    };
+    let drawn = Rectangle::new(top_left, size);
+    if size.width == 0 || size.height == 0 {
+        return Ok(drawn);
+    }
+
+    let colors = drawn.points().map(|point| {
+        let offset = point - top_left;
+        // Nearest-neighbour scaling.
+        let x = u64::from(offset.x as u32) * u64::from(source.width) / u64::from(size.width);
+        let y = u64::from(offset.y as u32) * u64::from(source.height) / u64::from(size.height);
+        quantize(pixel(Point::new(x as i32, y as i32)), point, dither)
+    });
+    target.clipped(&area).fill_contiguous(&drawn, colors)?;
+    Ok(drawn.intersection(&area))
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::prelude::GrayColor;
+
+    use super::*;
+
+    fn gray_to_rgb(luma: u8) -> Rgb888 {
+        Rgb888::new(luma, luma, luma)
+    }
+
+    #[test]
+    fn test_threshold_rounds_to_nearest_level() {
+        let at = |luma, dither| quantize::<Gray2>(gray_to_rgb(luma), Point::zero(), dither).luma();
+        assert_eq!(at(0, Dither::Threshold), 0);
+        assert_eq!(at(80, Dither::Threshold), 1);
+        assert_eq!(at(180, Dither::Threshold), 2);
+        assert_eq!(at(255, Dither::Threshold), 3);
+        assert_eq!(
+            quantize::<BinaryColor>(Rgb888::WHITE, Point::zero(), Dither::Threshold),
+            BinaryColor::On
+        );
+    }
+
+    #[test]
+    fn test_ordered_dither_mixes_levels() {
+        // Mid gray should be half black, half white across the pattern.
+        let white = (0..4)
+            .flat_map(|y| (0..4).map(move |x| Point::new(x, y)))
+            .filter(|p| {
+                quantize::<BinaryColor>(gray_to_rgb(128), *p, Dither::Ordered) == BinaryColor::On
+            })
+            .count();
+        assert_eq!(white, 8);
+        // Black and white stay solid.
+        for x in 0..4 {
+            let p = Point::new(x, x);
+            assert_eq!(
+                quantize::<BinaryColor>(Rgb888::BLACK, p, Dither::Ordered),
+                BinaryColor::Off
+            );
+            assert_eq!(
+                quantize::<BinaryColor>(Rgb888::WHITE, p, Dither::Ordered),
+                BinaryColor::On
+            );
+        }
+    }
+
+    #[test]
+    fn test_nearest_picks_closest_palette_color() {
+        let at = |color| nearest::<SevenColor>(color, Point::zero(), Dither::Threshold);
+        assert_eq!(at(Rgb888::new(250, 10, 10)), SevenColor::Red);
+        assert_eq!(at(Rgb888::new(240, 140, 20)), SevenColor::Orange);
+        assert_eq!(at(Rgb888::new(20, 20, 200)), SevenColor::Blue);
+        assert_eq!(at(Rgb888::new(30, 30, 30)), SevenColor::Black);
+        assert_eq!(
+            nearest::<TriColor>(Rgb888::new(200, 40, 60), Point::zero(), Dither::Threshold),
+            TriColor::Red
+        );
+    }
+
+    #[test]
+    fn test_palette_target_converts_and_dithers() {
+        use crate::buffer::{binary_buffer_length, BinaryBuffer};
+        use embedded_graphics::{pixelcolor::Rgb565, prelude::Size};
+
+        const SIZE: Size = Size::new(8, 4);
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let area = Rectangle::new(Point::zero(), SIZE);
+        PaletteTarget::<_, Rgb565>::new(&mut buffer, Dither::Threshold)
+            .fill_solid(&area, Rgb565::WHITE)
+            .unwrap();
+        assert!(buffer.data().iter().all(|byte| *byte == 0xFF));
+
+        PaletteTarget::<_, Rgb888>::new(&mut buffer, Dither::Ordered)
+            .fill_solid(&area, gray_to_rgb(128))
+            .unwrap();
+        let white: u32 = buffer.data().iter().map(|byte| byte.count_ones()).sum();
+        assert_eq!(white, 16);
+    }
+}
@@ -0,0 +1,772 @@
+//! Driver for Waveshare's 1.9" segment e-paper display, which shows a temperature and a humidity
+//! reading with a few status icons.
+//!
+//! Unlike the framebuffer displays, this panel is driven over I2C, and each bit of its data turns
+//! on one fixed segment. Draw into [Segments], then show it with [Epd1In9Segment::display]:
+//!
+//! ```text
+//! let mut epd = Epd1In9Segment::new(hw)
+//!     .init(&mut i2c, RefreshMode::Full, 21)
+//!     .await?;
+//!
+//! let mut segments = Segments::new();
+//! segments.set_temperature(215, TemperatureUnit::Celsius)?; // 21.5°C
+//! segments.set_humidity(483)?; // 48.3%
+//! epd.display(&mut i2c, &segments).await?;
+//! ```
+//!
+//! The panel's waveform depends on the temperature, which it can't measure itself. Pass the
+//! ambient temperature to [Epd1In9Segment::init], and update it with
+//! [Epd1In9Segment::set_temperature] when it changes, e.g. from the same sensor that you display.
+//!
+//! The segment map is taken from Waveshare's sample code.
+
+// Drivers run on-device, so they must not panic.
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
+
+use core::time::Duration;
+
+use embedded_graphics::prelude::Size;
+use embedded_hal::digital::{ErrorType as PinErrorType, OutputPin, PinState};
+use embedded_hal_async::{
+    delay::DelayNs,
+    i2c::{ErrorType as I2cErrorType, I2c},
+};
+
+use crate::{
+    hw::{
+        BusyHw, BusyWait as _, DelayHw, ErrorHw, ErrorSource, I2cHw, PowerProfile, ResetHw,
+        WithSource as _,
+    },
+    log::{debug, trace},
+    Capabilities, Reset, WaitIdle,
+};
+
+/// The I2C address that commands are written to.
+pub const COMMAND_ADDRESS: u8 = 0x3C;
+/// The I2C address that segment data is written to.
+pub const DATA_ADDRESS: u8 = 0x3D;
+/// The default pin state that indicates the display is busy.
+pub const DEFAULT_BUSY_WHEN: PinState = PinState::Low;
+/// The number of bytes of segment data.
+pub const SEGMENT_BYTES: usize = 15;
+
+const POWER_ON: u8 = 0x2B;
+const POWER_OFF: u8 = 0x28;
+const BOOST: u8 = 0xA7;
+/// Turns on the temperature sensing circuit.
+const TEMPERATURE_SENSING_ON: u8 = 0xE0;
+/// Sets the frame time, from the next command byte.
+const FRAME_TIME: u8 = 0xE7;
+const WRITE_RAM: u8 = 0x40;
+const FIRST_SRAM_ON: u8 = 0xA9;
+const FIRST_SRAM_OFF: u8 = 0xA8;
+const SECOND_SRAM_ON: u8 = 0xAB;
+const SECOND_SRAM_OFF: u8 = 0xAA;
+const DISPLAY_ON: u8 = 0xAF;
+const DISPLAY_OFF: u8 = 0xAE;
+const SLEEP_OUT: u8 = 0xAC;
+const SLEEP_IN: u8 = 0xAD;
+
+/// Returns how long to let the supply settle after each power up stage, in milliseconds.
+///
+/// The controller has no booster settings, so [PowerProfile::LowPeakCurrent] spreads the stages
+/// out instead, and [PowerProfile::Fast] is the same as [PowerProfile::Standard].
+fn power_up_settle_ms(profile: PowerProfile) -> u32 {
+    match profile {
+        PowerProfile::LowPeakCurrent => 50,
+        PowerProfile::Standard | PowerProfile::Fast => 10,
+    }
+}
+
+/// The waveform to refresh the display with.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshMode {
+    /// A full refresh, which flashes the segments to avoid ghosting.
+    Full,
+    /// A fast refresh, which switches segments directly between black and white. This ghosts more,
+    /// so do a [RefreshMode::Full] refresh occasionally.
+    Fast,
+    /// A long refresh, which clears any image retention.
+    Clean,
+}
+
+impl RefreshMode {
+    /// Returns the command bytes that select this waveform.
+    pub fn lut(&self) -> &[u8; 7] {
+        match self {
+            RefreshMode::Full => &[0x82, 0x20, 0x00, 0xA0, 0x80, 0x40, 0x63],
+            RefreshMode::Fast => &[0x82, 0x80, 0x00, 0xC0, 0x80, 0x80, 0x62],
+            RefreshMode::Clean => &[0x82, 0x28, 0x20, 0xA8, 0xA0, 0x50, 0x65],
+        }
+    }
+}
+
+/// Returns the frame time that the display uses at `celsius`. Refreshes take several frames, so
+/// they are slower in the cold.
+pub fn frame_time(celsius: i8) -> Duration {
+    Duration::from_millis((u64::from(frame_time_setting(celsius)) + 1) * 20)
+}
+
+/// The [FRAME_TIME] setting for `celsius`, in 20ms steps.
+fn frame_time_setting(celsius: i8) -> u8 {
+    match celsius {
+        ..5 => 0x31,
+        5..10 => 0x22,
+        10..15 => 0x18,
+        15..20 => 0x13,
+        _ => 0x0E,
+    }
+}
+
+/// The command bytes that compensate the drive voltages for `celsius`.
+fn voltage_setting(celsius: i8) -> [u8; 3] {
+    if celsius < 10 {
+        [0x7E, 0x81, 0xB4]
+    } else {
+        [0x7B, 0x81, 0xB4]
+    }
+}
+
+/// A 7-segment digit on the display.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Digit {
+    TemperatureTens,
+    TemperatureOnes,
+    TemperatureTenths,
+    HumidityTens,
+    HumidityOnes,
+    HumidityTenths,
+}
+
+impl Digit {
+    /// The index of the first of the digit's two bytes.
+    fn index(self) -> usize {
+        match self {
+            Digit::TemperatureTens => 1,
+            Digit::TemperatureOnes => 3,
+            Digit::TemperatureTenths => 5,
+            Digit::HumidityTens => 7,
+            Digit::HumidityOnes => 9,
+            Digit::HumidityTenths => 11,
+        }
+    }
+}
+
+/// The segments of each digit value, from 0 to 9.
+const DIGITS: [[u8; 2]; 10] = [
+    [0xBF, 0x1F],
+    [0x00, 0x1F],
+    [0xFD, 0x17],
+    [0xF5, 0x1F],
+    [0x47, 0x1F],
+    [0xF7, 0x1D],
+    [0xFF, 0x1D],
+    [0x21, 0x1F],
+    [0xFF, 0x1F],
+    [0xF7, 0x1F],
+];
+/// The bits of a digit's second byte that belong to the digit. The rest are symbols.
+const DIGIT_SECOND_BYTE_MASK: u8 = 0x1F;
+
+/// A fixed symbol on the display.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symbol {
+    /// The leading "1" of temperatures of 100 or more.
+    TemperatureHundred,
+    TemperatureDecimalPoint,
+    HumidityDecimalPoint,
+    Percent,
+    /// The degree sign and "C".
+    Celsius,
+    /// The degree sign and "F".
+    Fahrenheit,
+    Bluetooth,
+    LowPower,
+}
+
+impl Symbol {
+    /// The byte index and bits of the symbol.
+    fn bits(self) -> (usize, u8) {
+        match self {
+            Symbol::TemperatureHundred => (0, 0xFF),
+            Symbol::TemperatureDecimalPoint => (4, 0x20),
+            Symbol::HumidityDecimalPoint => (10, 0x20),
+            Symbol::Percent => (12, 0x20),
+            Symbol::Celsius => (13, 0x05),
+            Symbol::Fahrenheit => (13, 0x06),
+            Symbol::Bluetooth => (13, 0x08),
+            Symbol::LowPower => (13, 0x10),
+        }
+    }
+}
+
+/// The unit to show a temperature in.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemperatureUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+/// A value that doesn't fit in the display's digits.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange;
+
+impl core::fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("the value doesn't fit in the display's digits")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for OutOfRange {}
+
+/// The state of every segment on the display. Set bits are black.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Segments {
+    data: [u8; SEGMENT_BYTES],
+}
+
+impl Segments {
+    /// Creates segments that are all off (white).
+    pub const fn new() -> Self {
+        Segments {
+            data: [0; SEGMENT_BYTES],
+        }
+    }
+
+    /// Creates segments that are all on (black).
+    pub const fn all_on() -> Self {
+        Segments {
+            data: [0xFF; SEGMENT_BYTES],
+        }
+    }
+
+    /// Creates segments from raw data, in the layout of Waveshare's sample code.
+    pub const fn from_data(data: [u8; SEGMENT_BYTES]) -> Self {
+        Segments { data }
+    }
+
+    /// Returns the raw segment data.
+    pub fn data(&self) -> &[u8; SEGMENT_BYTES] {
+        &self.data
+    }
+
+    /// Shows `value` (0 to 9) on `digit`, or blanks it if `value` is `None`. Values above 9 blank
+    /// the digit.
+    pub fn set_digit(&mut self, digit: Digit, value: Option<u8>) {
+        let segments = value
+            .and_then(|v| DIGITS.get(usize::from(v)))
+            .copied()
+            .unwrap_or([0, 0]);
+        let i = digit.index();
+        self.data[i] = segments[0];
+        self.data[i + 1] = (self.data[i + 1] & !DIGIT_SECOND_BYTE_MASK) | segments[1];
+    }
+
+    /// Turns `symbol` on or off.
+    pub fn set_symbol(&mut self, symbol: Symbol, on: bool) {
+        let (i, bits) = symbol.bits();
+        if on {
+            self.data[i] |= bits;
+        } else {
+            self.data[i] &= !bits;
+        }
+    }
+
+    /// Shows a temperature, in tenths of a degree, from 0.0 to 199.9, with its decimal point and
+    /// unit. Leading zeros are blanked.
+    pub fn set_temperature(
+        &mut self,
+        tenths: u16,
+        unit: TemperatureUnit,
+    ) -> Result<(), OutOfRange> {
+        if tenths > 1999 {
+            return Err(OutOfRange);
+        }
+        self.set_symbol(Symbol::TemperatureHundred, tenths >= 1000);
+        let tens = (tenths / 100 % 10) as u8;
+        let tens = (tenths >= 100).then_some(tens);
+        self.set_digit(Digit::TemperatureTens, tens);
+        self.set_digit(Digit::TemperatureOnes, Some((tenths / 10 % 10) as u8));
+        self.set_digit(Digit::TemperatureTenths, Some((tenths % 10) as u8));
+        self.set_symbol(Symbol::TemperatureDecimalPoint, true);
+        self.set_symbol(Symbol::Celsius, false);
+        self.set_symbol(Symbol::Fahrenheit, false);
+        match unit {
+            TemperatureUnit::Celsius => self.set_symbol(Symbol::Celsius, true),
+            TemperatureUnit::Fahrenheit => self.set_symbol(Symbol::Fahrenheit, true),
+        }
+        Ok(())
+    }
+
+    /// Shows a relative humidity, in tenths of a percent, from 0.0 to 99.9, with its decimal point
+    /// and percent sign. Leading zeros are blanked.
+    pub fn set_humidity(&mut self, tenths: u16) -> Result<(), OutOfRange> {
+        if tenths > 999 {
+            return Err(OutOfRange);
+        }
+        let tens = (tenths >= 100).then_some((tenths / 100) as u8);
+        self.set_digit(Digit::HumidityTens, tens);
+        self.set_digit(Digit::HumidityOnes, Some((tenths / 10 % 10) as u8));
+        self.set_digit(Digit::HumidityTenths, Some((tenths % 10) as u8));
+        self.set_symbol(Symbol::HumidityDecimalPoint, true);
+        self.set_symbol(Symbol::Percent, true);
+        Ok(())
+    }
+}
+
+pub trait State {}
+
+/// The display has not been initialised, or has been reset.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateUninitialized();
+impl State for StateUninitialized {}
+
+/// The display is initialised and ready to show segments.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateReady {
+    mode: RefreshMode,
+    celsius: i8,
+}
+impl State for StateReady {}
+
+/// The display is in deep sleep. It must be initialised again to wake it.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateAsleep();
+impl State for StateAsleep {}
+
+/// Controls Waveshare's 1.9" segment e-paper display.
+///
+/// * [sample code](https://github.com/waveshareteam/e-Paper/tree/master/E-paper_Separate_Program/1.9inch_segment_e-Paper)
+///
+/// HW should implement [I2cHw], [ResetHw], [BusyHw], [DelayHw], and [ErrorHw].
+pub struct Epd1In9Segment<HW, STATE> {
+    hw: HW,
+    state: STATE,
+}
+
+/// Formats the display's state. The hardware is omitted, as it rarely implements [defmt::Format].
+#[cfg(feature = "defmt")]
+impl<HW, STATE: defmt::Format> defmt::Format for Epd1In9Segment<HW, STATE> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Epd1In9Segment {{ state: {} }}", self.state)
+    }
+}
+
+impl<HW> Epd1In9Segment<HW, StateUninitialized> {
+    pub fn new(hw: HW) -> Self {
+        Epd1In9Segment {
+            hw,
+            state: StateUninitialized(),
+        }
+    }
+}
+
+impl<HW, STATE: State> Epd1In9Segment<HW, STATE>
+where
+    HW: I2cHw + ResetHw + BusyHw + DelayHw + ErrorHw,
+    HW::Error: From<<HW::I2c as I2cErrorType>::Error>
+        + From<<HW::Reset as PinErrorType>::Error>
+        + From<<HW::Busy as PinErrorType>::Error>,
+{
+    /// Resets and initialises the display, with the waveform for `mode` compensated for the
+    /// ambient temperature in `celsius`. This also wakes the display from sleep.
+    pub async fn init(
+        self,
+        i2c: &mut HW::I2c,
+        mode: RefreshMode,
+        celsius: i8,
+    ) -> Result<Epd1In9Segment<HW, StateReady>, HW::Error> {
+        debug!("Initialising segment display");
+        let mut epd = self.reset().await?;
+        epd.hw.delay().delay_ms(100).await;
+        let settle_ms = power_up_settle_ms(epd.hw.power_profile());
+        send_commands::<HW>(i2c, &[POWER_ON]).await?;
+        epd.hw.delay().delay_ms(settle_ms).await;
+        send_commands::<HW>(i2c, &[BOOST, TEMPERATURE_SENSING_ON]).await?;
+        epd.hw.delay().delay_ms(settle_ms).await;
+
+        let mut epd = Epd1In9Segment {
+            hw: epd.hw,
+            state: StateReady { mode, celsius },
+        };
+        epd.set_temperature(i2c, celsius).await?;
+        epd.set_refresh_mode(i2c, mode).await?;
+        Ok(epd)
+    }
+}
+
+impl<HW> Epd1In9Segment<HW, StateReady>
+where
+    HW: I2cHw + BusyHw + ResetHw + ErrorHw,
+    HW::Error: From<<HW::I2c as I2cErrorType>::Error> + From<<HW::Busy as PinErrorType>::Error>,
+{
+    /// Returns the ambient temperature that the waveform is compensated for.
+    pub fn temperature(&self) -> i8 {
+        self.state.celsius
+    }
+
+    /// Returns the time that each refresh takes at the current temperature, roughly.
+    pub fn estimated_refresh_duration(&self) -> Duration {
+        frame_time(self.state.celsius)
+    }
+
+    /// Returns what the display supports, at the current temperature. It has no framebuffer, so
+    /// its resolution is zero.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            resolution: Size::zero(),
+            colors: 2,
+            supports_partial: false,
+            supports_fast: true,
+            supports_gray: false,
+            full_refresh: self.estimated_refresh_duration(),
+            fast_refresh: Some(self.estimated_refresh_duration()),
+            partial_refresh: None,
+        }
+    }
+
+    /// Compensates the drive voltages and frame time for the ambient temperature in `celsius`.
+    pub async fn set_temperature(
+        &mut self,
+        i2c: &mut HW::I2c,
+        celsius: i8,
+    ) -> Result<(), HW::Error> {
+        debug!("Compensating for {}C", celsius);
+        send_commands::<HW>(i2c, &voltage_setting(celsius)).await?;
+        self.wait_until_idle().await?;
+        send_commands::<HW>(i2c, &[FRAME_TIME, frame_time_setting(celsius)]).await?;
+        self.state.celsius = celsius;
+        Ok(())
+    }
+
+    /// Selects the waveform for the next refreshes.
+    pub async fn set_refresh_mode(
+        &mut self,
+        i2c: &mut HW::I2c,
+        mode: RefreshMode,
+    ) -> Result<(), HW::Error> {
+        debug!("Changing refresh mode to {:?}", mode);
+        send_commands::<HW>(i2c, mode.lut()).await?;
+        self.state.mode = mode;
+        Ok(())
+    }
+
+    /// Writes `segments` to the display and refreshes it, then powers down the drive circuits
+    /// until the next refresh.
+    pub async fn display(
+        &mut self,
+        i2c: &mut HW::I2c,
+        segments: &Segments,
+    ) -> Result<(), HW::Error> {
+        debug!("Updating segments");
+        send_commands::<HW>(
+            i2c,
+            &[
+                SLEEP_OUT,
+                POWER_ON,
+                WRITE_RAM,
+                FIRST_SRAM_ON,
+                FIRST_SRAM_OFF,
+            ],
+        )
+        .await?;
+        // The controller expects one byte more than the panel has segments for.
+        let mut data = [0; SEGMENT_BYTES + 1];
+        data[..SEGMENT_BYTES].copy_from_slice(segments.data());
+        i2c.write(DATA_ADDRESS, &data)
+            .await
+            .with_source::<HW>(ErrorSource::I2c)?;
+        send_commands::<HW>(i2c, &[SECOND_SRAM_ON, SECOND_SRAM_OFF, DISPLAY_ON]).await?;
+        self.wait_until_idle().await?;
+        send_commands::<HW>(i2c, &[DISPLAY_OFF, POWER_OFF, SLEEP_IN]).await
+    }
+
+    /// Turns every segment off.
+    pub async fn clear(&mut self, i2c: &mut HW::I2c) -> Result<(), HW::Error> {
+        self.display(i2c, &Segments::new()).await
+    }
+
+    /// Sends command bytes directly, for low-level control or experimentation.
+    pub async fn send_commands(
+        &mut self,
+        i2c: &mut HW::I2c,
+        commands: &[u8],
+    ) -> Result<(), HW::Error> {
+        send_commands::<HW>(i2c, commands).await
+    }
+
+    /// Puts the display into deep sleep. Use [Epd1In9Segment::init] to wake it.
+    pub async fn sleep(
+        mut self,
+        i2c: &mut HW::I2c,
+    ) -> Result<Epd1In9Segment<HW, StateAsleep>, HW::Error> {
+        debug!("Sleeping segment display");
+        send_commands::<HW>(i2c, &[POWER_OFF]).await?;
+        self.wait_until_idle().await?;
+        send_commands::<HW>(i2c, &[SLEEP_IN]).await?;
+        self.hw
+            .power_off()
+            .await
+            .with_source::<HW>(ErrorSource::Power)?;
+        Ok(Epd1In9Segment {
+            hw: self.hw,
+            state: StateAsleep(),
+        })
+    }
+}
+
+impl<HW> WaitIdle<HW::Error> for Epd1In9Segment<HW, StateReady>
+where
+    HW: BusyHw + ErrorHw,
+    HW::Error: From<<HW::Busy as PinErrorType>::Error>,
+{
+    async fn wait_until_idle(&mut self) -> Result<(), HW::Error> {
+        self.hw.wait_if_busy().await
+    }
+}
+
+impl<HW, STATE: State> Reset<HW::Error> for Epd1In9Segment<HW, STATE>
+where
+    HW: ResetHw + DelayHw + ErrorHw,
+    HW::Error: From<<HW::Reset as PinErrorType>::Error>,
+{
+    type DisplayOut = Epd1In9Segment<HW, StateUninitialized>;
+
+    async fn reset(mut self) -> Result<Self::DisplayOut, HW::Error> {
+        let hw = &mut self.hw;
+        hw.power_on().await.with_source::<HW>(ErrorSource::Power)?;
+        debug!("Resetting segment display");
+        hw.reset()
+            .set_high()
+            .with_source::<HW>(ErrorSource::Reset)?;
+        hw.delay().delay_ms(200).await;
+        hw.reset().set_low().with_source::<HW>(ErrorSource::Reset)?;
+        hw.delay().delay_ms(20).await;
+        hw.reset()
+            .set_high()
+            .with_source::<HW>(ErrorSource::Reset)?;
+        hw.delay().delay_ms(200).await;
+        Ok(Epd1In9Segment::new(self.hw))
+    }
+}
+
+/// Sends each command byte in its own write, as the sample code does.
+async fn send_commands<HW>(i2c: &mut HW::I2c, commands: &[u8]) -> Result<(), HW::Error>
+where
+    HW: I2cHw + ErrorHw,
+    HW::Error: From<<HW::I2c as I2cErrorType>::Error>,
+{
+    trace!("Sending {} segment display commands", commands.len());
+    for command in commands {
+        i2c.write(COMMAND_ADDRESS, &[*command])
+            .await
+            .with_source::<HW>(ErrorSource::I2c)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+    use std::vec::Vec;
+
+    use embedded_hal_async::i2c::Operation;
+
+    use super::*;
+    use crate::testing::{block_on, MockHw};
+
+    /// An I2C bus that records each write, with its address.
+    #[derive(Default)]
+    struct MockI2c {
+        writes: Vec<(u8, Vec<u8>)>,
+    }
+
+    impl I2cErrorType for MockI2c {
+        type Error = Infallible;
+    }
+
+    impl I2c for MockI2c {
+        async fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            for op in operations {
+                match op {
+                    Operation::Write(data) => self.writes.push((address, data.to_vec())),
+                    Operation::Read(buf) => buf.fill(0),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Adds this module's I2C bus to [MockHw], which the touch tests already implement [I2cHw] for.
+    struct SegmentHw(MockHw);
+
+    impl ErrorHw for SegmentHw {
+        type Error = Infallible;
+    }
+
+    impl I2cHw for SegmentHw {
+        type I2c = MockI2c;
+    }
+
+    impl ResetHw for SegmentHw {
+        type Reset = <MockHw as ResetHw>::Reset;
+
+        fn reset(&mut self) -> &mut Self::Reset {
+            self.0.reset()
+        }
+    }
+
+    impl BusyHw for SegmentHw {
+        type Busy = <MockHw as BusyHw>::Busy;
+
+        fn busy(&mut self) -> &mut Self::Busy {
+            self.0.busy()
+        }
+
+        fn busy_when(&self) -> PinState {
+            self.0.busy_when()
+        }
+    }
+
+    impl DelayHw for SegmentHw {
+        type Delay = <MockHw as DelayHw>::Delay;
+
+        fn delay(&mut self) -> &mut Self::Delay {
+            self.0.delay()
+        }
+    }
+
+    fn commands(i2c: &MockI2c) -> Vec<u8> {
+        i2c.writes
+            .iter()
+            .filter(|(address, _)| *address == COMMAND_ADDRESS)
+            .flat_map(|(_, data)| data.iter().copied())
+            .collect()
+    }
+
+    #[test]
+    fn test_segments_show_readings() {
+        let mut segments = Segments::new();
+        segments
+            .set_temperature(1075, TemperatureUnit::Fahrenheit)
+            .unwrap();
+        segments.set_humidity(53).unwrap();
+        assert_eq!(
+            segments.data(),
+            &[
+                0xFF, 0xBF, 0x1F, 0x21, 0x3F, 0xF7, 0x1D, 0x00, 0x00, 0xF7, 0x3D, 0xF5, 0x3F, 0x06,
+                0x00
+            ]
+        );
+
+        segments
+            .set_temperature(85, TemperatureUnit::Celsius)
+            .unwrap();
+        assert_eq!(segments.data()[..5], [0x00, 0x00, 0x00, 0xFF, 0x3F]);
+        assert_eq!(segments.data()[13], 0x05);
+        assert_eq!(
+            segments.set_temperature(2000, TemperatureUnit::Celsius),
+            Err(OutOfRange)
+        );
+        assert_eq!(segments.set_humidity(1000), Err(OutOfRange));
+    }
+
+    #[test]
+    fn test_temperature_compensation() {
+        assert_eq!(frame_time(-5), Duration::from_millis(1000));
+        assert_eq!(frame_time(12), Duration::from_millis(500));
+        assert_eq!(frame_time(25), Duration::from_millis(300));
+
+        let mut i2c = MockI2c::default();
+        let hw = SegmentHw(MockHw::new(DEFAULT_BUSY_WHEN));
+        let mut epd =
+            block_on(Epd1In9Segment::new(hw).init(&mut i2c, RefreshMode::Fast, 3)).unwrap();
+        assert_eq!(
+            commands(&i2c),
+            [
+                POWER_ON,
+                BOOST,
+                TEMPERATURE_SENSING_ON,
+                0x7E,
+                0x81,
+                0xB4,
+                FRAME_TIME,
+                0x31,
+                0x82,
+                0x80,
+                0x00,
+                0xC0,
+                0x80,
+                0x80,
+                0x62
+            ]
+        );
+
+        i2c.writes.clear();
+        block_on(epd.set_temperature(&mut i2c, 22)).unwrap();
+        assert_eq!(commands(&i2c), [0x7B, 0x81, 0xB4, FRAME_TIME, 0x0E]);
+        assert_eq!(epd.temperature(), 22);
+    }
+
+    #[test]
+    fn test_display_writes_data_between_sram_commands() {
+        let mut i2c = MockI2c::default();
+        let hw = SegmentHw(MockHw::new(DEFAULT_BUSY_WHEN));
+        let mut epd =
+            block_on(Epd1In9Segment::new(hw).init(&mut i2c, RefreshMode::Full, 20)).unwrap();
+        i2c.writes.clear();
+
+        block_on(epd.display(&mut i2c, &Segments::all_on())).unwrap();
+        let data_write = i2c
+            .writes
+            .iter()
+            .position(|(address, _)| *address == DATA_ADDRESS)
+            .unwrap();
+        let mut expected_data = std::vec![0xFF; SEGMENT_BYTES];
+        expected_data.push(0x00);
+        assert_eq!(i2c.writes[data_write].1, expected_data);
+        assert_eq!(
+            i2c.writes[data_write - 1],
+            (COMMAND_ADDRESS, std::vec![FIRST_SRAM_OFF])
+        );
+        assert_eq!(
+            i2c.writes[data_write + 1],
+            (COMMAND_ADDRESS, std::vec![SECOND_SRAM_ON])
+        );
+        assert_eq!(
+            commands(&i2c)[5..],
+            [
+                SECOND_SRAM_ON,
+                SECOND_SRAM_OFF,
+                DISPLAY_ON,
+                DISPLAY_OFF,
+                POWER_OFF,
+                SLEEP_IN
+            ]
+        );
+    }
+}
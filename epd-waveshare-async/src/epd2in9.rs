@@ -1,7 +1,19 @@
+// Drivers run on-device, so they must not panic.
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
+
 use core::time::Duration;
 use embedded_graphics::{
     pixelcolor::BinaryColor,
-    prelude::{Point, Size},
+    prelude::{OriginDimensions, Point, Size},
     primitives::Rectangle,
 };
 use embedded_hal::{
@@ -11,10 +23,18 @@ use embedded_hal::{
 use embedded_hal_async::delay::DelayNs;
 
 use crate::{
-    buffer::{binary_buffer_length, split_low_and_high, BinaryBuffer, BufferView},
-    hw::{BusyHw, DcHw, DelayHw, ErrorHw, ResetHw, SpiHw},
-    log::{debug, debug_assert},
-    DisplayPartial, DisplaySimple, Displayable, Reset, Sleep, Wake,
+    buffer::{binary_buffer_length, BinaryBuffer, BufferView, WindowError},
+    command::{command_methods, commands},
+    gray_emulation::WhiteBypass,
+    hw::{
+        BusyHw, BusyWait as _, DcHw, DelayHw, ErrorHw, ErrorSource, PowerProfile, ResetHw, SpiHw,
+        Timings, WithSource as _,
+    },
+    log::debug,
+    refresh::{RefreshKind, SetRefreshKind},
+    self_test::{self, SelfTestReport, SelfTestTarget},
+    ssd16xx, Capabilities, DisplayPartial, DisplaySimple, Displayable, Epd, Reset, Sleep,
+    StateSnapshot, WaitIdle, Wake,
 };
 
 /// LUT for a full refresh. This should be used occasionally for best display results.
@@ -56,6 +76,101 @@ pub enum RefreshMode {
     PartialWhiteBypass,
 }
 
+/// How the border around the display's active area is driven. See [Epd2In9::set_border].
+///
+/// A [BinaryColor] converts into this, with `Off` as [Border::Black] and `On` as [Border::White].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Border {
+    /// Follows the black waveform.
+    Black,
+    /// Follows the white waveform.
+    White,
+    /// Leaves the border floating (high impedance), so refreshes don't change it.
+    HiZ,
+}
+
+impl Border {
+    /// Returns the [Command::BorderWaveformControl] setting.
+    fn register(self) -> u8 {
+        match self {
+            Border::Black => 0x00,
+            Border::White => 0x01,
+            // Fixed level (bit 6), with the level set to Hi-Z (bits 5 and 4).
+            Border::HiZ => 0x70,
+        }
+    }
+}
+
+impl From<BinaryColor> for Border {
+    fn from(color: BinaryColor) -> Self {
+        match color {
+            BinaryColor::Off => Border::Black,
+            BinaryColor::On => Border::White,
+        }
+    }
+}
+
+/// A [LineTiming] setting outside the ranges in the datasheet.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange;
+
+impl core::fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("the line timing is outside the datasheet's ranges")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for OutOfRange {}
+
+/// How the display's gate lines are timed, which sets the frame rate of each refresh. This affects
+/// contrast and refresh artifacts, as the LUTs count their phases in frames. See
+/// [Epd2In9::set_line_timing].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineTiming {
+    dummy_lines: u8,
+    gate_line_width: u8,
+}
+
+impl LineTiming {
+    /// The timing from Waveshare's sample code, which [Epd2In9::init] uses.
+    pub const DEFAULT: LineTiming = LineTiming {
+        dummy_lines: 0x1A,
+        gate_line_width: 0x08,
+    };
+
+    /// Creates a timing with `dummy_lines` (0 to 127) after each frame, and the
+    /// `gate_line_width` setting (0 to 15) for how long each line is driven.
+    pub const fn new(dummy_lines: u8, gate_line_width: u8) -> Result<Self, OutOfRange> {
+        if dummy_lines > 0x7F || gate_line_width > 0x0F {
+            return Err(OutOfRange);
+        }
+        Ok(LineTiming {
+            dummy_lines,
+            gate_line_width,
+        })
+    }
+
+    /// The number of dummy lines after each frame.
+    pub const fn dummy_lines(&self) -> u8 {
+        self.dummy_lines
+    }
+
+    /// The gate line width setting.
+    pub const fn gate_line_width(&self) -> u8 {
+        self.gate_line_width
+    }
+}
+
+impl Default for LineTiming {
+    fn default() -> Self {
+        LineTiming::DEFAULT
+    }
+}
+
 impl RefreshMode {
     /// Returns the LUT to use for this refresh mode.
     pub fn lut(&self) -> &[u8; 30] {
@@ -87,81 +202,78 @@ pub const RECOMMENDED_SPI_POLARITY: Polarity = Polarity::IdleLow;
 /// it's low, but this is incorrect. The sample code treats it as active high, which works.
 pub const DEFAULT_BUSY_WHEN: PinState = PinState::High;
 
-/// Low-level commands for the Epd2In9. You probably want to use the other methods exposed on the
-/// [Epd2In9] for most operations, but can send commands directly with [Epd2In9::send] for low-level
-/// control or experimentation.
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Command {
-    /// Used to initialise the display.
-    DriverOutputControl = 0x01,
-    /// Used to configure the on chip voltage booster and regulator.
-    BoosterSoftStartControl = 0x0C,
-    /// Used to enter deep sleep mode. Requires a hardware reset and reinitialisation to wake up.
-    DeepSleepMode = 0x10,
-    /// Changes the auto-increment behaviour of the address counter.
-    DataEntryModeSetting = 0x11,
-    /// Resets all commands and parameters to default values (except deep sleep mode).
-    SwReset = 0x12,
-    /// Writes to the temperature register.
-    TemperatureSensorControl = 0x1A,
-    /// Activates the display update sequence. This must be set beforehand using [Command::DisplayUpdateControl2].
-    /// This operation must not be interrupted.
-    MasterActivation = 0x20,
-    /// Used for a RAM "bypass" mode when using [RefreshMode::Partial]. This is poorly explained in the docs,
-    /// but essentially we have three options:
-    ///
-    /// 1. `0x00` (default): just update the pixels that have changed **between the two internal
-    ///    frame buffers**. This normally does what you expect. You can hack it a bit to do
-    ///    interesting things by writing to both the old and new frame buffers.
-    /// 2. `0x80`: just update the white (`BinaryColor::On`) pixels in the current frame buffer. It
-    ///    doesn't matter what is in the old frame buffer.
-    /// 3. `0x90`: just update the black (`BinaryColor::Off`) pixels in the current frame buffer.
-    ///    It doesn't matter what is in the old frame buffer.
-    ///
-    /// Options 2 and 3 are what the datasheet calls "bypass" mode.
-    DisplayUpdateControl1 = 0x21,
-    /// Configures the display update sequence for use with [Command::MasterActivation].
-    DisplayUpdateControl2 = 0x22,
-    /// Writes data to the current frame buffer, auto-incrementing the address counter.
-    WriteRam = 0x24,
-    /// Writes data to the old frame buffer, auto-incrementing the address counter.
-    WriteOldRam = 0x26,
-    /// Writes to the VCOM register.
-    WriteVcom = 0x2C,
-    /// Writes the LUT register (30 bytes, exclude the VSH/VSL and dummy bits).
-    WriteLut = 0x32,
-    /// ? Part of magic config.
-    SetDummyLinePeriod = 0x3A,
-    /// ? Part of magic config.
-    SetGateLineWidth = 0x3B,
-    /// Register to configure the behaviour of the border.
-    BorderWaveformControl = 0x3C,
-    /// Sets the start and end positions of the X axis for the auto-incrementing address counter.
-    /// Start and end are inclusive.
-    ///
-    /// Note that the x position can only be written on a whole byte basis (8 bits at once). The
-    /// start and end positions are therefore sent right shifted 3 bits to indicate the byte number
-    /// being written. For example, to write the first 32 x positions, you would send 0 (0 >> 3 =
-    /// 0), and 3 (31 >> 3 = 3). If you tried to write just the first 25 x positions, you would end
-    /// up sending the same values and actually writing all 32.
-    SetRamXStartEnd = 0x44,
-    /// Sets the start and end positions of the Y axis for the auto-incrementing address counter.
-    /// Start and end are inclusive.
-    SetRamYStartEnd = 0x45,
-    /// Sets the current x coordinate of the address counter.
-    /// Note that the x position can only be configured as a multiple of 8.
-    SetRamX = 0x4E,
-    /// Sets the current y coordinate of the address counter.
-    SetRamY = 0x4F,
-    /// Does nothing, but can be used to terminate other commands such as [Command::WriteRam]
-    Noop = 0xFF,
-}
-
-impl Command {
-    /// Returns the register address for this command.
-    fn register(&self) -> u8 {
-        *self as u8
+commands! {
+    /// Low-level commands for the Epd2In9. You probably want to use the other methods exposed on the
+    /// [Epd2In9] for most operations, but can send commands directly with [Epd2In9::send] for low-level
+    /// control or experimentation.
+    pub enum Command {
+        /// Used to initialise the display.
+        DriverOutputControl = 0x01,
+        /// Used to configure the on chip voltage booster and regulator.
+        BoosterSoftStartControl = 0x0C,
+        /// Used to enter deep sleep mode. Requires a hardware reset and reinitialisation to wake up.
+        DeepSleepMode = 0x10,
+        /// Changes the auto-increment behaviour of the address counter.
+        DataEntryModeSetting = 0x11,
+        /// Resets all commands and parameters to default values (except deep sleep mode).
+        SwReset = 0x12,
+        /// Writes to the temperature register.
+        ///
+        /// The driver doesn't use this, and has no `set_external_temperature` like the
+        /// `epd2in9_v2`'s. The temperature only selects a waveform from the panel's OTP, but every
+        /// [RefreshMode] here loads its own LUT, so a written temperature has no effect.
+        TemperatureSensorControl = 0x1A,
+        /// Activates the display update sequence. This must be set beforehand using [Command::DisplayUpdateControl2].
+        /// This operation must not be interrupted.
+        MasterActivation = ssd16xx::Command::MasterActivation as isize,
+        /// Used for a RAM "bypass" mode when using [RefreshMode::Partial]. This is poorly explained in the docs,
+        /// but essentially we have three options:
+        ///
+        /// 1. `0x00` (default): just update the pixels that have changed **between the two internal
+        ///    frame buffers**. This normally does what you expect. You can hack it a bit to do
+        ///    interesting things by writing to both the old and new frame buffers.
+        /// 2. `0x80`: just update the white (`BinaryColor::On`) pixels in the current frame buffer. It
+        ///    doesn't matter what is in the old frame buffer.
+        /// 3. `0x90`: just update the black (`BinaryColor::Off`) pixels in the current frame buffer.
+        ///    It doesn't matter what is in the old frame buffer.
+        ///
+        /// Options 2 and 3 are what the datasheet calls "bypass" mode.
+        DisplayUpdateControl1 = 0x21,
+        /// Configures the display update sequence for use with [Command::MasterActivation].
+        DisplayUpdateControl2 = ssd16xx::Command::DisplayUpdateControl2 as isize,
+        /// Writes data to the current frame buffer, auto-incrementing the address counter.
+        WriteRam = 0x24,
+        /// Writes data to the old frame buffer, auto-incrementing the address counter.
+        WriteOldRam = 0x26,
+        /// Writes to the VCOM register.
+        WriteVcom = 0x2C,
+        /// Writes the LUT register (30 bytes, exclude the VSH/VSL and dummy bits).
+        WriteLut = 0x32,
+        /// Sets the number of dummy lines after each frame. See [LineTiming].
+        SetDummyLinePeriod = 0x3A,
+        /// Sets the time that each gate line is driven for. See [LineTiming].
+        SetGateLineWidth = 0x3B,
+        /// Register to configure the behaviour of the border.
+        BorderWaveformControl = 0x3C,
+        /// Sets the start and end positions of the X axis for the auto-incrementing address counter.
+        /// Start and end are inclusive.
+        ///
+        /// Note that the x position can only be written on a whole byte basis (8 bits at once). The
+        /// start and end positions are therefore sent right shifted 3 bits to indicate the byte number
+        /// being written. For example, to write the first 32 x positions, you would send 0 (0 >> 3 =
+        /// 0), and 3 (31 >> 3 = 3). If you tried to write just the first 25 x positions, you would end
+        /// up sending the same values and actually writing all 32.
+        SetRamXStartEnd = ssd16xx::Command::SetRamXStartEnd as isize,
+        /// Sets the start and end positions of the Y axis for the auto-incrementing address counter.
+        /// Start and end are inclusive.
+        SetRamYStartEnd = ssd16xx::Command::SetRamYStartEnd as isize,
+        /// Sets the current x coordinate of the address counter.
+        /// Note that the x position can only be configured as a multiple of 8.
+        SetRamX = ssd16xx::Command::SetRamX as isize,
+        /// Sets the current y coordinate of the address counter.
+        SetRamY = ssd16xx::Command::SetRamY as isize,
+        /// Does nothing, but can be used to terminate other commands such as [Command::WriteRam]
+        Noop = 0xFF,
     }
 }
 
@@ -191,6 +303,19 @@ const BOOSTER_SOFT_START_INIT_DATA: [u8; 3] = [0xD7, 0xD6, 0x9D];
 // Datasheet:
 // const BOOSTER_SOFT_START_INIT_DATA: [u8; 3] = [0xCF, 0xCE, 0x8D];
 
+/// Returns the [Command::BoosterSoftStartControl] data for `profile`.
+///
+/// Each byte sets the driving strength (bits 4-6) and the minimum off time (bits 0-3) of a soft
+/// start phase. [PowerProfile::LowPeakCurrent] uses the weakest drive and the longest off time,
+/// and [PowerProfile::Fast] a stronger drive than the sample code.
+fn booster_soft_start_data(profile: PowerProfile) -> &'static [u8; 3] {
+    match profile {
+        PowerProfile::LowPeakCurrent => &[0x8F, 0x8F, 0x8F],
+        PowerProfile::Standard => &BOOSTER_SOFT_START_INIT_DATA,
+        PowerProfile::Fast => &[0xF7, 0xF6, 0xAD],
+    }
+}
+
 trait StateInternal {}
 #[allow(private_bounds)]
 pub trait State: StateInternal {}
@@ -225,6 +350,58 @@ pub struct StateAsleep<W: StateAwake> {
 impl<W: StateAwake> StateInternal for StateAsleep<W> {}
 impl<W: StateAwake> State for StateAsleep<W> {}
 
+/// Set in the snapshots of ready states.
+const SNAPSHOT_READY: u32 = 0x10;
+/// Set in the snapshots of asleep states, on top of the snapshot of the state to wake to.
+const SNAPSHOT_ASLEEP: u32 = 0x80;
+
+impl StateSnapshot for StateUninitialized {
+    fn to_snapshot(&self) -> u32 {
+        0
+    }
+
+    fn from_snapshot(snapshot: u32) -> Option<Self> {
+        (snapshot == 0).then_some(StateUninitialized())
+    }
+}
+
+impl StateSnapshot for StateReady {
+    fn to_snapshot(&self) -> u32 {
+        SNAPSHOT_READY
+            | match self.mode {
+                RefreshMode::Full => 0,
+                RefreshMode::Partial => 1,
+                RefreshMode::PartialBlackBypass => 2,
+                RefreshMode::PartialWhiteBypass => 3,
+            }
+    }
+
+    fn from_snapshot(snapshot: u32) -> Option<Self> {
+        let mode = match snapshot.checked_sub(SNAPSHOT_READY)? {
+            0 => RefreshMode::Full,
+            1 => RefreshMode::Partial,
+            2 => RefreshMode::PartialBlackBypass,
+            3 => RefreshMode::PartialWhiteBypass,
+            _ => return None,
+        };
+        Some(StateReady { mode })
+    }
+}
+
+impl<W: StateAwake + StateSnapshot> StateSnapshot for StateAsleep<W> {
+    fn to_snapshot(&self) -> u32 {
+        SNAPSHOT_ASLEEP | self.wake_state.to_snapshot()
+    }
+
+    fn from_snapshot(snapshot: u32) -> Option<Self> {
+        if snapshot & SNAPSHOT_ASLEEP == 0 {
+            return None;
+        }
+        let wake_state = W::from_snapshot(snapshot & !SNAPSHOT_ASLEEP)?;
+        Some(StateAsleep { wake_state })
+    }
+}
+
 /// Controls v1 of the 2.9" Waveshare e-paper display.
 ///
 /// * [datasheet](https://files.waveshare.com/upload/e/e6/2.9inch_e-Paper_Datasheet.pdf)
@@ -232,12 +409,22 @@ impl<W: StateAwake> State for StateAsleep<W> {}
 ///
 /// The display has a portrait orientation. This uses [BinaryColor], where `Off` is black and `On` is white.
 ///
-/// HW should implement [ResetHw], [BusyHw], [DcHw], [SpiHw], [DelayHw], and [ErrorHw].
+/// HW should implement [ResetHw], [BusyHw], [DcHw], [SpiHw], [DelayHw], and [ErrorHw]. Writing
+/// framebuffers also needs [ErrorHw::Error] to implement `From<WindowError>`, for buffers whose
+/// window isn't on the display.
 pub struct Epd2In9<HW, STATE> {
     hw: HW,
     state: STATE,
 }
 
+/// Formats the display's state. The hardware is omitted, as it rarely implements [defmt::Format].
+#[cfg(feature = "defmt")]
+impl<HW, STATE: defmt::Format> defmt::Format for Epd2In9<HW, STATE> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Epd2In9 {{ state: {} }}", self.state)
+    }
+}
+
 impl<HW> Epd2In9<HW, StateUninitialized>
 where
     HW: DcHw + ResetHw + BusyHw + DelayHw + ErrorHw + SpiHw,
@@ -252,6 +439,80 @@ where
             state: StateUninitialized(),
         }
     }
+
+    /// Takes control of a display that is already initialised and awake, without resetting it,
+    /// e.g. after the microcontroller restarts from a watchdog reset.
+    ///
+    /// This doesn't send anything to the display, so the image on it is kept. `mode` must be the
+    /// refresh mode that the display was last set to. If the display might be asleep or in an
+    /// unknown state, use [Epd2In9::init] instead.
+    pub fn attach(hw: HW, mode: RefreshMode) -> Epd2In9<HW, StateReady> {
+        debug!("Attaching to initialised display");
+        Epd2In9 {
+            hw,
+            state: StateReady { mode },
+        }
+    }
+}
+
+impl<HW, STATE: State> Epd2In9<HW, STATE> {
+    /// Splits the driver into its hardware and its state, e.g. to save the state with
+    /// [StateSnapshot::to_snapshot] before the microcontroller enters deep sleep.
+    pub fn into_state_snapshot(self) -> (HW, STATE) {
+        (self.hw, self.state)
+    }
+
+    /// Recreates a driver from its hardware and a state saved with
+    /// [Epd2In9::into_state_snapshot].
+    ///
+    /// This doesn't send anything to the display, so the display must still be in `state`, e.g.
+    /// because it stayed asleep while the microcontroller was powered down.
+    pub fn from_state_snapshot(hw: HW, state: STATE) -> Self {
+        Epd2In9 { hw, state }
+    }
+
+    /// Returns the typical time that a refresh in `mode` keeps the display busy, at room
+    /// temperature. Refreshes take longer in the cold.
+    ///
+    /// Use a [crate::refresh::RefreshEstimate] to refine this from measured refreshes.
+    pub fn estimated_refresh_duration(&self, mode: RefreshMode) -> Duration {
+        match mode {
+            RefreshMode::Full => Duration::from_millis(2000),
+            RefreshMode::Partial
+            | RefreshMode::PartialBlackBypass
+            | RefreshMode::PartialWhiteBypass => Duration::from_millis(300),
+        }
+    }
+
+    /// Returns what the display supports. There's only one full refresh waveform, so it has no
+    /// fast refresh.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            resolution: self.size(),
+            colors: 2,
+            supports_partial: true,
+            supports_fast: false,
+            supports_gray: false,
+            full_refresh: self.estimated_refresh_duration(RefreshMode::Full),
+            fast_refresh: None,
+            partial_refresh: Some(self.estimated_refresh_duration(RefreshMode::Partial)),
+        }
+    }
+}
+
+/// The display's size, in its native orientation, so that layouts can be computed from the driver.
+impl<HW, STATE> OriginDimensions for Epd2In9<HW, STATE> {
+    fn size(&self) -> Size {
+        Size::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32)
+    }
+}
+
+impl<HW: BusyHw, STATE> Epd2In9<HW, STATE> {
+    /// Returns the hardware's timing counters, if it has instrumentation enabled. See
+    /// [BusyHw::timings].
+    pub fn timings(&mut self) -> Option<&mut Timings> {
+        self.hw.timings()
+    }
 }
 
 impl<HW, STATE> Epd2In9<HW, STATE>
@@ -280,7 +541,7 @@ where
         self.send(
             spi,
             Command::BoosterSoftStartControl,
-            &BOOSTER_SOFT_START_INIT_DATA,
+            booster_soft_start_data(self.hw.power_profile()),
         )
         .await?;
         // Auto-increment X and Y, moving in the X direction first.
@@ -290,10 +551,7 @@ where
         // Apply more magical config settings from the sample code.
         // Potentially: configure VCOM for 7 degrees celsius?
         self.send(spi, Command::WriteVcom, &[0xA8]).await?;
-        // Configure 4 dummy lines per gate.
-        self.send(spi, Command::SetDummyLinePeriod, &[0x1A]).await?;
-        // 2us per line.
-        self.send(spi, Command::SetGateLineWidth, &[0x08]).await?;
+        self.set_line_timing(spi, LineTiming::DEFAULT).await?;
 
         let mut epd = Epd2In9 {
             hw: self.hw,
@@ -312,34 +570,37 @@ where
         + From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
 {
-    /// Sets the border to the specified colour. You need to call [Displayable::update_display]
-    /// using [RefreshMode::Full] afterwards to apply this change.
+    /// Sets the border to the specified colour, or [Border::HiZ]. You need to call
+    /// [Displayable::update_display] using [RefreshMode::Full] afterwards to apply this change.
     ///
-    /// Note: on my board, the white setting fades to grey fairly quickly. I have not found a way
-    /// to avoid this.
+    /// The border is kept when the refresh mode changes, until the display is initialised again.
+    ///
+    /// Note: on my board, the white setting fades to grey fairly quickly. [Border::HiZ] stops the
+    /// border being driven after that refresh, which may help.
     pub async fn set_border(
         &mut self,
         spi: &mut HW::Spi,
-        color: BinaryColor,
+        border: impl Into<Border>,
     ) -> Result<(), HW::Error> {
-        let border_setting: u8 = match color {
-            BinaryColor::Off => 0x00,
-            BinaryColor::On => 0x01,
-        };
-        self.send(spi, Command::BorderWaveformControl, &[border_setting])
+        let border: Border = border.into();
+        self.send(spi, Command::BorderWaveformControl, &[border.register()])
             .await
     }
 
-    /// Send the following command and data to the display. Waits until the display is no longer busy before sending.
-    pub async fn send(
+    /// Sets the line timing, e.g. to tune contrast or refresh artifacts. This is kept until the
+    /// display is initialised again.
+    pub async fn set_line_timing(
         &mut self,
         spi: &mut HW::Spi,
-        command: Command,
-        data: &[u8],
+        timing: LineTiming,
     ) -> Result<(), HW::Error> {
-        use crate::hw::CommandDataSend;
-        self.hw.send(spi, command.register(), data).await
+        self.send(spi, Command::SetDummyLinePeriod, &[timing.dummy_lines])
+            .await?;
+        self.send(spi, Command::SetGateLineWidth, &[timing.gate_line_width])
+            .await
     }
+
+    command_methods!(Command);
 }
 
 impl<HW> Epd2In9<HW, StateReady>
@@ -366,57 +627,39 @@ where
 
     /// Sets the window to which the next image data will be written.
     ///
-    /// The x-axis only supports multiples of 8; values outside this result in a debug-mode panic,
-    /// or potentially misaligned content when debug assertions are disabled.
+    /// The x-axis only supports multiples of 8; other values are rounded out to whole bytes, which
+    /// will lead to misaligned content. Use [crate::buffer::align_window] to align an area first.
+    ///
+    /// Together with [Epd2In9::set_cursor] and [Epd2In9::send], this streams your own data into a
+    /// window, e.g. straight from a decompressor. The address counter carries on between writes,
+    /// so the data can be sent in chunks of whole rows or bytes:
+    ///
+    /// ```text
+    /// epd.set_window(&mut spi, window).await?;
+    /// epd.set_cursor(&mut spi, window.top_left).await?;
+    /// while let Some(chunk) = decompressor.next_chunk() {
+    ///     epd.send(&mut spi, Command::WriteRam, chunk).await?;
+    /// }
+    /// epd.update_display(&mut spi).await?;
+    /// ```
     pub async fn set_window(
         &mut self,
         spi: &mut HW::Spi,
         shape: Rectangle,
     ) -> Result<(), HW::Error> {
-        // Use a debug assert as this is a soft failure in production; it will just lead to
-        // slightly misaligned display content.
-        let x_start = shape.top_left.x;
-        let x_end = x_start + shape.size.width as i32 - 1;
-        debug_assert!(
-            x_start % 8 == 0 && x_end % 8 == 7,
-            "window's top_left.x and width must be 8-bit aligned"
-        );
-        let x_start_byte = ((x_start >> 3) & 0xFF) as u8;
-        let x_end_byte = ((x_end >> 3) & 0xFF) as u8;
-        self.send(spi, Command::SetRamXStartEnd, &[x_start_byte, x_end_byte])
-            .await?;
-
-        let (y_start_low, y_start_high) = split_low_and_high(shape.top_left.y as u16);
-        let (y_end_low, y_end_high) =
-            split_low_and_high((shape.top_left.y + shape.size.height as i32 - 1) as u16);
-        self.send(
-            spi,
-            Command::SetRamYStartEnd,
-            &[y_start_low, y_start_high, y_end_low, y_end_high],
-        )
-        .await?;
-
-        Ok(())
+        ssd16xx::set_window(&mut self.hw, spi, shape).await
     }
 
     /// Sets the cursor position to write the next data to.
     ///
-    /// The x-axis only supports multiples of 8; values outside this will result in a panic in
-    /// debug mode, or potentially misaligned content if debug assertions are disabled.
+    /// The x-axis only supports multiples of 8; other values are rounded down, which will lead to
+    /// misaligned content.
     pub async fn set_cursor(
         &mut self,
         spi: &mut HW::Spi,
         position: Point,
     ) -> Result<(), HW::Error> {
-        // Use a debug assert as this is a soft failure in production; it will just lead to
-        // slightly misaligned display content.
-        debug_assert_eq!(position.x % 8, 0, "position.x must be 8-bit aligned");
-
-        self.send(spi, Command::SetRamX, &[(position.x >> 3) as u8])
-            .await?;
-        let (y_low, y_high) = split_low_and_high(position.y as u16);
-        self.send(spi, Command::SetRamY, &[y_low, y_high]).await?;
-        Ok(())
+        ssd16xx::set_cursor(&mut self.hw, spi, position).await
     }
 
     async fn set_refresh_mode_impl(
@@ -446,6 +689,16 @@ where
     }
 }
 
+impl<HW, STATE: StateAwake> WaitIdle<HW::Error> for Epd2In9<HW, STATE>
+where
+    HW: BusyHw + ErrorHw,
+    HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>,
+{
+    async fn wait_until_idle(&mut self) -> Result<(), HW::Error> {
+        self.hw.wait_if_busy().await
+    }
+}
+
 impl<HW> Displayable<HW::Spi, HW::Error> for Epd2In9<HW, StateReady>
 where
     HW: DcHw + BusyHw + DelayHw + ErrorHw + SpiHw,
@@ -466,9 +719,7 @@ where
         // [Command::DisplayUpdateControl1], but the precise mode is unclear.
         debug!("Updating display");
 
-        self.send(spi, Command::DisplayUpdateControl2, &[0xC4])
-            .await?;
-        self.send(spi, Command::MasterActivation, &[]).await?;
+        ssd16xx::activate(&mut self.hw, spi, &[0xC4]).await?;
         self.send(spi, Command::Noop, &[]).await?;
         Ok(())
     }
@@ -479,35 +730,169 @@ where
     HW: DcHw + BusyHw + DelayHw + ErrorHw + SpiHw,
     HW::Error: From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
-        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>
+        + From<WindowError>,
 {
-    async fn display_framebuffer(
+    async fn write_framebuffer(
         &mut self,
         spi: &mut HW::Spi,
         buf: &dyn BufferView<1, 1>,
     ) -> Result<(), HW::Error> {
-        self.write_framebuffer(spi, buf).await?;
-        self.update_display(spi).await
+        let display = Size::new(DISPLAY_WIDTH.into(), DISPLAY_HEIGHT.into());
+        let buffer_bounds = ssd16xx::checked_window(buf, display)?;
+        ssd16xx::write_ram(
+            &mut self.hw,
+            spi,
+            Command::WriteRam.register(),
+            buffer_bounds,
+            buf.data()[0],
+        )
+        .await
     }
+}
 
-    async fn write_framebuffer(
+impl<HW> SetRefreshKind<HW::Spi, HW::Error> for Epd2In9<HW, StateReady>
+where
+    HW: DcHw + BusyHw + DelayHw + ErrorHw + SpiHw,
+    HW::Error: From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
+{
+    async fn set_refresh_kind(
         &mut self,
         spi: &mut HW::Spi,
-        buf: &dyn BufferView<1, 1>,
+        kind: RefreshKind,
     ) -> Result<(), HW::Error> {
-        let buffer_bounds = buf.window();
-        self.set_window(spi, buffer_bounds).await?;
-        self.set_cursor(spi, buffer_bounds.top_left).await?;
-        self.send(spi, Command::WriteRam, buf.data()[0]).await
+        self.set_refresh_mode(spi, kind.into()).await
     }
 }
 
-impl<HW> DisplayPartial<1, 1, HW::Spi, HW::Error> for Epd2In9<HW, StateReady>
+/// There's only one full refresh waveform, so [RefreshKind::Fast] and [RefreshKind::Full] both
+/// use [RefreshMode::Full].
+impl From<RefreshKind> for RefreshMode {
+    fn from(kind: RefreshKind) -> Self {
+        match kind {
+            RefreshKind::Partial => RefreshMode::Partial,
+            RefreshKind::Fast | RefreshKind::Full => RefreshMode::Full,
+        }
+    }
+}
+
+impl<HW> Epd<HW::Spi, HW::Error> for Epd2In9<HW, StateUninitialized>
+where
+    HW: DcHw + ResetHw + BusyHw + DelayHw + ErrorHw + SpiHw,
+    HW::Error: From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Reset as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>
+        + From<WindowError>,
+{
+    const SIZE: Size = Size::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32);
+
+    type Color = BinaryColor;
+    type Buffer = Epd2In9Buffer;
+    type Ready = Epd2In9<HW, StateReady>;
+    type Asleep = Epd2In9<HW, StateAsleep<StateReady>>;
+
+    fn new_buffer() -> Self::Buffer {
+        new_buffer()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Epd2In9::capabilities(self)
+    }
+
+    async fn init(self, spi: &mut HW::Spi, kind: RefreshKind) -> Result<Self::Ready, HW::Error> {
+        Epd2In9::init(self, spi, kind.into()).await
+    }
+}
+
+impl<HW> Epd2In9<HW, StateReady>
+where
+    HW: DcHw + BusyHw + DelayHw + ErrorHw + SpiHw,
+    HW::Error: From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>
+        + From<WindowError>,
+{
+    /// Runs a [self test](crate::self_test), pausing for `pause_ms` after each step so the
+    /// result can be checked by eye.
+    ///
+    /// The display is left white, in [RefreshMode::Full].
+    pub async fn self_test(
+        &mut self,
+        spi: &mut HW::Spi,
+        pause_ms: u32,
+    ) -> Result<SelfTestReport, HW::Error> {
+        let display = Size::new(DISPLAY_WIDTH.into(), DISPLAY_HEIGHT.into());
+        self_test::run(self, spi, display, pause_ms).await
+    }
+}
+
+impl<HW> SelfTestTarget<HW::Spi, HW::Error> for Epd2In9<HW, StateReady>
+where
+    HW: DcHw + BusyHw + DelayHw + ErrorHw + SpiHw,
+    HW::Error: From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>
+        + From<WindowError>,
+{
+    async fn set_test_border(
+        &mut self,
+        spi: &mut HW::Spi,
+        color: BinaryColor,
+    ) -> Result<(), HW::Error> {
+        self.set_border(spi, color).await
+    }
+
+    /// Like [Displayable::update_display], but checks the busy pin before the trailing no-op
+    /// waits for the refresh to finish.
+    async fn start_test_refresh(&mut self, spi: &mut HW::Spi) -> Result<bool, HW::Error> {
+        self.send(spi, Command::DisplayUpdateControl2, &[0xC4])
+            .await?;
+        self.send(spi, Command::MasterActivation, &[]).await?;
+        let went_busy = self.hw.is_busy()?;
+        self.send(spi, Command::Noop, &[]).await?;
+        Ok(went_busy)
+    }
+
+    fn test_timings(&mut self) -> Option<&mut Timings> {
+        self.hw.timings()
+    }
+
+    async fn pause(&mut self, ms: u32) {
+        self.hw.delay().delay_ms(ms).await;
+    }
+}
+
+impl<HW> WhiteBypass<HW::Spi, HW::Error> for Epd2In9<HW, StateReady>
 where
     HW: DcHw + BusyHw + DelayHw + ErrorHw + SpiHw,
     HW::Error: From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
+{
+    async fn set_white_bypass(
+        &mut self,
+        spi: &mut HW::Spi,
+        enabled: bool,
+    ) -> Result<(), HW::Error> {
+        let mode = if enabled {
+            RefreshMode::PartialWhiteBypass
+        } else {
+            RefreshMode::Partial
+        };
+        self.set_refresh_mode(spi, mode).await
+    }
+}
+
+impl<HW> DisplayPartial<1, 1, HW::Spi, HW::Error> for Epd2In9<HW, StateReady>
+where
+    HW: DcHw + BusyHw + DelayHw + ErrorHw + SpiHw,
+    HW::Error: From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>
+        + From<WindowError>,
 {
     /// Writes buffer data into the old internal framebuffer. This can be useful either:
     ///
@@ -519,10 +904,16 @@ where
         spi: &mut HW::Spi,
         buf: &dyn BufferView<1, 1>,
     ) -> Result<(), HW::Error> {
-        let buffer_bounds = buf.window();
-        self.set_window(spi, buffer_bounds).await?;
-        self.set_cursor(spi, buffer_bounds.top_left).await?;
-        self.send(spi, Command::WriteOldRam, buf.data()[0]).await
+        let display = Size::new(DISPLAY_WIDTH.into(), DISPLAY_HEIGHT.into());
+        let buffer_bounds = ssd16xx::checked_window(buf, display)?;
+        ssd16xx::write_ram(
+            &mut self.hw,
+            spi,
+            Command::WriteOldRam.register(),
+            buffer_bounds,
+            buf.data()[0],
+        )
+        .await
     }
 }
 
@@ -531,11 +922,14 @@ where
     HW: ResetHw + DelayHw + ErrorHw,
     HW::Error: From<<HW::Reset as embedded_hal::digital::ErrorType>::Error>,
 {
+    hw.power_on().await.with_source::<HW>(ErrorSource::Power)?;
     debug!("Resetting EPD");
     // Assume reset is already high.
-    hw.reset().set_low()?;
+    hw.reset().set_low().with_source::<HW>(ErrorSource::Reset)?;
     hw.delay().delay_ms(10).await;
-    hw.reset().set_high()?;
+    hw.reset()
+        .set_high()
+        .with_source::<HW>(ErrorSource::Reset)?;
     hw.delay().delay_ms(10).await;
     Ok(())
 }
@@ -573,7 +967,7 @@ where
 
 impl<HW, STATE> Sleep<HW::Spi, HW::Error> for Epd2In9<HW, STATE>
 where
-    HW: DcHw + BusyHw + ErrorHw + SpiHw,
+    HW: DcHw + BusyHw + ResetHw + ErrorHw + SpiHw,
     HW::Error: From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
@@ -585,6 +979,10 @@ where
 where {
         debug!("Sleeping EPD");
         self.send(spi, Command::DeepSleepMode, &[0x01]).await?;
+        self.hw
+            .power_off()
+            .await
+            .with_source::<HW>(ErrorSource::Power)?;
         Ok(Epd2In9 {
             hw: self.hw,
             state: StateAsleep {
@@ -610,3 +1008,172 @@ where
         // Confirmed with a physical screen that init is not required after waking.
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::Bitmap,
+        testing::{block_on, Event, MockError, MockHw, PoweredHw},
+    };
+
+    #[test]
+    fn test_border_hi_z_is_kept_across_refresh_modes() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        log.take_events();
+
+        block_on(epd.set_border(&mut spi, Border::HiZ)).unwrap();
+        block_on(epd.set_refresh_mode(&mut spi, RefreshMode::Partial)).unwrap();
+        let events = log.take_events();
+        assert_eq!(
+            events[..2],
+            [
+                Event::Command(Command::BorderWaveformControl as u8),
+                Event::Data(std::vec![0x70])
+            ]
+        );
+        assert!(!events[2..].contains(&Event::Command(Command::BorderWaveformControl as u8)));
+    }
+
+    #[test]
+    fn test_line_timing_is_validated() {
+        assert_eq!(LineTiming::new(0x80, 0x08), Err(OutOfRange));
+        assert_eq!(LineTiming::new(0x1A, 0x10), Err(OutOfRange));
+        let timing = LineTiming::new(0x7F, 0x0F).unwrap();
+
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        log.take_events();
+        block_on(epd.set_line_timing(&mut spi, timing)).unwrap();
+        assert_eq!(
+            log.take_events(),
+            [
+                Event::Command(Command::SetDummyLinePeriod as u8),
+                Event::Data(std::vec![0x7F]),
+                Event::Command(Command::SetGateLineWidth as u8),
+                Event::Data(std::vec![0x0F]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_attach_keeps_the_display_state() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = Epd2In9::attach(hw, RefreshMode::Partial);
+        assert!(log.take_events().is_empty());
+
+        let buffer = new_buffer();
+        block_on(epd.display_framebuffer(&mut spi, &buffer)).unwrap();
+        assert!(!log.commands().contains(&(Command::SwReset as u8)));
+        assert_eq!(
+            epd.into_state_snapshot().1,
+            StateReady {
+                mode: RefreshMode::Partial
+            }
+        );
+    }
+
+    #[test]
+    fn test_state_snapshot_round_trips() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let mut spi = hw.spi();
+        let epd = Epd2In9::attach(hw, RefreshMode::PartialWhiteBypass);
+        let epd = block_on(epd.sleep(&mut spi)).unwrap();
+
+        let (hw, state) = epd.into_state_snapshot();
+        let snapshot = state.to_snapshot();
+        assert_eq!(snapshot, 0x93);
+        assert_eq!(StateReady::from_snapshot(snapshot), None);
+        assert_eq!(StateReady::from_snapshot(0x14), None);
+        let state = StateAsleep::<StateReady>::from_snapshot(snapshot).unwrap();
+        let epd = Epd2In9::from_state_snapshot(hw, state);
+        let epd = block_on(epd.wake(&mut spi)).unwrap();
+        assert_eq!(
+            epd.into_state_snapshot().1,
+            StateReady {
+                mode: RefreshMode::PartialWhiteBypass
+            }
+        );
+    }
+
+    #[test]
+    fn test_runs_power_hooks_around_reset_and_sleep() {
+        let hw = PoweredHw::new(MockHw::new(DEFAULT_BUSY_WHEN));
+        let log = hw.log();
+        let mut spi = hw.spi();
+
+        let epd = block_on(Epd2In9::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        let epd = block_on(epd.sleep(&mut spi)).unwrap();
+        let epd = block_on(epd.wake(&mut spi)).unwrap();
+
+        let events = log.events();
+        let hw = epd.into_state_snapshot().0;
+        // Powered on before both resets, before any reset pin changes.
+        assert_eq!(hw.power_ons().len(), 2);
+        assert_eq!(hw.power_ons()[0], 0);
+        assert!(matches!(events[hw.power_ons()[1]], Event::Reset(_)));
+        // Powered off straight after the deep sleep command.
+        assert_eq!(hw.power_offs().len(), 1);
+        assert_eq!(
+            events[hw.power_offs()[0] - 2],
+            Event::Command(Command::DeepSleepMode as u8)
+        );
+        assert_eq!(hw.power_offs()[0], hw.power_ons()[1]);
+    }
+
+    #[test]
+    fn test_starts_the_booster_for_the_power_profile() {
+        let booster_data = |profile| {
+            let hw = PoweredHw::new(MockHw::new(DEFAULT_BUSY_WHEN)).with_power_profile(profile);
+            let log = hw.log();
+            let mut spi = hw.spi();
+            block_on(Epd2In9::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+            let events = log.events();
+            let booster = events
+                .iter()
+                .position(|e| *e == Event::Command(Command::BoosterSoftStartControl as u8))
+                .unwrap();
+            events[booster + 1].clone()
+        };
+
+        assert_eq!(
+            booster_data(PowerProfile::Standard),
+            Event::Data(BOOSTER_SOFT_START_INIT_DATA.to_vec())
+        );
+        assert_eq!(
+            booster_data(PowerProfile::LowPeakCurrent),
+            Event::Data(std::vec![0x8F, 0x8F, 0x8F])
+        );
+        assert_eq!(
+            booster_data(PowerProfile::Fast),
+            Event::Data(std::vec![0xF7, 0xF6, 0xAD])
+        );
+    }
+
+    #[test]
+    fn test_invalid_windows_are_rejected() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = Epd2In9::attach(hw, RefreshMode::Full);
+
+        let empty = Bitmap::new(&[], Size::new(0, 8)).unwrap();
+        assert_eq!(
+            block_on(epd.write_framebuffer(&mut spi, &empty)),
+            Err(MockError::Window(WindowError::Empty))
+        );
+        let too_wide = Bitmap::new(&[0; 17], Size::new(DISPLAY_WIDTH as u32 + 8, 1)).unwrap();
+        assert_eq!(
+            block_on(epd.write_base_framebuffer(&mut spi, &too_wide)),
+            Err(MockError::Window(WindowError::OutOfBounds))
+        );
+        assert!(log.events().is_empty());
+    }
+}
@@ -1,6 +1,19 @@
+// Drivers run on-device, so they must not panic.
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
+
 use core::time::Duration;
 use embedded_graphics::{
-    prelude::{Point, Size},
+    pixelcolor::{BinaryColor, Gray2, GrayColor},
+    prelude::{OriginDimensions, Point, Size, Transform},
     primitives::Rectangle,
 };
 use embedded_hal::{
@@ -10,14 +23,22 @@ use embedded_hal::{
 use embedded_hal_async::delay::DelayNs;
 
 use crate::{
-    buffer::{
-        binary_buffer_length, split_low_and_high, BinaryBuffer, BufferView, Gray2SplitBuffer,
+    buffer::{binary_buffer_length, BinaryBuffer, BufferView, Gray2SplitBuffer, WindowError},
+    command::{command_methods, commands},
+    gray_emulation::WhiteBypass,
+    hw::{
+        BusyHw, BusyWait as _, CommandDataSend as _, DcHw, DelayHw, ErrorHw, ErrorSource,
+        PowerProfile, ResetHw, SpiHw, Timings, WithSource as _,
     },
-    hw::{BusyHw, CommandDataSend as _, DcHw, DelayHw, ErrorHw, ResetHw, SpiHw},
-    log::{debug, debug_assert},
-    DisplayPartial, DisplaySimple, Displayable, Reset, Sleep, Wake,
+    log::{debug, warning},
+    otp::{self, Confirmed, OtpStep, OtpTarget, VcomOtp, VcomReadBack, WaveformOtp},
+    refresh::{self, RefreshKind, SetRefreshKind},
+    self_test::{self, SelfTestReport, SelfTestTarget},
+    ssd16xx, Capabilities, DisplayPartial, DisplaySimple, Displayable, Epd, Reset, Sleep,
+    StateSnapshot, WaitIdle, Wake,
 };
 
+#[cfg(feature = "epd2in9_v2-full-slow")]
 const LUT_FULL_SLOW_UPDATE: [u8; 153] = [
     0x80, 0x66, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x40, 0x0, 0x0, 0x0, 0x10, 0x66, 0x0, 0x0, 0x0, 0x0,
     0x0, 0x0, 0x20, 0x0, 0x0, 0x0, 0x80, 0x66, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x40, 0x0, 0x0, 0x0,
@@ -30,9 +51,13 @@ const LUT_FULL_SLOW_UPDATE: [u8; 153] = [
     0x44, 0x44, 0x0, 0x0, 0x0,
     // 0x22,	0x17,	0x41,	0x0,	0x32,	0x36
 ];
+#[cfg(feature = "epd2in9_v2-full-slow")]
 const LUT_MAGIC_FULL_SLOW_UPDATE: [u8; 1] = [0x22];
+#[cfg(feature = "epd2in9_v2-full-slow")]
 const GATE_VOLTAGE_FULL_SLOW_UPDATE: [u8; 1] = [0x17];
+#[cfg(feature = "epd2in9_v2-full-slow")]
 const SOURCE_VOLTAGE_FULL_SLOW_UPDATE: [u8; 3] = [0x41, 0x0, 0x32];
+#[cfg(feature = "epd2in9_v2-full-slow")]
 const VCOM_FULL_SLOW_UPDATE: [u8; 1] = [0x36];
 
 const LUT_FULL_UPDATE: [u8; 153] = [
@@ -66,6 +91,7 @@ const LUT_MAGIC_PARTIAL_UPDATE: [u8; 1] = [0x22];
 const GATE_VOLTAGE_PARTIAL_UPDATE: [u8; 1] = [0x17];
 const SOURCE_VOLTAGE_PARTIAL_UPDATE: [u8; 3] = [0x41, 0xB0, 0x32];
 const VCOM_PARTIAL_UPDATE: [u8; 1] = [0x36];
+#[cfg(feature = "epd2in9_v2-gray2")]
 const LUT_GRAY2: [u8; 153] = [
     0x00, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x60, 0x10, 0x00,
     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x28, 0x60, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -78,14 +104,37 @@ const LUT_GRAY2: [u8; 153] = [
     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
     0x24, 0x22, 0x22, 0x22, 0x23, 0x32, 0x00, 0x00, 0x00,
 ];
+#[cfg(feature = "epd2in9_v2-gray2")]
 const LUT_MAGIC_GRAY2: [u8; 1] = [0x22];
+#[cfg(feature = "epd2in9_v2-gray2")]
 const GATE_VOLTAGE_GRAY2: [u8; 1] = [0x17];
+#[cfg(feature = "epd2in9_v2-gray2")]
 const SOURCE_VOLTAGE_GRAY2: [u8; 3] = [0x41, 0xAE, 0x32];
+#[cfg(feature = "epd2in9_v2-gray2")]
 const VCOM_GRAY2: [u8; 1] = [0x28];
 
+/// Returns the [Command::BoosterSoftStartControl] data for `profile`, or `None` to keep the
+/// power-on default, as the sample code does.
+///
+/// The first three bytes set the driving strength (bits 4-6) and the minimum off time (bits 0-3)
+/// of each soft start phase, and the last byte sets their durations.
+/// [PowerProfile::LowPeakCurrent] uses the weakest drive with the longest off times and
+/// durations, and [PowerProfile::Fast] a stronger drive with the shortest durations.
+fn booster_soft_start_data(profile: PowerProfile) -> Option<&'static [u8; 4]> {
+    match profile {
+        PowerProfile::LowPeakCurrent => Some(&[0x8F, 0x8F, 0x8F, 0x3F]),
+        PowerProfile::Standard => None,
+        PowerProfile::Fast => Some(&[0xCB, 0xCC, 0xC6, 0x00]),
+    }
+}
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// The refresh mode for the display.
+///
+/// [RefreshMode::FullSlow] and [RefreshMode::Gray2] need the `epd2in9_v2-full-slow` and
+/// `epd2in9_v2-gray2` features, which `all-displays` enables. Leave them out to keep their LUTs out
+/// of flash.
 pub enum RefreshMode {
     /// Use the full update LUT. This is slower than [RefreshMode::Partial], but should be done
     /// occasionally to avoid ghosting. If ghosting persists, try [RefreshMode::FullSlow].
@@ -98,6 +147,7 @@ pub enum RefreshMode {
     ///
     /// It's recommended to avoid full refreshes less than [RECOMMENDED_MIN_FULL_REFRESH_INTERVAL] apart,
     /// but to do a full refresh at least every [RECOMMENDED_MAX_FULL_REFRESH_INTERVAL].
+    #[cfg(feature = "epd2in9_v2-full-slow")]
     FullSlow,
     /// Uses the partial update LUT for fast refresh. A full refresh should be done occasionally to
     /// avoid ghosting, see [RECOMMENDED_MAX_FULL_REFRESH_INTERVAL].
@@ -109,7 +159,18 @@ pub enum RefreshMode {
     /// we use `Gray2` to align with the embedded-graphics color [embedded_graphics::pixelcolor::Gray2].
     ///
     /// There is no partial update version for Gray2. All updates require writing to both on-device framebuffers.
+    #[cfg(feature = "epd2in9_v2-gray2")]
     Gray2,
+    /// A full refresh with the waveform stored in the panel's OTP, instead of a LUT from this
+    /// driver. The controller picks the waveform for the temperature from its internal sensor
+    /// before each refresh.
+    ///
+    /// This is what the vendor recommends for some panel revisions, whose OTP waveforms suit them
+    /// better than the sample code's LUTs. See also [crate::otp] for programming your own.
+    ///
+    /// The LUTs of [RefreshMode::Full] and [RefreshMode::Partial] are always built in, so using
+    /// only this mode doesn't save flash.
+    Otp,
 }
 
 impl RefreshMode {
@@ -117,18 +178,25 @@ impl RefreshMode {
     pub fn border_waveform(&self) -> Option<&[u8]> {
         match self {
             RefreshMode::Full => Some(&[0x05]),
+            #[cfg(feature = "epd2in9_v2-full-slow")]
             RefreshMode::FullSlow => None,
             RefreshMode::Partial => Some(&[0x80]),
+            RefreshMode::Otp => Some(&[0x05]),
+            #[cfg(feature = "epd2in9_v2-gray2")]
             RefreshMode::Gray2 => Some(&[0x04]),
         }
     }
 
-    /// Returns the LUT to use for this refresh mode.
+    /// Returns the LUT to use for this refresh mode. This, and the voltages below, are empty for
+    /// [RefreshMode::Otp], which loads them from the OTP.
     pub fn lut(&self) -> &[u8] {
         match self {
             RefreshMode::Full => &LUT_FULL_UPDATE,
+            #[cfg(feature = "epd2in9_v2-full-slow")]
             RefreshMode::FullSlow => &LUT_FULL_SLOW_UPDATE,
             RefreshMode::Partial => &LUT_PARTIAL_UPDATE,
+            RefreshMode::Otp => &[],
+            #[cfg(feature = "epd2in9_v2-gray2")]
             RefreshMode::Gray2 => &LUT_GRAY2,
         }
     }
@@ -136,8 +204,11 @@ impl RefreshMode {
     pub fn lut_magic(&self) -> &[u8] {
         match self {
             RefreshMode::Full => &LUT_MAGIC_FULL_UPDATE,
+            #[cfg(feature = "epd2in9_v2-full-slow")]
             RefreshMode::FullSlow => &LUT_MAGIC_FULL_SLOW_UPDATE,
             RefreshMode::Partial => &LUT_MAGIC_PARTIAL_UPDATE,
+            RefreshMode::Otp => &[],
+            #[cfg(feature = "epd2in9_v2-gray2")]
             RefreshMode::Gray2 => &LUT_MAGIC_GRAY2,
         }
     }
@@ -145,8 +216,11 @@ impl RefreshMode {
     pub fn gate_voltage(&self) -> &[u8] {
         match self {
             RefreshMode::Full => &GATE_VOLTAGE_FULL_UPDATE,
+            #[cfg(feature = "epd2in9_v2-full-slow")]
             RefreshMode::FullSlow => &GATE_VOLTAGE_FULL_SLOW_UPDATE,
             RefreshMode::Partial => &GATE_VOLTAGE_PARTIAL_UPDATE,
+            RefreshMode::Otp => &[],
+            #[cfg(feature = "epd2in9_v2-gray2")]
             RefreshMode::Gray2 => &GATE_VOLTAGE_GRAY2,
         }
     }
@@ -154,8 +228,11 @@ impl RefreshMode {
     pub fn source_voltage(&self) -> &[u8] {
         match self {
             RefreshMode::Full => &SOURCE_VOLTAGE_FULL_UPDATE,
+            #[cfg(feature = "epd2in9_v2-full-slow")]
             RefreshMode::FullSlow => &SOURCE_VOLTAGE_FULL_SLOW_UPDATE,
             RefreshMode::Partial => &SOURCE_VOLTAGE_PARTIAL_UPDATE,
+            RefreshMode::Otp => &[],
+            #[cfg(feature = "epd2in9_v2-gray2")]
             RefreshMode::Gray2 => &SOURCE_VOLTAGE_GRAY2,
         }
     }
@@ -163,8 +240,11 @@ impl RefreshMode {
     pub fn vcom(&self) -> &[u8] {
         match self {
             RefreshMode::Full => &VCOM_FULL_UPDATE,
+            #[cfg(feature = "epd2in9_v2-full-slow")]
             RefreshMode::FullSlow => &VCOM_FULL_SLOW_UPDATE,
             RefreshMode::Partial => &VCOM_PARTIAL_UPDATE,
+            RefreshMode::Otp => &[],
+            #[cfg(feature = "epd2in9_v2-gray2")]
             RefreshMode::Gray2 => &VCOM_GRAY2,
         }
     }
@@ -176,13 +256,94 @@ impl RefreshMode {
             // analog. These are already enabled elsewhere in the sample code, but we do a slightly
             // different set up.
             RefreshMode::Partial => &[0xCF],
+            // Also load the temperature and the waveform for it from the OTP.
+            RefreshMode::Otp => &[0xF7],
             _ => &[0xC7],
         }
     }
 
     /// If this refresh mode is black and white only.
     pub fn is_black_and_white(&self) -> bool {
-        *self != RefreshMode::Gray2
+        match self {
+            #[cfg(feature = "epd2in9_v2-gray2")]
+            RefreshMode::Gray2 => false,
+            _ => true,
+        }
+    }
+}
+
+/// How the border around the display's active area is driven. See [Epd2In9V2::set_border].
+///
+/// A [BinaryColor] converts into this, with `Off` as [Border::Black] and `On` as [Border::White],
+/// and a [Gray2] into [Border::Gray].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Border {
+    /// Follows the black waveform. Valid in every refresh mode.
+    ///
+    /// [RefreshMode::Partial] chooses each pixel's waveform from its previous value too, so this
+    /// follows a pixel that stays black.
+    Black,
+    /// Follows the white waveform. Valid in every refresh mode.
+    ///
+    /// In [RefreshMode::Partial], this follows a pixel that turns from black to white, so it
+    /// drives the border towards white with every partial refresh.
+    White,
+    /// Follows the waveform of pixels of the given gray level, so that the border matches them.
+    ///
+    /// Only [RefreshMode::Gray2] has a waveform for each level. The other modes follow the black
+    /// waveform for levels 0 and 1, and the white waveform for levels 2 and 3.
+    Gray(Gray2),
+    /// Holds the border at VCOM, so that refreshes don't change it. This is what
+    /// [RefreshMode::Partial] uses, and it's valid in every mode.
+    Vcom,
+    /// Leaves the border floating (high impedance), so refreshes don't change it. Valid in every
+    /// mode.
+    HiZ,
+}
+
+impl Border {
+    /// Returns the [Command::SetBorderWaveform] setting for refreshes in `mode`.
+    fn register(self, mode: RefreshMode) -> u8 {
+        // Bit 2 follows the LUT chosen by bits 1 and 0, which is the pixels' value in RAM.
+        let lut = |level: u8| 0x04 | level;
+        match (self, mode.is_black_and_white()) {
+            (Border::Black, _) => lut(0),
+            (Border::White, true) => lut(1),
+            (Border::White, false) => lut(3),
+            (Border::Gray(gray), true) => lut(gray.luma() >> 1),
+            (Border::Gray(gray), false) => lut(gray.luma()),
+            // Bits 7 and 6 select VCOM or high impedance instead of a LUT.
+            (Border::Vcom, _) => 0x80,
+            (Border::HiZ, _) => 0xC0,
+        }
+    }
+}
+
+impl From<BinaryColor> for Border {
+    fn from(color: BinaryColor) -> Self {
+        match color {
+            BinaryColor::Off => Border::Black,
+            BinaryColor::On => Border::White,
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Border {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Border::Black => defmt::write!(f, "Black"),
+            Border::White => defmt::write!(f, "White"),
+            Border::Gray(gray) => defmt::write!(f, "Gray({})", gray.luma()),
+            Border::Vcom => defmt::write!(f, "Vcom"),
+            Border::HiZ => defmt::write!(f, "HiZ"),
+        }
+    }
+}
+
+impl From<Gray2> for Border {
+    fn from(gray: Gray2) -> Self {
+        Border::Gray(gray)
     }
 }
 
@@ -204,120 +365,127 @@ pub const RECOMMENDED_SPI_POLARITY: Polarity = Polarity::IdleLow;
 /// The default pin state that indicates the display is busy.
 pub const DEFAULT_BUSY_WHEN: PinState = PinState::High;
 
-/// Low-level commands for the Epd2In9 v2 display. You probably want to use the other methods
-/// exposed on the [Epd2In9V2] for most operations, but can send commands directly with [Epd2In9V2::send] for low-level
-/// control or experimentation.
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Command {
-    /// Used to initialise the display.
-    DriverOutputControl = 0x01,
-    /// Sets the gate driving voltage (standard value: 0x00, or 0x17).
-    SetGateDrivingVoltage = 0x03,
-    /// Sets the source driving voltage (standard value: [0x41, 0xA8, 0x32]).
-    SetSourceDrivingVoltage = 0x04,
-    /// Used to enter deep sleep mode. Requires a hardware reset and reinitialisation to wake up.
-    DeepSleepMode = 0x10,
-    /// Changes the auto-increment behaviour of the address counter.
-    DataEntryModeSetting = 0x11,
-    /// Resets all commands and parameters to default values (except deep sleep mode).
-    SwReset = 0x12,
-    /// Activates the display update sequence. This must be set beforehand using [Command::DisplayUpdateControl2].
-    /// This operation must not be interrupted.
-    MasterActivation = 0x20,
-    /// Used for a RAM "bypass" mode when using [RefreshMode::Partial]. This is poorly explained in the docs,
-    /// but essentially we have these options:
-    ///
-    /// In black and white mode:
-    ///
-    /// 1. `0x00` (default): just update the pixels that have changed **between the two internal
-    ///    frame buffers**. This normally does what you expect. You can hack it a bit to do
-    ///    interesting things by writing to both the old and new frame buffers.
-    /// 2. `0x04`: just update the white (`BinaryColor::On`) pixels in the current frame buffer. It
-    ///    doesn't matter what is in the old frame buffer.
-    /// 3. `0x08`: just update the black (`BinaryColor::Off`) pixels in the current frame buffer.
-    ///    It doesn't matter what is in the old frame buffer.
-    ///
-    /// In 4-color greyscale mode: same as above for the behaviour of the black and white bit, but
-    /// OR-ed with:
-    ///
-    /// 1. `0x00` (default)
-    /// 2. `0x40` (just update 1 bits)
-    /// 3. `0x80` (just update 0 bits)
-    ///
-    /// TODO: verify the behaviour of greyscale mode.
-    DisplayUpdateControl1 = 0x21,
-    /// Configures the display update sequence for use with [Command::MasterActivation].
-    DisplayUpdateControl2 = 0x22,
-    /// Writes low bits to the current frame buffer.
-    WriteLowRam = 0x24,
-    /// Writes high bits to the current frame buffer.
-    WriteHighRam = 0x26,
-    /// Triggers a read of the VCOM voltage. Requires that CLKEN and ANALOGEN have been enabled via
-    /// [Command::DisplayUpdateControl2].
-    ReadVcom = 0x28,
-    /// Sets the duration to hold before reading the VCOM value.
-    SetVcomReadDuration = 0x29,
-    /// Programs the VCOM register into the OTP. Requires that CLKEN has been enabled via
-    /// [Command::DisplayUpdateControl2].
-    ProgramVcomOtp = 0x2A,
-    /// Writes to the VCOM register.
-    WriteVcom = 0x2C,
-
-    /// ?? Reads OTP registers (sections: VCOM OTP selection, VCOM register, Display Mode, Waveform Version).
-    ReadOtpRegisters = 0x2D,
-    /// ?? Reads 10 byte User ID stored in OTP.
-    ReadUserId = 0x2E,
-    /// ?? Programs the OTP of Waveform Setting (requires writing the bytes into RAM first). Requires
-    /// CLKEN to have been enabled via [Command::DisplayUpdateControl2].
-    ProgramWsOtp = 0x30,
-    /// ?? Loads the OTP of Waveform Setting. Requires CLKEN to have been enabled via
-    /// [Command::DisplayUpdateControl2].
-    LoadWsOtp = 0x31,
-
-    /// Writes the LUT register (153 bytes, containing VS\[nX-LUTm\], TP\[nX\], RP\[n\], SR\[nXY\], FR\[n\], and XON\[nXY\]).
-    WriteLut = 0x32,
-
-    /// ?? Programs OTP selection according to the OTP selection control (registers 0x37 and 0x38).
-    /// Requires CLKEN to have been enabled via [Command::DisplayUpdateControl2].
-    ProgramOtpSelection = 0x36,
-
-    /// Undocumented command for writing OTP data.    
-    /// Writes the register for the user ID that can be stored in the OTP.
-    WriteRegisterForUserId = 0x38,
-    /// ?? Sets the OTP program mode:
-    ///
-    /// * 0x00: normal mode
-    /// * 0x03: internally generated OTP programming voltage
-    SetOtpProgramMode = 0x39,
-    /// Undocumented command used when initialising each refresh mode.
-    SetBorderWaveform = 0x3C,
-    /// Undocumented command needed for setting the LUT.
-    SetLutMagic = 0x3F,
-
-    /// Sets the start and end positions of the X axis for the auto-incrementing address counter.
-    /// Start and end are inclusive.
-    ///
-    /// Note that the x position can only be written on a whole byte basis (8 bits at once). The
-    /// start and end positions are therefore sent right shifted 3 bits to indicate the byte number
-    /// being written. For example, to write the first 32 x positions, you would send 0 (0 >> 3 =
-    /// 0), and 3 (31 >> 3 = 3). If you tried to write just the first 25 x positions, you would end
-    /// up sending the same values and actually writing all 32.
-    SetRamXStartEnd = 0x44,
-    /// Sets the start and end positions of the Y axis for the auto-incrementing address counter.
-    /// Start and end are inclusive.
-    SetRamYStartEnd = 0x45,
-    /// Sets the current x coordinate of the address counter.
-    /// Note that the x position can only be configured as a multiple of 8.
-    SetRamX = 0x4E,
-    /// Sets the current y coordinate of the address counter.
-    SetRamY = 0x4F,
-}
-
-impl Command {
-    /// Returns the register address for this command.
-    fn register(&self) -> u8 {
-        *self as u8
+commands! {
+    /// Low-level commands for the Epd2In9 v2 display. You probably want to use the other methods
+    /// exposed on the [Epd2In9V2] for most operations, but can send commands directly with [Epd2In9V2::send] for low-level
+    /// control or experimentation.
+    pub enum Command {
+        /// Used to initialise the display.
+        DriverOutputControl = 0x01,
+        /// Sets the gate driving voltage (standard value: 0x00, or 0x17).
+        SetGateDrivingVoltage = 0x03,
+        /// Sets the source driving voltage (standard value: [0x41, 0xA8, 0x32]).
+        SetSourceDrivingVoltage = 0x04,
+        /// Configures how the booster soft starts (power-on default: [0x8B, 0x9C, 0x96, 0x0F]).
+        BoosterSoftStartControl = 0x0C,
+        /// Used to enter deep sleep mode. Requires a hardware reset and reinitialisation to wake up.
+        DeepSleepMode = 0x10,
+        /// Changes the auto-increment behaviour of the address counter.
+        DataEntryModeSetting = 0x11,
+        /// Resets all commands and parameters to default values (except deep sleep mode).
+        SwReset = 0x12,
+        /// Checks the supply voltage (VCI) against a [VciLevel].
+        /// Requires CLKEN and ANALOGEN to have been enabled via [Command::DisplayUpdateControl2].
+        /// The result is read with [Command::StatusBitRead].
+        VciDetection = 0x15,
+        /// Selects the temperature sensor that the OTP waveform is chosen by (0x80: internal).
+        TemperatureSensorSelection = 0x18,
+        /// Writes the temperature register, as a 12-bit value in 1/16ths of a degree Celsius.
+        WriteTemperature = 0x1A,
+        /// Activates the display update sequence. This must be set beforehand using [Command::DisplayUpdateControl2].
+        /// This operation must not be interrupted.
+        MasterActivation = ssd16xx::Command::MasterActivation as isize,
+        /// Used for a RAM "bypass" mode when using [RefreshMode::Partial]. This is poorly explained in the docs,
+        /// but essentially we have these options:
+        ///
+        /// In black and white mode:
+        ///
+        /// 1. `0x00` (default): just update the pixels that have changed **between the two internal
+        ///    frame buffers**. This normally does what you expect. You can hack it a bit to do
+        ///    interesting things by writing to both the old and new frame buffers.
+        /// 2. `0x04`: just update the white (`BinaryColor::On`) pixels in the current frame buffer. It
+        ///    doesn't matter what is in the old frame buffer.
+        /// 3. `0x08`: just update the black (`BinaryColor::Off`) pixels in the current frame buffer.
+        ///    It doesn't matter what is in the old frame buffer.
+        ///
+        /// In 4-color greyscale mode: same as above for the behaviour of the black and white bit, but
+        /// OR-ed with:
+        ///
+        /// 1. `0x00` (default)
+        /// 2. `0x40` (just update 1 bits)
+        /// 3. `0x80` (just update 0 bits)
+        ///
+        /// TODO: verify the behaviour of greyscale mode.
+        DisplayUpdateControl1 = 0x21,
+        /// Configures the display update sequence for use with [Command::MasterActivation].
+        DisplayUpdateControl2 = ssd16xx::Command::DisplayUpdateControl2 as isize,
+        /// Writes low bits to the current frame buffer.
+        WriteLowRam = 0x24,
+        /// Writes high bits to the current frame buffer.
+        WriteHighRam = 0x26,
+        /// Triggers a read of the VCOM voltage. Requires that CLKEN and ANALOGEN have been enabled via
+        /// [Command::DisplayUpdateControl2].
+        ReadVcom = 0x28,
+        /// Sets the duration to hold before reading the VCOM value.
+        SetVcomReadDuration = 0x29,
+        /// Programs the VCOM register into the OTP. Requires that CLKEN has been enabled via
+        /// [Command::DisplayUpdateControl2]. See [Epd2In9V2::program_vcom_otp].
+        ProgramVcomOtp = 0x2A,
+        /// Writes to the VCOM register.
+        WriteVcom = 0x2C,
+
+        /// ?? Reads OTP registers (sections: VCOM OTP selection, VCOM register, Display Mode, Waveform Version).
+        ReadOtpRegisters = 0x2D,
+        /// ?? Reads 10 byte User ID stored in OTP.
+        ReadUserId = 0x2E,
+        /// Reads the status byte. Bit 5 is set if the high voltage isn't ready,
+        /// and bit 4 if the supply was below the [Command::VciDetection] level.
+        StatusBitRead = 0x2F,
+        /// ?? Programs the OTP of Waveform Setting (requires writing the bytes into RAM first). Requires
+        /// CLKEN to have been enabled via [Command::DisplayUpdateControl2]. See
+        /// [Epd2In9V2::program_waveform_otp].
+        ProgramWsOtp = 0x30,
+        /// ?? Loads the OTP of Waveform Setting. Requires CLKEN to have been enabled via
+        /// [Command::DisplayUpdateControl2].
+        LoadWsOtp = 0x31,
+
+        /// Writes the LUT register (153 bytes, containing VS\[nX-LUTm\], TP\[nX\], RP\[n\], SR\[nXY\], FR\[n\], and XON\[nXY\]).
+        WriteLut = 0x32,
+
+        /// ?? Programs OTP selection according to the OTP selection control (registers 0x37 and 0x38).
+        /// Requires CLKEN to have been enabled via [Command::DisplayUpdateControl2].
+        ProgramOtpSelection = 0x36,
+
+        /// Undocumented command for writing OTP data.
+        /// Writes the register for the user ID that can be stored in the OTP.
+        WriteRegisterForUserId = 0x38,
+        /// ?? Sets the OTP program mode:
+        ///
+        /// * 0x00: normal mode
+        /// * 0x03: internally generated OTP programming voltage
+        SetOtpProgramMode = 0x39,
+        /// Sets how the border is driven. Each refresh mode sets its own, see [Epd2In9V2::set_border].
+        SetBorderWaveform = 0x3C,
+        /// Undocumented command needed for setting the LUT.
+        SetLutMagic = 0x3F,
+
+        /// Sets the start and end positions of the X axis for the auto-incrementing address counter.
+        /// Start and end are inclusive.
+        ///
+        /// Note that the x position can only be written on a whole byte basis (8 bits at once). The
+        /// start and end positions are therefore sent right shifted 3 bits to indicate the byte number
+        /// being written. For example, to write the first 32 x positions, you would send 0 (0 >> 3 =
+        /// 0), and 3 (31 >> 3 = 3). If you tried to write just the first 25 x positions, you would end
+        /// up sending the same values and actually writing all 32.
+        SetRamXStartEnd = ssd16xx::Command::SetRamXStartEnd as isize,
+        /// Sets the start and end positions of the Y axis for the auto-incrementing address counter.
+        /// Start and end are inclusive.
+        SetRamYStartEnd = ssd16xx::Command::SetRamYStartEnd as isize,
+        /// Sets the current x coordinate of the address counter.
+        /// Note that the x position can only be configured as a multiple of 8.
+        SetRamX = ssd16xx::Command::SetRamX as isize,
+        /// Sets the current y coordinate of the address counter.
+        SetRamY = ssd16xx::Command::SetRamY as isize,
     }
 }
 
@@ -355,12 +523,22 @@ const DRIVER_OUTPUT_INIT_DATA: [u8; 3] = [0x27, 0x01, 0x00];
 ///
 /// When using `BinaryColor`, `Off` is black and `On` is white.
 ///
-/// HW should implement [ResetHw], [BusyHw], [DcHw], [SpiHw], [DelayHw], and [ErrorHw].
+/// HW should implement [ResetHw], [BusyHw], [DcHw], [SpiHw], [DelayHw], and [ErrorHw]. Writing
+/// framebuffers also needs [ErrorHw::Error] to implement `From<WindowError>`, for buffers whose
+/// window isn't on the display.
 pub struct Epd2In9V2<HW, STATE> {
     hw: HW,
     state: STATE,
 }
 
+/// Formats the display's state. The hardware is omitted, as it rarely implements [defmt::Format].
+#[cfg(feature = "defmt")]
+impl<HW, STATE: defmt::Format> defmt::Format for Epd2In9V2<HW, STATE> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Epd2In9V2 {{ state: {} }}", self.state)
+    }
+}
+
 trait StateInternal {}
 #[allow(private_bounds)]
 pub trait State: StateInternal {}
@@ -383,6 +561,11 @@ impl StateAwake for StateUninitialized {}
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct StateReady {
     mode: RefreshMode,
+    /// The border from [Epd2In9V2::set_border], which replaces the mode's own border.
+    border: Option<Border>,
+    /// The temperature from [Epd2In9V2::set_external_temperature], which [RefreshMode::Otp] uses
+    /// rather than the internal sensor.
+    external_temperature: Option<i8>,
 }
 impl_base_state!(StateReady);
 impl StateAwake for StateReady {}
@@ -395,6 +578,107 @@ pub struct StateAsleep<W: StateAwake> {
 impl<W: StateAwake> StateInternal for StateAsleep<W> {}
 impl<W: StateAwake> State for StateAsleep<W> {}
 
+/// Set in the snapshots of ready states.
+const SNAPSHOT_READY: u32 = 0x10;
+/// Set in the snapshots of ready states that use an external temperature.
+const SNAPSHOT_EXTERNAL_TEMPERATURE: u32 = 0x20;
+/// The shift of the external temperature in the snapshots of ready states that use one.
+const SNAPSHOT_TEMPERATURE_SHIFT: u32 = 16;
+/// Set in the snapshots of asleep states, on top of the snapshot of the state to wake to.
+const SNAPSHOT_ASLEEP: u32 = 0x80;
+/// The shift of the border in the snapshots of ready states, which is 0 for the mode's own border.
+const SNAPSHOT_BORDER_SHIFT: u32 = 8;
+
+impl StateSnapshot for StateUninitialized {
+    fn to_snapshot(&self) -> u32 {
+        0
+    }
+
+    fn from_snapshot(snapshot: u32) -> Option<Self> {
+        (snapshot == 0).then_some(StateUninitialized())
+    }
+}
+
+impl StateSnapshot for StateReady {
+    fn to_snapshot(&self) -> u32 {
+        let external_temperature = match self.external_temperature {
+            Some(celsius) => {
+                SNAPSHOT_EXTERNAL_TEMPERATURE
+                    | (u32::from(celsius as u8) << SNAPSHOT_TEMPERATURE_SHIFT)
+            }
+            None => 0,
+        };
+        let border = match self.border {
+            None => 0,
+            Some(Border::Black) => 1,
+            Some(Border::White) => 2,
+            Some(Border::Gray(gray)) => 3 + u32::from(gray.luma()),
+            Some(Border::Vcom) => 7,
+            Some(Border::HiZ) => 8,
+        };
+        SNAPSHOT_READY
+            | external_temperature
+            | (border << SNAPSHOT_BORDER_SHIFT)
+            | match self.mode {
+                RefreshMode::Full => 0,
+                #[cfg(feature = "epd2in9_v2-full-slow")]
+                RefreshMode::FullSlow => 1,
+                RefreshMode::Partial => 2,
+                RefreshMode::Otp => 4,
+                #[cfg(feature = "epd2in9_v2-gray2")]
+                RefreshMode::Gray2 => 3,
+            }
+    }
+
+    fn from_snapshot(snapshot: u32) -> Option<Self> {
+        let celsius = snapshot >> SNAPSHOT_TEMPERATURE_SHIFT;
+        let external_temperature = match (snapshot & SNAPSHOT_EXTERNAL_TEMPERATURE != 0, celsius) {
+            (true, 0..=0xFF) => Some(celsius as u8 as i8),
+            (false, 0) => None,
+            _ => return None,
+        };
+        let border = match (snapshot >> SNAPSHOT_BORDER_SHIFT) & 0xFF {
+            0 => None,
+            1 => Some(Border::Black),
+            2 => Some(Border::White),
+            level @ 3..=6 => Some(Border::Gray(Gray2::new((level - 3) as u8))),
+            7 => Some(Border::Vcom),
+            8 => Some(Border::HiZ),
+            _ => return None,
+        };
+        let mode_bits = snapshot & 0xFF & !SNAPSHOT_EXTERNAL_TEMPERATURE;
+        let mode = match mode_bits.checked_sub(SNAPSHOT_READY)? {
+            0 => RefreshMode::Full,
+            #[cfg(feature = "epd2in9_v2-full-slow")]
+            1 => RefreshMode::FullSlow,
+            2 => RefreshMode::Partial,
+            4 => RefreshMode::Otp,
+            #[cfg(feature = "epd2in9_v2-gray2")]
+            3 => RefreshMode::Gray2,
+            _ => return None,
+        };
+        Some(StateReady {
+            mode,
+            border,
+            external_temperature,
+        })
+    }
+}
+
+impl<W: StateAwake + StateSnapshot> StateSnapshot for StateAsleep<W> {
+    fn to_snapshot(&self) -> u32 {
+        SNAPSHOT_ASLEEP | self.wake_state.to_snapshot()
+    }
+
+    fn from_snapshot(snapshot: u32) -> Option<Self> {
+        if snapshot & SNAPSHOT_ASLEEP == 0 {
+            return None;
+        }
+        let wake_state = W::from_snapshot(snapshot & !SNAPSHOT_ASLEEP)?;
+        Some(StateAsleep { wake_state })
+    }
+}
+
 impl<HW> Epd2In9V2<HW, StateUninitialized>
 where
     HW: BusyHw + DcHw + ResetHw + DelayHw + SpiHw + ErrorHw,
@@ -409,8 +693,29 @@ where
             state: StateUninitialized(),
         }
     }
+
+    /// Takes control of a display that is already initialised and awake, without resetting it,
+    /// e.g. after the microcontroller restarts from a watchdog reset.
+    ///
+    /// This doesn't send anything to the display, so the image on it is kept. `mode` must be the
+    /// refresh mode that the display was last set to. If the display might be asleep or in an
+    /// unknown state, use [Epd2In9V2::init] instead.
+    pub fn attach(hw: HW, mode: RefreshMode) -> Epd2In9V2<HW, StateReady> {
+        debug!("Attaching to initialised display");
+        Epd2In9V2 {
+            hw,
+            state: StateReady {
+                mode,
+                border: None,
+                external_temperature: None,
+            },
+        }
+    }
 }
 
+/// Modifies what the display reads from RAM when refreshing. See [Epd2In9V2::set_ram_bypass].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Bypass {
     /// Remove any RAM bypass setting.
     Normal = 0,
@@ -420,6 +725,87 @@ pub enum Bypass {
     Inverted = 0b1000,
 }
 
+/// The supply voltage that [Epd2In9V2::read_low_power_detection] checks against.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VciLevel {
+    /// 2.3V.
+    V2_3 = 0b011,
+    /// 2.4V, the controller's default.
+    V2_4 = 0b100,
+    /// 2.5V.
+    V2_5 = 0b101,
+    /// 2.6V.
+    V2_6 = 0b110,
+}
+
+impl<HW, STATE: State> Epd2In9V2<HW, STATE> {
+    /// Splits the driver into its hardware and its state, e.g. to save the state with
+    /// [StateSnapshot::to_snapshot] before the microcontroller enters deep sleep.
+    pub fn into_state_snapshot(self) -> (HW, STATE) {
+        (self.hw, self.state)
+    }
+
+    /// Recreates a driver from its hardware and a state saved with
+    /// [Epd2In9V2::into_state_snapshot].
+    ///
+    /// This doesn't send anything to the display, so the display must still be in `state`, e.g.
+    /// because it stayed asleep while the microcontroller was powered down.
+    pub fn from_state_snapshot(hw: HW, state: STATE) -> Self {
+        Epd2In9V2 { hw, state }
+    }
+
+    /// Returns the typical time that a refresh in `mode` keeps the display busy, at room
+    /// temperature. Refreshes take longer in the cold.
+    ///
+    /// Use a [crate::refresh::RefreshEstimate] to refine this from measured refreshes.
+    pub fn estimated_refresh_duration(&self, mode: RefreshMode) -> Duration {
+        match mode {
+            RefreshMode::Full => Duration::from_millis(3000),
+            #[cfg(feature = "epd2in9_v2-full-slow")]
+            RefreshMode::FullSlow => Duration::from_millis(4000),
+            RefreshMode::Partial => Duration::from_millis(300),
+            RefreshMode::Otp => Duration::from_millis(3000),
+            #[cfg(feature = "epd2in9_v2-gray2")]
+            RefreshMode::Gray2 => Duration::from_millis(3000),
+        }
+    }
+
+    /// Returns what the display supports. Gray levels need the `epd2in9_v2-gray2` feature, and
+    /// there's only a fast refresh with the `epd2in9_v2-full-slow` feature, whose slow waveform is
+    /// used for full refreshes.
+    pub fn capabilities(&self) -> Capabilities {
+        let supports_fast = cfg!(feature = "epd2in9_v2-full-slow");
+        let supports_gray = cfg!(feature = "epd2in9_v2-gray2");
+        Capabilities {
+            resolution: self.size(),
+            colors: if supports_gray { 4 } else { 2 },
+            supports_partial: true,
+            supports_fast,
+            supports_gray,
+            full_refresh: self.estimated_refresh_duration(RefreshKind::Full.into()),
+            fast_refresh: supports_fast
+                .then(|| self.estimated_refresh_duration(RefreshKind::Fast.into())),
+            partial_refresh: Some(self.estimated_refresh_duration(RefreshMode::Partial)),
+        }
+    }
+}
+
+/// The display's size, in its native orientation, so that layouts can be computed from the driver.
+impl<HW, STATE> OriginDimensions for Epd2In9V2<HW, STATE> {
+    fn size(&self) -> Size {
+        Size::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32)
+    }
+}
+
+impl<HW: BusyHw, STATE> Epd2In9V2<HW, STATE> {
+    /// Returns the hardware's timing counters, if it has instrumentation enabled. See
+    /// [BusyHw::timings].
+    pub fn timings(&mut self) -> Option<&mut Timings> {
+        self.hw.timings()
+    }
+}
+
 impl<HW, STATE> Epd2In9V2<HW, STATE>
 where
     HW: BusyHw + DcHw + ResetHw + DelayHw + SpiHw + ErrorHw,
@@ -440,7 +826,11 @@ where
 
         let mut epd = Epd2In9V2 {
             hw: self.hw,
-            state: StateReady { mode },
+            state: StateReady {
+                mode,
+                border: None,
+                external_temperature: None,
+            },
         };
 
         epd.set_refresh_mode_impl(spi, mode).await?;
@@ -456,14 +846,44 @@ where
         + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
     STATE: StateAwake,
 {
-    /// Send the following command and data to the display. Waits until the display is no longer busy before sending.
-    pub async fn send(
+    command_methods!(Command, read);
+
+    /// Reads the 10 byte user ID stored in the display's OTP, e.g. to tell panel batches apart.
+    ///
+    /// Like [Epd2In9V2::send_read], this requires the display's data line to be readable by your
+    /// SPI device, so it doesn't work through [crate::hw::ThreeWireSpi]. Panels whose user ID was
+    /// never programmed return all zeros.
+    pub async fn read_user_id(&mut self, spi: &mut HW::Spi) -> Result<[u8; 10], HW::Error> {
+        let mut user_id = [0; 10];
+        self.send_read(spi, Command::ReadUserId, &[], &mut user_id)
+            .await?;
+        Ok(user_id)
+    }
+
+    /// Returns whether the panel's supply voltage is below `level`, e.g. so that a battery-powered
+    /// device can skip a refresh that would come out corrupted.
+    ///
+    /// Like [Epd2In9V2::read_user_id], this requires the display's data line to be readable by your
+    /// SPI device.
+    pub async fn read_low_power_detection(
         &mut self,
         spi: &mut HW::Spi,
-        command: Command,
-        data: &[u8],
-    ) -> Result<(), HW::Error> {
-        self.hw.send(spi, command.register(), data).await
+        level: VciLevel,
+    ) -> Result<bool, HW::Error> {
+        // Enable the clock and analog circuits, which the detection needs.
+        self.send(spi, Command::DisplayUpdateControl2, &[0xC0])
+            .await?;
+        self.send(spi, Command::MasterActivation, &[]).await?;
+        self.send(spi, Command::VciDetection, &[level as u8])
+            .await?;
+        let mut status = [0; 1];
+        self.send_read(spi, Command::StatusBitRead, &[], &mut status)
+            .await?;
+        let low_power = status[0] & 0x10 != 0;
+        if low_power {
+            warning!("Supply voltage is below {:?}", level);
+        }
+        Ok(low_power)
     }
 }
 
@@ -496,9 +916,17 @@ where
     ) -> Result<(), HW::Error> {
         // Reset all configurations to default.
         self.send(spi, Command::SwReset, &[]).await?;
+        if let Some(celsius) = self.state.external_temperature {
+            self.send(spi, Command::WriteTemperature, &[celsius as u8, 0x00])
+                .await?;
+        }
 
         self.send(spi, Command::DriverOutputControl, &DRIVER_OUTPUT_INIT_DATA)
             .await?;
+        if let Some(booster) = booster_soft_start_data(self.hw.power_profile()) {
+            self.send(spi, Command::BoosterSoftStartControl, booster)
+                .await?;
+        }
         // Auto-increment X and Y, moving in the X direction first.
         self.send(spi, Command::DataEntryModeSetting, &[0b11])
             .await?;
@@ -515,19 +943,28 @@ where
         )
         .await?;
 
-        if let Some(border_waveform) = mode.border_waveform() {
+        if let Some(border) = self.state.border {
+            self.send(spi, Command::SetBorderWaveform, &[border.register(mode)])
+                .await?;
+        } else if let Some(border_waveform) = mode.border_waveform() {
             self.send(spi, Command::SetBorderWaveform, border_waveform)
                 .await?;
         }
 
-        self.send(spi, Command::WriteLut, mode.lut()).await?;
-        self.send(spi, Command::SetLutMagic, mode.lut_magic())
-            .await?;
-        self.send(spi, Command::SetGateDrivingVoltage, mode.gate_voltage())
-            .await?;
-        self.send(spi, Command::SetSourceDrivingVoltage, mode.source_voltage())
-            .await?;
-        self.send(spi, Command::WriteVcom, mode.vcom()).await?;
+        if mode == RefreshMode::Otp {
+            // The OTP waveform is loaded before each refresh, for the temperature at the time.
+            self.send(spi, Command::TemperatureSensorSelection, &[0x80])
+                .await?;
+        } else {
+            self.send(spi, Command::WriteLut, mode.lut()).await?;
+            self.send(spi, Command::SetLutMagic, mode.lut_magic())
+                .await?;
+            self.send(spi, Command::SetGateDrivingVoltage, mode.gate_voltage())
+                .await?;
+            self.send(spi, Command::SetSourceDrivingVoltage, mode.source_voltage())
+                .await?;
+            self.send(spi, Command::WriteVcom, mode.vcom()).await?;
+        }
 
         if mode == RefreshMode::Partial {
             // Mystery undocumented command from sample code.
@@ -539,15 +976,73 @@ where
                 )
                 .await?;
 
-            self.send(spi, Command::DisplayUpdateControl2, &[0xC3])
-                .await?;
-            self.send(spi, Command::MasterActivation, &[]).await?;
+            ssd16xx::activate(&mut self.hw, spi, &[0xC3]).await?;
         }
         self.state.mode = mode;
 
         Ok(())
     }
 
+    /// Uses `celsius` to choose the waveform in [RefreshMode::Otp], e.g. from an accurate sensor
+    /// next to the panel, rather than the controller's internal sensor. The other refresh modes use
+    /// fixed waveforms, so they ignore the temperature.
+    ///
+    /// This is kept until [Epd2In9V2::use_internal_temperature] is called, so update it when the
+    /// temperature changes. It's sent again after the resets from changing the refresh mode and
+    /// waking, and it's kept in state snapshots.
+    pub async fn set_external_temperature(
+        &mut self,
+        spi: &mut HW::Spi,
+        celsius: i8,
+    ) -> Result<(), HW::Error> {
+        debug!("Setting external temperature to {}C", celsius);
+        self.send(spi, Command::WriteTemperature, &[celsius as u8, 0x00])
+            .await?;
+        self.state.external_temperature = Some(celsius);
+        Ok(())
+    }
+
+    /// Goes back to choosing the waveform in [RefreshMode::Otp] with the controller's internal
+    /// temperature sensor, after [Epd2In9V2::set_external_temperature].
+    pub fn use_internal_temperature(&mut self) {
+        self.state.external_temperature = None;
+    }
+
+    /// Replaces the current refresh mode's LUT with `lut`, such as one from
+    /// [crate::lut::GrayLut], keeping the mode's voltages.
+    ///
+    /// The mode's own LUT is loaded again the next time the refresh mode is set, or when the
+    /// display wakes, so write `lut` again after those.
+    pub async fn write_lut(
+        &mut self,
+        spi: &mut HW::Spi,
+        lut: &[u8; crate::lut::LUT_LENGTH],
+    ) -> Result<(), HW::Error> {
+        debug!("Writing a custom LUT");
+        self.send(spi, Command::WriteLut, lut).await
+    }
+
+    /// Sets how the border is driven by the following refreshes, e.g. to a colour or gray level.
+    /// See [Border] for the refresh modes that each setting is valid in.
+    ///
+    /// This replaces the border of each refresh mode, so it's sent again when the refresh mode is
+    /// set, or when the display wakes, and it's kept in state snapshots.
+    pub async fn set_border(
+        &mut self,
+        spi: &mut HW::Spi,
+        border: impl Into<Border>,
+    ) -> Result<(), HW::Error> {
+        let border: Border = border.into();
+        self.send(
+            spi,
+            Command::SetBorderWaveform,
+            &[border.register(self.state.mode)],
+        )
+        .await?;
+        self.state.border = Some(border);
+        Ok(())
+    }
+
     /// Sets the "ram bypass", which modifies what the display reads when it tries to access the
     /// framebuffers.
     ///
@@ -579,69 +1074,51 @@ where
 
     /// Sets the window to which the next image data will be written.
     ///
-    /// The x-axis only supports multiples of 8; values outside this result in a debug-mode panic,
-    /// or potentially misaligned content when debug assertions are disabled.
+    /// The x-axis only supports multiples of 8; other values are rounded out to whole bytes, which
+    /// will lead to misaligned content. Use [crate::buffer::align_window] to align an area first.
+    ///
+    /// Together with [Epd2In9V2::set_cursor] and [Epd2In9V2::send], this streams your own data into a
+    /// window, e.g. straight from a decompressor. The address counter carries on between writes,
+    /// so the data can be sent in chunks of whole rows or bytes:
+    ///
+    /// ```text
+    /// epd.set_window(&mut spi, window).await?;
+    /// epd.set_cursor(&mut spi, window.top_left).await?;
+    /// while let Some(chunk) = decompressor.next_chunk() {
+    ///     epd.send(&mut spi, Command::WriteLowRam, chunk).await?;
+    /// }
+    /// epd.update_display(&mut spi).await?;
+    /// ```
     pub async fn set_window(
         &mut self,
         spi: &mut HW::Spi,
         shape: Rectangle,
     ) -> Result<(), HW::Error> {
-        let (x_start, x_end) = if self.state.mode == RefreshMode::Gray2 {
-            // When using gray2, for some reason the position is misaligned. This fixes it.
-            let x_start = shape.top_left.x + 8;
-            let x_end = shape.top_left.x + shape.size.width as i32 + 7;
-            (x_start, x_end)
-        } else {
-            let x_start = shape.top_left.x;
-            (x_start, x_start + shape.size.width as i32 - 1)
-        };
-        // Use a debug assert as this is a soft failure in production; it will just lead to
-        // slightly misaligned display content.
-        debug_assert!(
-            x_start % 8 == 0 && x_end % 8 == 7,
-            "window's top_left.x and width must be 8-bit aligned"
-        );
-        let x_start_byte = ((x_start >> 3) & 0xFF) as u8;
-        let x_end_byte = ((x_end >> 3) & 0xFF) as u8;
-        self.send(spi, Command::SetRamXStartEnd, &[x_start_byte, x_end_byte])
-            .await?;
-
-        let (y_start_low, y_start_high) = split_low_and_high(shape.top_left.y as u16);
-        let (y_end_low, y_end_high) =
-            split_low_and_high((shape.top_left.y + shape.size.height as i32 - 1) as u16);
-        self.send(
-            spi,
-            Command::SetRamYStartEnd,
-            &[y_start_low, y_start_high, y_end_low, y_end_high],
-        )
-        .await?;
-
-        Ok(())
+        let shape = shape.translate(self.ram_offset());
+        ssd16xx::set_window(&mut self.hw, spi, shape).await
     }
 
     /// Sets the cursor position to write the next data to.
     ///
-    /// The x-axis only supports multiples of 8; values outside this will result in a panic in
-    /// debug mode, or potentially misaligned content if debug assertions are disabled.
+    /// The x-axis only supports multiples of 8; other values are rounded down, which will lead to
+    /// misaligned content.
     pub async fn set_cursor(
         &mut self,
         spi: &mut HW::Spi,
         position: Point,
     ) -> Result<(), HW::Error> {
-        // Use a debug assert as this is a soft failure in production; it will just lead to
-        // slightly misaligned display content.
-        debug_assert_eq!(position.x % 8, 0, "position.x must be 8-bit aligned");
-        let x_pos = if self.state.mode == RefreshMode::Gray2 {
-            position.x + 8
-        } else {
-            position.x
-        };
+        let position = position + self.ram_offset();
+        ssd16xx::set_cursor(&mut self.hw, spi, position).await
+    }
 
-        self.send(spi, Command::SetRamX, &[(x_pos >> 3) as u8])
-            .await?;
-        let (y_low, y_high) = split_low_and_high(position.y as u16);
-        self.send(spi, Command::SetRamY, &[y_low, y_high]).await?;
-        Ok(())
+    /// The offset from display coordinates to RAM addresses.
+    fn ram_offset(&self) -> Point {
+        match self.state.mode {
+            // When using gray2, for some reason the position is misaligned. This fixes it.
+            #[cfg(feature = "epd2in9_v2-gray2")]
+            RefreshMode::Gray2 => Point::new(8, 0),
+            _ => Point::zero(),
+        }
     }
 }
 
@@ -650,11 +1127,14 @@ where
     HW: ResetHw + DelayHw + ErrorHw,
     HW::Error: From<<HW::Reset as embedded_hal::digital::ErrorType>::Error>,
 {
+    hw.power_on().await.with_source::<HW>(ErrorSource::Power)?;
     debug!("Resetting EPD");
     // Assume reset is already high.
-    hw.reset().set_low()?;
+    hw.reset().set_low().with_source::<HW>(ErrorSource::Reset)?;
     hw.delay().delay_ms(10).await;
-    hw.reset().set_high()?;
+    hw.reset()
+        .set_high()
+        .with_source::<HW>(ErrorSource::Reset)?;
     hw.delay().delay_ms(10).await;
     Ok(())
 }
@@ -690,7 +1170,7 @@ where
 
 impl<HW, STATE: StateAwake> Sleep<HW::Spi, HW::Error> for Epd2In9V2<HW, STATE>
 where
-    HW: BusyHw + DcHw + SpiHw + ErrorHw,
+    HW: BusyHw + DcHw + ResetHw + SpiHw + ErrorHw,
     HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
@@ -700,6 +1180,10 @@ where
     async fn sleep(mut self, spi: &mut HW::Spi) -> Result<Self::DisplayOut, HW::Error> {
         debug!("Sleeping EPD");
         self.send(spi, Command::DeepSleepMode, &[0x01]).await?;
+        self.hw
+            .power_off()
+            .await
+            .with_source::<HW>(ErrorSource::Power)?;
         Ok(Epd2In9V2 {
             hw: self.hw,
             state: StateAsleep {
@@ -709,7 +1193,7 @@ where
     }
 }
 
-impl<HW, W: StateAwake> Wake<HW::Spi, HW::Error> for Epd2In9V2<HW, StateAsleep<W>>
+impl<HW> Wake<HW::Spi, HW::Error> for Epd2In9V2<HW, StateAsleep<StateUninitialized>>
 where
     HW: BusyHw + DcHw + ResetHw + DelayHw + SpiHw + ErrorHw,
     HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
@@ -717,13 +1201,45 @@ where
         + From<<HW::Reset as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
 {
-    type DisplayOut = Epd2In9V2<HW, W>;
+    type DisplayOut = Epd2In9V2<HW, StateUninitialized>;
     async fn wake(self, _spi: &mut HW::Spi) -> Result<Self::DisplayOut, HW::Error> {
         debug!("Waking EPD");
         self.reset().await
     }
 }
 
+/// The reset clears the controller's configuration, so waking replays the refresh mode that the
+/// display was in, including its data entry settings and any border from [Epd2In9V2::set_border].
+///
+/// This includes an external temperature from [Epd2In9V2::set_external_temperature].
+impl<HW> Wake<HW::Spi, HW::Error> for Epd2In9V2<HW, StateAsleep<StateReady>>
+where
+    HW: BusyHw + DcHw + ResetHw + DelayHw + SpiHw + ErrorHw,
+    HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Reset as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
+{
+    type DisplayOut = Epd2In9V2<HW, StateReady>;
+    async fn wake(self, spi: &mut HW::Spi) -> Result<Self::DisplayOut, HW::Error> {
+        debug!("Waking EPD");
+        let mut epd = self.reset().await?;
+        let mode = epd.state.mode;
+        epd.set_refresh_mode_impl(spi, mode).await?;
+        Ok(epd)
+    }
+}
+
+impl<HW, STATE: StateAwake> WaitIdle<HW::Error> for Epd2In9V2<HW, STATE>
+where
+    HW: BusyHw + ErrorHw,
+    HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>,
+{
+    async fn wait_until_idle(&mut self) -> Result<(), HW::Error> {
+        self.hw.wait_if_busy().await
+    }
+}
+
 impl<HW> Displayable<HW::Spi, HW::Error> for Epd2In9V2<HW, StateReady>
 where
     HW: BusyHw + DcHw + SpiHw + ErrorHw,
@@ -735,12 +1251,52 @@ where
         debug!("Updating display");
 
         let mode = self.state.mode;
-        let update_control = mode.display_update_control_2();
-        self.send(spi, Command::DisplayUpdateControl2, update_control)
-            .await?;
+        let update_control = match mode {
+            // Load the waveform for the written temperature, without reading the sensor.
+            RefreshMode::Otp if self.state.external_temperature.is_some() => &[0xD7],
+            _ => mode.display_update_control_2(),
+        };
+        ssd16xx::activate(&mut self.hw, spi, update_control).await
+    }
+}
 
-        self.send(spi, Command::MasterActivation, &[]).await?;
-        Ok(())
+impl<HW> Epd2In9V2<HW, StateReady>
+where
+    HW: BusyHw + DcHw + SpiHw + ErrorHw,
+    HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>
+        + From<WindowError>,
+{
+    /// Refreshes the whole display to black and then to white, `cycles` times, e.g. to clear
+    /// ghosting, to test a panel for burn in, or to draw attention to it.
+    ///
+    /// The black comes from bypassing the RAM with [Bypass::AllZero], and the white from the RAM,
+    /// which is filled with white first. The framebuffer is therefore overwritten, so write it
+    /// again before the next refresh. The RAM bypass is turned off afterwards, including any from
+    /// [WhiteBypass::set_white_bypass].
+    ///
+    /// Each flash uses the current refresh mode's waveform, so use [RefreshMode::Full] to clear
+    /// ghosting. [RefreshMode::Partial] only drives the pixels that changed, so it wouldn't flash,
+    /// and [RefreshMode::Gray2] would need white in both RAMs. In those modes, this switches to
+    /// [RefreshMode::Full] for the flashes, and then back.
+    pub async fn flash_cycles(&mut self, spi: &mut HW::Spi, cycles: u32) -> Result<(), HW::Error> {
+        debug!("Flashing the display {} times", cycles);
+        let mode = self.state.mode;
+        if mode == RefreshMode::Partial || !mode.is_black_and_white() {
+            self.set_refresh_mode(spi, RefreshMode::Full).await?;
+        }
+        let size = self.size();
+        refresh::write_pattern(self, spi, size, |_| (&refresh::WHITE, u32::MAX)).await?;
+        for _ in 0..cycles {
+            for bypass in [Bypass::AllZero, Bypass::Normal] {
+                self.set_ram_bypass(spi, bypass, bypass).await?;
+                self.update_display(spi).await?;
+            }
+        }
+        self.set_ram_bypass(spi, Bypass::Normal, Bypass::Normal)
+            .await?;
+        self.set_refresh_mode(spi, mode).await
     }
 }
 
@@ -749,75 +1305,702 @@ where
     HW: BusyHw + DcHw + SpiHw + ErrorHw,
     HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
-        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>
+        + From<WindowError>,
 {
-    async fn display_framebuffer(
+    async fn write_framebuffer(
         &mut self,
         spi: &mut HW::Spi,
         buf: &dyn BufferView<1, 1>,
     ) -> Result<(), HW::Error> {
-        self.write_framebuffer(spi, buf).await?;
-
-        self.update_display(spi).await
+        let display = Size::new(DISPLAY_WIDTH.into(), DISPLAY_HEIGHT.into());
+        let buffer_bounds = ssd16xx::checked_window(buf, display)?;
+        let window = buffer_bounds.translate(self.ram_offset());
+        ssd16xx::write_ram(
+            &mut self.hw,
+            spi,
+            Command::WriteLowRam.register(),
+            window,
+            buf.data()[0],
+        )
+        .await
     }
+}
 
+#[cfg(feature = "epd2in9_v2-gray2")]
+impl<HW> DisplaySimple<1, 2, HW::Spi, HW::Error> for Epd2In9V2<HW, StateReady>
+where
+    HW: BusyHw + DcHw + SpiHw + ErrorHw,
+    HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>
+        + From<WindowError>,
+{
     async fn write_framebuffer(
         &mut self,
         spi: &mut HW::Spi,
-        buf: &dyn BufferView<1, 1>,
+        buf: &dyn BufferView<1, 2>,
     ) -> Result<(), HW::Error> {
-        let buffer_bounds = buf.window();
+        let display = Size::new(DISPLAY_WIDTH.into(), DISPLAY_HEIGHT.into());
+        let buffer_bounds = ssd16xx::checked_window(buf, display)?;
         self.set_window(spi, buffer_bounds).await?;
         self.set_cursor(spi, buffer_bounds.top_left).await?;
-        self.send(spi, Command::WriteLowRam, buf.data()[0]).await
+        self.hw
+            .send_image(spi, Command::WriteLowRam.register(), buf.data()[0])
+            .await?;
+        self.hw
+            .send_image(spi, Command::WriteHighRam.register(), buf.data()[1])
+            .await
     }
 }
 
-impl<HW> DisplaySimple<1, 2, HW::Spi, HW::Error> for Epd2In9V2<HW, StateReady>
+impl<HW> SetRefreshKind<HW::Spi, HW::Error> for Epd2In9V2<HW, StateReady>
 where
     HW: BusyHw + DcHw + SpiHw + ErrorHw,
     HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
 {
-    async fn display_framebuffer(
+    async fn set_refresh_kind(
         &mut self,
         spi: &mut HW::Spi,
-        buf: &dyn BufferView<1, 2>,
+        kind: RefreshKind,
     ) -> Result<(), HW::Error> {
-        self.write_framebuffer(spi, buf).await?;
+        self.set_refresh_mode(spi, kind.into()).await
+    }
+}
 
-        self.update_display(spi).await
+/// [RefreshKind::Fast] uses [RefreshMode::Full], and [RefreshKind::Full] uses
+/// [RefreshMode::FullSlow], or [RefreshMode::Full] without the `epd2in9_v2-full-slow` feature.
+impl From<RefreshKind> for RefreshMode {
+    fn from(kind: RefreshKind) -> Self {
+        match kind {
+            RefreshKind::Partial => RefreshMode::Partial,
+            RefreshKind::Fast => RefreshMode::Full,
+            #[cfg(feature = "epd2in9_v2-full-slow")]
+            RefreshKind::Full => RefreshMode::FullSlow,
+            #[cfg(not(feature = "epd2in9_v2-full-slow"))]
+            RefreshKind::Full => RefreshMode::Full,
+        }
     }
+}
 
-    async fn write_framebuffer(
+impl<HW> Epd<HW::Spi, HW::Error> for Epd2In9V2<HW, StateUninitialized>
+where
+    HW: BusyHw + DcHw + ResetHw + DelayHw + SpiHw + ErrorHw,
+    HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Reset as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>
+        + From<WindowError>,
+{
+    const SIZE: Size = Size::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32);
+
+    type Color = BinaryColor;
+    type Buffer = Epd2In9BinaryBuffer;
+    type Ready = Epd2In9V2<HW, StateReady>;
+    type Asleep = Epd2In9V2<HW, StateAsleep<StateReady>>;
+
+    fn new_buffer() -> Self::Buffer {
+        new_binary_buffer()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Epd2In9V2::capabilities(self)
+    }
+
+    async fn init(self, spi: &mut HW::Spi, kind: RefreshKind) -> Result<Self::Ready, HW::Error> {
+        Epd2In9V2::init(self, spi, kind.into()).await
+    }
+}
+
+impl<HW> Epd2In9V2<HW, StateReady>
+where
+    HW: BusyHw + DcHw + DelayHw + SpiHw + ErrorHw,
+    HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>
+        + From<WindowError>,
+{
+    /// Runs a [self test](crate::self_test), pausing for `pause_ms` after each step so the
+    /// result can be checked by eye.
+    ///
+    /// The display is left white, in [RefreshMode::FullSlow], or [RefreshMode::Full] without the
+    /// `epd2in9_v2-full-slow` feature.
+    pub async fn self_test(
         &mut self,
         spi: &mut HW::Spi,
-        buf: &dyn BufferView<1, 2>,
+        pause_ms: u32,
+    ) -> Result<SelfTestReport, HW::Error> {
+        let display = Size::new(DISPLAY_WIDTH.into(), DISPLAY_HEIGHT.into());
+        self_test::run(self, spi, display, pause_ms).await
+    }
+}
+
+impl<HW> SelfTestTarget<HW::Spi, HW::Error> for Epd2In9V2<HW, StateReady>
+where
+    HW: BusyHw + DcHw + DelayHw + SpiHw + ErrorHw,
+    HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>
+        + From<WindowError>,
+{
+    /// Makes the border follow the black or white waveform.
+    async fn set_test_border(
+        &mut self,
+        spi: &mut HW::Spi,
+        color: BinaryColor,
     ) -> Result<(), HW::Error> {
-        let buffer_bounds = buf.window();
-        self.set_window(spi, buffer_bounds).await?;
-        self.set_cursor(spi, buffer_bounds.top_left).await?;
-        self.send(spi, Command::WriteLowRam, buf.data()[0]).await?;
-        self.send(spi, Command::WriteHighRam, buf.data()[1]).await
+        self.set_border(spi, color).await
+    }
+
+    async fn start_test_refresh(&mut self, spi: &mut HW::Spi) -> Result<bool, HW::Error> {
+        self.update_display(spi).await?;
+        self.hw.is_busy()
+    }
+
+    fn test_timings(&mut self) -> Option<&mut Timings> {
+        self.hw.timings()
+    }
+
+    async fn pause(&mut self, ms: u32) {
+        self.hw.delay().delay_ms(ms).await;
     }
 }
 
-impl<HW> DisplayPartial<1, 1, HW::Spi, HW::Error> for Epd2In9V2<HW, StateReady>
+impl<HW> Epd2In9V2<HW, StateReady>
 where
     HW: BusyHw + DcHw + SpiHw + ErrorHw,
     HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
         + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
+{
+    /// **Dangerous:** permanently programs a waveform into the display's OTP. See [crate::otp].
+    ///
+    /// The display's registers and RAM are overwritten, so the display must be initialised again
+    /// afterwards.
+    pub async fn program_waveform_otp(
+        mut self,
+        spi: &mut HW::Spi,
+        program: WaveformOtp<'_, Confirmed>,
+    ) -> Result<Epd2In9V2<HW, StateUninitialized>, HW::Error> {
+        otp::program(&mut self, spi, program).await?;
+        Ok(Epd2In9V2 {
+            hw: self.hw,
+            state: StateUninitialized(),
+        })
+    }
+
+    /// **Dangerous:** permanently programs the display's VCOM voltage into its OTP, then reads the
+    /// VCOM register back to check it. See [crate::otp].
+    ///
+    /// The read back requires the display's data line to be readable, as with
+    /// [Epd2In9V2::send_read]. The VCOM register keeps the programmed value until the refresh
+    /// mode is next changed.
+    pub async fn program_vcom_otp(
+        &mut self,
+        spi: &mut HW::Spi,
+        program: VcomOtp<Confirmed>,
+    ) -> Result<VcomReadBack, HW::Error> {
+        otp::program_vcom(self, spi, program).await
+    }
+}
+
+impl<HW> OtpTarget<HW::Spi, HW::Error> for Epd2In9V2<HW, StateReady>
+where
+    HW: BusyHw + DcHw + SpiHw + ErrorHw,
+    HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
+{
+    async fn otp_step(
+        &mut self,
+        spi: &mut HW::Spi,
+        step: OtpStep,
+        data: &[u8],
+    ) -> Result<(), HW::Error> {
+        debug!("Running OTP step {:?}", step);
+        match step {
+            OtpStep::EnableClock => ssd16xx::activate(&mut self.hw, spi, &[0x80]).await,
+            OtpStep::SetInternalVoltage(internal) => {
+                let mode = if internal { 0x03 } else { 0x00 };
+                self.send(spi, Command::SetOtpProgramMode, &[mode]).await
+            }
+            OtpStep::WriteRam => {
+                let display = Size::new(DISPLAY_WIDTH.into(), DISPLAY_HEIGHT.into());
+                self.set_window(spi, Rectangle::new(Point::zero(), display))
+                    .await?;
+                self.set_cursor(spi, Point::zero()).await?;
+                self.send(spi, Command::WriteLowRam, data).await
+            }
+            OtpStep::WriteVcom(vcom) => self.send(spi, Command::WriteVcom, &[vcom]).await,
+            OtpStep::ProgramWaveform => self.send(spi, Command::ProgramWsOtp, &[]).await,
+            OtpStep::ProgramVcom => self.send(spi, Command::ProgramVcomOtp, &[]).await,
+            OtpStep::ProgramSelection => self.send(spi, Command::ProgramOtpSelection, &[]).await,
+            OtpStep::LoadWaveform => self.send(spi, Command::LoadWsOtp, &[]).await,
+        }
+    }
+
+    async fn read_vcom_register(&mut self, spi: &mut HW::Spi) -> Result<u8, HW::Error> {
+        // The VCOM OTP selection, followed by the VCOM register.
+        let mut registers = [0; 2];
+        self.send_read(spi, Command::ReadOtpRegisters, &[], &mut registers)
+            .await?;
+        Ok(registers[1])
+    }
+}
+
+impl<HW> WhiteBypass<HW::Spi, HW::Error> for Epd2In9V2<HW, StateReady>
+where
+    HW: BusyHw + DcHw + SpiHw + ErrorHw,
+    HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>,
+{
+    /// Uses [RefreshMode::Partial], with the diff base bypassed as all black, so only the white
+    /// pixels differ from it.
+    async fn set_white_bypass(
+        &mut self,
+        spi: &mut HW::Spi,
+        enabled: bool,
+    ) -> Result<(), HW::Error> {
+        self.set_refresh_mode(spi, RefreshMode::Partial).await?;
+        let base = if enabled {
+            Bypass::AllZero
+        } else {
+            Bypass::Normal
+        };
+        self.set_ram_bypass(spi, Bypass::Normal, base).await
+    }
+}
+
+impl<HW> DisplayPartial<1, 1, HW::Spi, HW::Error> for Epd2In9V2<HW, StateReady>
+where
+    HW: BusyHw + DcHw + SpiHw + ErrorHw,
+    HW::Error: From<<HW::Busy as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Dc as embedded_hal::digital::ErrorType>::Error>
+        + From<<HW::Spi as embedded_hal_async::spi::ErrorType>::Error>
+        + From<WindowError>,
 {
     async fn write_base_framebuffer(
         &mut self,
         spi: &mut HW::Spi,
         buf: &dyn BufferView<1, 1>,
     ) -> Result<(), HW::Error> {
-        let buffer_bounds = buf.window();
-        self.set_window(spi, buffer_bounds).await?;
-        self.set_cursor(spi, buffer_bounds.top_left).await?;
-        self.send(spi, Command::WriteHighRam, buf.data()[0]).await
+        let display = Size::new(DISPLAY_WIDTH.into(), DISPLAY_HEIGHT.into());
+        let buffer_bounds = ssd16xx::checked_window(buf, display)?;
+        let window = buffer_bounds.translate(self.ram_offset());
+        ssd16xx::write_ram(
+            &mut self.hw,
+            spi,
+            Command::WriteHighRam.register(),
+            window,
+            buf.data()[0],
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::Bitmap,
+        testing::{block_on, Event, MockError, MockHw, PoweredHw},
+    };
+
+    #[test]
+    fn test_reads_user_id() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = Epd2In9V2::attach(hw, RefreshMode::Full);
+
+        // The mock display reads back zeros, like a panel whose user ID was never programmed.
+        assert_eq!(block_on(epd.read_user_id(&mut spi)).unwrap(), [0; 10]);
+        assert_eq!(
+            log.take_events(),
+            [Event::Command(Command::ReadUserId as u8), Event::Read(10)]
+        );
+    }
+
+    #[test]
+    fn test_reads_low_power_detection() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = Epd2In9V2::attach(hw, RefreshMode::Full);
+
+        // The mock display reads back zeros, so the supply is fine.
+        assert!(!block_on(epd.read_low_power_detection(&mut spi, VciLevel::V2_5)).unwrap());
+        assert_eq!(
+            log.take_events(),
+            [
+                Event::Command(Command::DisplayUpdateControl2 as u8),
+                Event::Data(std::vec![0xC0]),
+                Event::Command(Command::MasterActivation as u8),
+                Event::Command(Command::VciDetection as u8),
+                Event::Data(std::vec![0b101]),
+                Event::Command(Command::StatusBitRead as u8),
+                Event::Read(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_attach_keeps_the_display_state() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = Epd2In9V2::attach(hw, RefreshMode::Partial);
+        assert!(log.take_events().is_empty());
+
+        let buffer = new_binary_buffer();
+        block_on(epd.display_framebuffer(&mut spi, &buffer)).unwrap();
+        assert!(!log.commands().contains(&(Command::SwReset as u8)));
+    }
+
+    #[test]
+    #[cfg(feature = "epd2in9_v2-gray2")]
+    fn test_state_snapshot_round_trips() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let mut spi = hw.spi();
+        let epd = Epd2In9V2::attach(hw, RefreshMode::Gray2);
+        let epd = block_on(epd.sleep(&mut spi)).unwrap();
+
+        let (hw, state) = epd.into_state_snapshot();
+        let snapshot = state.to_snapshot();
+        assert_eq!(StateReady::from_snapshot(snapshot), None);
+        let state = StateAsleep::<StateReady>::from_snapshot(snapshot).unwrap();
+        let epd = Epd2In9V2::from_state_snapshot(hw, state);
+        let epd = block_on(epd.wake(&mut spi)).unwrap();
+        assert_eq!(
+            epd.into_state_snapshot().1,
+            StateReady::from_snapshot(0x13).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_otp_mode_skips_the_lut() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Otp)).unwrap();
+        let commands = log.commands();
+        assert!(!commands.contains(&(Command::WriteLut as u8)));
+        assert!(commands.contains(&(Command::TemperatureSensorSelection as u8)));
+
+        let buffer = new_binary_buffer();
+        block_on(epd.display_framebuffer(&mut spi, &buffer)).unwrap();
+        let events = log.take_events();
+        let update = events
+            .iter()
+            .rposition(|e| *e == Event::Command(Command::DisplayUpdateControl2 as u8))
+            .unwrap();
+        assert_eq!(events[update + 1], Event::Data(std::vec![0xF7]));
+    }
+
+    #[test]
+    fn test_otp_mode_uses_external_temperature() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = Epd2In9V2::attach(hw, RefreshMode::Otp);
+
+        block_on(epd.set_external_temperature(&mut spi, -5)).unwrap();
+        block_on(epd.update_display(&mut spi)).unwrap();
+        assert_eq!(
+            log.take_events()[..4],
+            [
+                Event::Command(Command::WriteTemperature as u8),
+                Event::Data(std::vec![0xFB, 0x00]),
+                Event::Command(Command::DisplayUpdateControl2 as u8),
+                Event::Data(std::vec![0xD7]),
+            ]
+        );
+
+        let (hw, state) = epd.into_state_snapshot();
+        assert_eq!(StateReady::from_snapshot(state.to_snapshot()), Some(state));
+        let mut epd = Epd2In9V2::from_state_snapshot(hw, state);
+        epd.use_internal_temperature();
+        block_on(epd.update_display(&mut spi)).unwrap();
+        assert_eq!(log.take_events()[1], Event::Data(std::vec![0xF7]));
+    }
+
+    #[test]
+    fn test_external_temperature_is_kept_across_resets() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = Epd2In9V2::attach(hw, RefreshMode::Otp);
+        let temperature_after_reset = |events: std::vec::Vec<Event>| {
+            let reset = events
+                .iter()
+                .rposition(|e| *e == Event::Command(Command::SwReset as u8))
+                .unwrap();
+            events[reset + 1..reset + 3].to_vec()
+        };
+        let temperature = [
+            Event::Command(Command::WriteTemperature as u8),
+            Event::Data(std::vec![0xFB, 0x00]),
+        ];
+
+        block_on(epd.set_external_temperature(&mut spi, -5)).unwrap();
+        block_on(epd.set_refresh_mode(&mut spi, RefreshMode::Full)).unwrap();
+        block_on(epd.set_refresh_mode(&mut spi, RefreshMode::Otp)).unwrap();
+        assert_eq!(temperature_after_reset(log.take_events()), temperature);
+        block_on(epd.update_display(&mut spi)).unwrap();
+        assert_eq!(log.take_events()[1], Event::Data(std::vec![0xD7]));
+
+        let epd = block_on(epd.sleep(&mut spi)).unwrap();
+        let (hw, state) = epd.into_state_snapshot();
+        let state = StateAsleep::<StateReady>::from_snapshot(state.to_snapshot()).unwrap();
+        let epd = Epd2In9V2::from_state_snapshot(hw, state);
+        log.take_events();
+        let mut epd = block_on(epd.wake(&mut spi)).unwrap();
+        assert_eq!(temperature_after_reset(log.take_events()), temperature);
+        block_on(epd.update_display(&mut spi)).unwrap();
+        assert_eq!(log.take_events()[1], Event::Data(std::vec![0xD7]));
+    }
+
+    #[test]
+    fn test_wake_restores_the_refresh_mode() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        let init_events = log.take_events();
+
+        let epd = block_on(epd.sleep(&mut spi)).unwrap();
+        log.take_events();
+        let epd = block_on(epd.wake(&mut spi)).unwrap();
+
+        // Waking replays the same reset and configuration as initialising.
+        assert_eq!(log.take_events(), init_events);
+        let expected = Epd2In9V2::attach(MockHw::new(DEFAULT_BUSY_WHEN), RefreshMode::Partial);
+        assert_eq!(
+            epd.into_state_snapshot().1,
+            expected.into_state_snapshot().1
+        );
+    }
+
+    #[test]
+    fn test_runs_power_hooks_around_reset_and_sleep() {
+        let hw = PoweredHw::new(MockHw::new(DEFAULT_BUSY_WHEN));
+        let log = hw.log();
+        let mut spi = hw.spi();
+
+        let epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        let epd = block_on(epd.sleep(&mut spi)).unwrap();
+        let epd = block_on(epd.wake(&mut spi)).unwrap();
+
+        let events = log.events();
+        let hw = epd.into_state_snapshot().0;
+        // Powered on before both resets, before any reset pin changes.
+        assert_eq!(hw.power_ons().len(), 2);
+        assert_eq!(hw.power_ons()[0], 0);
+        assert!(matches!(events[hw.power_ons()[1]], Event::Reset(_)));
+        // Powered off straight after the deep sleep command.
+        assert_eq!(hw.power_offs().len(), 1);
+        assert_eq!(
+            events[hw.power_offs()[0] - 2],
+            Event::Command(Command::DeepSleepMode as u8)
+        );
+        assert_eq!(hw.power_offs()[0], hw.power_ons()[1]);
+    }
+
+    #[test]
+    fn test_starts_the_booster_for_the_power_profile() {
+        let hw = PoweredHw::new(MockHw::new(DEFAULT_BUSY_WHEN))
+            .with_power_profile(PowerProfile::LowPeakCurrent);
+        let log = hw.log();
+        let mut spi = hw.spi();
+
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        block_on(epd.set_refresh_mode(&mut spi, RefreshMode::Partial)).unwrap();
+
+        // The booster is configured after every software reset, which restores its defaults.
+        let events = log.events();
+        let boosts: std::vec::Vec<_> = events
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| **event == Event::Command(Command::BoosterSoftStartControl as u8))
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(boosts.len(), 2);
+        for i in boosts {
+            assert_eq!(
+                events[i + 1],
+                Event::Data(std::vec![0x8F, 0x8F, 0x8F, 0x3F])
+            );
+        }
+        let software_resets = log
+            .commands()
+            .iter()
+            .filter(|&&command| command == Command::SwReset as u8)
+            .count();
+        assert_eq!(software_resets, 2);
+    }
+
+    #[test]
+    #[cfg(feature = "epd2in9_v2-gray2")]
+    fn test_border_follows_the_refresh_mode() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        let mut border_data = |mode: RefreshMode, border: Border| {
+            block_on(epd.set_refresh_mode(&mut spi, mode)).unwrap();
+            log.take_events();
+            block_on(epd.set_border(&mut spi, border)).unwrap();
+            assert_eq!(log.commands(), [Command::SetBorderWaveform as u8]);
+            match log.events()[1] {
+                Event::Data(ref data) => data[0],
+                ref event => panic!("expected the border data, got {event:?}"),
+            }
+        };
+
+        assert_eq!(border_data(RefreshMode::Full, Border::White), 0x05);
+        let gray = Border::Gray(Gray2::new(2));
+        assert_eq!(border_data(RefreshMode::Full, gray), 0x05);
+        assert_eq!(border_data(RefreshMode::Full, Border::HiZ), 0xC0);
+        assert_eq!(border_data(RefreshMode::Gray2, Border::White), 0x07);
+        assert_eq!(border_data(RefreshMode::Gray2, gray), 0x06);
+        assert_eq!(border_data(RefreshMode::Gray2, Border::Vcom), 0x80);
+    }
+
+    #[test]
+    fn test_border_is_kept_across_refresh_modes() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        block_on(epd.set_border(&mut spi, Border::HiZ)).unwrap();
+        log.take_events();
+
+        block_on(epd.set_refresh_mode(&mut spi, RefreshMode::Partial)).unwrap();
+        let events = log.take_events();
+        let border = events
+            .iter()
+            .rposition(|e| *e == Event::Command(Command::SetBorderWaveform as u8))
+            .unwrap();
+        assert_eq!(events[border + 1], Event::Data(std::vec![0xC0]));
+
+        let snapshot = epd.into_state_snapshot().1.to_snapshot();
+        let state = StateReady::from_snapshot(snapshot).unwrap();
+        assert_eq!(state.border, Some(Border::HiZ));
+        assert_eq!(state.mode, RefreshMode::Partial);
+    }
+
+    #[test]
+    fn test_wake_restores_the_border() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        block_on(epd.set_border(&mut spi, Border::Black)).unwrap();
+        let epd = block_on(epd.sleep(&mut spi)).unwrap();
+        log.take_events();
+
+        block_on(epd.wake(&mut spi)).unwrap();
+        let events = log.take_events();
+        let borders: std::vec::Vec<_> = events
+            .windows(2)
+            .filter(|pair| pair[0] == Event::Command(Command::SetBorderWaveform as u8))
+            .map(|pair| pair[1].clone())
+            .collect();
+        assert_eq!(borders, [Event::Data(std::vec![0x04])]);
+    }
+
+    #[test]
+    fn test_flash_cycles_bypass_the_ram_for_black() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        log.take_events();
+
+        block_on(epd.flash_cycles(&mut spi, 2)).unwrap();
+        let events = log.events();
+        let bypasses: std::vec::Vec<_> = events
+            .windows(2)
+            .filter(|pair| pair[0] == Event::Command(Command::DisplayUpdateControl1 as u8))
+            .map(|pair| pair[1].clone())
+            .collect();
+        let black = Event::Data(std::vec![0x44, 0x80]);
+        let normal = Event::Data(std::vec![0x00, 0x80]);
+        assert_eq!(
+            bypasses,
+            [black.clone(), normal.clone(), black, normal.clone(), normal]
+        );
+        let commands = log.commands();
+        let refreshes = commands
+            .iter()
+            .filter(|&&command| command == Command::MasterActivation as u8)
+            .count();
+        assert_eq!(refreshes, 4);
+        // The white comes from the RAM, which is filled before the first flash.
+        let first_write = commands
+            .iter()
+            .position(|&command| command == Command::WriteLowRam as u8)
+            .unwrap();
+        let first_bypass = commands
+            .iter()
+            .position(|&command| command == Command::DisplayUpdateControl1 as u8)
+            .unwrap();
+        assert!(first_write < first_bypass);
+        let white: usize = events
+            .windows(2)
+            .filter_map(|pair| match pair {
+                [Event::Command(c), Event::Data(data)] if *c == Command::WriteLowRam as u8 => {
+                    assert!(data.iter().all(|&byte| byte == 0xFF));
+                    Some(data.len())
+                }
+                _ => None,
+            })
+            .sum();
+        assert_eq!(white, binary_buffer_length(epd.size()));
+    }
+
+    #[test]
+    fn test_flash_cycles_use_the_full_waveform_in_partial_mode() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        log.take_events();
+
+        block_on(epd.flash_cycles(&mut spi, 1)).unwrap();
+        let events = log.events();
+        let position = |event: Event| events.iter().position(|e| *e == event).unwrap();
+        let full_lut = position(Event::Data(RefreshMode::Full.lut().to_vec()));
+        let partial_lut = position(Event::Data(RefreshMode::Partial.lut().to_vec()));
+        // Both flashes of the cycle are between the full and partial LUTs.
+        let flashes = events[full_lut..partial_lut]
+            .iter()
+            .filter(|e| **e == Event::Command(Command::MasterActivation as u8))
+            .count();
+        assert_eq!(flashes, 2);
+        assert_eq!(epd.into_state_snapshot().1.mode, RefreshMode::Partial);
+    }
+
+    #[test]
+    fn test_invalid_windows_are_rejected() {
+        let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = Epd2In9V2::attach(hw, RefreshMode::Full);
+
+        let empty = Bitmap::new(&[], Size::new(0, 8)).unwrap();
+        assert_eq!(
+            block_on(epd.write_framebuffer(&mut spi, &empty)),
+            Err(MockError::Window(WindowError::Empty))
+        );
+        let too_wide = Bitmap::new(&[0; 17], Size::new(DISPLAY_WIDTH as u32 + 8, 1)).unwrap();
+        assert_eq!(
+            block_on(epd.write_base_framebuffer(&mut spi, &too_wide)),
+            Err(MockError::Window(WindowError::OutOfBounds))
+        );
+        assert!(log.events().is_empty());
     }
 }
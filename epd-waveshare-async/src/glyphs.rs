@@ -0,0 +1,272 @@
+//! Caches the glyphs of a [MonoFont] as packed bitmaps, so that text that's redrawn often, such as
+//! the digits of a clock, can be copied into a [BinaryBuffer] without rendering it through
+//! `embedded-graphics` each time.
+//!
+//! Glyphs are copied a byte at a time, and whole bytes are written directly when the text starts
+//! on a multiple of 8 pixels.
+//!
+#![cfg_attr(feature = "epd2in9_v2", doc = "```")]
+#![cfg_attr(not(feature = "epd2in9_v2"), doc = "```ignore")]
+//! use embedded_graphics::{mono_font::ascii::FONT_10X20, pixelcolor::BinaryColor, prelude::Point};
+//! use epd_waveshare_async::{
+//!     epd2in9_v2,
+//!     glyphs::{glyph_length, GlyphCache},
+//! };
+//!
+//! let digits =
+//!     GlyphCache::<11, { glyph_length(&FONT_10X20) }>::new(&FONT_10X20, "0123456789:");
+//! let mut buffer = epd2in9_v2::new_binary_buffer();
+//! let changed = digits.draw(
+//!     "12:34",
+//!     Point::new(16, 8),
+//!     BinaryColor::Off,
+//!     Some(BinaryColor::On),
+//!     &mut buffer,
+//! );
+//! assert_eq!(changed.size, digits.text_size("12:34"));
+//! ```
+use core::convert::Infallible;
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    mono_font::{MonoFont, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::{Dimensions, Point, Size},
+    primitives::Rectangle,
+    text::{Baseline, Text},
+    Drawable, Pixel,
+};
+use heapless::Vec;
+
+use crate::{
+    buffer::{BinaryBuffer, Bitmap},
+    log::warning,
+};
+
+/// Computes the number of bytes that each of `font`'s glyphs takes when packed, with each row
+/// rounded up to a whole byte.
+pub const fn glyph_length(font: &MonoFont) -> usize {
+    (font.character_size.width as usize).div_ceil(8) * font.character_size.height as usize
+}
+
+/// Up to `N` glyphs of a font, each packed into `L` bytes.
+///
+/// `L` must be [glyph_length] of the font.
+pub struct GlyphCache<'a, const N: usize, const L: usize> {
+    font: &'a MonoFont<'a>,
+    glyphs: Vec<(char, [u8; L]), N>,
+}
+
+impl<'a, const N: usize, const L: usize> GlyphCache<'a, N, L> {
+    /// Renders each character of `chars` with `font`.
+    ///
+    /// Repeated characters are only stored once. Characters beyond the first `N` are ignored.
+    pub fn new(font: &'a MonoFont<'a>, chars: &str) -> Self {
+        debug_assert_eq!(
+            glyph_length(font),
+            L,
+            "Glyph length must match the font's character size"
+        );
+        let mut cache = Self {
+            font,
+            glyphs: Vec::new(),
+        };
+        for c in chars.chars() {
+            if cache.contains(c) {
+                continue;
+            }
+            let glyph = cache.rasterize(c);
+            if cache.glyphs.push((c, glyph)).is_err() {
+                warning!("Glyph cache is full; ignoring the remaining characters");
+                break;
+            }
+        }
+        cache
+    }
+
+    fn rasterize(&self, c: char) -> [u8; L] {
+        let mut canvas = GlyphCanvas {
+            size: self.font.character_size,
+            data: [0; L],
+        };
+        let mut text = [0; 4];
+        let style = MonoTextStyle::new(self.font, BinaryColor::On);
+        // Drawing to the canvas can't fail.
+        let _ = Text::with_baseline(
+            c.encode_utf8(&mut text),
+            Point::zero(),
+            style,
+            Baseline::Top,
+        )
+        .draw(&mut canvas);
+        canvas.data
+    }
+
+    /// Whether `c` has been cached.
+    pub fn contains(&self, c: char) -> bool {
+        self.glyph(c).is_some()
+    }
+
+    /// Returns the packed glyph for `c`, with its rows from top to bottom, and the leftmost pixel
+    /// of each byte in the most significant bit.
+    pub fn glyph(&self, c: char) -> Option<&[u8; L]> {
+        self.glyphs
+            .iter()
+            .find(|(cached, _)| *cached == c)
+            .map(|(_, glyph)| glyph)
+    }
+
+    /// Returns the size of `text` when drawn with this cache, including the font's character
+    /// spacing.
+    pub fn text_size(&self, text: &str) -> Size {
+        let count = text.chars().count() as u32;
+        if count == 0 {
+            return Size::zero();
+        }
+        let character = self.font.character_size;
+        Size::new(
+            count * character.width + (count - 1) * self.font.character_spacing,
+            character.height,
+        )
+    }
+
+    /// Draws `text` with its top left at `top_left`, and returns the area that it covers.
+    ///
+    /// Glyph pixels are set to `foreground`. If `background` is given, the rest of the text's
+    /// area is filled with it first; otherwise, it's left unchanged. Characters that aren't cached
+    /// are drawn as blank space.
+    pub fn draw<const BL: usize>(
+        &self,
+        text: &str,
+        top_left: Point,
+        foreground: BinaryColor,
+        background: Option<BinaryColor>,
+        buffer: &mut BinaryBuffer<BL>,
+    ) -> Rectangle {
+        let area = Rectangle::new(top_left, self.text_size(text));
+        if let Some(background) = background {
+            // Filling a buffer can't fail.
+            let _ = buffer.fill_solid(&area, background);
+        }
+
+        let size = self.font.character_size;
+        let advance = (size.width + self.font.character_spacing) as i32;
+        for (i, c) in text.chars().enumerate() {
+            let Some(glyph) = self.glyph(c).and_then(|glyph| Bitmap::new(glyph, size)) else {
+                continue;
+            };
+            let x = top_left.x + i as i32 * advance;
+            buffer.stamp(&glyph, Point::new(x, top_left.y), foreground);
+        }
+        area
+    }
+}
+
+/// A single glyph's packed bitmap, for rasterizing it with `embedded-graphics`.
+struct GlyphCanvas<const L: usize> {
+    size: Size,
+    data: [u8; L],
+}
+
+impl<const L: usize> Dimensions for GlyphCanvas<L> {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.size)
+    }
+}
+
+impl<const L: usize> DrawTarget for GlyphCanvas<L> {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let bytes_per_row = (self.size.width as usize).div_ceil(8);
+        let bounds = self.bounding_box();
+        for Pixel(point, color) in pixels {
+            if color != BinaryColor::On || !bounds.contains(point) {
+                continue;
+            }
+            let index = point.y as usize * bytes_per_row + point.x as usize / 8;
+            self.data[index] |= 0x80 >> (point.x % 8);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::mono_font::{ascii::FONT_6X10, MonoTextStyleBuilder};
+
+    use super::*;
+    use crate::buffer::binary_buffer_length;
+
+    const SIZE: Size = Size::new(64, 16);
+
+    type Buffer = BinaryBuffer<{ binary_buffer_length(SIZE) }>;
+
+    fn cache() -> GlyphCache<'static, 4, { glyph_length(&FONT_6X10) }> {
+        GlyphCache::new(&FONT_6X10, "0123")
+    }
+
+    fn render(text: &str, top_left: Point) -> Buffer {
+        let mut buffer = Buffer::new(SIZE);
+        let style = MonoTextStyleBuilder::new()
+            .font(&FONT_6X10)
+            .text_color(BinaryColor::On)
+            .build();
+        Text::with_baseline(text, top_left, style, Baseline::Top)
+            .draw(&mut buffer)
+            .unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_drawn_text_matches_embedded_graphics() {
+        let cache = cache();
+        for top_left in [Point::new(8, 2), Point::new(3, 2), Point::new(-2, -3)] {
+            let mut buffer = Buffer::new(SIZE);
+            let area = cache.draw("2013", top_left, BinaryColor::On, None, &mut buffer);
+            assert_eq!(area, Rectangle::new(top_left, Size::new(24, 10)));
+            assert_eq!(buffer.data(), render("2013", top_left).data());
+        }
+    }
+
+    #[test]
+    fn test_background_is_filled_and_missing_glyphs_are_blank() {
+        let cache = cache();
+        let mut buffer = Buffer::new(SIZE);
+        cache.draw(
+            "04",
+            Point::zero(),
+            BinaryColor::Off,
+            Some(BinaryColor::On),
+            &mut buffer,
+        );
+
+        let mut expected = Buffer::new(SIZE);
+        expected
+            .fill_solid(
+                &Rectangle::new(Point::zero(), Size::new(12, 10)),
+                BinaryColor::On,
+            )
+            .unwrap();
+        let style = MonoTextStyleBuilder::new()
+            .font(&FONT_6X10)
+            .text_color(BinaryColor::Off)
+            .build();
+        Text::with_baseline("0", Point::zero(), style, Baseline::Top)
+            .draw(&mut expected)
+            .unwrap();
+        assert_eq!(buffer.data(), expected.data());
+    }
+
+    #[test]
+    fn test_stores_each_character_once() {
+        let cache = GlyphCache::<2, { glyph_length(&FONT_6X10) }>::new(&FONT_6X10, "11213");
+        assert!(cache.contains('1'));
+        assert!(cache.contains('2'));
+        assert!(!cache.contains('3'));
+    }
+}
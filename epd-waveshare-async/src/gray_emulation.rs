@@ -0,0 +1,135 @@
+//! Experimental: shows 4 gray levels on black and white displays, by refreshing each pixel towards
+//! white a number of times in proportion to its gray level.
+//!
+//! The display is first fully refreshed to black. Then 3 partial refreshes follow, each of which
+//! only drives pixels towards white, leaving the rest untouched: the first lightens every pixel that
+//! isn't black, the second those that are light gray or white, and the third only the white ones.
+//!
+//! The exact shades depend on the panel and the temperature, and they fade with further partial
+//! refreshes, so do a full refresh before showing anything else. The 2.9" V2 display also supports
+//! true grayscale, with `epd2in9_v2::RefreshMode::Gray2`.
+//!
+//! ```text
+//! let mut gray = epd2in9::new_gray2_buffer();
+//! draw_photo(&mut gray)?;
+//! let mut scratch = epd2in9::new_binary_buffer();
+//! gray_emulation::display_gray2(&mut epd, &mut spi, &gray, &mut scratch).await?;
+//! ```
+use embedded_graphics::prelude::Dimensions;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::{
+    buffer::{BinaryBuffer, Gray2SplitBuffer},
+    log::debug,
+    refresh::{RefreshKind, SetRefreshKind},
+    DisplaySimple, WaitIdle,
+};
+
+/// Displays that can do partial refreshes that only drive pixels towards white.
+pub trait WhiteBypass<SPI: SpiDevice, ERROR> {
+    /// Switches to partial refreshes that only update the framebuffer's white pixels, if
+    /// `enabled`, or back to normal partial refreshes otherwise.
+    async fn set_white_bypass(&mut self, spi: &mut SPI, enabled: bool) -> Result<(), ERROR>;
+}
+
+/// Shows `gray` by refreshing the display once to black, and then 3 times towards white.
+///
+/// `scratch` holds each refresh's frame, so it must be the same size as `gray`. The display is
+/// left in its normal partial refresh mode.
+pub async fn display_gray2<D, SPI, ERROR, const L: usize>(
+    epd: &mut D,
+    spi: &mut SPI,
+    gray: &Gray2SplitBuffer<L>,
+    scratch: &mut BinaryBuffer<L>,
+) -> Result<(), ERROR>
+where
+    D: DisplaySimple<1, 1, SPI, ERROR>
+        + SetRefreshKind<SPI, ERROR>
+        + WhiteBypass<SPI, ERROR>
+        + WaitIdle<ERROR>,
+    SPI: SpiDevice,
+{
+    debug_assert_eq!(
+        gray.low.bounding_box(),
+        scratch.bounding_box(),
+        "Buffer sizes must match"
+    );
+    debug!("Emulating grayscale");
+    epd.set_refresh_kind(spi, RefreshKind::Full).await?;
+    scratch.data_mut().fill(0);
+    epd.display_framebuffer(spi, scratch).await?;
+    epd.wait_until_idle().await?;
+
+    epd.set_white_bypass(spi, true).await?;
+    for pass in 0..3 {
+        lighter_than(gray, pass, scratch);
+        epd.display_framebuffer(spi, scratch).await?;
+        epd.wait_until_idle().await?;
+    }
+    epd.set_white_bypass(spi, false).await
+}
+
+/// Sets each pixel in `out` to white if its level in `gray` is greater than `level`, or black
+/// otherwise.
+fn lighter_than<const L: usize>(gray: &Gray2SplitBuffer<L>, level: u8, out: &mut BinaryBuffer<L>) {
+    let pixels = gray.low.data().iter().zip(gray.high.data());
+    for (out, (low, high)) in out.data_mut().iter_mut().zip(pixels) {
+        *out = match level {
+            0 => low | high,
+            1 => *high,
+            _ => low & high,
+        };
+    }
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use embedded_graphics::{
+        pixelcolor::Gray2,
+        prelude::{DrawTarget, Point, Size},
+        Pixel,
+    };
+
+    use super::*;
+    use crate::{
+        buffer::binary_buffer_length,
+        epd2in9_v2::{self, Bypass, Command, Epd2In9V2, RefreshMode},
+        testing::{block_on, Event, MockHw},
+    };
+
+    const SIZE: Size = Size::new(8, 1);
+
+    #[test]
+    fn test_display_gray2_lightens_in_passes() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        log.take_events();
+
+        let mut gray = Gray2SplitBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let levels = [0, 1, 2, 3, 3, 2, 1, 0];
+        gray.draw_iter(
+            levels
+                .iter()
+                .enumerate()
+                .map(|(x, level)| Pixel(Point::new(x as i32, 0), Gray2::new(*level))),
+        )
+        .unwrap();
+        let mut scratch = BinaryBuffer::new(SIZE);
+        block_on(display_gray2(&mut epd, &mut spi, &gray, &mut scratch)).unwrap();
+
+        let events = log.take_events();
+        let frames: Vec<_> = events
+            .windows(2)
+            .filter(|pair| pair[0] == Event::Command(Command::WriteLowRam as u8))
+            .map(|pair| pair[1].clone())
+            .collect();
+        assert_eq!(
+            frames,
+            [0x00, 0b0111_1110, 0b0011_1100, 0b0001_1000].map(|frame| Event::Data(vec![frame]))
+        );
+        // The passes only read white pixels, against a base of all zeros (black).
+        assert!(events.contains(&Event::Data(vec![(Bypass::AllZero as u8) << 4, 0x80])));
+    }
+}
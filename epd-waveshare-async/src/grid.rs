@@ -0,0 +1,479 @@
+//! Tables and month calendars, drawn as a grid of cells with optional headers and shading.
+//!
+//! ```
+//! use embedded_graphics::{
+//!     mono_font::ascii::FONT_6X10,
+//!     pixelcolor::BinaryColor,
+//!     prelude::{Point, Size},
+//!     primitives::Rectangle,
+//! };
+//! use epd_waveshare_async::{
+//!     epd2in9_v2,
+//!     grid::{MonthGrid, Shade},
+//!     widgets::{Widget, WidgetStyle},
+//! };
+//!
+//! const WEEKDAYS: [&str; 7] = ["M", "T", "W", "T", "F", "S", "S"];
+//!
+//! let style = WidgetStyle::new(&FONT_6X10, BinaryColor::Off, BinaryColor::On);
+//! let mut buffer = epd2in9_v2::new_binary_buffer();
+//!
+//! // A month of 31 days that starts on a Wednesday, with weekends shaded.
+//! let area = Rectangle::new(Point::zero(), Size::new(128, 96));
+//! let month = MonthGrid::new(area, 2, 31)
+//!     .with_weekdays(&WEEKDAYS)
+//!     .with_today(Some(17))
+//!     .with_shading(|_day, weekday| if weekday >= 5 { Shade::Light } else { Shade::None });
+//! assert_eq!(month.draw(&mut buffer, &style), Ok(area));
+//! ```
+use core::fmt::Write as _;
+
+use embedded_graphics::{
+    draw_target::{DrawTarget, DrawTargetExt},
+    mono_font::MonoTextStyleBuilder,
+    pixelcolor::{BinaryColor, Rgb888},
+    prelude::{PixelColor, Point, Size},
+    primitives::{PointsIter, Primitive, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable, Pixel,
+};
+
+use crate::{
+    dither::{quantize, Dither},
+    widgets::{Widget, WidgetStyle},
+};
+
+/// The most bytes of text that a [Cell] can hold.
+pub const CELL_TEXT_LEN: usize = 16;
+
+/// How much of a cell's background is covered by the foreground color, using an ordered dither
+/// pattern.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Shade {
+    #[default]
+    None,
+    /// A quarter of the pixels.
+    Light,
+    /// Half of the pixels.
+    Medium,
+    /// Three quarters of the pixels.
+    Dark,
+    /// Every pixel. Text is drawn in the background color, so the cell looks inverted.
+    Solid,
+}
+
+impl Shade {
+    /// Whether the pixel at `point` is covered by this shade.
+    fn covers(self, point: Point) -> bool {
+        let luma = match self {
+            Shade::None => return false,
+            Shade::Light => 192,
+            Shade::Medium => 128,
+            Shade::Dark => 64,
+            Shade::Solid => return true,
+        };
+        // Dark pixels of the equivalent gray are covered.
+        let gray = Rgb888::new(luma, luma, luma);
+        quantize::<BinaryColor>(gray, point, Dither::Ordered) == BinaryColor::Off
+    }
+}
+
+/// The content of one cell of a [Table].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Cell {
+    text: heapless::String<CELL_TEXT_LEN>,
+    shade: Shade,
+}
+
+impl Cell {
+    /// Creates a cell with the given text, truncated to [CELL_TEXT_LEN] bytes.
+    pub fn new(text: &str) -> Self {
+        let mut cell = Cell::default();
+        for c in text.chars() {
+            if cell.text.push(c).is_err() {
+                break;
+            }
+        }
+        cell
+    }
+
+    /// Creates a cell that shows `value`.
+    pub fn number(value: i32) -> Self {
+        let mut cell = Cell::default();
+        // An i32 always fits.
+        let _ = write!(cell.text, "{}", value);
+        cell
+    }
+
+    /// Shades the cell's background.
+    pub fn with_shade(mut self, shade: Shade) -> Self {
+        self.shade = shade;
+        self
+    }
+}
+
+/// A grid of cells, with an optional header row, whose content is given by a function of each
+/// cell's column and row.
+///
+/// The columns and rows share the table's width and height as evenly as possible. Text is
+/// centred in its cell, and has a background behind it so that it stays readable on shaded
+/// cells.
+#[derive(Debug, Clone, Copy)]
+pub struct Table<'a, F> {
+    area: Rectangle,
+    columns: u32,
+    rows: u32,
+    headers: &'a [&'a str],
+    lines: bool,
+    cell: F,
+}
+
+impl<'a, F: Fn(u32, u32) -> Cell> Table<'a, F> {
+    /// Creates a table of `columns` by `rows` cells, which calls `cell(column, row)` for each
+    /// cell's content. Grid lines are drawn by default.
+    pub fn new(area: Rectangle, columns: u32, rows: u32, cell: F) -> Self {
+        Table {
+            area,
+            columns,
+            rows,
+            headers: &[],
+            lines: true,
+            cell,
+        }
+    }
+
+    /// Adds a header row above the cells, with one header per column.
+    pub fn with_headers(mut self, headers: &'a [&'a str]) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Sets whether lines are drawn around and between the cells.
+    pub fn with_lines(mut self, lines: bool) -> Self {
+        self.lines = lines;
+        self
+    }
+
+    /// The area of the cell at `column` and `row`, including its surrounding grid lines, or
+    /// `None` if there's no such cell.
+    ///
+    /// Use this to find what changed after redrawing a single cell with [Table::draw_cell].
+    pub fn cell_bounds<C>(
+        &self,
+        style: &WidgetStyle<'_, C>,
+        column: u32,
+        row: u32,
+    ) -> Option<Rectangle> {
+        if column >= self.columns || row >= self.rows {
+            return None;
+        }
+        let body = self.body(style);
+        let (left, right) = boundaries(body.size.width, self.columns, column);
+        let (top, bottom) = boundaries(body.size.height, self.rows, row);
+        Some(Rectangle::with_corners(
+            body.top_left + Point::new(left, top),
+            body.top_left + Point::new(right, bottom),
+        ))
+    }
+
+    /// Redraws the cell at `column` and `row`, and returns the area that changed.
+    pub fn draw_cell<D: DrawTarget>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, D::Color>,
+        column: u32,
+        row: u32,
+    ) -> Result<Option<Rectangle>, D::Error> {
+        let Some(bounds) = self.cell_bounds(style, column, row) else {
+            return Ok(None);
+        };
+        let mut target = target.clipped(&self.area);
+        if self.lines {
+            bounds
+                .into_styled(PrimitiveStyle::with_stroke(style.foreground, 1))
+                .draw(&mut target)?;
+        }
+        draw_cell(
+            &mut target,
+            style,
+            bounds.offset(-1),
+            &(self.cell)(column, row),
+        )?;
+        Ok(Some(bounds.intersection(&self.area)))
+    }
+
+    /// The area below the headers that the grid lines are spaced across. It's a pixel narrower
+    /// and shorter than the table, so that the last lines, drawn one past its edges, stay inside
+    /// the table.
+    fn body<C>(&self, style: &WidgetStyle<'_, C>) -> Rectangle {
+        let header_height = if self.headers.is_empty() {
+            0
+        } else {
+            style.font.character_size.height + 2
+        };
+        Rectangle::new(
+            self.area.top_left + Point::new(0, header_height as i32),
+            Size::new(
+                self.area.size.width.saturating_sub(1),
+                self.area.size.height.saturating_sub(header_height + 1),
+            ),
+        )
+    }
+}
+
+impl<C: PixelColor, F: Fn(u32, u32) -> Cell> Widget<C> for Table<'_, F> {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error> {
+        let mut target = target.clipped(&self.area);
+        target.fill_solid(&self.area, style.background)?;
+
+        let body = self.body(style);
+        let header = Rectangle::new(
+            self.area.top_left,
+            Size::new(
+                body.size.width,
+                (body.top_left.y - self.area.top_left.y) as u32,
+            ),
+        );
+        for (column, text) in (0..self.columns).zip(self.headers) {
+            let (left, right) = boundaries(header.size.width, self.columns, column);
+            let area = Rectangle::with_corners(
+                header.top_left + Point::new(left, 0),
+                header.top_left + Point::new(right, header.size.height as i32 - 1),
+            );
+            draw_cell(&mut target, style, area, &Cell::new(text))?;
+        }
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                self.draw_cell(&mut target, style, column, row)?;
+            }
+        }
+        Ok(self.area)
+    }
+}
+
+/// A month calendar, with a column for each day of the week and a row for each week.
+///
+/// Days are numbered from 1, and weekdays from 0 for the first column.
+#[derive(Debug, Clone, Copy)]
+pub struct MonthGrid<'a> {
+    area: Rectangle,
+    first_weekday: u32,
+    days: u32,
+    weekdays: &'a [&'a str],
+    today: Option<u32>,
+    shading: fn(u32, u32) -> Shade,
+}
+
+impl<'a> MonthGrid<'a> {
+    /// Creates a calendar for a month of `days` days, whose first day is in the `first_weekday`
+    /// column. Only as many rows as the month needs are shown.
+    pub fn new(area: Rectangle, first_weekday: u32, days: u32) -> Self {
+        MonthGrid {
+            area,
+            first_weekday: first_weekday % 7,
+            days,
+            weekdays: &[],
+            today: None,
+            shading: |_, _| Shade::None,
+        }
+    }
+
+    /// Shows the names of the weekdays above their columns.
+    pub fn with_weekdays(mut self, weekdays: &'a [&'a str; 7]) -> Self {
+        self.weekdays = weekdays;
+        self
+    }
+
+    /// Highlights the given day, by drawing it inverted.
+    pub fn with_today(mut self, today: Option<u32>) -> Self {
+        self.today = today;
+        self
+    }
+
+    /// Shades each day with `shading(day, weekday)`, e.g. to mark weekends or busy days.
+    pub fn with_shading(mut self, shading: fn(u32, u32) -> Shade) -> Self {
+        self.shading = shading;
+        self
+    }
+
+    /// The area of `day`, including its grid lines, or `None` if the month has no such day.
+    ///
+    /// Use this with [DrawTarget::clipped] to redraw only the days that changed, such as when
+    /// the highlighted day moves.
+    pub fn day_bounds<C>(&self, style: &WidgetStyle<'_, C>, day: u32) -> Option<Rectangle> {
+        if day == 0 || day > self.days {
+            return None;
+        }
+        let index = self.first_weekday + day - 1;
+        self.table().cell_bounds(style, index % 7, index / 7)
+    }
+
+    fn table(&self) -> Table<'a, impl Fn(u32, u32) -> Cell + '_> {
+        let weeks = (self.first_weekday + self.days).div_ceil(7);
+        Table::new(self.area, 7, weeks, move |column, row| {
+            let Some(day) = (row * 7 + column + 1).checked_sub(self.first_weekday) else {
+                return Cell::default();
+            };
+            if day == 0 || day > self.days {
+                return Cell::default();
+            }
+            let shade = if self.today == Some(day) {
+                Shade::Solid
+            } else {
+                (self.shading)(day, column)
+            };
+            Cell::number(day as i32).with_shade(shade)
+        })
+        .with_headers(self.weekdays)
+    }
+}
+
+impl<C: PixelColor> Widget<C> for MonthGrid<'_> {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error> {
+        self.table().draw(target, style)
+    }
+}
+
+/// Returns the offsets of the grid lines either side of the `index`th of `count` cells, along a
+/// length of `total` pixels.
+fn boundaries(total: u32, count: u32, index: u32) -> (i32, i32) {
+    let at = |i: u32| (u64::from(total) * u64::from(i) / u64::from(count.max(1))) as i32;
+    (at(index), at(index + 1))
+}
+
+/// Fills `area` with the cell's shade, and draws its text centred over it.
+fn draw_cell<D: DrawTarget>(
+    target: &mut D,
+    style: &WidgetStyle<'_, D::Color>,
+    area: Rectangle,
+    cell: &Cell,
+) -> Result<(), D::Error> {
+    let mut target = target.clipped(&area);
+    let shade = cell.shade;
+    target.draw_iter(area.points().map(|point| {
+        let color = if shade.covers(point) {
+            style.foreground
+        } else {
+            style.background
+        };
+        Pixel(point, color)
+    }))?;
+    if cell.text.is_empty() {
+        return Ok(());
+    }
+
+    let (text_color, background) = if shade == Shade::Solid {
+        (style.background, style.foreground)
+    } else {
+        (style.foreground, style.background)
+    };
+    let character_style = MonoTextStyleBuilder::new()
+        .font(style.font)
+        .text_color(text_color)
+        .background_color(background)
+        .build();
+    let text_style = TextStyleBuilder::new()
+        .alignment(Alignment::Center)
+        .baseline(Baseline::Middle)
+        .build();
+    Text::with_text_style(&cell.text, area.center(), character_style, text_style)
+        .draw(&mut target)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{mono_font::ascii::FONT_4X6, pixelcolor::BinaryColor};
+
+    use super::*;
+    use crate::buffer::{binary_buffer_length, BinaryBuffer};
+
+    const SIZE: Size = Size::new(16, 9);
+
+    fn style() -> WidgetStyle<'static, BinaryColor> {
+        WidgetStyle::new(&FONT_4X6, BinaryColor::On, BinaryColor::Off)
+    }
+
+    fn buffer() -> BinaryBuffer<{ binary_buffer_length(SIZE) }> {
+        BinaryBuffer::new(SIZE)
+    }
+
+    #[test]
+    fn test_table_shares_grid_lines_between_cells() {
+        let mut buffer = buffer();
+        let area = Rectangle::new(Point::zero(), SIZE);
+        let table = Table::new(area, 3, 2, |column, row| {
+            let shade = if (column, row) == (2, 1) {
+                Shade::Solid
+            } else {
+                Shade::None
+            };
+            Cell::default().with_shade(shade)
+        });
+        assert_eq!(table.draw(&mut buffer, &style()), Ok(area));
+
+        assert_eq!(
+            table.cell_bounds(&style(), 0, 0),
+            Some(Rectangle::new(Point::zero(), Size::new(6, 5)))
+        );
+        assert_eq!(
+            table.cell_bounds(&style(), 2, 1),
+            Some(Rectangle::new(Point::new(10, 4), Size::new(6, 5)))
+        );
+        assert_eq!(table.cell_bounds(&style(), 3, 0), None);
+
+        let rows: std::vec::Vec<_> = buffer.data().chunks(2).map(|r| [r[0], r[1]]).collect();
+        assert_eq!(
+            rows,
+            [
+                [0xFF, 0xFF],
+                [0b1000_0100, 0b0010_0001],
+                [0b1000_0100, 0b0010_0001],
+                [0b1000_0100, 0b0010_0001],
+                [0xFF, 0xFF],
+                [0b1000_0100, 0b0011_1111],
+                [0b1000_0100, 0b0011_1111],
+                [0b1000_0100, 0b0011_1111],
+                [0xFF, 0xFF],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_month_grid_places_days_by_weekday() {
+        let area = Rectangle::new(Point::zero(), Size::new(71, 41));
+        let month = MonthGrid::new(area, 5, 30).with_today(Some(2));
+        // The month starts on the 6th column, so it fills exactly 5 weeks.
+        assert_eq!(
+            month.day_bounds(&style(), 1),
+            Some(Rectangle::new(Point::new(50, 0), Size::new(11, 9)))
+        );
+        assert_eq!(
+            month.day_bounds(&style(), 30),
+            Some(Rectangle::new(Point::new(60, 32), Size::new(11, 9)))
+        );
+        assert_eq!(month.day_bounds(&style(), 31), None);
+
+        let table = month.table();
+        assert_eq!(table.rows, 5);
+        assert_eq!((table.cell)(4, 0), Cell::default());
+        assert_eq!((table.cell)(6, 0), Cell::number(2).with_shade(Shade::Solid));
+    }
+}
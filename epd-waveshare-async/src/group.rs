@@ -0,0 +1,313 @@
+//! Utilities for driving several displays from one SPI bus.
+//!
+//! Each display needs its own CS, DC, Busy (and Reset) pins, so each one has its own hardware
+//! struct and [SpiDevice]. A [DisplayGroup] keeps these together, and updates the displays either
+//! one after another, or overlapping so that one display refreshes while the next one is written.
+//!
+//! When the displays are tiled to form one larger display, a [VirtualCanvas] lets you draw across
+//! all their buffers as if they were one.
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    prelude::{Dimensions, Point},
+    primitives::Rectangle,
+    Pixel,
+};
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::{buffer::BufferView, log::debug, DisplaySimple, WaitIdle};
+
+/// A fixed-size group of displays of the same type, each with its own [SpiDevice].
+///
+/// ```text
+/// let mut tags = DisplayGroup::new([(tag_0, spi_0), (tag_1, spi_1), (tag_2, spi_2)]);
+/// // Writes each tag while the previous one is still refreshing.
+/// tags.display_overlapped([&buffer_0, &buffer_1, &buffer_2]).await?;
+/// ```
+pub struct DisplayGroup<D, SPI, const N: usize> {
+    displays: [(D, SPI); N],
+}
+
+impl<D, SPI, const N: usize> DisplayGroup<D, SPI, N> {
+    /// Creates a group from displays and their SPI devices.
+    pub fn new(displays: [(D, SPI); N]) -> Self {
+        DisplayGroup { displays }
+    }
+
+    /// Returns the display at `index` and its SPI device, for individual control.
+    pub fn get_mut(&mut self, index: usize) -> Option<(&mut D, &mut SPI)> {
+        self.displays
+            .get_mut(index)
+            .map(|(display, spi)| (display, spi))
+    }
+
+    /// Returns the displays and their SPI devices.
+    pub fn into_inner(self) -> [(D, SPI); N] {
+        self.displays
+    }
+
+    /// Displays each buffer on the display with the same index, one display at a time.
+    ///
+    /// Each display finishes refreshing before the next one starts. This is slower than
+    /// [DisplayGroup::display_overlapped], but limits the peak current draw to one refreshing
+    /// display.
+    pub async fn display_sequential<const BITS: usize, const FRAMES: usize, ERROR>(
+        &mut self,
+        buffers: [&dyn BufferView<BITS, FRAMES>; N],
+    ) -> Result<(), ERROR>
+    where
+        SPI: SpiDevice,
+        D: DisplaySimple<BITS, FRAMES, SPI, ERROR> + WaitIdle<ERROR>,
+    {
+        debug!("Displaying {} group members", N);
+        for ((display, spi), buffer) in self.displays.iter_mut().zip(buffers) {
+            display.display_framebuffer(spi, buffer).await?;
+            display.wait_until_idle().await?;
+        }
+        Ok(())
+    }
+
+    /// Displays each buffer on the display with the same index, starting each display's refresh
+    /// before writing to the next one.
+    ///
+    /// This overlaps each display's refresh with the SPI transfers to the following displays, and
+    /// waits for them all to finish at the end.
+    pub async fn display_overlapped<const BITS: usize, const FRAMES: usize, ERROR>(
+        &mut self,
+        buffers: [&dyn BufferView<BITS, FRAMES>; N],
+    ) -> Result<(), ERROR>
+    where
+        SPI: SpiDevice,
+        D: DisplaySimple<BITS, FRAMES, SPI, ERROR> + WaitIdle<ERROR>,
+    {
+        debug!("Displaying {} group members", N);
+        for ((display, spi), buffer) in self.displays.iter_mut().zip(buffers) {
+            display.display_framebuffer(spi, buffer).await?;
+        }
+        self.wait_until_idle().await
+    }
+
+    /// Waits until all displays are no longer busy.
+    pub async fn wait_until_idle<ERROR>(&mut self) -> Result<(), ERROR>
+    where
+        D: WaitIdle<ERROR>,
+    {
+        for (display, _) in self.displays.iter_mut() {
+            display.wait_until_idle().await?;
+        }
+        Ok(())
+    }
+}
+
+/// A draw target that spans the buffers of several tiled displays.
+///
+/// Each buffer is placed at an offset in the canvas' coordinates, and drawing is split between
+/// the buffers that each pixel or area falls in. Anything outside every buffer is ignored.
+///
+/// ```text
+/// // Two 7.5" panels side by side.
+/// let mut canvas = VirtualCanvas::new([
+///     (new_buffer(), Point::zero()),
+///     (new_buffer(), Point::new(800, 0)),
+/// ]);
+/// Text::new("Spans both panels", Point::new(760, 240), style).draw(&mut canvas)?;
+/// canvas.display_overlapped(&mut panels).await?;
+/// ```
+pub struct VirtualCanvas<B, const N: usize> {
+    panels: [(B, Point); N],
+}
+
+impl<B: Dimensions, const N: usize> VirtualCanvas<B, N> {
+    /// Creates a canvas from buffers and the offsets of their top left corners in the canvas.
+    pub fn new(panels: [(B, Point); N]) -> Self {
+        VirtualCanvas { panels }
+    }
+
+    /// Returns the buffer at `index`, e.g. to draw to one panel directly.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut B> {
+        self.panels.get_mut(index).map(|(buffer, _)| buffer)
+    }
+
+    /// Returns the buffers and their offsets.
+    pub fn into_inner(self) -> [(B, Point); N] {
+        self.panels
+    }
+
+    /// Returns the area that the buffer at `index` covers, in the canvas' coordinates.
+    pub fn panel_bounds(&self, index: usize) -> Option<Rectangle> {
+        self.panels
+            .get(index)
+            .map(|(buffer, offset)| Self::placed(buffer, *offset))
+    }
+
+    /// Returns the buffers in order, for writing to a [DisplayGroup] yourself.
+    pub fn buffers<const BITS: usize, const FRAMES: usize>(
+        &self,
+    ) -> [&dyn BufferView<BITS, FRAMES>; N]
+    where
+        B: BufferView<BITS, FRAMES>,
+    {
+        core::array::from_fn(|i| &self.panels[i].0 as &dyn BufferView<BITS, FRAMES>)
+    }
+
+    /// Displays each buffer on the display in `group` with the same index, overlapping each
+    /// display's refresh with writing to the next display.
+    ///
+    /// See [DisplayGroup::display_overlapped].
+    pub async fn display_overlapped<const BITS: usize, const FRAMES: usize, D, SPI, ERROR>(
+        &self,
+        group: &mut DisplayGroup<D, SPI, N>,
+    ) -> Result<(), ERROR>
+    where
+        B: BufferView<BITS, FRAMES>,
+        SPI: SpiDevice,
+        D: DisplaySimple<BITS, FRAMES, SPI, ERROR> + WaitIdle<ERROR>,
+    {
+        group.display_overlapped(self.buffers()).await
+    }
+
+    fn placed(buffer: &B, offset: Point) -> Rectangle {
+        Rectangle::new(offset, buffer.bounding_box().size)
+    }
+
+    /// Converts a point in the canvas to the buffer's coordinates.
+    fn to_local(buffer: &B, offset: Point, point: Point) -> Point {
+        point - offset + buffer.bounding_box().top_left
+    }
+}
+
+impl<B: Dimensions, const N: usize> Dimensions for VirtualCanvas<B, N> {
+    /// The smallest rectangle that covers every buffer.
+    fn bounding_box(&self) -> Rectangle {
+        let mut corners: Option<(Point, Point)> = None;
+        for (buffer, offset) in &self.panels {
+            let placed = Self::placed(buffer, *offset);
+            let Some(bottom_right) = placed.bottom_right() else {
+                continue;
+            };
+            corners = Some(match corners {
+                Some((top_left, max)) => (
+                    top_left.component_min(placed.top_left),
+                    max.component_max(bottom_right),
+                ),
+                None => (placed.top_left, bottom_right),
+            });
+        }
+        match corners {
+            Some((top_left, bottom_right)) => Rectangle::with_corners(top_left, bottom_right),
+            None => Rectangle::zero(),
+        }
+    }
+}
+
+impl<B: DrawTarget, const N: usize> DrawTarget for VirtualCanvas<B, N> {
+    type Color = B::Color;
+    type Error = B::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            for (buffer, offset) in &mut self.panels {
+                if Self::placed(buffer, *offset).contains(point) {
+                    let local = Self::to_local(buffer, *offset, point);
+                    buffer.draw_iter(core::iter::once(Pixel(local, color)))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        for (buffer, offset) in &mut self.panels {
+            let overlap = area.intersection(&Self::placed(buffer, *offset));
+            if overlap.is_zero_sized() {
+                continue;
+            }
+            let local = Rectangle::new(
+                Self::to_local(buffer, *offset, overlap.top_left),
+                overlap.size,
+            );
+            buffer.fill_solid(&local, color)?;
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        for (buffer, _) in &mut self.panels {
+            buffer.clear(color)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use embedded_graphics::{
+        pixelcolor::BinaryColor,
+        prelude::Size,
+        primitives::{Line, Primitive, PrimitiveStyle},
+        Drawable,
+    };
+
+    use super::*;
+    use crate::buffer::{binary_buffer_length, BinaryBuffer};
+    use crate::epd2in9_v2::{self, Command, Epd2In9V2, RefreshMode};
+    use crate::testing::{block_on, MockHw};
+
+    #[test]
+    fn test_display_writes_each_buffer_to_its_display() {
+        let mut logs = Vec::new();
+        let displays = [0, 1].map(|_| {
+            let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+            let mut spi = hw.spi();
+            logs.push(hw.log());
+            let epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+            (epd, spi)
+        });
+        logs.iter().for_each(|log| {
+            log.take_events();
+        });
+        let mut group = DisplayGroup::new(displays);
+
+        let buffer = epd2in9_v2::new_binary_buffer();
+        block_on(group.display_overlapped([&buffer, &buffer])).unwrap();
+        block_on(group.display_sequential([&buffer, &buffer])).unwrap();
+
+        for log in logs {
+            let activations = log
+                .commands()
+                .iter()
+                .filter(|c| **c == Command::MasterActivation as u8)
+                .count();
+            assert_eq!(activations, 2);
+        }
+    }
+
+    #[test]
+    fn test_virtual_canvas_splits_drawing_between_panels() {
+        const SIZE: Size = Size::new(8, 2);
+        let panel = || BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let mut canvas =
+            VirtualCanvas::new([(panel(), Point::zero()), (panel(), Point::new(8, 0))]);
+        assert_eq!(
+            canvas.bounding_box(),
+            Rectangle::new(Point::zero(), Size::new(16, 2))
+        );
+
+        Line::new(Point::new(4, 0), Point::new(11, 0))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(&mut canvas)
+            .unwrap();
+        canvas
+            .fill_solid(
+                &Rectangle::new(Point::new(6, 1), Size::new(4, 4)),
+                BinaryColor::On,
+            )
+            .unwrap();
+
+        let [(left, _), (right, _)] = canvas.into_inner();
+        assert_eq!(left.data(), [0b0000_1111, 0b0000_0011]);
+        assert_eq!(right.data(), [0b1111_0000, 0b1100_0000]);
+    }
+}
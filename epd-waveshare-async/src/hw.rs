@@ -1,22 +1,185 @@
-use embedded_hal::{
-    digital::{ErrorType as PinErrorType, InputPin, OutputPin, PinState},
-    spi::ErrorType as SpiErrorType,
-};
-use embedded_hal_async::{delay::DelayNs, digital::Wait, spi::SpiDevice};
+// Drivers run on-device, so they must not panic.
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
 
+#[cfg(any(
+    feature = "epd1in9_segment",
+    feature = "epd2in9",
+    feature = "epd2in9_v2"
+))]
+use embedded_hal::digital::ErrorType as PinErrorType;
+use embedded_hal::digital::{InputPin, OutputPin, PinState};
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+use embedded_hal::spi::ErrorType as SpiErrorType;
+use embedded_hal_async::{delay::DelayNs, digital::Wait, i2c::I2c, spi::SpiDevice};
+
+#[cfg(any(
+    feature = "epd1in9_segment",
+    feature = "epd2in9",
+    feature = "epd2in9_v2"
+))]
 use crate::log::trace;
 
+#[cfg(feature = "display-interface")]
+mod display_interface;
+#[cfg(feature = "display-interface")]
+pub use display_interface::{InterfaceDc, InterfaceDcPin, InterfaceError, InterfaceSpi};
+#[cfg(feature = "embassy-time")]
+mod embassy;
+#[cfg(feature = "embassy-time")]
+pub use embassy::EmbassyDelay;
+mod debounce;
+pub use debounce::DebouncedBusy;
+mod polled;
+pub use polled::PolledBusy;
+mod three_wire;
+pub use three_wire::{NoDc, ThreeWireSpi};
+
 /// Provides access to a shared error type.
 ///
 /// Drivers rely on this trait to provide a single Error type that supports [From] conversions
 /// from all the hardware-specific error types.
 pub trait ErrorHw {
     type Error;
+
+    /// Adds context about which peripheral produced an error, after it has been converted into
+    /// [ErrorHw::Error].
+    ///
+    /// The default returns the error unchanged. Override this if you want your errors to record
+    /// their source, e.g. to tell a DC pin error apart from a Reset pin error of the same type.
+    fn with_source(error: Self::Error, source: ErrorSource) -> Self::Error {
+        let _ = source;
+        error
+    }
+}
+
+/// The peripheral that produced an error. See [ErrorHw::with_source].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorSource {
+    Spi,
+    Dc,
+    Reset,
+    Busy,
+    /// The I2C bus. See [I2cHw].
+    I2c,
+    /// The touch controller's interrupt pin. See [crate::touch].
+    Int,
+    /// The panel's power supply. See [ResetHw::power_on] and [ResetHw::power_off].
+    Power,
+}
+
+/// Converts hardware errors into [ErrorHw::Error], with their [ErrorSource].
+#[cfg(any(
+    feature = "epd1in9_segment",
+    feature = "epd2in9",
+    feature = "epd2in9_v2",
+    feature = "touch"
+))]
+pub(crate) trait WithSource<T, E> {
+    fn with_source<HW>(self, source: ErrorSource) -> Result<T, HW::Error>
+    where
+        HW: ErrorHw,
+        HW::Error: From<E>;
+}
+
+#[cfg(any(
+    feature = "epd1in9_segment",
+    feature = "epd2in9",
+    feature = "epd2in9_v2",
+    feature = "touch"
+))]
+impl<T, E> WithSource<T, E> for Result<T, E> {
+    fn with_source<HW>(self, source: ErrorSource) -> Result<T, HW::Error>
+    where
+        HW: ErrorHw,
+        HW::Error: From<E>,
+    {
+        self.map_err(|e| HW::with_source(HW::Error::from(e), source))
+    }
 }
 
 /// Describes the SPI hardware to use for interacting with the EPD.
 pub trait SpiHw {
     type Spi: SpiDevice;
+
+    /// The maximum number of bytes to send in a single SPI write. Longer data writes are split
+    /// into multiple writes of at most this many bytes.
+    ///
+    /// Override this if your SPI peripheral can't handle a whole framebuffer in one transfer, such
+    /// as the nRF52832's EasyDMA, which is limited to 255 bytes. Each chunk is a separate
+    /// [SpiDevice] transaction, so a smaller value also lets other devices on a shared bus get a
+    /// turn while a large framebuffer is being written. This must be greater than zero, which is
+    /// checked when the display's writes are compiled.
+    const MAX_WRITE_LEN: usize = usize::MAX;
+
+    /// How the display distinguishes commands from data. Use [ThreeWireSpi] rather than setting
+    /// this directly.
+    const WIRING: SpiWiring = SpiWiring::FourWire;
+
+    /// Whether image data is inverted as it's written to the display's RAM.
+    ///
+    /// Defaults to [Polarity::Normal], which suits the panels that each driver was written for.
+    /// Override this if your panel shows this crate's buffers inverted, e.g. because its
+    /// controller was initialised to read 0 as white rather than black. Only image data is
+    /// affected, not commands.
+    fn polarity(&self) -> Polarity {
+        Polarity::Normal
+    }
+
+    /// How gently the display's booster starts when it's initialised or woken. See
+    /// [PowerProfile].
+    fn power_profile(&self) -> PowerProfile {
+        PowerProfile::Standard
+    }
+}
+
+/// How the display distinguishes commands from data. See [SpiHw::WIRING].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SpiWiring {
+    /// Bytes are sent as 8-bit words, with a DC pin to mark commands.
+    FourWire,
+    /// Bytes are sent as 9-bit words, where the first bit marks data rather than commands.
+    ThreeWire,
+}
+
+/// How image data is written to the display's RAM. See [SpiHw::polarity].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Polarity {
+    /// Buffers are written as they are.
+    #[default]
+    Normal,
+    /// Every bit of the buffers is inverted as it's written.
+    Inverted,
+}
+
+/// Trades the display's peak current draw against its speed, by configuring how its booster starts
+/// up. See [SpiHw::power_profile] and [I2cHw::power_profile].
+///
+/// Each driver maps these to its controller's own settings.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PowerProfile {
+    /// Starts the booster as gently as possible, for batteries and energy harvesting supplies
+    /// that can't deliver much current at once. Waking and refreshing take a little longer.
+    LowPeakCurrent,
+    /// The settings from the vendor's sample code.
+    #[default]
+    Standard,
+    /// Starts the booster with more current, so that it's ready sooner.
+    Fast,
 }
 
 /// Provides access to the Data/Command pin for EPD control.
@@ -26,24 +189,174 @@ pub trait DcHw {
     fn dc(&mut self) -> &mut Self::Dc;
 }
 
-/// Provides access to the Reset pin for EPD control.
-pub trait ResetHw {
+/// Provides access to the Reset pin for EPD control, and hooks for sequencing the panel's power.
+pub trait ResetHw: ErrorHw {
     type Reset: OutputPin;
 
     fn reset(&mut self) -> &mut Self::Reset;
+
+    /// Called before each hardware reset, i.e. whenever the display is initialised or woken.
+    ///
+    /// Does nothing by default. Override this if the panel needs powering up first, e.g. to enable
+    /// a separate supply rail and wait for its supervisor.
+    async fn power_on(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called after the display has been put into deep sleep.
+    ///
+    /// Does nothing by default. Override this to power the panel down, e.g. by disabling its
+    /// supply rail. The display then loses its RAM and settings, so initialise it again rather than
+    /// relying on waking it to restore them.
+    async fn power_off(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 /// Provides access to the Busy pin for EPD status monitoring.
+///
+/// If the pin glitches, e.g. on a long cable, wrap it in a [DebouncedBusy]. To do other work during
+/// long busy waits, such as feeding a watchdog, wrap it in a [PolledBusy].
 pub trait BusyHw {
     type Busy: InputPin + Wait;
 
     fn busy(&mut self) -> &mut Self::Busy;
 
+    /// The state of the busy pin that indicates that it's busy, if it's known at compile time.
+    ///
+    /// Set this instead of implementing [BusyHw::busy_when] to let the compiler remove the runtime
+    /// check. Leaving this unset without implementing [BusyHw::busy_when] fails to compile.
+    const BUSY_WHEN: Option<PinState> = None;
+
     /// Indicates which state of the busy pin indicates that it's busy.
     ///
     /// This is user-configurable, rather than enforced by the display driver, to allow the user to
-    /// use more unexpected wiring configurations.
-    fn busy_when(&self) -> embedded_hal::digital::PinState;
+    /// use more unexpected wiring configurations. Defaults to [BusyHw::BUSY_WHEN].
+    fn busy_when(&self) -> PinState {
+        const {
+            assert!(
+                Self::BUSY_WHEN.is_some(),
+                "BusyHw must either set BUSY_WHEN or implement busy_when"
+            )
+        };
+        match Self::BUSY_WHEN {
+            Some(busy_when) => busy_when,
+            // Unreachable, due to the assertion above.
+            None => PinState::High,
+        }
+    }
+
+    /// Returns the [Timings] to record busy waits and SPI transfers into, if any.
+    ///
+    /// Instrumentation is disabled by default. Override this to profile whether your refreshes are
+    /// limited by SPI transfers or by the display itself.
+    fn timings(&mut self) -> Option<&mut Timings> {
+        None
+    }
+}
+
+/// Counters for how long the driver spends waiting on the busy pin and transferring data over SPI.
+///
+/// ```
+/// use epd_waveshare_async::hw::Timings;
+///
+/// // Use your platform's monotonic clock, e.g. `embassy_time::Instant::now().as_micros()`.
+/// fn now_us() -> u64 {
+///     0
+/// }
+///
+/// let timings = Timings::new(now_us);
+/// assert_eq!(timings.busy_wait_us(), 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Timings {
+    now_us: fn() -> u64,
+    busy_wait_count: u32,
+    busy_wait_us: u64,
+    transfer_count: u32,
+    transfer_bytes: u64,
+    transfer_us: u64,
+}
+
+impl Timings {
+    /// Creates new, zeroed counters that use the given clock. The clock must return a monotonic
+    /// time in microseconds.
+    pub const fn new(now_us: fn() -> u64) -> Self {
+        Timings {
+            now_us,
+            busy_wait_count: 0,
+            busy_wait_us: 0,
+            transfer_count: 0,
+            transfer_bytes: 0,
+            transfer_us: 0,
+        }
+    }
+
+    /// The number of times the driver had to wait for the display to stop being busy.
+    pub fn busy_wait_count(&self) -> u32 {
+        self.busy_wait_count
+    }
+
+    /// The total time spent waiting for the display to stop being busy.
+    pub fn busy_wait_us(&self) -> u64 {
+        self.busy_wait_us
+    }
+
+    /// The number of commands sent to (or read from) the display.
+    pub fn transfer_count(&self) -> u32 {
+        self.transfer_count
+    }
+
+    /// The total number of bytes transferred, including command bytes.
+    pub fn transfer_bytes(&self) -> u64 {
+        self.transfer_bytes
+    }
+
+    /// The total time spent transferring data over SPI, excluding busy waits.
+    pub fn transfer_us(&self) -> u64 {
+        self.transfer_us
+    }
+
+    /// Resets all counters to zero.
+    pub fn reset(&mut self) {
+        *self = Timings::new(self.now_us);
+    }
+
+    pub(crate) fn now(&self) -> u64 {
+        (self.now_us)()
+    }
+
+    #[cfg(any(
+        feature = "epd1in9_segment",
+        feature = "epd2in9",
+        feature = "epd2in9_v2"
+    ))]
+    fn record_busy_wait(&mut self, start_us: u64) {
+        self.busy_wait_count = self.busy_wait_count.saturating_add(1);
+        self.busy_wait_us += self.now().saturating_sub(start_us);
+    }
+
+    #[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+    fn record_transfer(&mut self, bytes: usize, start_us: u64) {
+        self.transfer_count = self.transfer_count.saturating_add(1);
+        self.transfer_bytes += bytes as u64;
+        self.transfer_us += self.now().saturating_sub(start_us);
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Timings {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Timings {{ busy_waits: {} ({}us), transfers: {} ({} bytes, {}us) }}",
+            self.busy_wait_count,
+            self.busy_wait_us,
+            self.transfer_count,
+            self.transfer_bytes,
+            self.transfer_us
+        )
+    }
 }
 
 /// Provides access to delay functionality for EPD timing control.
@@ -53,8 +366,27 @@ pub trait DelayHw {
     fn delay(&mut self) -> &mut Self::Delay;
 }
 
+/// Describes the I2C hardware to use, for devices that are controlled over I2C rather than SPI,
+/// such as the `epd1in9_segment` display and the `touch` controllers.
+pub trait I2cHw {
+    type I2c: I2c;
+
+    /// How gently the display's booster starts when it's initialised. See [PowerProfile].
+    fn power_profile(&self) -> PowerProfile {
+        PowerProfile::Standard
+    }
+}
+
 /// Provides "wait" support for hardware with a busy state.
+#[cfg(any(
+    feature = "epd1in9_segment",
+    feature = "epd2in9",
+    feature = "epd2in9_v2"
+))]
 pub(crate) trait BusyWait: ErrorHw {
+    /// Returns whether the display is currently busy.
+    fn is_busy(&mut self) -> Result<bool, Self::Error>;
+
     /// Waits for the current operation to complete if the display is busy.
     ///
     /// Note that this will wait forever if the display is asleep.
@@ -62,42 +394,134 @@ pub(crate) trait BusyWait: ErrorHw {
 }
 
 /// Provides the ability to send <command> then <data> style communications.
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
 pub(crate) trait CommandDataSend: SpiHw + ErrorHw {
     /// Send the following command and data to the display. Waits until the display is no longer busy before sending.
+    ///
+    /// The data is split into writes of at most [SpiHw::MAX_WRITE_LEN] bytes.
+    ///
+    /// Note that with a DC pin, the command and its data are sent in separate [SpiDevice]
+    /// transactions, so CS is released in between. The DC pin has to change after the command
+    /// byte, and [embedded_hal_async::spi::Operation] has no way to toggle a GPIO within a
+    /// transaction. The supported controllers latch the command on the DC level of each byte, so
+    /// this is fine as long as nothing else on the bus touches the DC pin. [ThreeWireSpi] sends the
+    /// DC bit in-band, so it writes the command with the start of its data in one transaction.
     async fn send(
         &mut self,
         spi: &mut Self::Spi,
         command: u8,
         data: &[u8],
     ) -> Result<(), Self::Error>;
+
+    /// Sends image data to a RAM command as with [CommandDataSend::send], inverting it first if
+    /// [SpiHw::polarity] is [Polarity::Inverted].
+    async fn send_image(
+        &mut self,
+        spi: &mut Self::Spi,
+        command: u8,
+        data: &[u8],
+    ) -> Result<(), Self::Error>;
+
+    /// Sends the command and data as with [CommandDataSend::send], then reads the display's
+    /// response into `buf` with the DC pin high.
+    ///
+    /// Nothing is discarded from the response, so callers must skip any dummy bytes that the
+    /// controller sends for the given command.
+    #[cfg(feature = "epd2in9_v2")]
+    async fn send_read(
+        &mut self,
+        spi: &mut Self::Spi,
+        command: u8,
+        data: &[u8],
+        buf: &mut [u8],
+    ) -> Result<(), Self::Error>;
 }
 
+#[cfg(any(
+    feature = "epd1in9_segment",
+    feature = "epd2in9",
+    feature = "epd2in9_v2"
+))]
 impl<HW> BusyWait for HW
 where
     HW: BusyHw + ErrorHw,
     <HW as ErrorHw>::Error: From<<HW::Busy as PinErrorType>::Error>,
 {
+    fn is_busy(&mut self) -> Result<bool, HW::Error> {
+        match self.busy_when() {
+            PinState::High => self.busy().is_high().with_source::<Self>(ErrorSource::Busy),
+            PinState::Low => self.busy().is_low().with_source::<Self>(ErrorSource::Busy),
+        }
+    }
+
     async fn wait_if_busy(&mut self) -> Result<(), HW::Error> {
-        let busy_when = self.busy_when();
-        let busy = self.busy();
-        match busy_when {
-            PinState::High => {
-                if busy.is_high()? {
-                    trace!("Waiting for busy EPD");
-                    busy.wait_for_low().await?;
-                }
-            }
-            PinState::Low => {
-                if busy.is_low()? {
-                    trace!("Waiting for busy EPD");
-                    busy.wait_for_high().await?;
-                }
-            }
+        if !self.is_busy()? {
+            return Ok(());
+        }
+
+        trace!("Waiting for busy EPD");
+        let start = self.timings().map(|t| t.now());
+        match self.busy_when() {
+            PinState::High => self
+                .busy()
+                .wait_for_low()
+                .await
+                .with_source::<Self>(ErrorSource::Busy)?,
+            PinState::Low => self
+                .busy()
+                .wait_for_high()
+                .await
+                .with_source::<Self>(ErrorSource::Busy)?,
         };
+        if let (Some(start), Some(timings)) = (start, self.timings()) {
+            timings.record_busy_wait(start);
+        }
         Ok(())
     }
 }
 
+/// Writes `bytes` as data, or as commands if `data` is false, and returns the number of bytes
+/// written.
+///
+/// The bytes are split into writes of at most [SpiHw::MAX_WRITE_LEN] bytes.
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+async fn write_bytes<HW>(
+    hw: &mut HW,
+    spi: &mut HW::Spi,
+    data: bool,
+    bytes: &[u8],
+) -> Result<usize, HW::Error>
+where
+    HW: DcHw + SpiHw + ErrorHw,
+    HW::Error: From<<HW::Spi as SpiErrorType>::Error> + From<<HW::Dc as PinErrorType>::Error>,
+{
+    const {
+        assert!(
+            HW::MAX_WRITE_LEN > 0,
+            "SpiHw::MAX_WRITE_LEN must be greater than zero"
+        )
+    };
+    match HW::WIRING {
+        SpiWiring::FourWire => {
+            hw.dc()
+                .set_state(PinState::from(data))
+                .with_source::<HW>(ErrorSource::Dc)?;
+            for chunk in bytes.chunks(HW::MAX_WRITE_LEN) {
+                spi.write(chunk).await.with_source::<HW>(ErrorSource::Spi)?;
+            }
+            Ok(bytes.len())
+        }
+        SpiWiring::ThreeWire => three_wire::write_words(spi, data, bytes, HW::MAX_WRITE_LEN)
+            .await
+            .with_source::<HW>(ErrorSource::Spi),
+    }
+}
+
+/// The most bytes of image data inverted per write, for hardware with [Polarity::Inverted].
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+const INVERTED_LEN: usize = 256;
+
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
 impl<HW> CommandDataSend for HW
 where
     HW: DcHw + BusyHw + BusyWait + SpiHw + ErrorHw,
@@ -114,14 +538,322 @@ where
         trace!("Sending EPD command: {:?}", command);
         self.wait_if_busy().await?;
 
-        self.dc().set_low()?;
-        spi.write(&[command]).await?;
+        let start = self.timings().map(|t| t.now());
+        let written = match Self::WIRING {
+            SpiWiring::FourWire => {
+                let mut written = write_bytes(self, spi, false, &[command]).await?;
+                if !data.is_empty() {
+                    written += write_bytes(self, spi, true, data).await?;
+                }
+                written
+            }
+            SpiWiring::ThreeWire => {
+                three_wire::write_command(spi, command, data, Self::MAX_WRITE_LEN)
+                    .await
+                    .with_source::<Self>(ErrorSource::Spi)?
+            }
+        };
+
+        if let (Some(start), Some(timings)) = (start, self.timings()) {
+            timings.record_transfer(written, start);
+        }
+        Ok(())
+    }
+
+    async fn send_image(
+        &mut self,
+        spi: &mut Self::Spi,
+        command: u8,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        if self.polarity() == Polarity::Normal {
+            return self.send(spi, command, data).await;
+        }
+        trace!("Sending inverted EPD image: {:?}", command);
+        self.wait_if_busy().await?;
 
-        if !data.is_empty() {
-            self.dc().set_high()?;
-            spi.write(data).await?;
+        let start = self.timings().map(|t| t.now());
+        let mut written = match Self::WIRING {
+            SpiWiring::FourWire => write_bytes(self, spi, false, &[command]).await?,
+            SpiWiring::ThreeWire => {
+                three_wire::write_command(spi, command, &[], Self::MAX_WRITE_LEN)
+                    .await
+                    .with_source::<Self>(ErrorSource::Spi)?
+            }
+        };
+        // Invert the data a write at a time, as there's nowhere to invert it all at once.
+        let mut buffer = [0; INVERTED_LEN];
+        let chunk_len = Self::MAX_WRITE_LEN.min(INVERTED_LEN);
+        for chunk in data.chunks(chunk_len) {
+            let inverted = &mut buffer[..chunk.len()];
+            for (inverted, byte) in inverted.iter_mut().zip(chunk) {
+                *inverted = !byte;
+            }
+            written += write_bytes(self, spi, true, inverted).await?;
         }
 
+        if let (Some(start), Some(timings)) = (start, self.timings()) {
+            timings.record_transfer(written, start);
+        }
         Ok(())
     }
+
+    #[cfg(feature = "epd2in9_v2")]
+    async fn send_read(
+        &mut self,
+        spi: &mut Self::Spi,
+        command: u8,
+        data: &[u8],
+        buf: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.send(spi, command, data).await?;
+
+        if !buf.is_empty() {
+            trace!("Reading {} bytes from EPD", buf.len());
+            let start = self.timings().map(|t| t.now());
+            self.dc().set_high().with_source::<Self>(ErrorSource::Dc)?;
+            spi.read(buf).await.with_source::<Self>(ErrorSource::Spi)?;
+            if let (Some(start), Some(timings)) = (start, self.timings()) {
+                timings.record_transfer(buf.len(), start);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(
+    test,
+    any(
+        feature = "epd1in9_segment",
+        feature = "epd2in9",
+        feature = "epd2in9_v2"
+    )
+))]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, MockHw, MockPin};
+
+    #[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+    mod command_data_send {
+        use super::*;
+        use crate::testing::{Event, MockSpi};
+
+        /// Wraps [MockHw] with a small maximum write length, instrumentation, and a polarity.
+        struct ChunkedHw(MockHw, Timings, Polarity);
+
+        impl ChunkedHw {
+            fn new(mock: MockHw) -> Self {
+                ChunkedHw(mock, Timings::new(fake_now_us), Polarity::Normal)
+            }
+        }
+
+        /// A fake clock that advances 10us every time it's read.
+        fn fake_now_us() -> u64 {
+            std::thread_local! {
+                static NOW_US: core::cell::Cell<u64> = const { core::cell::Cell::new(0) };
+            }
+            NOW_US.replace(NOW_US.get() + 10)
+        }
+
+        impl ErrorHw for ChunkedHw {
+            type Error = core::convert::Infallible;
+        }
+
+        impl SpiHw for ChunkedHw {
+            type Spi = MockSpi;
+
+            const MAX_WRITE_LEN: usize = 3;
+
+            fn polarity(&self) -> Polarity {
+                self.2
+            }
+        }
+
+        impl DcHw for ChunkedHw {
+            type Dc = MockPin;
+
+            fn dc(&mut self) -> &mut Self::Dc {
+                self.0.dc()
+            }
+        }
+
+        impl BusyHw for ChunkedHw {
+            type Busy = MockPin;
+
+            fn busy(&mut self) -> &mut Self::Busy {
+                self.0.busy()
+            }
+
+            fn busy_when(&self) -> PinState {
+                self.0.busy_when()
+            }
+
+            fn timings(&mut self) -> Option<&mut Timings> {
+                Some(&mut self.1)
+            }
+        }
+
+        #[test]
+        fn test_send_splits_data_writes() {
+            let mock = MockHw::new(PinState::High);
+            let log = mock.log();
+            let mut spi = mock.spi();
+            let mut hw = ChunkedHw::new(mock);
+
+            block_on(hw.send(&mut spi, 0x24, &[1, 2, 3, 4, 5, 6, 7, 8])).unwrap();
+
+            // One transaction for the command, then 3 for the data.
+            assert_eq!(log.transaction_count(), 4);
+            assert_eq!(
+                log.events(),
+                [
+                    Event::Command(0x24),
+                    Event::Data(vec![1, 2, 3, 4, 5, 6, 7, 8])
+                ]
+            );
+        }
+
+        #[test]
+        fn test_send_image_inverts_data_for_inverted_polarity() {
+            let mock = MockHw::new(PinState::High);
+            let log = mock.log();
+            let mut spi = mock.spi();
+            let mut hw = ChunkedHw::new(mock);
+            let image: std::vec::Vec<u8> = (0..100).collect();
+
+            block_on(hw.send_image(&mut spi, 0x24, &image)).unwrap();
+            assert_eq!(log.take_events()[1], Event::Data(image.clone()));
+
+            hw.2 = Polarity::Inverted;
+            hw.1.reset();
+            let transactions = log.transaction_count();
+            block_on(hw.send_image(&mut spi, 0x24, &image)).unwrap();
+            let inverted: std::vec::Vec<u8> = image.iter().map(|byte| !byte).collect();
+            assert_eq!(
+                log.take_events(),
+                [Event::Command(0x24), Event::Data(inverted)]
+            );
+            // The data is still split by MAX_WRITE_LEN, but timed as one transfer.
+            assert_eq!(log.transaction_count() - transactions, 1 + 34);
+            assert_eq!(hw.1.transfer_count(), 1);
+            assert_eq!(hw.1.transfer_bytes(), 101);
+            // Commands' data is never inverted.
+            block_on(hw.send(&mut spi, 0x22, &[0xC7])).unwrap();
+            assert_eq!(log.take_events()[1], Event::Data(vec![0xC7]));
+        }
+
+        #[test]
+        fn test_send_without_limit_writes_once() {
+            let mut hw = MockHw::new(PinState::High);
+            let log = hw.log();
+            let mut spi = hw.spi();
+
+            block_on(hw.send(&mut spi, 0x24, &[0; 1000])).unwrap();
+
+            assert_eq!(log.transaction_count(), 2);
+        }
+
+        #[test]
+        #[cfg(feature = "epd2in9_v2")]
+        fn test_send_read_reads_after_data() {
+            let mut hw = MockHw::new(PinState::High);
+            let log = hw.log();
+            let mut spi = hw.spi();
+
+            let mut buf = [0xFF; 4];
+            block_on(hw.send_read(&mut spi, 0x2D, &[0x01], &mut buf)).unwrap();
+
+            assert_eq!(buf, [0; 4]);
+            assert_eq!(
+                log.events(),
+                [
+                    Event::Command(0x2D),
+                    Event::Data(vec![0x01]),
+                    Event::Read(4)
+                ]
+            );
+        }
+
+        #[test]
+        fn test_send_records_timings() {
+            let mock = MockHw::new(PinState::High);
+            let mut spi = mock.spi();
+            let mut hw = ChunkedHw::new(mock);
+
+            block_on(hw.send(&mut spi, 0x24, &[1, 2, 3, 4])).unwrap();
+            block_on(hw.send(&mut spi, 0x20, &[])).unwrap();
+
+            let timings = hw.timings().unwrap();
+            assert_eq!(timings.transfer_count(), 2);
+            assert_eq!(timings.transfer_bytes(), 6);
+            assert_eq!(timings.transfer_us(), 20);
+            // The mock display is never busy.
+            assert_eq!(timings.busy_wait_count(), 0);
+
+            timings.reset();
+            assert_eq!(timings.transfer_count(), 0);
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct SourcedError {
+        source: Option<ErrorSource>,
+        code: u8,
+    }
+
+    impl From<u8> for SourcedError {
+        fn from(code: u8) -> Self {
+            SourcedError { source: None, code }
+        }
+    }
+
+    struct SourcedHw;
+
+    impl ErrorHw for SourcedHw {
+        type Error = SourcedError;
+
+        fn with_source(error: SourcedError, source: ErrorSource) -> SourcedError {
+            SourcedError {
+                source: Some(source),
+                ..error
+            }
+        }
+    }
+
+    #[test]
+    fn test_with_source_adds_context() {
+        let result: Result<(), u8> = Err(3);
+        assert_eq!(
+            result.with_source::<SourcedHw>(ErrorSource::Dc),
+            Err(SourcedError {
+                source: Some(ErrorSource::Dc),
+                code: 3
+            })
+        );
+    }
+
+    /// Busy when low, fixed at compile time.
+    struct ConstBusyHw(MockHw);
+
+    impl ErrorHw for ConstBusyHw {
+        type Error = core::convert::Infallible;
+    }
+
+    impl BusyHw for ConstBusyHw {
+        type Busy = MockPin;
+
+        const BUSY_WHEN: Option<PinState> = Some(PinState::Low);
+
+        fn busy(&mut self) -> &mut Self::Busy {
+            self.0.busy()
+        }
+    }
+
+    #[test]
+    fn test_busy_when_defaults_to_const() {
+        let mut hw = ConstBusyHw(MockHw::new(PinState::Low));
+        assert_eq!(hw.busy_when(), PinState::Low);
+        block_on(hw.wait_if_busy()).unwrap();
+    }
 }
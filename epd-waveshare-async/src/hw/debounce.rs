@@ -0,0 +1,148 @@
+use embedded_hal::digital::{ErrorType as PinErrorType, InputPin};
+use embedded_hal_async::{delay::DelayNs, digital::Wait};
+
+use crate::log::trace;
+
+/// Filters glitches on a busy pin, e.g. from long ribbon cables, which would otherwise end busy
+/// waits early and corrupt refreshes.
+///
+/// Waiting for a level only finishes once the pin is still at that level `stable_us` microseconds
+/// after reaching it. Shorter pulses are ignored, and the wait starts again. Use this as your
+/// [BusyHw::Busy](super::BusyHw::Busy) pin:
+///
+/// ```text
+/// type Busy = DebouncedBusy<Input<'static>, embassy_time::Delay>;
+///
+/// let busy = DebouncedBusy::new(Input::new(p.PIN_13, Pull::None), embassy_time::Delay, 50);
+/// ```
+///
+/// Reads of the pin's current level are not filtered.
+pub struct DebouncedBusy<P, D> {
+    pin: P,
+    delay: D,
+    stable_us: u32,
+}
+
+impl<P, D> DebouncedBusy<P, D> {
+    /// Wraps `pin`, using `delay` to check that it stays at a level for `stable_us` microseconds.
+    pub fn new(pin: P, delay: D, stable_us: u32) -> Self {
+        DebouncedBusy {
+            pin,
+            delay,
+            stable_us,
+        }
+    }
+
+    /// Provides access to the wrapped pin.
+    pub fn inner(&mut self) -> &mut P {
+        &mut self.pin
+    }
+
+    /// Returns the wrapped pin.
+    pub fn into_inner(self) -> P {
+        self.pin
+    }
+}
+
+impl<P: PinErrorType, D> PinErrorType for DebouncedBusy<P, D> {
+    type Error = P::Error;
+}
+
+impl<P: InputPin, D> InputPin for DebouncedBusy<P, D> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        self.pin.is_high()
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.pin.is_low()
+    }
+}
+
+impl<P: InputPin + Wait, D: DelayNs> Wait for DebouncedBusy<P, D> {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        loop {
+            self.pin.wait_for_high().await?;
+            self.delay.delay_us(self.stable_us).await;
+            if self.pin.is_high()? {
+                return Ok(());
+            }
+            trace!("Ignoring a glitch on the busy pin");
+        }
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        loop {
+            self.pin.wait_for_low().await?;
+            self.delay.delay_us(self.stable_us).await;
+            if self.pin.is_low()? {
+                return Ok(());
+            }
+            trace!("Ignoring a glitch on the busy pin");
+        }
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.pin.wait_for_rising_edge().await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.pin.wait_for_falling_edge().await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.pin.wait_for_any_edge().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, MockDelay, ScriptedPin};
+
+    #[test]
+    fn test_accepts_a_stable_level_after_one_window() {
+        let delay = MockDelay::new();
+        let log = delay.log();
+        let mut busy = DebouncedBusy::new(ScriptedPin::new([false]), delay, 50);
+        block_on(busy.wait_for_low()).unwrap();
+
+        assert_eq!(log.delays(), [50_000]);
+        assert_eq!(busy.into_inner().waits(), 1);
+    }
+
+    #[test]
+    fn test_rejects_a_glitch_shorter_than_the_window() {
+        // The pin goes low, but is high again 50us later, then goes low for good.
+        let delay = MockDelay::new();
+        let log = delay.log();
+        let mut busy = DebouncedBusy::new(ScriptedPin::new([true, false]), delay, 50);
+        block_on(busy.wait_for_low()).unwrap();
+
+        assert_eq!(log.delays(), [50_000, 50_000]);
+        let pin = busy.into_inner();
+        assert_eq!(pin.waits(), 2);
+        assert_eq!(pin.remaining(), 0);
+    }
+
+    #[test]
+    fn test_rejects_glitches_while_waiting_for_high() {
+        let delay = MockDelay::new();
+        let log = delay.log();
+        let mut busy = DebouncedBusy::new(ScriptedPin::new([false, false, true]), delay, 20);
+        block_on(busy.wait_for_high()).unwrap();
+
+        assert_eq!(log.delays(), [20_000; 3]);
+        assert_eq!(busy.into_inner().waits(), 3);
+    }
+
+    #[test]
+    fn test_reads_are_not_filtered() {
+        let delay = MockDelay::new();
+        let log = delay.log();
+        let mut busy = DebouncedBusy::new(ScriptedPin::new([true, false]), delay, 50);
+
+        assert!(busy.is_high().unwrap());
+        assert!(busy.is_low().unwrap());
+        assert!(log.delays().is_empty());
+    }
+}
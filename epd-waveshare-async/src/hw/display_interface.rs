@@ -0,0 +1,276 @@
+use core::{
+    convert::Infallible,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use display_interface::{AsyncWriteOnlyDataCommand, DataFormat, DisplayError};
+use embedded_hal::{
+    digital::{ErrorType as PinErrorType, OutputPin},
+    spi::{Error as SpiError, ErrorKind, ErrorType as SpiErrorType},
+};
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+/// Adapts a `display-interface` [AsyncWriteOnlyDataCommand], such as `display-interface-spi`'s
+/// `SPIInterface`, into the [SpiDevice] for a display, so that the SPI bus and DC pin that you
+/// set up for another display driver can be reused.
+///
+/// The interface drives the real DC pin itself, so your [DcHw](super::DcHw) returns an
+/// [InterfaceDcPin] instead. Both share an [InterfaceDc], which records whether the driver is
+/// sending a command or data: writes are sent with [AsyncWriteOnlyDataCommand::send_commands] while
+/// the pin is low, and with [AsyncWriteOnlyDataCommand::send_data] while it's high. `HW` then only
+/// needs the Reset, Busy and Delay pins, and [SpiHw::Spi](super::SpiHw::Spi) is this type.
+///
+/// ```text
+/// static DC: InterfaceDc = InterfaceDc::new();
+///
+/// let mut spi = InterfaceSpi::new(SPIInterface::new(spi_device, dc), &DC);
+/// // DisplayHw's DcHw::Dc is InterfaceDcPin<'static>.
+/// let epd = Epd2In9V2::new(DisplayHw::new(DC.pin(), reset, busy));
+/// let epd = epd.init(&mut spi, RefreshMode::Full).await?;
+/// // Later, give the interface back to another driver.
+/// let interface = spi.into_inner();
+/// ```
+///
+/// Reading from the display is not supported, as the interface is write-only.
+pub struct InterfaceSpi<'a, DI> {
+    interface: DI,
+    dc: &'a InterfaceDc,
+}
+
+impl<'a, DI> InterfaceSpi<'a, DI> {
+    /// Wraps `interface`, sending each write as a command or data according to `dc`.
+    pub fn new(interface: DI, dc: &'a InterfaceDc) -> Self {
+        InterfaceSpi { interface, dc }
+    }
+
+    /// Provides access to the wrapped interface.
+    pub fn inner(&mut self) -> &mut DI {
+        &mut self.interface
+    }
+
+    /// Returns the wrapped interface.
+    pub fn into_inner(self) -> DI {
+        self.interface
+    }
+}
+
+/// Whether an [InterfaceSpi] is sending commands or data. See [InterfaceSpi] for an example.
+#[derive(Debug, Default)]
+pub struct InterfaceDc {
+    data: AtomicBool,
+}
+
+impl InterfaceDc {
+    /// Starts out sending commands.
+    pub const fn new() -> Self {
+        InterfaceDc {
+            data: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the DC pin for your [DcHw](super::DcHw). Setting it high marks the following writes
+    /// as data.
+    pub fn pin(&self) -> InterfaceDcPin<'_> {
+        InterfaceDcPin { dc: self }
+    }
+}
+
+/// The DC pin of an [InterfaceSpi]. See [InterfaceDc::pin].
+#[derive(Debug, Clone, Copy)]
+pub struct InterfaceDcPin<'a> {
+    dc: &'a InterfaceDc,
+}
+
+impl PinErrorType for InterfaceDcPin<'_> {
+    type Error = Infallible;
+}
+
+impl OutputPin for InterfaceDcPin<'_> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.dc.data.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.dc.data.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// An error from the `display-interface`, or an unsupported SPI operation (with
+/// [DisplayError::DataFormatNotImplemented]).
+#[derive(Debug, Clone)]
+pub struct InterfaceError(pub DisplayError);
+
+impl SpiError for InterfaceError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl core::fmt::Display for InterfaceError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "display interface error: {:?}", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InterfaceError {}
+
+/// Lets [InterfaceError] be the [ErrorHw::Error](super::ErrorHw::Error) of hardware whose DC pin
+/// is an [InterfaceDcPin].
+impl From<Infallible> for InterfaceError {
+    fn from(error: Infallible) -> Self {
+        match error {}
+    }
+}
+
+impl<DI> SpiErrorType for InterfaceSpi<'_, DI> {
+    type Error = InterfaceError;
+}
+
+impl<DI: AsyncWriteOnlyDataCommand> SpiDevice for InterfaceSpi<'_, DI> {
+    async fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> Result<(), Self::Error> {
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    let bytes = DataFormat::U8(bytes);
+                    let result = if self.dc.data.load(Ordering::Relaxed) {
+                        self.interface.send_data(bytes).await
+                    } else {
+                        self.interface.send_commands(bytes).await
+                    };
+                    result.map_err(InterfaceError)?;
+                }
+                Operation::DelayNs(_) => {}
+                _ => return Err(InterfaceError(DisplayError::DataFormatNotImplemented)),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{vec, vec::Vec};
+
+    use super::*;
+    use crate::testing::block_on;
+
+    /// Records what was sent, as `(is_data, bytes)`.
+    #[derive(Default)]
+    struct MockInterface(Vec<(bool, Vec<u8>)>);
+
+    impl AsyncWriteOnlyDataCommand for MockInterface {
+        async fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result<(), DisplayError> {
+            match cmd {
+                DataFormat::U8(bytes) => self.0.push((false, bytes.to_vec())),
+                _ => return Err(DisplayError::DataFormatNotImplemented),
+            }
+            Ok(())
+        }
+
+        async fn send_data(&mut self, buf: DataFormat<'_>) -> Result<(), DisplayError> {
+            match buf {
+                DataFormat::U8(bytes) => self.0.push((true, bytes.to_vec())),
+                _ => return Err(DisplayError::DataFormatNotImplemented),
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_writes_follow_the_dc_pin() {
+        let dc = InterfaceDc::new();
+        let mut pin = dc.pin();
+        let mut spi = InterfaceSpi::new(MockInterface::default(), &dc);
+
+        block_on(spi.write(&[0x24])).unwrap();
+        pin.set_high().unwrap();
+        block_on(spi.write(&[0xFF, 0x00])).unwrap();
+        pin.set_low().unwrap();
+        block_on(spi.write(&[0x20])).unwrap();
+
+        assert_eq!(
+            spi.into_inner().0,
+            [
+                (false, vec![0x24]),
+                (true, vec![0xFF, 0x00]),
+                (false, vec![0x20])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reads_are_not_supported() {
+        let dc = InterfaceDc::new();
+        let mut spi = InterfaceSpi::new(MockInterface::default(), &dc);
+
+        let result = block_on(spi.read(&mut [0; 2]));
+        assert!(matches!(
+            result,
+            Err(InterfaceError(DisplayError::DataFormatNotImplemented))
+        ));
+    }
+
+    #[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+    #[test]
+    fn test_sends_commands_and_data() {
+        use embedded_hal::digital::PinState;
+
+        use crate::{
+            hw::{BusyHw, CommandDataSend as _, DcHw, ErrorHw, SpiHw},
+            testing::MockHw,
+        };
+
+        /// [MockHw]'s busy pin, with [InterfaceSpi] as its SPI device.
+        struct InterfaceHw<'a>(MockHw, InterfaceDcPin<'a>);
+
+        impl<'a> SpiHw for InterfaceHw<'a> {
+            type Spi = InterfaceSpi<'a, MockInterface>;
+        }
+
+        impl<'a> DcHw for InterfaceHw<'a> {
+            type Dc = InterfaceDcPin<'a>;
+
+            fn dc(&mut self) -> &mut Self::Dc {
+                &mut self.1
+            }
+        }
+
+        impl ErrorHw for InterfaceHw<'_> {
+            type Error = InterfaceError;
+        }
+
+        impl BusyHw for InterfaceHw<'_> {
+            type Busy = <MockHw as BusyHw>::Busy;
+
+            fn busy(&mut self) -> &mut Self::Busy {
+                self.0.busy()
+            }
+
+            fn busy_when(&self) -> PinState {
+                self.0.busy_when()
+            }
+        }
+
+        let dc = InterfaceDc::new();
+        let mut spi = InterfaceSpi::new(MockInterface::default(), &dc);
+        let mut hw = InterfaceHw(MockHw::new(PinState::High), dc.pin());
+
+        block_on(hw.send(&mut spi, 0x24, &[0xFF, 0x00])).unwrap();
+        block_on(hw.send(&mut spi, 0x20, &[])).unwrap();
+
+        assert_eq!(
+            spi.into_inner().0,
+            [
+                (false, vec![0x24]),
+                (true, vec![0xFF, 0x00]),
+                (false, vec![0x20])
+            ]
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use embassy_time::Delay;
+use embedded_hal::digital::PinState;
+
+use super::{
+    BusyHw, DcHw, DelayHw, ErrorHw, ErrorSource, Polarity, PowerProfile, ResetHw, SpiHw, SpiWiring,
+    Timings,
+};
+
+/// Provides [DelayHw] using [embassy_time::Delay], so that your hardware struct only needs to
+/// implement the other `XHw` traits.
+///
+/// ```text
+/// let epd = Epd2In9V2::new(EmbassyDelay::new(DisplayHw::new(dc, reset, busy)));
+/// ```
+pub struct EmbassyDelay<HW> {
+    hw: HW,
+    delay: Delay,
+}
+
+impl<HW> EmbassyDelay<HW> {
+    /// Wraps `hw`, which must implement every `XHw` trait except [DelayHw].
+    pub fn new(hw: HW) -> Self {
+        EmbassyDelay { hw, delay: Delay }
+    }
+
+    /// Provides access to the wrapped hardware.
+    pub fn inner(&mut self) -> &mut HW {
+        &mut self.hw
+    }
+
+    /// Returns the wrapped hardware.
+    pub fn into_inner(self) -> HW {
+        self.hw
+    }
+}
+
+impl<HW> DelayHw for EmbassyDelay<HW> {
+    type Delay = Delay;
+
+    fn delay(&mut self) -> &mut Self::Delay {
+        &mut self.delay
+    }
+}
+
+impl<HW: ErrorHw> ErrorHw for EmbassyDelay<HW> {
+    type Error = HW::Error;
+
+    fn with_source(error: Self::Error, source: ErrorSource) -> Self::Error {
+        HW::with_source(error, source)
+    }
+}
+
+impl<HW: SpiHw> SpiHw for EmbassyDelay<HW> {
+    type Spi = HW::Spi;
+
+    const MAX_WRITE_LEN: usize = HW::MAX_WRITE_LEN;
+
+    const WIRING: SpiWiring = HW::WIRING;
+
+    fn polarity(&self) -> Polarity {
+        self.hw.polarity()
+    }
+
+    fn power_profile(&self) -> PowerProfile {
+        self.hw.power_profile()
+    }
+}
+
+impl<HW: DcHw> DcHw for EmbassyDelay<HW> {
+    type Dc = HW::Dc;
+
+    fn dc(&mut self) -> &mut Self::Dc {
+        self.hw.dc()
+    }
+}
+
+impl<HW: ResetHw> ResetHw for EmbassyDelay<HW> {
+    type Reset = HW::Reset;
+
+    fn reset(&mut self) -> &mut Self::Reset {
+        self.hw.reset()
+    }
+
+    async fn power_on(&mut self) -> Result<(), Self::Error> {
+        self.hw.power_on().await
+    }
+
+    async fn power_off(&mut self) -> Result<(), Self::Error> {
+        self.hw.power_off().await
+    }
+}
+
+impl<HW: BusyHw> BusyHw for EmbassyDelay<HW> {
+    type Busy = HW::Busy;
+
+    const BUSY_WHEN: Option<PinState> = HW::BUSY_WHEN;
+
+    fn busy(&mut self) -> &mut Self::Busy {
+        self.hw.busy()
+    }
+
+    fn busy_when(&self) -> PinState {
+        self.hw.busy_when()
+    }
+
+    fn timings(&mut self) -> Option<&mut Timings> {
+        self.hw.timings()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epd2in9_v2::{self, Epd2In9V2};
+    use crate::hw::ThreeWireSpi;
+    use crate::testing::{block_on, MockHw, MockPin};
+
+    #[test]
+    fn test_embassy_delay_forwards_to_inner_hw() {
+        let mut hw = EmbassyDelay::new(MockHw::new(PinState::Low));
+        assert_eq!(hw.busy_when(), PinState::Low);
+        assert!(hw.timings().is_none());
+        assert_eq!(
+            <EmbassyDelay<ThreeWireSpi<MockHw>> as SpiHw>::WIRING,
+            SpiWiring::ThreeWire
+        );
+
+        // Check that the wrapper satisfies the driver's bounds.
+        let _epd = Epd2In9V2::new(EmbassyDelay::new(MockHw::new(
+            epd2in9_v2::DEFAULT_BUSY_WHEN,
+        )));
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct PowerError(Option<ErrorSource>);
+
+    /// [MockHw], with a power supply that fails to turn on.
+    struct UnpoweredHw(MockHw);
+
+    impl ErrorHw for UnpoweredHw {
+        type Error = PowerError;
+
+        fn with_source(_error: PowerError, source: ErrorSource) -> PowerError {
+            PowerError(Some(source))
+        }
+    }
+
+    impl ResetHw for UnpoweredHw {
+        type Reset = MockPin;
+
+        fn reset(&mut self) -> &mut Self::Reset {
+            self.0.reset()
+        }
+
+        async fn power_on(&mut self) -> Result<(), Self::Error> {
+            Err(PowerError(None))
+        }
+    }
+
+    #[test]
+    fn test_embassy_delay_forwards_errors() {
+        let mut hw = EmbassyDelay::new(UnpoweredHw(MockHw::new(PinState::Low)));
+        assert_eq!(block_on(hw.power_on()), Err(PowerError(None)));
+        assert_eq!(block_on(hw.power_off()), Ok(()));
+        assert_eq!(
+            EmbassyDelay::<UnpoweredHw>::with_source(PowerError(None), ErrorSource::Power),
+            PowerError(Some(ErrorSource::Power))
+        );
+    }
+}
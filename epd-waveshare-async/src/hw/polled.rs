@@ -0,0 +1,165 @@
+use embedded_hal::digital::{ErrorType as PinErrorType, InputPin};
+use embedded_hal_async::{delay::DelayNs, digital::Wait};
+
+/// Waits for a busy pin by polling it, sleeping for at most `interval_ms` between polls, and calling
+/// `on_poll` each time, e.g. to feed a watchdog.
+///
+/// Waiting on the pin's interrupt can leave a single-future design stuck for a whole refresh,
+/// which can be 30 seconds on some panels. Use this as your [BusyHw::Busy](super::BusyHw::Busy)
+/// pin instead:
+///
+/// ```text
+/// type Busy = PolledBusy<Input<'static>, embassy_time::Delay>;
+///
+/// let busy = PolledBusy::new(Input::new(p.PIN_13, Pull::None), embassy_time::Delay, 100)
+///     .with_hook(feed_watchdog as fn());
+/// ```
+pub struct PolledBusy<P, D, F = fn()> {
+    pin: P,
+    delay: D,
+    interval_ms: u32,
+    on_poll: F,
+}
+
+impl<P, D> PolledBusy<P, D> {
+    /// Wraps `pin`, using `delay` to sleep for `interval_ms` between polls.
+    pub fn new(pin: P, delay: D, interval_ms: u32) -> Self {
+        PolledBusy {
+            pin,
+            delay,
+            interval_ms,
+            on_poll: || {},
+        }
+    }
+
+    /// Calls `on_poll` before each sleep between polls.
+    pub fn with_hook<F: FnMut()>(self, on_poll: F) -> PolledBusy<P, D, F> {
+        PolledBusy {
+            pin: self.pin,
+            delay: self.delay,
+            interval_ms: self.interval_ms,
+            on_poll,
+        }
+    }
+}
+
+impl<P, D, F> PolledBusy<P, D, F> {
+    /// Provides access to the wrapped pin.
+    pub fn inner(&mut self) -> &mut P {
+        &mut self.pin
+    }
+
+    /// Returns the wrapped pin.
+    pub fn into_inner(self) -> P {
+        self.pin
+    }
+}
+
+impl<P: InputPin, D: DelayNs, F: FnMut()> PolledBusy<P, D, F> {
+    async fn poll_until(&mut self, high: bool) -> Result<(), P::Error> {
+        while self.pin.is_high()? != high {
+            (self.on_poll)();
+            self.delay.delay_ms(self.interval_ms).await;
+        }
+        Ok(())
+    }
+}
+
+impl<P: PinErrorType, D, F> PinErrorType for PolledBusy<P, D, F> {
+    type Error = P::Error;
+}
+
+impl<P: InputPin, D, F> InputPin for PolledBusy<P, D, F> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        self.pin.is_high()
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.pin.is_low()
+    }
+}
+
+impl<P: InputPin + Wait, D: DelayNs, F: FnMut()> Wait for PolledBusy<P, D, F> {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.poll_until(true).await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.poll_until(false).await
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.pin.wait_for_rising_edge().await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.pin.wait_for_falling_edge().await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.pin.wait_for_any_edge().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        pin::pin,
+        task::{Context, Waker},
+    };
+
+    use super::*;
+    use crate::testing::{block_on, MockDelay, ScriptedPin};
+
+    #[test]
+    fn test_calls_the_hook_between_polls() {
+        let mut polls = 0;
+        let delay = MockDelay::new();
+        let log = delay.log();
+        let pin = ScriptedPin::new([true, true, true, false]);
+        let mut busy = PolledBusy::new(pin, delay, 100).with_hook(|| polls += 1);
+        block_on(busy.wait_for_low()).unwrap();
+
+        assert_eq!(log.delays(), [100_000_000; 3]);
+        // The pin is only polled, never waited on.
+        assert_eq!(busy.into_inner().waits(), 0);
+        assert_eq!(polls, 3);
+    }
+
+    #[test]
+    fn test_returns_immediately_when_idle() {
+        let mut polls = 0;
+        let delay = MockDelay::new();
+        let log = delay.log();
+        let mut busy =
+            PolledBusy::new(ScriptedPin::new([true]), delay, 100).with_hook(|| polls += 1);
+        block_on(busy.wait_for_high()).unwrap();
+
+        assert!(log.delays().is_empty());
+        drop(busy);
+        assert_eq!(polls, 0);
+    }
+
+    #[test]
+    fn test_yields_between_polls_so_a_stuck_wait_can_time_out() {
+        let mut polls = 0;
+        let delay = MockDelay::new().yielding();
+        let log = delay.log();
+        // The display never finishes.
+        let mut busy =
+            PolledBusy::new(ScriptedPin::new([true]), delay, 100).with_hook(|| polls += 1);
+        {
+            let mut wait = pin!(busy.wait_for_low());
+            let mut cx = Context::from_waker(Waker::noop());
+            for _ in 0..5 {
+                assert!(wait.as_mut().poll(&mut cx).is_pending());
+            }
+            // Dropping the wait here is what a timeout around it does.
+        }
+
+        assert_eq!(log.delays().len(), 5);
+        drop(busy);
+        assert_eq!(polls, 5);
+    }
+}
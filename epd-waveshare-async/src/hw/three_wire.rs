@@ -0,0 +1,257 @@
+use core::convert::Infallible;
+
+use embedded_hal::digital::{ErrorType as PinErrorType, OutputPin, PinState};
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+use embedded_hal_async::spi::SpiDevice;
+
+use super::{
+    BusyHw, DcHw, DelayHw, ErrorHw, ErrorSource, Polarity, PowerProfile, ResetHw, SpiHw, SpiWiring,
+    Timings,
+};
+
+/// Adapts hardware without a DC pin, for boards that strap the controller for 3-wire SPI.
+///
+/// In 3-wire mode, each byte is sent as a 9-bit word, where the first bit is the DC bit. `HW` must
+/// implement every `XHw` trait except [DcHw], and its [ErrorHw::Error] must implement
+/// `From<Infallible>`.
+///
+/// Your SPI device still sends 8-bit words: the 9-bit words are packed into bytes, and any trailing
+/// bits in a transaction are ignored by the controller when CS is released. Reading from the
+/// display is not supported on most 3-wire boards, as the controller's data line is bidirectional.
+///
+/// ```text
+/// let epd = Epd2In9V2::new(ThreeWireSpi::new(DisplayHw::new(reset, busy)));
+/// ```
+pub struct ThreeWireSpi<HW> {
+    hw: HW,
+    dc: NoDc,
+}
+
+impl<HW> ThreeWireSpi<HW> {
+    /// Wraps `hw`, which must implement every `XHw` trait except [DcHw].
+    pub fn new(hw: HW) -> Self {
+        ThreeWireSpi { hw, dc: NoDc }
+    }
+
+    /// Provides access to the wrapped hardware.
+    pub fn inner(&mut self) -> &mut HW {
+        &mut self.hw
+    }
+
+    /// Returns the wrapped hardware.
+    pub fn into_inner(self) -> HW {
+        self.hw
+    }
+}
+
+/// A placeholder DC pin for [ThreeWireSpi], which ignores all changes.
+pub struct NoDc;
+
+impl PinErrorType for NoDc {
+    type Error = Infallible;
+}
+
+impl OutputPin for NoDc {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<HW> DcHw for ThreeWireSpi<HW> {
+    type Dc = NoDc;
+
+    fn dc(&mut self) -> &mut Self::Dc {
+        &mut self.dc
+    }
+}
+
+impl<HW: ErrorHw> ErrorHw for ThreeWireSpi<HW> {
+    type Error = HW::Error;
+
+    fn with_source(error: Self::Error, source: ErrorSource) -> Self::Error {
+        HW::with_source(error, source)
+    }
+}
+
+impl<HW: SpiHw> SpiHw for ThreeWireSpi<HW> {
+    type Spi = HW::Spi;
+
+    const MAX_WRITE_LEN: usize = HW::MAX_WRITE_LEN;
+    const WIRING: SpiWiring = SpiWiring::ThreeWire;
+
+    fn polarity(&self) -> Polarity {
+        self.hw.polarity()
+    }
+
+    fn power_profile(&self) -> PowerProfile {
+        self.hw.power_profile()
+    }
+}
+
+impl<HW: ResetHw> ResetHw for ThreeWireSpi<HW> {
+    type Reset = HW::Reset;
+
+    fn reset(&mut self) -> &mut Self::Reset {
+        self.hw.reset()
+    }
+
+    async fn power_on(&mut self) -> Result<(), Self::Error> {
+        self.hw.power_on().await
+    }
+
+    async fn power_off(&mut self) -> Result<(), Self::Error> {
+        self.hw.power_off().await
+    }
+}
+
+impl<HW: BusyHw> BusyHw for ThreeWireSpi<HW> {
+    type Busy = HW::Busy;
+
+    const BUSY_WHEN: Option<PinState> = HW::BUSY_WHEN;
+
+    fn busy(&mut self) -> &mut Self::Busy {
+        self.hw.busy()
+    }
+
+    fn busy_when(&self) -> PinState {
+        self.hw.busy_when()
+    }
+
+    fn timings(&mut self) -> Option<&mut Timings> {
+        self.hw.timings()
+    }
+}
+
+impl<HW: DelayHw> DelayHw for ThreeWireSpi<HW> {
+    type Delay = HW::Delay;
+
+    fn delay(&mut self) -> &mut Self::Delay {
+        self.hw.delay()
+    }
+}
+
+/// The most bytes packed per write. 72 bytes hold exactly 64 9-bit words.
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+const PACKED_LEN: usize = 72;
+
+/// Writes `bytes` as 9-bit words with the given DC bit, in writes of at most `max_write_len` bytes.
+///
+/// Returns the number of bytes written.
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+pub(crate) async fn write_words<SPI: SpiDevice>(
+    spi: &mut SPI,
+    dc: bool,
+    bytes: &[u8],
+    max_write_len: usize,
+) -> Result<usize, SPI::Error> {
+    let mut packed = [0; PACKED_LEN];
+    let mut written = 0;
+    for chunk in bytes.chunks(words_per_write(max_write_len)) {
+        let len = pack_words(chunk.iter().map(|&byte| (dc, byte)), &mut packed);
+        spi.write(&packed[..len]).await?;
+        written += len;
+    }
+    Ok(written)
+}
+
+/// Writes `command`, then `data`, as 9-bit words in writes of at most `max_write_len` bytes.
+///
+/// The DC bit is sent in-band, so the command shares its write with the start of the data. Commands
+/// whose data fits in one write are therefore sent under a single CS assertion.
+///
+/// Returns the number of bytes written.
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+pub(crate) async fn write_command<SPI: SpiDevice>(
+    spi: &mut SPI,
+    command: u8,
+    data: &[u8],
+    max_write_len: usize,
+) -> Result<usize, SPI::Error> {
+    let (first, rest) = data.split_at(data.len().min(words_per_write(max_write_len) - 1));
+    let words = core::iter::once((false, command)).chain(first.iter().map(|&byte| (true, byte)));
+    let mut packed = [0; PACKED_LEN];
+    let len = pack_words(words, &mut packed);
+    spi.write(&packed[..len]).await?;
+    Ok(len + write_words(spi, true, rest, max_write_len).await?)
+}
+
+/// Returns how many 9-bit words fit in a write of at most `max_write_len` bytes.
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+fn words_per_write(max_write_len: usize) -> usize {
+    // Each write needs room for at least one word, i.e. 2 bytes.
+    (max_write_len.min(PACKED_LEN) * 8 / 9).max(1)
+}
+
+/// Packs each `(dc, byte)` word into `out` as 9 bits, most significant bit first, and returns the
+/// number of bytes used. Any unused bits in the last byte are zero.
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+fn pack_words(words: impl Iterator<Item = (bool, u8)>, out: &mut [u8]) -> usize {
+    out.fill(0);
+    let mut bit = 0;
+    for (dc, byte) in words {
+        let word = (u16::from(dc) << 8) | u16::from(byte);
+        for i in (0..9).rev() {
+            if word & (1 << i) != 0 {
+                out[bit / 8] |= 0x80 >> (bit % 8);
+            }
+            bit += 1;
+        }
+    }
+    bit.div_ceil(8)
+}
+
+#[cfg(all(test, any(feature = "epd2in9", feature = "epd2in9_v2")))]
+mod tests {
+    use super::*;
+    use crate::hw::CommandDataSend as _;
+    use crate::testing::{block_on, Event, MockHw};
+
+    #[test]
+    fn test_pack_words() {
+        let mut out = [0xAA; 4];
+        assert_eq!(pack_words([(false, 0x24)].into_iter(), &mut out), 2);
+        assert_eq!(out[..2], [0x12, 0x00]);
+        assert_eq!(
+            pack_words([(true, 0xFF), (true, 0x00)].into_iter(), &mut out),
+            3
+        );
+        assert_eq!(out[..3], [0xFF, 0xC0, 0x00]);
+        assert_eq!(
+            pack_words([(false, 0x24), (true, 0xFF)].into_iter(), &mut out),
+            3
+        );
+        assert_eq!(out[..3], [0x12, 0x7F, 0xC0]);
+    }
+
+    #[test]
+    fn test_send_packs_dc_bit() {
+        let mock = MockHw::new(PinState::High);
+        let log = mock.log();
+        let mut spi = mock.spi();
+        let mut hw = ThreeWireSpi::new(mock);
+
+        block_on(hw.send(&mut spi, 0x24, &[0xFF])).unwrap();
+
+        // The command and its data share one transaction. The mock's DC pin is never driven, so
+        // the write is recorded as data.
+        assert_eq!(log.transaction_count(), 1);
+        assert_eq!(log.events(), [Event::Data(vec![0x12, 0x7F, 0xC0])]);
+    }
+
+    #[test]
+    fn test_send_splits_long_data_after_the_command() {
+        let mock = MockHw::new(PinState::High);
+        let log = mock.log();
+        let mut spi = mock.spi();
+        let mut hw = ThreeWireSpi::new(mock);
+
+        // 64 words fit in a write, so the command takes 63 data bytes with it.
+        block_on(hw.send(&mut spi, 0x24, &[0; 100])).unwrap();
+
+        assert_eq!(log.transaction_count(), 2);
+    }
+}
@@ -0,0 +1,408 @@
+//! A small set of 16x16, 1-bit icons for status displays.
+//!
+//! ```
+//! use embedded_graphics::prelude::Point;
+//! use epd_waveshare_async::{epd2in9_v2, icons::Icon};
+//!
+//! let mut buffer = epd2in9_v2::new_binary_buffer();
+//! let changed = Icon::Sunny.draw_at(Point::new(8, 8), &mut buffer).unwrap();
+//! assert_eq!(changed.size, Icon::SIZE);
+//! ```
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    prelude::{Point, Size},
+    primitives::Rectangle,
+    Pixel,
+};
+
+use crate::dither::Quantize;
+
+/// A built-in icon.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Icon {
+    Sunny,
+    Cloudy,
+    Rain,
+    Snow,
+    Storm,
+    BatteryFull,
+    BatteryHalf,
+    BatteryEmpty,
+    Wifi,
+    SignalNone,
+    SignalLow,
+    SignalMedium,
+    SignalHigh,
+    SignalFull,
+    ArrowUp,
+    ArrowDown,
+    ArrowLeft,
+    ArrowRight,
+}
+
+impl Icon {
+    /// The size of every icon.
+    pub const SIZE: Size = Size::new(16, 16);
+
+    /// Returns the icon's rows from top to bottom, with the leftmost pixel in the most significant
+    /// bit.
+    pub fn bitmap(self) -> &'static [u16; 16] {
+        match self {
+            Icon::Sunny => &SUNNY,
+            Icon::Cloudy => &CLOUDY,
+            Icon::Rain => &RAIN,
+            Icon::Snow => &SNOW,
+            Icon::Storm => &STORM,
+            Icon::BatteryFull => &BATTERY_FULL,
+            Icon::BatteryHalf => &BATTERY_HALF,
+            Icon::BatteryEmpty => &BATTERY_EMPTY,
+            Icon::Wifi => &WIFI,
+            Icon::SignalNone => &SIGNAL_NONE,
+            Icon::SignalLow => &SIGNAL_LOW,
+            Icon::SignalMedium => &SIGNAL_MEDIUM,
+            Icon::SignalHigh => &SIGNAL_HIGH,
+            Icon::SignalFull => &SIGNAL_FULL,
+            Icon::ArrowUp => &ARROW_UP,
+            Icon::ArrowDown => &ARROW_DOWN,
+            Icon::ArrowLeft => &ARROW_LEFT,
+            Icon::ArrowRight => &ARROW_RIGHT,
+        }
+    }
+
+    /// Draws the icon in black with its top left at `point`, leaving the other pixels unchanged.
+    ///
+    /// Returns the area that the icon covers.
+    pub fn draw_at<D>(self, point: Point, target: &mut D) -> Result<Rectangle, D::Error>
+    where
+        D: DrawTarget,
+        D::Color: Quantize,
+    {
+        self.draw_with_color(point, target, D::Color::from_level(0))
+    }
+
+    /// Draws the icon in `color` with its top left at `point`, leaving the other pixels unchanged.
+    ///
+    /// Returns the area that the icon covers.
+    pub fn draw_with_color<D: DrawTarget>(
+        self,
+        point: Point,
+        target: &mut D,
+        color: D::Color,
+    ) -> Result<Rectangle, D::Error> {
+        let pixels = self.bitmap().iter().enumerate().flat_map(|(y, row)| {
+            (0..16)
+                .filter(move |x| row & (0x8000 >> x) != 0)
+                .map(move |x| Pixel(point + Point::new(x, y as i32), color))
+        });
+        target.draw_iter(pixels)?;
+        Ok(Rectangle::new(point, Self::SIZE))
+    }
+}
+
+/// Parses an icon drawn with `#` for set pixels, and `.` for clear ones.
+const fn parse(rows: [&str; 16]) -> [u16; 16] {
+    let mut bitmap = [0; 16];
+    let mut y = 0;
+    while y < 16 {
+        let row = rows[y].as_bytes();
+        assert!(row.len() == 16, "icon rows must be 16 pixels wide");
+        let mut x = 0;
+        while x < 16 {
+            if row[x] == b'#' {
+                bitmap[y] |= 0x8000 >> x;
+            }
+            x += 1;
+        }
+        y += 1;
+    }
+    bitmap
+}
+
+/// Flips an icon upside down.
+const fn flip_vertical(bitmap: [u16; 16]) -> [u16; 16] {
+    let mut flipped = [0; 16];
+    let mut y = 0;
+    while y < 16 {
+        flipped[y] = bitmap[15 - y];
+        y += 1;
+    }
+    flipped
+}
+
+/// Swaps an icon's rows and columns.
+const fn transpose(bitmap: [u16; 16]) -> [u16; 16] {
+    let mut transposed = [0; 16];
+    let mut y = 0;
+    while y < 16 {
+        let mut x = 0;
+        while x < 16 {
+            if bitmap[x] & (0x8000 >> y) != 0 {
+                transposed[y] |= 0x8000 >> x;
+            }
+            x += 1;
+        }
+        y += 1;
+    }
+    transposed
+}
+
+/// Draws 4 signal strength bars, with the first `bars` filled and the rest shown as just their
+/// bottom row.
+const fn signal(bars: usize) -> [u16; 16] {
+    let mut bitmap = [0; 16];
+    let mut bar = 0;
+    while bar < 4 {
+        let columns = 0xE000 >> (bar * 4);
+        let mut y = if bar < bars { 11 - bar * 3 } else { 14 };
+        while y < 15 {
+            bitmap[y] |= columns;
+            y += 1;
+        }
+        bar += 1;
+    }
+    bitmap
+}
+
+const SUNNY: [u16; 16] = parse([
+    "................",
+    ".......##.......",
+    "..#....##....#..",
+    "...#........#...",
+    "......####......",
+    ".....######.....",
+    "....########....",
+    ".##.########.##.",
+    ".##.########.##.",
+    "....########....",
+    ".....######.....",
+    "......####......",
+    "...#........#...",
+    "..#....##....#..",
+    ".......##.......",
+    "................",
+]);
+
+const CLOUDY: [u16; 16] = parse([
+    "................",
+    "................",
+    "................",
+    "......####......",
+    ".....#....#.....",
+    "...###.....##...",
+    "..#..........#..",
+    ".#...........#..",
+    ".#............#.",
+    "#..............#",
+    "#..............#",
+    ".#............#.",
+    "..############..",
+    "................",
+    "................",
+    "................",
+]);
+
+const RAIN: [u16; 16] = parse([
+    "......####......",
+    ".....#....#.....",
+    "...###.....##...",
+    "..#..........#..",
+    ".#............#.",
+    "#..............#",
+    "#..............#",
+    ".##############.",
+    "................",
+    "...#...#...#....",
+    "..#...#...#...#.",
+    "................",
+    "....#...#...#...",
+    "...#...#...#....",
+    "................",
+    "................",
+]);
+
+const SNOW: [u16; 16] = parse([
+    "......####......",
+    ".....#....#.....",
+    "...###.....##...",
+    "..#..........#..",
+    ".#............#.",
+    "#..............#",
+    "#..............#",
+    ".##############.",
+    "................",
+    "..#....#....#...",
+    ".###..###..###..",
+    "..#....#....#...",
+    "................",
+    "....#....#......",
+    "...###..###.....",
+    "....#....#......",
+]);
+
+const STORM: [u16; 16] = parse([
+    "......####......",
+    ".....#....#.....",
+    "...###.....##...",
+    "..#..........#..",
+    ".#............#.",
+    "#..............#",
+    "#..............#",
+    ".##############.",
+    "........#.......",
+    ".......##.......",
+    "......##........",
+    ".....######.....",
+    "........##......",
+    ".......##.......",
+    "......#.........",
+    "................",
+]);
+
+const BATTERY_FULL: [u16; 16] = parse([
+    "................",
+    "................",
+    "................",
+    "................",
+    "##############..",
+    "#............#..",
+    "#.##########.#..",
+    "#.##########.##.",
+    "#.##########.##.",
+    "#.##########.#..",
+    "#............#..",
+    "##############..",
+    "................",
+    "................",
+    "................",
+    "................",
+]);
+
+const BATTERY_HALF: [u16; 16] = parse([
+    "................",
+    "................",
+    "................",
+    "................",
+    "##############..",
+    "#............#..",
+    "#.#####......#..",
+    "#.#####......##.",
+    "#.#####......##.",
+    "#.#####......#..",
+    "#............#..",
+    "##############..",
+    "................",
+    "................",
+    "................",
+    "................",
+]);
+
+const BATTERY_EMPTY: [u16; 16] = parse([
+    "................",
+    "................",
+    "................",
+    "................",
+    "##############..",
+    "#............#..",
+    "#............#..",
+    "#............##.",
+    "#............##.",
+    "#............#..",
+    "#............#..",
+    "##############..",
+    "................",
+    "................",
+    "................",
+    "................",
+]);
+
+const WIFI: [u16; 16] = parse([
+    "................",
+    "................",
+    "....########....",
+    "..##........##..",
+    ".#............#.",
+    "#....######....#",
+    "...##......##...",
+    "..#..........#..",
+    "......####......",
+    ".....#....#.....",
+    "................",
+    ".......##.......",
+    ".......##.......",
+    "................",
+    "................",
+    "................",
+]);
+
+const SIGNAL_NONE: [u16; 16] = signal(0);
+const SIGNAL_LOW: [u16; 16] = signal(1);
+const SIGNAL_MEDIUM: [u16; 16] = signal(2);
+const SIGNAL_HIGH: [u16; 16] = signal(3);
+const SIGNAL_FULL: [u16; 16] = signal(4);
+
+const ARROW_UP: [u16; 16] = parse([
+    "................",
+    ".......##.......",
+    "......####......",
+    ".....######.....",
+    "....########....",
+    "...###.##.###...",
+    "..###..##..###..",
+    ".###...##...###.",
+    ".......##.......",
+    ".......##.......",
+    ".......##.......",
+    ".......##.......",
+    ".......##.......",
+    ".......##.......",
+    ".......##.......",
+    "................",
+]);
+
+const ARROW_DOWN: [u16; 16] = flip_vertical(ARROW_UP);
+const ARROW_LEFT: [u16; 16] = transpose(ARROW_UP);
+const ARROW_RIGHT: [u16; 16] = transpose(ARROW_DOWN);
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::pixelcolor::BinaryColor;
+
+    use super::*;
+    use crate::buffer::{binary_buffer_length, BinaryBuffer};
+
+    #[test]
+    fn test_derived_arrows_point_the_right_way() {
+        // The tip of each arrow is at the edge it points to.
+        assert_eq!(ARROW_UP[1], 0b0000_0001_1000_0000);
+        assert_eq!(ARROW_DOWN[14], 0b0000_0001_1000_0000);
+        assert_eq!(ARROW_LEFT[7], 0b0111_1111_1111_1110);
+        assert_eq!(ARROW_RIGHT[7], 0b0111_1111_1111_1110);
+        assert_eq!(ARROW_LEFT[4], 0b0000_1110_0000_0000);
+        assert_eq!(ARROW_RIGHT[4], 0b0000_0000_0111_0000);
+    }
+
+    #[test]
+    fn test_signal_bars_grow_left_to_right() {
+        assert_eq!(SIGNAL_FULL[2], 0b0000_0000_0000_1110);
+        assert_eq!(SIGNAL_FULL[11], 0b1110_1110_1110_1110);
+        assert_eq!(SIGNAL_LOW[11], 0b1110_0000_0000_0000);
+        // Empty bars are just their bottom row.
+        assert_eq!(SIGNAL_NONE[13], 0);
+        assert_eq!(SIGNAL_NONE[14], 0b1110_1110_1110_1110);
+        assert_eq!(SIGNAL_NONE[15], 0);
+    }
+
+    #[test]
+    fn test_draw_at_only_sets_icon_pixels() {
+        const SIZE: Size = Size::new(24, 16);
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        buffer.clear(BinaryColor::On).unwrap();
+
+        let changed = Icon::ArrowUp.draw_at(Point::new(8, 0), &mut buffer);
+        assert_eq!(changed, Ok(Rectangle::new(Point::new(8, 0), Icon::SIZE)));
+        // Row 1 has just the arrow's tip, in black.
+        assert_eq!(buffer.data()[3..6], [0xFF, 0b1111_1110, 0b0111_1111]);
+    }
+}
@@ -0,0 +1,404 @@
+//! Puts a display to sleep once it's been idle for a while, and wakes it again when it's next used.
+//!
+//! [AutoSleep] implements the same display traits as the display it wraps, so it can be used in
+//! place of it without orchestrating [Sleep] and [Wake] by hand. Call [AutoSleep::sleep_if_idle]
+//! periodically, e.g. from your main loop, to put the display to sleep.
+//!
+//! ```text
+//! fn now_us() -> u64 {
+//!     embassy_time::Instant::now().as_micros()
+//! }
+//!
+//! let mut epd = AutoSleep::new(epd, Duration::from_secs(30), now_us);
+//! loop {
+//!     match select(buttons.receive(), Timer::after_secs(1)).await {
+//!         Either::First(button) => {
+//!             draw_menu(&mut buffer, button);
+//!             // Wakes the display first if it's asleep.
+//!             epd.display_framebuffer(&mut spi, &buffer).await?;
+//!         }
+//!         Either::Second(()) => {
+//!             epd.sleep_if_idle(&mut spi).await?;
+//!         }
+//!     }
+//! }
+//! ```
+use core::time::Duration;
+
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::{
+    buffer::BufferView,
+    log::debug,
+    refresh::{RefreshKind, SetRefreshKind},
+    DisplayPartial, DisplaySimple, Displayable, Sleep, WaitIdle, Wake,
+};
+
+/// A display in either its ready state `D` or its sleeping state `S`.
+#[derive(Debug)]
+pub enum Power<D, S> {
+    /// The display is awake.
+    Awake(D),
+    /// The display is asleep.
+    Asleep(S),
+}
+
+/// The display was lost to an error while it was being woken or put to sleep, as [Wake] and
+/// [Sleep] consume it.
+///
+/// Wrappers such as [AutoSleep] return this from every call after that, so recover by creating
+/// the display again, e.g. from its hardware.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayLost;
+
+impl core::fmt::Display for DisplayLost {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("the display was lost while waking or sleeping")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DisplayLost {}
+
+/// Holds a display that's woken and put to sleep by value, which is lost if that fails.
+pub(crate) struct PowerCell<D, S> {
+    /// `None` once the display is lost.
+    power: Option<Power<D, S>>,
+}
+
+impl<D, S> PowerCell<D, S> {
+    pub(crate) fn new(display: D) -> Self {
+        PowerCell {
+            power: Some(Power::Awake(display)),
+        }
+    }
+
+    pub(crate) fn is_awake(&self) -> bool {
+        matches!(self.power, Some(Power::Awake(_)))
+    }
+
+    pub(crate) fn is_asleep(&self) -> bool {
+        matches!(self.power, Some(Power::Asleep(_)))
+    }
+
+    /// Returns the display if it's awake.
+    pub(crate) fn awake_display(&mut self) -> Option<&mut D> {
+        match &mut self.power {
+            Some(Power::Awake(display)) => Some(display),
+            _ => None,
+        }
+    }
+
+    /// Returns the awake display, waking it first if it's asleep.
+    pub(crate) async fn wake<SPI, ERROR>(&mut self, spi: &mut SPI) -> Result<&mut D, ERROR>
+    where
+        SPI: SpiDevice,
+        S: Wake<SPI, ERROR, DisplayOut = D>,
+        ERROR: From<DisplayLost>,
+    {
+        match self.power.take() {
+            Some(Power::Asleep(display)) => {
+                debug!("Waking display");
+                self.power = Some(Power::Awake(display.wake(spi).await?));
+            }
+            power => self.power = power,
+        }
+        self.awake_display().ok_or_else(|| DisplayLost.into())
+    }
+
+    /// Puts the display to sleep if it's awake, once any refresh in progress has finished.
+    pub(crate) async fn sleep<SPI, ERROR>(&mut self, spi: &mut SPI) -> Result<(), ERROR>
+    where
+        SPI: SpiDevice,
+        D: WaitIdle<ERROR> + Sleep<SPI, ERROR, DisplayOut = S>,
+        ERROR: From<DisplayLost>,
+    {
+        self.awake_display()
+            .ok_or(DisplayLost)?
+            .wait_until_idle()
+            .await?;
+        match self.power.take() {
+            Some(Power::Awake(display)) => {
+                debug!("Sleeping display");
+                self.power = Some(Power::Asleep(display.sleep(spi).await?));
+            }
+            power => self.power = power,
+        }
+        Ok(())
+    }
+
+    pub(crate) fn into_inner(self) -> Option<Power<D, S>> {
+        self.power
+    }
+}
+
+/// Wraps a display, putting it to sleep after it's been idle for a timeout, and waking it on the
+/// next call that uses it.
+///
+/// The display is woken with [Wake], so it's restored to the refresh mode it was in before it
+/// slept. `D` is the ready state of the display, such as `Epd2In9V2<HW, StateReady>`, and `S` is
+/// its sleeping state.
+///
+/// If waking or sleeping the display fails, the display is lost with it, and every later call
+/// returns [DisplayLost], so your error type must convert from it.
+pub struct AutoSleep<D, S> {
+    power: PowerCell<D, S>,
+    timeout_us: u64,
+    now_us: fn() -> u64,
+    last_used_us: u64,
+}
+
+impl<D, S> AutoSleep<D, S> {
+    /// Wraps the given initialised display, which sleeps once it's been idle for `timeout`.
+    ///
+    /// The clock must return a monotonic time in microseconds.
+    pub fn new(display: D, timeout: Duration, now_us: fn() -> u64) -> Self {
+        AutoSleep {
+            power: PowerCell::new(display),
+            timeout_us: timeout.as_micros() as u64,
+            now_us,
+            last_used_us: now_us(),
+        }
+    }
+
+    /// Returns the display, awake or asleep, or `None` if it was lost to an error.
+    pub fn into_inner(self) -> Option<Power<D, S>> {
+        self.power.into_inner()
+    }
+
+    /// Whether the display is currently asleep.
+    pub fn is_asleep(&self) -> bool {
+        self.power.is_asleep()
+    }
+
+    /// The time at which the display will be idle for long enough to sleep, in the clock's
+    /// microseconds, or `None` if it's asleep or lost.
+    pub fn idle_deadline_us(&self) -> Option<u64> {
+        self.power
+            .is_awake()
+            .then(|| self.last_used_us.saturating_add(self.timeout_us))
+    }
+
+    /// Puts the display to sleep if it hasn't been used for the timeout, waiting for any refresh
+    /// in progress to finish first. Returns whether the display was put to sleep.
+    pub async fn sleep_if_idle<SPI, ERROR>(&mut self, spi: &mut SPI) -> Result<bool, ERROR>
+    where
+        SPI: SpiDevice,
+        D: WaitIdle<ERROR> + Sleep<SPI, ERROR, DisplayOut = S>,
+        ERROR: From<DisplayLost>,
+    {
+        match self.idle_deadline_us() {
+            Some(deadline) if (self.now_us)() >= deadline => {}
+            _ => return Ok(false),
+        }
+        self.power.sleep(spi).await?;
+        Ok(true)
+    }
+
+    /// Returns the awake display, waking it first if it's asleep, and restarts the idle timeout.
+    pub async fn awake<SPI, ERROR>(&mut self, spi: &mut SPI) -> Result<&mut D, ERROR>
+    where
+        SPI: SpiDevice,
+        S: Wake<SPI, ERROR, DisplayOut = D>,
+        ERROR: From<DisplayLost>,
+    {
+        self.restart_timeout();
+        self.power.wake(spi).await
+    }
+
+    /// Restarts the idle timeout, so that it counts from the end of the last call.
+    fn restart_timeout(&mut self) {
+        self.last_used_us = (self.now_us)();
+    }
+}
+
+impl<D, S, SPI, ERROR> Displayable<SPI, ERROR> for AutoSleep<D, S>
+where
+    SPI: SpiDevice,
+    D: Displayable<SPI, ERROR>,
+    S: Wake<SPI, ERROR, DisplayOut = D>,
+    ERROR: From<DisplayLost>,
+{
+    async fn update_display(&mut self, spi: &mut SPI) -> Result<(), ERROR> {
+        let result = self.awake(spi).await?.update_display(spi).await;
+        self.restart_timeout();
+        result
+    }
+}
+
+impl<const BITS: usize, const FRAMES: usize, D, S, SPI, ERROR>
+    DisplaySimple<BITS, FRAMES, SPI, ERROR> for AutoSleep<D, S>
+where
+    SPI: SpiDevice,
+    D: DisplaySimple<BITS, FRAMES, SPI, ERROR>,
+    S: Wake<SPI, ERROR, DisplayOut = D>,
+    ERROR: From<DisplayLost>,
+{
+    async fn write_framebuffer(
+        &mut self,
+        spi: &mut SPI,
+        buf: &dyn BufferView<BITS, FRAMES>,
+    ) -> Result<(), ERROR> {
+        let result = self.awake(spi).await?.write_framebuffer(spi, buf).await;
+        self.restart_timeout();
+        result
+    }
+}
+
+impl<const BITS: usize, const FRAMES: usize, D, S, SPI, ERROR>
+    DisplayPartial<BITS, FRAMES, SPI, ERROR> for AutoSleep<D, S>
+where
+    SPI: SpiDevice,
+    D: DisplayPartial<BITS, FRAMES, SPI, ERROR>,
+    S: Wake<SPI, ERROR, DisplayOut = D>,
+    ERROR: From<DisplayLost>,
+{
+    async fn write_base_framebuffer(
+        &mut self,
+        spi: &mut SPI,
+        buf: &dyn BufferView<BITS, FRAMES>,
+    ) -> Result<(), ERROR> {
+        let result = self
+            .awake(spi)
+            .await?
+            .write_base_framebuffer(spi, buf)
+            .await;
+        self.restart_timeout();
+        result
+    }
+}
+
+impl<D, S, SPI, ERROR> SetRefreshKind<SPI, ERROR> for AutoSleep<D, S>
+where
+    SPI: SpiDevice,
+    D: SetRefreshKind<SPI, ERROR>,
+    S: Wake<SPI, ERROR, DisplayOut = D>,
+    ERROR: From<DisplayLost>,
+{
+    async fn set_refresh_kind(&mut self, spi: &mut SPI, kind: RefreshKind) -> Result<(), ERROR> {
+        let result = self.awake(spi).await?.set_refresh_kind(spi, kind).await;
+        self.restart_timeout();
+        result
+    }
+}
+
+impl<D, S, ERROR> WaitIdle<ERROR> for AutoSleep<D, S>
+where
+    D: WaitIdle<ERROR>,
+{
+    /// Returns straight away if the display is asleep or lost.
+    async fn wait_until_idle(&mut self) -> Result<(), ERROR> {
+        match self.power.awake_display() {
+            Some(display) => display.wait_until_idle().await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+    use crate::{
+        buffer::WindowError,
+        epd2in9_v2::{self, Command, Epd2In9V2, RefreshMode},
+        testing::{block_on, Event, MockError, MockHw, MockSpi},
+        StateSnapshot,
+    };
+
+    std::thread_local! {
+        static NOW_US: Cell<u64> = const { Cell::new(0) };
+    }
+
+    fn fake_now_us() -> u64 {
+        NOW_US.get()
+    }
+
+    #[test]
+    fn test_sleeps_when_idle_and_wakes_on_use() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        let mut epd = AutoSleep::new(epd, Duration::from_secs(30), fake_now_us);
+        log.take_events();
+
+        NOW_US.set(29_000_000);
+        assert!(!block_on(epd.sleep_if_idle(&mut spi)).unwrap());
+        NOW_US.set(30_000_000);
+        assert!(block_on(epd.sleep_if_idle(&mut spi)).unwrap());
+        assert!(epd.is_asleep());
+        assert_eq!(log.commands(), [Command::DeepSleepMode as u8]);
+        log.take_events();
+
+        NOW_US.set(40_000_000);
+        let buffer = epd2in9_v2::new_binary_buffer();
+        block_on(epd.display_framebuffer(&mut spi, &buffer)).unwrap();
+        assert!(!epd.is_asleep());
+        assert!(matches!(log.events()[0], Event::Reset(_)));
+        assert_eq!(epd.idle_deadline_us(), Some(70_000_000));
+
+        let Some(Power::Awake(display)) = epd.into_inner() else {
+            panic!("display should be awake");
+        };
+        let partial = Epd2In9V2::attach(
+            MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN),
+            RefreshMode::Partial,
+        );
+        assert_eq!(
+            display.into_state_snapshot().1.to_snapshot(),
+            partial.into_state_snapshot().1.to_snapshot()
+        );
+    }
+
+    /// A display whose wake fails, for testing recovery.
+    struct Flaky;
+
+    impl Sleep<MockSpi, MockError> for Flaky {
+        type DisplayOut = Flaky;
+
+        async fn sleep(self, _spi: &mut MockSpi) -> Result<Flaky, MockError> {
+            Ok(Flaky)
+        }
+    }
+
+    impl Wake<MockSpi, MockError> for Flaky {
+        type DisplayOut = Flaky;
+
+        async fn wake(self, _spi: &mut MockSpi) -> Result<Flaky, MockError> {
+            Err(MockError::Window(WindowError::Empty))
+        }
+    }
+
+    impl WaitIdle<MockError> for Flaky {
+        async fn wait_until_idle(&mut self) -> Result<(), MockError> {
+            Ok(())
+        }
+    }
+
+    impl Displayable<MockSpi, MockError> for Flaky {
+        async fn update_display(&mut self, _spi: &mut MockSpi) -> Result<(), MockError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_failed_wake_loses_the_display() {
+        let mut spi = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN).spi();
+        let mut epd = AutoSleep::new(Flaky, Duration::ZERO, fake_now_us);
+        assert!(block_on(epd.sleep_if_idle(&mut spi)).unwrap());
+
+        assert_eq!(
+            block_on(epd.update_display(&mut spi)),
+            Err(MockError::Window(WindowError::Empty))
+        );
+        assert_eq!(
+            block_on(epd.update_display(&mut spi)),
+            Err(MockError::Lost(DisplayLost))
+        );
+        assert_eq!(block_on(epd.sleep_if_idle(&mut spi)), Ok(false));
+        assert!(epd.into_inner().is_none());
+    }
+}
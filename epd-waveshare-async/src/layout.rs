@@ -0,0 +1,227 @@
+//! A lightweight layout helper, which splits an area (such as a buffer's bounding box) into rows and
+//! columns of [Rectangle]s, for placing [crate::widgets].
+//!
+//! ```
+//! use embedded_graphics::{prelude::*, primitives::Rectangle};
+//! use epd_waveshare_async::{
+//!     epd2in9_v2,
+//!     layout::{columns, pad, rows, Length, Padding},
+//! };
+//!
+//! let buffer = epd2in9_v2::new_binary_buffer();
+//! let screen = pad(buffer.bounding_box(), Padding::all(4));
+//! // A 16 pixel header, and a body split into two equal columns.
+//! let [header, body] = rows(screen, [Length::Px(16), Length::Fill], 4);
+//! let [left, right] = columns(body, [Length::Percent(50), Length::Fill], 0);
+//!
+//! assert_eq!(header, Rectangle::new(Point::new(4, 4), Size::new(120, 16)));
+//! assert_eq!(left.size.width, right.size.width);
+//! ```
+use embedded_graphics::{
+    prelude::{Point, Size},
+    primitives::Rectangle,
+};
+
+/// The size of one cell along a row or column.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Length {
+    /// A fixed number of pixels.
+    Px(u32),
+    /// A percentage of the space that's available after the gaps.
+    Percent(u8),
+    /// An equal share of the space left over by the other cells.
+    Fill,
+}
+
+/// Space to leave inside each edge of an area.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Padding {
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    pub left: u32,
+}
+
+impl Padding {
+    /// The same padding on every edge.
+    pub const fn all(padding: u32) -> Self {
+        Padding {
+            top: padding,
+            right: padding,
+            bottom: padding,
+            left: padding,
+        }
+    }
+
+    /// `horizontal` padding on the left and right, and `vertical` padding on the top and bottom.
+    pub const fn symmetric(horizontal: u32, vertical: u32) -> Self {
+        Padding {
+            top: vertical,
+            right: horizontal,
+            bottom: vertical,
+            left: horizontal,
+        }
+    }
+}
+
+/// Where to place something within a larger area, along one axis.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+}
+
+/// Shrinks `area` by `padding`. Padding larger than the area leaves an empty rectangle.
+pub fn pad(area: Rectangle, padding: Padding) -> Rectangle {
+    let size = Size::new(
+        area.size
+            .width
+            .saturating_sub(padding.left.saturating_add(padding.right)),
+        area.size
+            .height
+            .saturating_sub(padding.top.saturating_add(padding.bottom)),
+    );
+    Rectangle::new(
+        area.top_left + Point::new(padding.left as i32, padding.top as i32),
+        size,
+    )
+}
+
+/// Places a rectangle of `size` within `area`. The size is clamped to fit within the area.
+pub fn align(area: Rectangle, size: Size, horizontal: Align, vertical: Align) -> Rectangle {
+    let size = size.component_min(area.size);
+    let offset = |free: u32, align| match align {
+        Align::Start => 0,
+        Align::Center => (free / 2) as i32,
+        Align::End => free as i32,
+    };
+    let top_left = area.top_left
+        + Point::new(
+            offset(area.size.width - size.width, horizontal),
+            offset(area.size.height - size.height, vertical),
+        );
+    Rectangle::new(top_left, size)
+}
+
+/// Splits `area` into rows from top to bottom, with `gap` pixels between each row.
+pub fn rows<const N: usize>(area: Rectangle, lengths: [Length; N], gap: u32) -> [Rectangle; N] {
+    let mut y = area.top_left.y;
+    split(area.size.height, lengths, gap).map(|height| {
+        let row = Rectangle::new(
+            Point::new(area.top_left.x, y),
+            Size::new(area.size.width, height),
+        );
+        y += (height + gap) as i32;
+        row
+    })
+}
+
+/// Splits `area` into columns from left to right, with `gap` pixels between each column.
+pub fn columns<const N: usize>(area: Rectangle, lengths: [Length; N], gap: u32) -> [Rectangle; N] {
+    let mut x = area.top_left.x;
+    split(area.size.width, lengths, gap).map(|width| {
+        let column = Rectangle::new(
+            Point::new(x, area.top_left.y),
+            Size::new(width, area.size.height),
+        );
+        x += (width + gap) as i32;
+        column
+    })
+}
+
+/// Resolves each length against `total` pixels. Cells that don't fit are shrunk, starting from
+/// the last one.
+fn split<const N: usize>(total: u32, lengths: [Length; N], gap: u32) -> [u32; N] {
+    let gaps = gap.saturating_mul(N.saturating_sub(1) as u32);
+    let available = total.saturating_sub(gaps);
+
+    let mut fixed = 0u32;
+    let mut fills = 0u32;
+    for length in lengths {
+        match length {
+            Length::Px(px) => fixed = fixed.saturating_add(px),
+            Length::Percent(percent) => {
+                fixed = fixed.saturating_add(percent_of(available, percent))
+            }
+            Length::Fill => fills += 1,
+        }
+    }
+    let leftover = available.saturating_sub(fixed);
+
+    let mut remaining = available;
+    let mut fills_seen = 0;
+    lengths.map(|length| {
+        let size = match length {
+            Length::Px(px) => px,
+            Length::Percent(percent) => percent_of(available, percent),
+            Length::Fill => {
+                fills_seen += 1;
+                // The last fill takes any remainder from the division.
+                if fills_seen == fills {
+                    leftover - leftover / fills * (fills - 1)
+                } else {
+                    leftover / fills
+                }
+            }
+        };
+        let size = size.min(remaining);
+        remaining -= size;
+        size
+    })
+}
+
+fn percent_of(total: u32, percent: u8) -> u32 {
+    (u64::from(total) * u64::from(percent.min(100)) / 100) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_resolves_lengths() {
+        assert_eq!(
+            split(100, [Length::Px(10), Length::Percent(50), Length::Fill], 0),
+            [10, 50, 40]
+        );
+        // Fills share the leftover space, with the remainder going to the last one.
+        assert_eq!(split(11, [Length::Fill, Length::Fill], 0), [5, 6]);
+        // Gaps are taken out before resolving percentages.
+        assert_eq!(split(22, [Length::Percent(50), Length::Fill], 2), [10, 10]);
+        // Cells that don't fit are shrunk.
+        assert_eq!(split(10, [Length::Px(8), Length::Px(8)], 0), [8, 2]);
+    }
+
+    #[test]
+    fn test_rows_and_columns_tile_area() {
+        let area = Rectangle::new(Point::new(2, 2), Size::new(10, 20));
+        let [top, bottom] = rows(area, [Length::Px(5), Length::Fill], 1);
+        assert_eq!(top, Rectangle::new(Point::new(2, 2), Size::new(10, 5)));
+        assert_eq!(bottom, Rectangle::new(Point::new(2, 8), Size::new(10, 14)));
+
+        let [left, right] = columns(area, [Length::Fill, Length::Px(4)], 0);
+        assert_eq!(left, Rectangle::new(Point::new(2, 2), Size::new(6, 20)));
+        assert_eq!(right, Rectangle::new(Point::new(8, 2), Size::new(4, 20)));
+    }
+
+    #[test]
+    fn test_pad_and_align() {
+        let area = Rectangle::new(Point::zero(), Size::new(10, 10));
+        assert_eq!(
+            pad(area, Padding::symmetric(1, 2)),
+            Rectangle::new(Point::new(1, 2), Size::new(8, 6))
+        );
+        assert_eq!(
+            pad(area, Padding::all(6)),
+            Rectangle::new(Point::new(6, 6), Size::zero())
+        );
+        assert_eq!(
+            align(area, Size::new(4, 20), Align::Center, Align::End),
+            Rectangle::new(Point::new(3, 0), Size::new(4, 10))
+        );
+    }
+}
@@ -23,30 +23,135 @@
 //! * [Reset]: basic hardware reset support
 //! * [Sleep]: displays that can be put to sleep
 //! * [Wake]: displays that can be woken from sleep
+//! * [WaitIdle]: displays that can wait for their current operation (e.g. a refresh) to finish
+//! * [StateSnapshot]: driver states that can be saved across a deep sleep of the microcontroller
 //! * [DisplaySimple]: basic support for writing and displaying a single framebuffer
 //! * [DisplayPartial]: support for partial refresh using a diff
+//! * [Epd]: any framebuffer display, so that apps can be written once for all of them, and its
+//!   [Capabilities]
 //!
 //! Additionally, the crate provides:
 //!
+//! - [`animation`] module: plays spinners and other small animations in a window of the display,
+//!   using partial refreshes, and scrolls long text through a window with a ticker.
+//! - [`bench`] module: splits the time of each display update into rendering, SPI transfers and
+//!   busy waits, for finding regressions and tuning the SPI clock.
 //! - [`buffer`] module: Contains utilities for creating and managing efficient display buffers that
 //!   implement `embedded-graphics::DrawTarget`. These are designed to be fast and compact.
+//! - [`direct`] module: draws straight into the display's RAM in small bands of rows, for
+//!   microcontrollers without the RAM for a framebuffer, optionally drawing the next band while the
+//!   previous one is written.
+//! - [`dirty`] module: records which rows of a buffer have been drawn to, and writes only those rows
+//!   to the display for a partial refresh. Also merges many small changed areas into a few larger
+//!   ones.
+//! - [`dither`] module: converts full-color images to the gray levels that the displays support,
+//!   or to the palettes of tri-color and 7-color panels.
+//! - [`bmp`] module (requires the `tinybmp` feature): draws BMP images into display buffers, with
+//!   dithering and scaling.
+//! - [`png`] module (requires the `png` feature, which needs a heap): decodes PNG images into
+//!   display buffers, with dithering and scaling.
 //! - various `<display>` modules: each display lives in its own module, such as `epd2in9` for the 2.9"
-//!   e-paper display.
-#![no_std]
+//!   e-paper display. `epd1in9_segment` drives the 1.9" segment display over I2C, rather than a
+//!   framebuffer over SPI. Each module is behind a feature of the same name. These are all enabled
+//!   by the default `all-displays` feature, so disable default features to only build the drivers
+//!   that you use.
+//! - [`refresh`] module: decides when to use partial, fast or full refreshes, to limit ghosting,
+//!   tracks which areas are partially refreshed most, and clears image retention with black and
+//!   white cycles.
+//! - [`scale`] module: scales 1-bit images into an area of a buffer, with nearest-neighbour or
+//!   box filtering.
+//! - [`screen`] module: splits a screen into named regions that are drawn from your app's state, and
+//!   redraws and partially refreshes only the regions whose data changed.
+//! - [`schedule`] module (requires the `embassy-time` feature): refreshes the display at a fixed
+//!   interval, such as every minute for a clock, sleeping it in between.
+//! - [`idle`] module: puts the display to sleep once it's been idle for a while, and wakes it again
+//!   when it's next used.
+//! - [`self_test`] module: the report from each driver's `self_test`, which runs a scripted
+//!   sequence of refreshes to check that a panel works.
+//! - [`otp`] module (dangerous): permanently programs a custom waveform or VCOM voltage into a
+//!   panel's one-time programmable memory.
+//! - [`lut`] module (experimental): generates grayscale LUTs from a few parameters, for panels
+//!   without a published gray waveform.
+//! - [`storage`] module (requires the `embedded-storage` feature): saves buffers to NOR flash, and
+//!   restores the display's base framebuffer from them after a deep sleep.
+//! - [`glyphs`] module: caches a font's glyphs as packed bitmaps, to quickly redraw text such as
+//!   the digits of a clock.
+//! - [`gray_emulation`] module (experimental): shows 4 gray levels on black and white displays,
+//!   using a sequence of partial refreshes.
+//! - [`log`] module: routes the drivers' diagnostic messages to `defmt`, `log`, or your own
+//!   [`log::Logger`].
+//! - [`group`] module: drives several displays that share one SPI bus, and draws across tiled
+//!   displays as one canvas.
+//! - [`testing`] module (requires the `std` feature): mock hardware for running driver code on a host
+//!   machine, e.g. in integration tests or desktop preview tools.
+//! - [`task`] module (requires the `embassy-sync` feature): an actor that owns a display, so that
+//!   multiple embassy tasks can draw to it and request refreshes.
+//! - [`shared`] module (requires the `embassy-sync` feature): a mutex-guarded display handle, so that
+//!   multiple tasks can each draw to and update their own region of the display.
+//! - [`touch`] module (requires the `touch` feature): drivers for the GT1151 and ICNT86 touch
+//!   controllers on Waveshare's Touch e-Paper HATs, with down, move and up touch events.
+//! - [`widgets`] module (requires the `widgets` feature): common UI elements, such as labels and
+//!   progress bars, that report which area they changed for partial refreshes.
+//! - [`layout`] module (requires the `widgets` feature): splits the display into rows and columns,
+//!   to position widgets without hand-computing coordinates.
+//! - [`icons`] module (requires the `widgets` feature): 16x16 weather, battery, Wi-Fi and arrow
+//!   icons.
+//! - [`chart`] module (requires the `widgets` feature): line charts, bar charts and sparklines for
+//!   sensor readings.
+//! - [`grid`] module (requires the `widgets` feature): tables and month calendars, with headers
+//!   and dithered cell shading.
+//! - [`epd_image!`] macro (requires the `macros` feature): converts PNG images into packed buffer
+//!   data at compile time, so that they can be stored in flash without decoding them at runtime.
+//!
+//! ## Executors and `Send`
+//!
+//! The drivers don't depend on any particular executor. The futures returned by the drivers are
+//! [Send] as long as your `XHw` struct and its SPI, pin and delay types are [Send], so they can be
+//! used with multi-priority executors such as RTIC 2.x, or embassy's interrupt executors. See the
+//! `samples` folder in the repository for an RTIC example.
+//!
+//! Note that the functionality traits use `async fn`, so generic code that's written against these
+//! traits can't require the returned futures to be [Send]. Call the methods on the concrete display
+//! types if you need this guarantee.
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 #![allow(async_fn_in_trait)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 use embedded_hal_async::spi::SpiDevice;
 
+pub mod animation;
+pub mod bench;
+#[cfg(feature = "tinybmp")]
+pub mod bmp;
 pub mod buffer;
+#[cfg(feature = "widgets")]
+pub mod chart;
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+mod command;
+pub mod direct;
+pub mod dirty;
+pub mod dither;
+#[cfg(feature = "epd1in9_segment")]
+pub mod epd1in9_segment;
+#[cfg(feature = "epd2in9")]
 pub mod epd2in9;
+#[cfg(feature = "epd2in9_v2")]
 pub mod epd2in9_v2;
+pub mod glyphs;
+pub mod gray_emulation;
+#[cfg(feature = "widgets")]
+pub mod grid;
+pub mod group;
 /// This module provides hardware abstraction traits that can be used by display drivers.
 /// You should implement all the traits on a single struct, so that you can pass this one
 /// hardware struct to your display driver.
 ///
 /// Example that remains generic over the specific SPI bus:
 ///
-/// ```
+#[cfg_attr(feature = "epd2in9", doc = "```")]
+#[cfg_attr(not(feature = "epd2in9"), doc = "```ignore")]
 /// # use core::convert::Infallible;
 /// # use core::marker::PhantomData;
 /// use embassy_embedded_hal::shared_bus::asynch::spi::SpiDevice as EmbassySpiDevice;
@@ -56,6 +161,7 @@ pub mod epd2in9_v2;
 /// use embassy_rp::Peri;
 /// use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 /// use embedded_hal::digital::PinState;
+/// use epd_waveshare_async::buffer::WindowError;
 /// use epd_waveshare_async::hw::{BusyHw, DcHw, DelayHw, ErrorHw, ResetHw, SpiHw};
 /// use thiserror::Error as ThisError;
 ///
@@ -111,13 +217,11 @@ pub mod epd2in9_v2;
 /// impl<'a, SPI> BusyHw for DisplayHw<'a, SPI> {
 ///     type Busy = Input<'a>;
 ///
+///     const BUSY_WHEN: Option<PinState> = Some(epd_waveshare_async::epd2in9::DEFAULT_BUSY_WHEN);
+///
 ///     fn busy(&mut self) -> &mut Self::Busy {
 ///         &mut self.busy
 ///     }
-///
-///     fn busy_when(&self) -> embedded_hal::digital::PinState {
-///         epd_waveshare_async::epd2in9::DEFAULT_BUSY_WHEN
-///     }
 /// }
 ///
 /// impl<'a, SPI> DelayHw for DisplayHw<'a, SPI> {
@@ -138,6 +242,8 @@ pub mod epd2in9_v2;
 /// pub enum Error {
 ///     #[error("SPI error: {0:?}")]
 ///     SpiError(RawSpiError),
+///     #[error("window error: {0}")]
+///     WindowError(WindowError),
 /// }
 ///
 /// impl From<Infallible> for Error {
@@ -151,12 +257,67 @@ pub mod epd2in9_v2;
 ///         Error::SpiError(e)
 ///     }
 /// }
+///
+/// impl From<WindowError> for Error {
+///     fn from(e: WindowError) -> Self {
+///         Error::WindowError(e)
+///     }
+/// }
 /// ```
 pub mod hw;
+#[cfg(feature = "widgets")]
+pub mod icons;
+pub mod idle;
+#[cfg(feature = "widgets")]
+pub mod layout;
+pub mod lut;
+pub mod otp;
+pub mod refresh;
+pub mod scale;
+#[cfg(feature = "embassy-time")]
+pub mod schedule;
+pub mod screen;
+pub mod self_test;
+#[cfg(feature = "embassy-sync")]
+pub mod shared;
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+mod ssd16xx;
+#[cfg(feature = "embedded-storage")]
+pub mod storage;
+#[cfg(feature = "embassy-sync")]
+pub mod task;
+#[cfg(any(test, feature = "std"))]
+pub mod testing;
+#[cfg(feature = "touch")]
+pub mod touch;
+#[cfg(feature = "widgets")]
+pub mod widgets;
+
+pub mod log;
+#[cfg(feature = "png")]
+pub mod png;
+
+#[cfg(feature = "macros")]
+pub use epd_waveshare_async_macros::epd_image;
+
+/// Items used by the code that [epd_image] generates.
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    pub use embedded_graphics::prelude::Size;
+}
 
-mod log;
+use core::time::Duration;
 
-use crate::buffer::BufferView;
+use embedded_graphics::{
+    prelude::{DrawTarget, OriginDimensions, PixelColor, Size},
+    primitives::Rectangle,
+};
+
+use crate::{
+    buffer::BufferView,
+    refresh::{RefreshKind, SetRefreshKind},
+};
 
 /// Displays that have a hardware reset.
 pub trait Reset<ERROR> {
@@ -182,6 +343,28 @@ pub trait Wake<SPI: SpiDevice, ERROR> {
     async fn wake(self, spi: &mut SPI) -> Result<Self::DisplayOut, ERROR>;
 }
 
+/// Driver states that can be saved as a word, e.g. in RTC RAM or flash, so that the driver can be
+/// recreated in the same state after the microcontroller sleeps.
+///
+/// Use each driver's `into_state_snapshot` and `from_state_snapshot` to split the driver into its
+/// hardware and state, and to put them back together.
+pub trait StateSnapshot: Sized {
+    /// Encodes the state as a word.
+    fn to_snapshot(&self) -> u32;
+
+    /// Decodes a state encoded with [StateSnapshot::to_snapshot], or returns `None` if the word
+    /// describes a different state.
+    fn from_snapshot(snapshot: u32) -> Option<Self>;
+}
+
+/// Displays with a busy signal, that can wait for their current operation to finish.
+pub trait WaitIdle<ERROR> {
+    /// Waits until the display is no longer busy, e.g. because a refresh has finished.
+    ///
+    /// Note that this will wait forever if the display is asleep.
+    async fn wait_until_idle(&mut self) -> Result<(), ERROR>;
+}
+
 /// Base trait for any display where the display can be updated separate from its framebuffer data.
 pub trait Displayable<SPI: SpiDevice, ERROR> {
     /// Updates (refreshes) the display based on what has been written to the framebuffer.
@@ -209,7 +392,10 @@ pub trait DisplaySimple<const BITS: usize, const FRAMES: usize, SPI: SpiDevice,
         &mut self,
         spi: &mut SPI,
         buf: &dyn BufferView<BITS, FRAMES>,
-    ) -> Result<(), ERROR>;
+    ) -> Result<(), ERROR> {
+        self.write_framebuffer(spi, buf).await?;
+        self.update_display(spi).await
+    }
 }
 
 /// Displays that support a partial update, where a "diff" framebuffer is diffed against a base
@@ -228,4 +414,222 @@ pub trait DisplayPartial<const BITS: usize, const FRAMES: usize, SPI: SpiDevice,
         spi: &mut SPI,
         buf: &dyn BufferView<BITS, FRAMES>,
     ) -> Result<(), ERROR>;
+
+    /// Writes each area of its buffer to the main framebuffer, then refreshes the display once, so
+    /// that several separate regions change with a single refresh rather than one each.
+    ///
+    /// The display should be in a partial refresh mode. Each area is written with
+    /// [dirty::write_area], so is clipped to its buffer and rounded out to whole bytes on the
+    /// x-axis.
+    async fn display_partial_regions(
+        &mut self,
+        spi: &mut SPI,
+        regions: &[(&dyn BufferView<BITS, FRAMES>, Rectangle)],
+    ) -> Result<(), ERROR> {
+        for &(buffer, area) in regions {
+            dirty::write_area(self, spi, buffer, area).await?;
+        }
+        self.update_display(spi).await
+    }
+}
+
+/// A framebuffer display, whichever panel it is, so that apps and libraries can be written once
+/// and used with any of the displays that a product ships with.
+///
+/// This is implemented by each framebuffer driver before it's initialised, e.g.
+/// `Epd2In9V2<HW, StateUninitialized>`, and ties together the display's size, its buffer, and its
+/// states. The segment display in `epd1in9_segment` has no framebuffer, so doesn't implement this.
+///
+#[cfg_attr(all(feature = "epd2in9_v2", feature = "std"), doc = "```")]
+#[cfg_attr(not(all(feature = "epd2in9_v2", feature = "std")), doc = "```ignore")]
+/// use embedded_graphics::prelude::{DrawTarget, Size};
+/// use embedded_hal_async::spi::SpiDevice;
+/// use epd_waveshare_async::{refresh::RefreshKind, DisplaySimple, Epd, Sleep, WaitIdle};
+///
+/// async fn show_status<D: Epd<SPI, ERROR>, SPI: SpiDevice, ERROR>(
+///     epd: D,
+///     spi: &mut SPI,
+/// ) -> Result<D::Asleep, ERROR> {
+///     let mut buffer = D::new_buffer();
+///     draw_status(&mut buffer, D::SIZE);
+///     let mut epd = epd.init(spi, RefreshKind::Full).await?;
+///     epd.display_framebuffer(spi, &buffer).await?;
+///     epd.wait_until_idle().await?;
+///     epd.sleep(spi).await
+/// }
+/// # fn draw_status<T: DrawTarget>(_target: &mut T, _size: Size) {}
+///
+/// // With the 2.9" v2 display, and mock hardware.
+/// use epd_waveshare_async::{
+///     epd2in9_v2::{Epd2In9V2, DEFAULT_BUSY_WHEN},
+///     testing::{block_on, MockHw},
+/// };
+///
+/// let hw = MockHw::new(DEFAULT_BUSY_WHEN);
+/// let mut spi = hw.spi();
+/// block_on(show_status(Epd2In9V2::new(hw), &mut spi)).unwrap();
+/// ```
+pub trait Epd<SPI: SpiDevice, ERROR>: OriginDimensions + Sized {
+    /// The display's size, in its native orientation.
+    const SIZE: Size;
+
+    /// The colour of the display's native framebuffer.
+    type Color: PixelColor;
+
+    /// A framebuffer for the whole display.
+    type Buffer: DrawTarget<Color = Self::Color> + BufferView<1, 1>;
+
+    /// The display once it's initialised.
+    type Ready: DisplaySimple<1, 1, SPI, ERROR>
+        + OriginDimensions
+        + SetRefreshKind<SPI, ERROR>
+        + WaitIdle<ERROR>
+        + Sleep<SPI, ERROR, DisplayOut = Self::Asleep>;
+
+    /// The display while it's asleep.
+    type Asleep: Wake<SPI, ERROR, DisplayOut = Self::Ready>;
+
+    /// Creates a framebuffer for the whole display.
+    fn new_buffer() -> Self::Buffer;
+
+    /// Returns what the display supports, e.g. to skip grayscale rendering on black and white
+    /// displays.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Initialises the display, with the refresh mode for `kind`.
+    async fn init(self, spi: &mut SPI, kind: RefreshKind) -> Result<Self::Ready, ERROR>;
+}
+
+/// What a display supports, from each driver's `capabilities`, so that generic code can adapt to
+/// the display without checking its model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The size of the framebuffer, in its native orientation. This is zero for segment displays.
+    pub resolution: Size,
+    /// The number of colours (including black and white) that the display can show natively.
+    pub colors: u8,
+    /// Whether the display can refresh just the pixels that changed, without flashing.
+    pub supports_partial: bool,
+    /// Whether the display has a full refresh that's faster than its best-quality one.
+    pub supports_fast: bool,
+    /// Whether the display can show gray levels natively, without
+    /// [gray_emulation](crate::gray_emulation).
+    pub supports_gray: bool,
+    /// The typical duration of a [RefreshKind::Full] refresh.
+    pub full_refresh: Duration,
+    /// The typical duration of a [RefreshKind::Fast] refresh, if it's supported.
+    pub fast_refresh: Option<Duration>,
+    /// The typical duration of a [RefreshKind::Partial] refresh, if it's supported.
+    pub partial_refresh: Option<Duration>,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Capabilities {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Capabilities {{ resolution: {}x{}, colors: {}, supports_partial: {}, supports_fast: {}, supports_gray: {} }}",
+            self.resolution.width,
+            self.resolution.height,
+            self.colors,
+            self.supports_partial,
+            self.supports_fast,
+            self.supports_gray
+        )
+    }
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use super::*;
+    use embedded_graphics::prelude::Dimensions;
+
+    use crate::testing::{block_on, MockHw};
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    #[test]
+    #[cfg(feature = "epd2in9")]
+    fn test_epd2in9_futures_are_send() {
+        let hw = MockHw::new(epd2in9::DEFAULT_BUSY_WHEN);
+        let mut spi = hw.spi();
+        let epd = epd2in9::Epd2In9::new(hw);
+        let init = epd.init(&mut spi, epd2in9::RefreshMode::Full);
+        assert_send(&init);
+        let mut epd = block_on(init).unwrap();
+
+        let buffer = epd2in9::new_buffer();
+        assert_send(&epd.display_framebuffer(&mut spi, &buffer));
+        assert_send(&epd.sleep(&mut spi));
+    }
+
+    #[test]
+    fn test_epd2in9_v2_futures_are_send() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let mut spi = hw.spi();
+        let epd = epd2in9_v2::Epd2In9V2::new(hw);
+        let init = epd.init(&mut spi, epd2in9_v2::RefreshMode::Full);
+        assert_send(&init);
+        let mut epd = block_on(init).unwrap();
+
+        let buffer = epd2in9_v2::new_binary_buffer();
+        assert_send(&epd.display_framebuffer(&mut spi, &buffer));
+        #[cfg(feature = "epd2in9_v2-gray2")]
+        {
+            let gray_buffer = epd2in9_v2::new_gray2_buffer();
+            assert_send(&epd.display_framebuffer(&mut spi, &gray_buffer));
+        }
+        assert_send(&epd.sleep(&mut spi));
+    }
+
+    /// Runs a display through a whole app cycle, knowing only that it's an [Epd].
+    fn run_generic_app<D: Epd<testing::MockSpi, testing::MockError>>(
+        epd: D,
+        spi: &mut testing::MockSpi,
+    ) {
+        let capabilities = epd.capabilities();
+        assert_eq!(capabilities.resolution, D::SIZE);
+        assert!(capabilities.supports_partial);
+        assert_eq!(epd.size(), D::SIZE);
+        let mut epd = block_on(epd.init(spi, RefreshKind::Full)).unwrap();
+        assert_eq!(epd.bounding_box().size, D::SIZE);
+        let buffer = D::new_buffer();
+        assert_eq!(buffer.window().size, D::SIZE);
+        block_on(epd.display_framebuffer(spi, &buffer)).unwrap();
+        block_on(epd.set_refresh_kind(spi, RefreshKind::Partial)).unwrap();
+        let epd = block_on(epd.sleep(spi)).unwrap();
+        let _: D::Ready = block_on(epd.wake(spi)).unwrap();
+    }
+
+    #[test]
+    fn test_drivers_implement_epd() {
+        #[cfg(feature = "epd2in9")]
+        {
+            let hw = MockHw::new(epd2in9::DEFAULT_BUSY_WHEN);
+            let mut spi = hw.spi();
+            run_generic_app(epd2in9::Epd2In9::new(hw), &mut spi);
+        }
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let mut spi = hw.spi();
+        run_generic_app(epd2in9_v2::Epd2In9V2::new(hw), &mut spi);
+    }
+
+    #[test]
+    fn test_epd2in9_v2_capabilities_follow_features() {
+        let epd = epd2in9_v2::Epd2In9V2::new(MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN));
+        let capabilities = epd.capabilities();
+        assert_eq!(
+            capabilities.supports_gray,
+            cfg!(feature = "epd2in9_v2-gray2")
+        );
+        assert_eq!(
+            capabilities.colors,
+            if capabilities.supports_gray { 4 } else { 2 }
+        );
+        assert_eq!(
+            capabilities.fast_refresh.is_some(),
+            cfg!(feature = "epd2in9_v2-full-slow")
+        );
+        assert!(capabilities.full_refresh >= capabilities.partial_refresh.unwrap());
+    }
 }
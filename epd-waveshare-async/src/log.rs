@@ -1,34 +1,155 @@
+//! Routes the drivers' diagnostic messages.
+//!
+//! By default, messages go to `defmt` if the `defmt` feature is enabled, or otherwise to `log` if
+//! the `log` feature is enabled. Call [set_logger] to send them somewhere else instead.
+//!
+//! ```
+//! use core::fmt::Arguments;
+//! use epd_waveshare_async::log::{set_logger, Level, Logger};
+//!
+//! struct UartLogger;
+//!
+//! impl Logger for UartLogger {
+//!     fn log(level: Level, args: Arguments<'_>) {
+//!         // Write to your UART here.
+//!         # let _ = (level, args);
+//!     }
+//! }
+//!
+//! set_logger::<UartLogger>();
+//! ```
+use core::{
+    fmt::Arguments,
+    ptr::null_mut,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// The severity of a log message.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Warn,
+}
+
+/// A sink for the drivers' log messages.
+pub trait Logger {
+    /// Logs a message at the given level.
+    fn log(level: Level, args: Arguments<'_>);
+}
+
+/// Forwards messages to `defmt`.
+#[cfg(feature = "defmt")]
+pub struct DefmtLogger;
+
+#[cfg(feature = "defmt")]
+impl Logger for DefmtLogger {
+    fn log(level: Level, args: Arguments<'_>) {
+        let args = defmt::Display2Format(&args);
+        match level {
+            Level::Trace => defmt::trace!("{}", args),
+            Level::Debug => defmt::debug!("{}", args),
+            Level::Warn => defmt::warn!("{}", args),
+        }
+    }
+}
+
+/// Forwards messages to `log`.
+#[cfg(feature = "log")]
+pub struct LogLogger;
+
+#[cfg(feature = "log")]
+impl Logger for LogLogger {
+    fn log(level: Level, args: Arguments<'_>) {
+        let level = match level {
+            Level::Trace => log::Level::Trace,
+            Level::Debug => log::Level::Debug,
+            Level::Warn => log::Level::Warn,
+        };
+        log::log!(level, "{}", args);
+    }
+}
+
+type LogFn = fn(Level, Arguments<'_>);
+
+// Function pointers are thin, so they can be stored atomically even on targets without
+// compare-and-swap (e.g. the RP2040).
+static LOGGER: AtomicPtr<()> = AtomicPtr::new(null_mut());
+
+/// Sends all future driver log messages to `L`, instead of the default `defmt` or `log` output.
+pub fn set_logger<L: Logger>() {
+    LOGGER.store(L::log as LogFn as *mut (), Ordering::Release);
+}
+
+/// Returns the logger set with [set_logger], if any.
+pub(crate) fn logger() -> Option<LogFn> {
+    let logger = LOGGER.load(Ordering::Acquire);
+    if logger.is_null() {
+        None
+    } else {
+        // SAFETY: the only non-null values stored in LOGGER are LogFn pointers.
+        Some(unsafe { core::mem::transmute::<*mut (), LogFn>(logger) })
+    }
+}
+
+macro_rules! log_at {
+    ($level:ident, $defmt:ident, $log:ident, $($arg:tt)*) => {
+        if let Some(logger) = $crate::log::logger() {
+            logger($crate::log::Level::$level, format_args!($($arg)*));
+        } else {
+            #[cfg(feature = "defmt")]
+            defmt::$defmt!($($arg)*);
+
+            // Only fall back to `log` if `defmt` isn't enabled, to avoid logging everything twice.
+            #[cfg(all(feature = "log", not(feature = "defmt")))]
+            log::$log!($($arg)*);
+        }
+    };
+}
+
 macro_rules! debug {
     ($($arg:tt)*) => {
-        #[cfg(feature = "defmt")]
-        defmt::debug!($($arg)*);
-
-        #[cfg(feature = "log")]
-        log::debug!($($arg)*);
+        $crate::log::log_at!(Debug, debug, debug, $($arg)*)
     };
 }
 
 macro_rules! trace {
     ($($arg:tt)*) => {
-        #[cfg(feature = "defmt")]
-        defmt::trace!($($arg)*);
-
-        #[cfg(feature = "log")]
-        log::trace!($($arg)*);
+        $crate::log::log_at!(Trace, trace, trace, $($arg)*)
     };
 }
 
-macro_rules! debug_assert {
-    ($assertion:expr, $message:expr) => {
-        #[cfg(feature = "defmt")]
-        {
-            defmt::debug_assert!($assertion, $message)
-        }
-        #[cfg(not(feature = "defmt"))]
-        {
-            core::debug_assert!($assertion, $message)
-        }
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::log::log_at!(Warn, warn, warn, $($arg)*)
     };
 }
 
-pub(crate) use {debug, debug_assert, trace};
+pub(crate) use {debug, log_at, trace, warning};
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+    struct CountingLogger;
+
+    impl Logger for CountingLogger {
+        fn log(level: Level, args: Arguments<'_>) {
+            if level == Level::Warn && args.to_string() == "counted 1" {
+                WARNINGS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_logger_routes_messages() {
+        set_logger::<CountingLogger>();
+        warning!("counted {}", 1);
+        assert_eq!(WARNINGS.load(Ordering::Relaxed), 1);
+    }
+}
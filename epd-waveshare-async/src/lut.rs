@@ -0,0 +1,297 @@
+//! Generates grayscale LUTs for SSD1680-style controllers, such as the `epd2in9_v2`'s, from a few
+//! parameters, for experimenting with panels that have no published gray waveform.
+//!
+//! The generated waveform has three stages:
+//!
+//! 1. Each gray level is driven towards white by one pulse per step of darkness, so that the
+//!    waveform stays DC balanced.
+//! 2. Every pixel is shaken to black and then to white, to clear the previous image.
+//! 3. Each gray level is driven towards black by one pulse per step of darkness.
+//!
+//! The generator is `const`, so the LUT can be built at compile time:
+//!
+//! ```
+//! use epd_waveshare_async::lut::{GrayLut, TemperatureBand};
+//!
+//! const COLD_LUT: [u8; 153] = GrayLut::new(4)
+//!     .with_pulses_per_level(2)
+//!     .with_temperature(TemperatureBand::Cold)
+//!     .build();
+//! ```
+//!
+//! Load it after switching to a grayscale refresh mode, e.g. with the `epd2in9_v2`'s `write_lut`.
+//! Start with short pulses, and lengthen them until the levels are distinct.
+
+/// The length of an SSD1680 LUT, in bytes.
+pub const LUT_LENGTH: usize = 153;
+
+/// The number of groups in an SSD1680 LUT, each of which has 4 phases.
+const GROUPS: usize = 12;
+/// The number of pixel LUTs. A 5th LUT drives VCOM, which is left at DC.
+const PIXEL_LUTS: usize = 4;
+const PHASES: usize = 4;
+/// The offset of the timing and repeat counts, after the voltage selections.
+const TIMING_OFFSET: usize = (PIXEL_LUTS + 1) * GROUPS;
+/// The offset of the frame rates, after the 7 timing and repeat bytes of each group.
+const FRAME_RATE_OFFSET: usize = TIMING_OFFSET + 7 * GROUPS;
+
+/// Voltage selections for a phase, in the pixel LUTs. Phases that are left at 0 select VSS.
+const VSH1: u8 = 0b01;
+const VSL: u8 = 0b10;
+
+/// The temperature range that a LUT is generated for. Colder panels respond more slowly, so the
+/// pulses are lengthened for them.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TemperatureBand {
+    /// Below 10C. Pulses are twice as long.
+    Cold,
+    /// From 10C to 30C.
+    #[default]
+    Room,
+    /// Above 30C. Pulses are half as long.
+    Hot,
+}
+
+impl TemperatureBand {
+    /// Returns the band that contains `celsius`.
+    pub const fn from_celsius(celsius: i8) -> Self {
+        if celsius < 10 {
+            TemperatureBand::Cold
+        } else if celsius > 30 {
+            TemperatureBand::Hot
+        } else {
+            TemperatureBand::Room
+        }
+    }
+
+    /// Scales a pulse length of `frames` for this band, keeping it at least 1 frame long.
+    const fn scale(self, frames: u8) -> u8 {
+        let scaled = match self {
+            TemperatureBand::Cold => frames.saturating_mul(2),
+            TemperatureBand::Room => frames,
+            TemperatureBand::Hot => frames.div_ceil(2),
+        };
+        if scaled == 0 {
+            1
+        } else {
+            scaled
+        }
+    }
+}
+
+/// The parameters of a generated grayscale LUT. Build it with [GrayLut::build].
+///
+/// Pixels whose 2-bit RAM value is `n` are `n` steps of darkness from white. Values at or above
+/// the number of levels get the darkest waveform.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrayLut {
+    levels: u8,
+    pulses_per_level: u8,
+    pulse_frames: u8,
+    clear_frames: u8,
+    clear_repeats: u8,
+    temperature: TemperatureBand,
+}
+
+impl GrayLut {
+    /// Generates a LUT with `levels` gray levels, including black and white.
+    ///
+    /// By default, each level is 1 pulse of 5 frames darker than the last, and the panel is
+    /// cleared with 20 frames each of black and white, once, at room temperature. These are close
+    /// to the sample code's gray LUT for the 2.9" V2 display.
+    pub const fn new(levels: u8) -> Self {
+        GrayLut {
+            levels,
+            pulses_per_level: 1,
+            pulse_frames: 5,
+            clear_frames: 20,
+            clear_repeats: 1,
+            temperature: TemperatureBand::Room,
+        }
+    }
+
+    /// Sets how many pulses darker each level is than the last.
+    pub const fn with_pulses_per_level(mut self, pulses_per_level: u8) -> Self {
+        self.pulses_per_level = pulses_per_level;
+        self
+    }
+
+    /// Sets the length of each gray pulse, in frames.
+    pub const fn with_pulse_frames(mut self, pulse_frames: u8) -> Self {
+        self.pulse_frames = pulse_frames;
+        self
+    }
+
+    /// Sets the length of each half of the clearing shake, in frames, and how many times it's
+    /// repeated.
+    pub const fn with_clear(mut self, clear_frames: u8, clear_repeats: u8) -> Self {
+        self.clear_frames = clear_frames;
+        self.clear_repeats = clear_repeats;
+        self
+    }
+
+    /// Sets the temperature band to scale the pulse lengths for.
+    pub const fn with_temperature(mut self, temperature: TemperatureBand) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// The number of pulses that the darkest level needs.
+    const fn max_pulses(&self) -> usize {
+        (self.levels as usize - 1) * self.pulses_per_level as usize
+    }
+
+    /// Generates the LUT, in the layout of the controller's `WriteLut` command.
+    ///
+    /// # Panics
+    ///
+    /// If there are fewer than 2 or more than 4 levels, or if the darkest level needs more than
+    /// 20 pulses, which don't fit in the LUT's groups.
+    pub const fn build(&self) -> [u8; LUT_LENGTH] {
+        assert!(
+            self.levels >= 2 && self.levels as usize <= PIXEL_LUTS,
+            "a LUT has 2 to 4 gray levels"
+        );
+        let max_pulses = self.max_pulses();
+        let pulse_groups = max_pulses.div_ceil(PHASES);
+        assert!(
+            2 * pulse_groups < GROUPS,
+            "the darkest level needs at most 20 pulses"
+        );
+
+        let mut lut = [0; LUT_LENGTH];
+        let pulse_frames = self.temperature.scale(self.pulse_frames);
+        let clear_frames = self.temperature.scale(self.clear_frames);
+        let clear_group = pulse_groups;
+
+        let mut pulse = 0;
+        while pulse < max_pulses {
+            let (group, phase) = (pulse / PHASES, pulse % PHASES);
+            let mut n = 0;
+            while n < PIXEL_LUTS {
+                let level = if n < self.levels as usize {
+                    n
+                } else {
+                    self.levels as usize - 1
+                };
+                if pulse < level * self.pulses_per_level as usize {
+                    set_voltage(&mut lut, n, group, phase, VSL);
+                    set_voltage(&mut lut, n, clear_group + 1 + group, phase, VSH1);
+                }
+                n += 1;
+            }
+            set_frames(&mut lut, group, phase, pulse_frames);
+            set_frames(&mut lut, clear_group + 1 + group, phase, pulse_frames);
+            pulse += 1;
+        }
+
+        let mut n = 0;
+        while n < PIXEL_LUTS {
+            set_voltage(&mut lut, n, clear_group, 0, VSH1);
+            set_voltage(&mut lut, n, clear_group, 1, VSL);
+            n += 1;
+        }
+        set_frames(&mut lut, clear_group, 0, clear_frames);
+        set_frames(&mut lut, clear_group, 1, clear_frames);
+        // The repeat count is one less than the number of runs.
+        lut[TIMING_OFFSET + 7 * clear_group + 6] = self.clear_repeats.saturating_sub(1);
+
+        let mut i = FRAME_RATE_OFFSET;
+        while i < FRAME_RATE_OFFSET + GROUPS / 2 {
+            lut[i] = 0x22;
+            i += 1;
+        }
+        lut
+    }
+}
+
+/// Selects `voltage` for `phase` of `group` in pixel LUT `n`.
+const fn set_voltage(
+    lut: &mut [u8; LUT_LENGTH],
+    n: usize,
+    group: usize,
+    phase: usize,
+    voltage: u8,
+) {
+    let shift = 6 - 2 * phase;
+    let byte = &mut lut[n * GROUPS + group];
+    *byte = (*byte & !(0b11 << shift)) | (voltage << shift);
+}
+
+/// Sets the length of `phase` of `group`, in frames.
+const fn set_frames(lut: &mut [u8; LUT_LENGTH], group: usize, phase: usize, frames: u8) {
+    // Each group's 7 bytes are TP[A], TP[B], SR[AB], TP[C], TP[D], SR[CD], RP.
+    let offset = [0, 1, 3, 4][phase];
+    lut[TIMING_OFFSET + 7 * group + offset] = frames;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The voltage selected for `phase` of `group` in pixel LUT `n`.
+    fn voltage(lut: &[u8; LUT_LENGTH], n: usize, group: usize, phase: usize) -> u8 {
+        (lut[n * GROUPS + group] >> (6 - 2 * phase)) & 0b11
+    }
+
+    #[test]
+    fn test_levels_get_balanced_pulses_around_the_clear() {
+        let lut = GrayLut::new(4).build();
+
+        // Group 0 lightens, group 1 clears, and group 2 darkens, by one pulse per level.
+        assert_eq!(&lut[0..3], [0x00, 0x60, 0x00]);
+        assert_eq!(&lut[GROUPS..GROUPS + 3], [0x80, 0x60, 0x40]);
+        assert_eq!(&lut[3 * GROUPS..3 * GROUPS + 3], [0xA8, 0x60, 0x54]);
+        assert_eq!(voltage(&lut, 2, 2, 1), VSH1);
+        assert_eq!(voltage(&lut, 2, 2, 2), 0);
+        assert_eq!(&lut[4 * GROUPS..5 * GROUPS], [0; GROUPS]);
+
+        assert_eq!(
+            &lut[TIMING_OFFSET..TIMING_OFFSET + 7],
+            [5, 5, 0, 5, 0, 0, 0]
+        );
+        assert_eq!(
+            &lut[TIMING_OFFSET + 7..TIMING_OFFSET + 14],
+            [20, 20, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            &lut[FRAME_RATE_OFFSET..],
+            [0x22, 0x22, 0x22, 0x22, 0x22, 0x22, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_parameters_scale_the_pulses() {
+        let lut = GrayLut::new(2)
+            .with_pulses_per_level(6)
+            .with_pulse_frames(3)
+            .with_clear(10, 2)
+            .with_temperature(TemperatureBand::from_celsius(0))
+            .build();
+
+        // 6 pulses take 2 groups either side of the clear, in group 2.
+        assert_eq!(&lut[0..5], [0x00, 0x00, 0x60, 0x00, 0x00]);
+        assert_eq!(
+            &lut[3 * GROUPS..3 * GROUPS + 5],
+            [0xAA, 0xA0, 0x60, 0x55, 0x50]
+        );
+        // Levels above the darkest repeat it.
+        assert_eq!(lut[GROUPS..2 * GROUPS], lut[3 * GROUPS..4 * GROUPS]);
+        assert_eq!(
+            &lut[TIMING_OFFSET + 7..TIMING_OFFSET + 14],
+            [6, 6, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            &lut[TIMING_OFFSET + 14..TIMING_OFFSET + 21],
+            [20, 20, 0, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "at most 20 pulses")]
+    fn test_rejects_too_many_pulses() {
+        GrayLut::new(4).with_pulses_per_level(7).build();
+    }
+}
@@ -0,0 +1,340 @@
+//! **Dangerous:** permanently programs a panel's waveform or VCOM voltage into its controller's
+//! one-time programmable (OTP) memory, for provisioning custom panels and factory calibration.
+//!
+//! OTP can only be written once. A wrong waveform can leave the panel unable to show a clean image
+//! with its built-in waveform, and nothing in this crate can undo that. The drivers load their own
+//! waveforms when initialised, so you don't need this to use a display.
+//!
+//! Programming takes a [WaveformOtp] or [VcomOtp] that has been confirmed with an
+//! [OtpConfirmation]:
+//!
+//! ```text
+//! let program = WaveformOtp::new(&MY_WAVEFORM)
+//!     .confirm(OtpConfirmation::i_understand_this_permanently_changes_the_panel());
+//! let epd = epd.program_waveform_otp(&mut spi, program).await?;
+//! // The display needs initialising again afterwards.
+//! let mut epd = epd.init(&mut spi, RefreshMode::Full).await?;
+//!
+//! let program = VcomOtp::new(0x28)
+//!     .confirm(OtpConfirmation::i_understand_this_permanently_changes_the_panel());
+//! let read_back = epd.program_vcom_otp(&mut spi, program).await?;
+//! assert!(read_back.is_verified());
+//! ```
+use core::marker::PhantomData;
+
+#[cfg(feature = "epd2in9_v2")]
+use embedded_hal_async::spi::SpiDevice;
+
+#[cfg(feature = "epd2in9_v2")]
+use crate::{log::warning, WaitIdle};
+
+/// Confirms that you understand that programming the OTP can't be undone.
+///
+/// This has no other purpose than to make OTP programming impossible to trigger by accident.
+#[derive(Debug)]
+pub struct OtpConfirmation(());
+
+impl OtpConfirmation {
+    /// Confirms that programming the OTP permanently changes the panel.
+    pub const fn i_understand_this_permanently_changes_the_panel() -> Self {
+        OtpConfirmation(())
+    }
+}
+
+/// A [WaveformOtp] that hasn't been confirmed yet, so it can't be programmed.
+#[derive(Debug)]
+pub struct Unconfirmed;
+
+/// A [WaveformOtp] that has been confirmed with an [OtpConfirmation].
+#[derive(Debug)]
+pub struct Confirmed;
+
+/// The waveform to program into the OTP, and how to program it.
+///
+/// This is a consuming builder: configure it, then [WaveformOtp::confirm] it, and pass it to the
+/// display's `program_waveform_otp`.
+#[derive(Debug)]
+pub struct WaveformOtp<'a, C = Unconfirmed> {
+    waveform: &'a [u8],
+    internal_voltage: bool,
+    program_selection: bool,
+    confirmation: PhantomData<C>,
+}
+
+impl<'a> WaveformOtp<'a, Unconfirmed> {
+    /// Programs `waveform`, in the layout of the controller's waveform setting OTP.
+    ///
+    /// By default, the programming voltage is generated internally, and the OTP selection is not
+    /// programmed.
+    pub const fn new(waveform: &'a [u8]) -> Self {
+        WaveformOtp {
+            waveform,
+            internal_voltage: true,
+            program_selection: false,
+            confirmation: PhantomData,
+        }
+    }
+
+    /// Sets whether the controller generates the programming voltage itself. Set this to `false`
+    /// if your board supplies an external programming voltage.
+    pub const fn with_internal_voltage(mut self, internal_voltage: bool) -> Self {
+        self.internal_voltage = internal_voltage;
+        self
+    }
+
+    /// Sets whether to also program the OTP selection, from the controller's OTP selection
+    /// control registers, after the waveform.
+    pub const fn with_program_selection(mut self, program_selection: bool) -> Self {
+        self.program_selection = program_selection;
+        self
+    }
+
+    /// Confirms the programming, so that it can be passed to a display.
+    pub fn confirm(self, _confirmation: OtpConfirmation) -> WaveformOtp<'a, Confirmed> {
+        WaveformOtp {
+            waveform: self.waveform,
+            internal_voltage: self.internal_voltage,
+            program_selection: self.program_selection,
+            confirmation: PhantomData,
+        }
+    }
+}
+
+/// A VCOM voltage to program into the OTP, in the controller's VCOM register format.
+///
+/// Like [WaveformOtp], this must be confirmed before it's passed to the display's
+/// `program_vcom_otp`.
+#[derive(Debug)]
+pub struct VcomOtp<C = Unconfirmed> {
+    vcom: u8,
+    confirmation: PhantomData<C>,
+}
+
+impl VcomOtp<Unconfirmed> {
+    /// Programs `vcom`, which must be one of the VCOM register values in the controller's
+    /// datasheet.
+    pub const fn new(vcom: u8) -> Self {
+        VcomOtp {
+            vcom,
+            confirmation: PhantomData,
+        }
+    }
+
+    /// Confirms the programming, so that it can be passed to a display.
+    pub fn confirm(self, _confirmation: OtpConfirmation) -> VcomOtp<Confirmed> {
+        VcomOtp {
+            vcom: self.vcom,
+            confirmation: PhantomData,
+        }
+    }
+}
+
+/// The VCOM value that was programmed, and the value that the controller reported afterwards.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VcomReadBack {
+    pub programmed: u8,
+    pub read_back: u8,
+}
+
+impl VcomReadBack {
+    /// Whether the controller reported the programmed value.
+    pub fn is_verified(&self) -> bool {
+        self.programmed == self.read_back
+    }
+}
+
+/// One step of programming the OTP.
+#[cfg(feature = "epd2in9_v2")]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OtpStep {
+    /// Enables the controller's clock, which the OTP commands need.
+    EnableClock,
+    /// Switches to generating the programming voltage internally, or back to normal.
+    SetInternalVoltage(bool),
+    /// Writes the data to program into the controller's RAM.
+    WriteRam,
+    /// Writes the VCOM register.
+    WriteVcom(u8),
+    ProgramWaveform,
+    ProgramVcom,
+    ProgramSelection,
+    /// Loads the waveform from the OTP, so it can be checked.
+    LoadWaveform,
+}
+
+/// The driver-specific parts of OTP programming.
+#[cfg(feature = "epd2in9_v2")]
+pub(crate) trait OtpTarget<SPI: SpiDevice, ERROR>: WaitIdle<ERROR> {
+    /// Runs `step`, with `data` only used by [OtpStep::WriteRam].
+    async fn otp_step(&mut self, spi: &mut SPI, step: OtpStep, data: &[u8]) -> Result<(), ERROR>;
+
+    /// Reads the VCOM register back from the controller.
+    async fn read_vcom_register(&mut self, spi: &mut SPI) -> Result<u8, ERROR>;
+}
+
+/// Programs the OTP, waiting for each step to finish.
+#[cfg(feature = "epd2in9_v2")]
+pub(crate) async fn program<D, SPI, ERROR>(
+    epd: &mut D,
+    spi: &mut SPI,
+    program: WaveformOtp<'_, Confirmed>,
+) -> Result<(), ERROR>
+where
+    D: OtpTarget<SPI, ERROR>,
+    SPI: SpiDevice,
+{
+    warning!(
+        "Programming {} bytes of waveform OTP; this can't be undone",
+        program.waveform.len()
+    );
+    let mut steps = [None; 7];
+    steps[0] = Some(OtpStep::EnableClock);
+    steps[1] = program
+        .internal_voltage
+        .then_some(OtpStep::SetInternalVoltage(true));
+    steps[2] = Some(OtpStep::WriteRam);
+    steps[3] = Some(OtpStep::ProgramWaveform);
+    steps[4] = program
+        .program_selection
+        .then_some(OtpStep::ProgramSelection);
+    steps[5] = program
+        .internal_voltage
+        .then_some(OtpStep::SetInternalVoltage(false));
+    steps[6] = Some(OtpStep::LoadWaveform);
+    for step in steps.into_iter().flatten() {
+        epd.otp_step(spi, step, program.waveform).await?;
+        epd.wait_until_idle().await?;
+    }
+    Ok(())
+}
+
+/// Programs the VCOM OTP, then reads the VCOM register back.
+#[cfg(feature = "epd2in9_v2")]
+pub(crate) async fn program_vcom<D, SPI, ERROR>(
+    epd: &mut D,
+    spi: &mut SPI,
+    program: VcomOtp<Confirmed>,
+) -> Result<VcomReadBack, ERROR>
+where
+    D: OtpTarget<SPI, ERROR>,
+    SPI: SpiDevice,
+{
+    warning!(
+        "Programming VCOM OTP to {:#04x}; this can't be undone",
+        program.vcom
+    );
+    for step in [
+        OtpStep::WriteVcom(program.vcom),
+        OtpStep::EnableClock,
+        OtpStep::ProgramVcom,
+    ] {
+        epd.otp_step(spi, step, &[]).await?;
+        epd.wait_until_idle().await?;
+    }
+    let read_back = VcomReadBack {
+        programmed: program.vcom,
+        read_back: epd.read_vcom_register(spi).await?,
+    };
+    if !read_back.is_verified() {
+        warning!(
+            "VCOM read back as {:#04x} after programming {:#04x}",
+            read_back.read_back,
+            read_back.programmed
+        );
+    }
+    Ok(read_back)
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use super::*;
+    use crate::{
+        epd2in9_v2::{self, Command, Epd2In9V2, RefreshMode},
+        testing::{block_on, Event, MockHw},
+    };
+
+    #[test]
+    fn test_programs_waveform_in_order() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        log.take_events();
+
+        let program = WaveformOtp::new(&[0x11, 0x22, 0x33])
+            .with_program_selection(true)
+            .confirm(OtpConfirmation::i_understand_this_permanently_changes_the_panel());
+        block_on(epd.program_waveform_otp(&mut spi, program)).unwrap();
+
+        let commands: std::vec::Vec<_> = log
+            .commands()
+            .into_iter()
+            .filter(|c| {
+                ![
+                    Command::DisplayUpdateControl2 as u8,
+                    Command::SetRamXStartEnd as u8,
+                    Command::SetRamYStartEnd as u8,
+                    Command::SetRamX as u8,
+                    Command::SetRamY as u8,
+                ]
+                .contains(c)
+            })
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                Command::MasterActivation as u8,
+                Command::SetOtpProgramMode as u8,
+                Command::WriteLowRam as u8,
+                Command::ProgramWsOtp as u8,
+                Command::ProgramOtpSelection as u8,
+                Command::SetOtpProgramMode as u8,
+                Command::LoadWsOtp as u8,
+            ]
+        );
+        assert!(log
+            .take_events()
+            .contains(&Event::Data(std::vec![0x11, 0x22, 0x33])));
+    }
+
+    #[test]
+    fn test_programs_vcom_and_reads_it_back() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        log.take_events();
+
+        let program = VcomOtp::new(0x28)
+            .confirm(OtpConfirmation::i_understand_this_permanently_changes_the_panel());
+        let read_back = block_on(epd.program_vcom_otp(&mut spi, program)).unwrap();
+        // The mock display reads back zeros.
+        assert_eq!(
+            read_back,
+            VcomReadBack {
+                programmed: 0x28,
+                read_back: 0x00
+            }
+        );
+        assert!(!read_back.is_verified());
+
+        let events = log.take_events();
+        assert_eq!(
+            events[..2],
+            [
+                Event::Command(Command::WriteVcom as u8),
+                Event::Data(std::vec![0x28])
+            ]
+        );
+        assert!(events.contains(&Event::Command(Command::ProgramVcomOtp as u8)));
+        assert_eq!(
+            events[events.len() - 2..],
+            [
+                Event::Command(Command::ReadOtpRegisters as u8),
+                Event::Read(2)
+            ]
+        );
+    }
+}
@@ -0,0 +1,436 @@
+//! Decodes PNG images into display buffers, converting their colors with [crate::dither].
+//!
+//! This needs a heap, as the whole image is decompressed before drawing. It suits targets with
+//! plenty of RAM, e.g. to show server-rendered dashboards fetched over Wi-Fi.
+//!
+//! All standard color types and bit depths are supported, but interlaced images are not.
+//! Transparent pixels are drawn over white. Chunk CRCs aren't checked, so check the integrity of
+//! images from untrusted sources yourself.
+//!
+//! ```text
+//! let body = http_client.get(url).await?;
+//! let image = PngImage::decode(&body)?.with_fit(Fit::Contain);
+//! let changed = image.draw(&mut buffer, buffer.bounding_box())?;
+//! ```
+use alloc::vec::Vec;
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    pixelcolor::Rgb888,
+    prelude::{Point, RgbColor, Size},
+    primitives::Rectangle,
+};
+
+use crate::dither::{draw_image, Dither, Fit, Quantize};
+
+/// Reasons that a PNG image can't be decoded.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PngError {
+    /// The data doesn't start with the PNG signature.
+    InvalidSignature,
+    /// The data ended part way through a chunk, or before the image data.
+    UnexpectedEnd,
+    /// The image uses an unsupported feature, such as interlacing.
+    Unsupported,
+    /// The image data is corrupt.
+    InvalidData,
+}
+
+impl core::fmt::Display for PngError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            PngError::InvalidSignature => "the data isn't a PNG image",
+            PngError::UnexpectedEnd => "the PNG data ended early",
+            PngError::Unsupported => "the PNG image uses an unsupported feature",
+            PngError::InvalidData => "the PNG image data is corrupt",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PngError {}
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorType {
+    Gray,
+    Rgb,
+    Indexed,
+    GrayAlpha,
+    RgbAlpha,
+}
+
+impl ColorType {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(ColorType::Gray),
+            2 => Some(ColorType::Rgb),
+            3 => Some(ColorType::Indexed),
+            4 => Some(ColorType::GrayAlpha),
+            6 => Some(ColorType::RgbAlpha),
+            _ => None,
+        }
+    }
+
+    fn channels(self) -> usize {
+        match self {
+            ColorType::Gray | ColorType::Indexed => 1,
+            ColorType::GrayAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::RgbAlpha => 4,
+        }
+    }
+
+    fn supports_bit_depth(self, bit_depth: u8) -> bool {
+        match self {
+            ColorType::Gray => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+            ColorType::Indexed => matches!(bit_depth, 1 | 2 | 4 | 8),
+            _ => matches!(bit_depth, 8 | 16),
+        }
+    }
+}
+
+/// A decoded PNG image that can be drawn into any buffer with [Quantize]d colors.
+#[derive(Debug, Clone)]
+pub struct PngImage {
+    size: Size,
+    color_type: ColorType,
+    bit_depth: u8,
+    stride: usize,
+    // Unfiltered scanlines.
+    data: Vec<u8>,
+    palette: Vec<Rgb888>,
+    dither: Dither,
+    fit: Fit,
+    centered: bool,
+}
+
+impl PngImage {
+    /// Decodes a PNG file. The image is drawn centered, at its original size, with ordered
+    /// dithering.
+    pub fn decode(bytes: &[u8]) -> Result<Self, PngError> {
+        let mut rest = bytes
+            .strip_prefix(&SIGNATURE)
+            .ok_or(PngError::InvalidSignature)?;
+
+        let mut header = None;
+        let mut palette = Vec::new();
+        let mut compressed = Vec::new();
+        while !rest.is_empty() {
+            let (kind, data, next) = split_chunk(rest)?;
+            rest = next;
+            match &kind {
+                b"IHDR" => header = Some(parse_header(data)?),
+                b"PLTE" => {
+                    palette = data
+                        .chunks_exact(3)
+                        .map(|rgb| Rgb888::new(rgb[0], rgb[1], rgb[2]))
+                        .collect();
+                }
+                b"tRNS" => {
+                    for (color, &alpha) in palette.iter_mut().zip(data) {
+                        *color = over_white(*color, alpha);
+                    }
+                }
+                b"IDAT" => compressed.extend_from_slice(data),
+                b"IEND" => break,
+                _ => {}
+            }
+        }
+        let (size, color_type, bit_depth) = header.ok_or(PngError::UnexpectedEnd)?;
+
+        let bits_per_pixel = color_type.channels() * usize::from(bit_depth);
+        let stride = (size.width as usize * bits_per_pixel).div_ceil(8);
+        let filtered_len = (stride + 1) * size.height as usize;
+        let filtered =
+            miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&compressed, filtered_len)
+                .map_err(|_| PngError::InvalidData)?;
+        if filtered.len() < filtered_len {
+            return Err(PngError::UnexpectedEnd);
+        }
+        let data = unfilter(&filtered, stride, bits_per_pixel.div_ceil(8))?;
+
+        Ok(PngImage {
+            size,
+            color_type,
+            bit_depth,
+            stride,
+            data,
+            palette,
+            dither: Dither::default(),
+            fit: Fit::default(),
+            centered: true,
+        })
+    }
+
+    /// Sets how colors are converted for the target buffer.
+    pub fn with_dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// Sets how the image is sized within the area it's drawn to.
+    pub fn with_fit(mut self, fit: Fit) -> Self {
+        self.fit = fit;
+        self
+    }
+
+    /// Sets whether the image is centered in the area it's drawn to, or drawn at its top left.
+    pub fn with_centering(mut self, centered: bool) -> Self {
+        self.centered = centered;
+        self
+    }
+
+    /// The image's original size.
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Returns the color of the pixel at `point`, drawn over white if it's transparent.
+    pub fn pixel(&self, point: Point) -> Option<Rgb888> {
+        if point.x < 0
+            || point.y < 0
+            || point.x as u32 >= self.size.width
+            || point.y as u32 >= self.size.height
+        {
+            return None;
+        }
+        let (x, y) = (point.x as usize, point.y as usize);
+        let channels = self.color_type.channels();
+        let sample = |channel| self.sample(y, x * channels + channel);
+
+        let color = match self.color_type {
+            ColorType::Gray => gray(sample(0)),
+            ColorType::GrayAlpha => over_white(gray(sample(0)), sample(1)),
+            ColorType::Rgb => Rgb888::new(sample(0), sample(1), sample(2)),
+            ColorType::RgbAlpha => {
+                over_white(Rgb888::new(sample(0), sample(1), sample(2)), sample(3))
+            }
+            ColorType::Indexed => {
+                let index = self.raw_sample(y, x);
+                return Some(self.palette.get(index).copied().unwrap_or(Rgb888::WHITE));
+            }
+        };
+        Some(color)
+    }
+
+    /// Returns the raw value of the `index`th sample in row `y`.
+    fn raw_sample(&self, y: usize, index: usize) -> usize {
+        let row = &self.data[y * self.stride..][..self.stride];
+        match self.bit_depth {
+            8 => usize::from(row[index]),
+            16 => usize::from(row[index * 2]) << 8 | usize::from(row[index * 2 + 1]),
+            bits => {
+                let bits = usize::from(bits);
+                let bit = index * bits;
+                let shift = 8 - bits - bit % 8;
+                usize::from(row[bit / 8] >> shift) & ((1 << bits) - 1)
+            }
+        }
+    }
+
+    /// Returns the `index`th sample in row `y`, scaled to 8 bits.
+    fn sample(&self, y: usize, index: usize) -> u8 {
+        let value = self.raw_sample(y, index);
+        match self.bit_depth {
+            16 => (value >> 8) as u8,
+            bits => (value * 255 / ((1 << bits) - 1)) as u8,
+        }
+    }
+
+    /// Draws the image into `area`, and returns the area that changed.
+    pub fn draw<D>(&self, target: &mut D, area: Rectangle) -> Result<Rectangle, D::Error>
+    where
+        D: DrawTarget,
+        D::Color: Quantize,
+    {
+        draw_image(
+            target,
+            area,
+            self.size,
+            self.fit,
+            self.centered,
+            self.dither,
+            |point| self.pixel(point).unwrap_or(Rgb888::WHITE),
+        )
+    }
+}
+
+/// A chunk's type and data, and the bytes after it.
+type Chunk<'a> = ([u8; 4], &'a [u8], &'a [u8]);
+
+/// Splits the first chunk from `bytes`.
+fn split_chunk(bytes: &[u8]) -> Result<Chunk<'_>, PngError> {
+    let (len, rest) = bytes
+        .split_first_chunk::<4>()
+        .ok_or(PngError::UnexpectedEnd)?;
+    let (kind, rest) = rest
+        .split_first_chunk::<4>()
+        .ok_or(PngError::UnexpectedEnd)?;
+    let len = u32::from_be_bytes(*len) as usize;
+    // The data is followed by a 4 byte CRC.
+    if rest.len() < len.saturating_add(4) {
+        return Err(PngError::UnexpectedEnd);
+    }
+    Ok((*kind, &rest[..len], &rest[len + 4..]))
+}
+
+fn parse_header(data: &[u8]) -> Result<(Size, ColorType, u8), PngError> {
+    let &[w0, w1, w2, w3, h0, h1, h2, h3, bit_depth, color_type, compression, filter, interlace] =
+        data
+    else {
+        return Err(PngError::InvalidData);
+    };
+    let color_type = ColorType::from_byte(color_type).ok_or(PngError::InvalidData)?;
+    if !color_type.supports_bit_depth(bit_depth) || compression != 0 || filter != 0 {
+        return Err(PngError::InvalidData);
+    }
+    if interlace != 0 {
+        return Err(PngError::Unsupported);
+    }
+    let size = Size::new(
+        u32::from_be_bytes([w0, w1, w2, w3]),
+        u32::from_be_bytes([h0, h1, h2, h3]),
+    );
+    Ok((size, color_type, bit_depth))
+}
+
+/// Reverses the filter on each scanline. `bpp` is the number of bytes per pixel, rounded up.
+fn unfilter(filtered: &[u8], stride: usize, bpp: usize) -> Result<Vec<u8>, PngError> {
+    let mut data: Vec<u8> = Vec::with_capacity(filtered.len() / (stride + 1) * stride);
+    for row in filtered.chunks_exact(stride + 1) {
+        let (&filter, row) = row.split_first().ok_or(PngError::InvalidData)?;
+        let start = data.len();
+        for (i, &byte) in row.iter().enumerate() {
+            let left = if i >= bpp { data[start + i - bpp] } else { 0 };
+            let up = if start > 0 {
+                data[start - stride + i]
+            } else {
+                0
+            };
+            let up_left = if start > 0 && i >= bpp {
+                data[start - stride + i - bpp]
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(PngError::InvalidData),
+            };
+            data.push(byte.wrapping_add(predicted));
+        }
+    }
+    Ok(data)
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let p = i16::from(left) + i16::from(up) - i16::from(up_left);
+    let (pa, pb, pc) = (
+        (p - i16::from(left)).abs(),
+        (p - i16::from(up)).abs(),
+        (p - i16::from(up_left)).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        left
+    } else if pb <= pc {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn gray(luma: u8) -> Rgb888 {
+    Rgb888::new(luma, luma, luma)
+}
+
+/// Blends `color` over white with the given opacity.
+fn over_white(color: Rgb888, alpha: u8) -> Rgb888 {
+    let blend = |c: u8| {
+        let alpha = u16::from(alpha);
+        ((u16::from(c) * alpha + 255 * (255 - alpha)) / 255) as u8
+    };
+    Rgb888::new(blend(color.r()), blend(color.g()), blend(color.b()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{binary_buffer_length, BinaryBuffer};
+
+    /// Builds a PNG from its header fields and filtered scanlines. The CRCs are left as zero.
+    fn encode_png(size: Size, bit_depth: u8, color_type: u8, scanlines: &[u8]) -> Vec<u8> {
+        let mut png = SIGNATURE.to_vec();
+        let mut chunk = |kind: &[u8; 4], data: &[u8]| {
+            png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            png.extend_from_slice(kind);
+            png.extend_from_slice(data);
+            png.extend_from_slice(&[0; 4]);
+        };
+        let mut header = Vec::new();
+        header.extend_from_slice(&size.width.to_be_bytes());
+        header.extend_from_slice(&size.height.to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        chunk(b"IHDR", &header);
+        if color_type == 3 {
+            chunk(b"PLTE", &[0, 0, 0, 255, 0, 0]);
+            chunk(b"tRNS", &[255, 0]);
+        }
+        chunk(
+            b"IDAT",
+            &miniz_oxide::deflate::compress_to_vec_zlib(scanlines, 6),
+        );
+        chunk(b"IEND", &[]);
+        png
+    }
+
+    #[test]
+    fn test_decode_reverses_filters() {
+        // 3x2 gray: [0, 128, 255] then [255, 128, 0], using the Sub and Up filters.
+        let png = encode_png(Size::new(3, 2), 8, 0, &[1, 0, 128, 127, 2, 255, 0, 1]);
+        let image = PngImage::decode(&png).unwrap();
+        assert_eq!(image.size(), Size::new(3, 2));
+        let lumas: Vec<_> = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]
+            .map(|(x, y)| image.pixel(Point::new(x, y)).unwrap().r())
+            .into();
+        assert_eq!(lumas, [0, 128, 255, 255, 128, 0]);
+        assert_eq!(image.pixel(Point::new(3, 0)), None);
+    }
+
+    #[test]
+    fn test_draw_indexed_with_transparency() {
+        // 1-bit palette of opaque black, and transparent red.
+        let png = encode_png(Size::new(4, 1), 1, 3, &[0, 0b0101_0000]);
+        let image = PngImage::decode(&png)
+            .unwrap()
+            .with_dither(Dither::Threshold)
+            .with_centering(false);
+        assert_eq!(image.pixel(Point::new(1, 0)), Some(Rgb888::WHITE));
+
+        const SIZE: Size = Size::new(8, 1);
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let area = Rectangle::new(Point::zero(), SIZE);
+        assert_eq!(
+            image.with_fit(Fit::Stretch).draw(&mut buffer, area),
+            Ok(area)
+        );
+        assert_eq!(buffer.data(), [0b0011_0011]);
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid_data() {
+        assert_eq!(
+            PngImage::decode(b"not a png").unwrap_err(),
+            PngError::InvalidSignature
+        );
+        let mut png = encode_png(Size::new(1, 1), 8, 0, &[0, 0]);
+        png.truncate(40);
+        assert_eq!(PngImage::decode(&png).unwrap_err(), PngError::UnexpectedEnd);
+    }
+}
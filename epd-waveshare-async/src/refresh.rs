@@ -0,0 +1,692 @@
+//! Chooses between partial and full refreshes, to keep ghosting in check without refreshing the
+//! whole display more than needed.
+//!
+#![cfg_attr(feature = "epd2in9_v2", doc = "```")]
+#![cfg_attr(not(feature = "epd2in9_v2"), doc = "```ignore")]
+//! use core::time::Duration;
+//! use embedded_graphics::{prelude::*, primitives::Rectangle};
+//! use epd_waveshare_async::{
+//!     epd2in9_v2,
+//!     refresh::{RefreshKind, RefreshPolicy},
+//! };
+//!
+//! let mut policy = RefreshPolicy::new(
+//!     Size::new(epd2in9_v2::DISPLAY_WIDTH.into(), epd2in9_v2::DISPLAY_HEIGHT.into()),
+//!     epd2in9_v2::RECOMMENDED_MIN_FULL_REFRESH_INTERVAL,
+//!     epd2in9_v2::RECOMMENDED_MAX_FULL_REFRESH_INTERVAL,
+//! );
+//! let clock = Rectangle::new(Point::zero(), Size::new(128, 32));
+//!
+//! // The first refresh is always full, to start from a clean display.
+//! assert_eq!(policy.decide(Duration::ZERO, clock), RefreshKind::Full);
+//! policy.record(Duration::ZERO, RefreshKind::Full);
+//! assert_eq!(policy.decide(Duration::from_secs(60), clock), RefreshKind::Partial);
+//! ```
+//!
+//! With a display that implements [SetRefreshKind], [RefreshPolicy::refresh] switches the display
+//! to the chosen mode and refreshes it in one step.
+//!
+//! A [WearTracker] counts the partial refreshes of each part of the display, so that areas that are
+//! refreshed far more often than the rest, such as a clock, get their ghosting cleared in time, and
+//! can be reported as hot spots.
+//!
+//! Image retention that builds up over days of partial refreshes can be cleared with [deghost],
+//! which flashes the whole display black and white.
+use core::time::Duration;
+
+use embedded_graphics::{
+    prelude::{Point, Size},
+    primitives::Rectangle,
+};
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::{buffer::BufferView, log::debug, DisplaySimple, Displayable, WaitIdle};
+
+/// How a display should be refreshed.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshKind {
+    /// Only updates the pixels that changed. This is fast and doesn't flash, but builds up ghosting.
+    Partial,
+    /// Refreshes every pixel with a fast waveform, which clears most ghosting.
+    Fast,
+    /// Refreshes every pixel with the display's cleanest waveform, which clears all ghosting.
+    Full,
+}
+
+/// Displays whose refresh mode can be chosen by a [RefreshPolicy].
+pub trait SetRefreshKind<SPI: SpiDevice, ERROR>: Displayable<SPI, ERROR> {
+    /// Switches to the display's refresh mode for `kind`.
+    async fn set_refresh_kind(&mut self, spi: &mut SPI, kind: RefreshKind) -> Result<(), ERROR>;
+}
+
+/// Decides whether each refresh should be partial, fast or full.
+///
+/// A full refresh is done first, and then at least every `max_full_interval`. In between,
+/// partial refreshes are used until there have been too many in a row, or until a refresh changes
+/// most of the display. Then the display is fully refreshed if `min_full_interval` has passed
+/// since the last full refresh, or refreshed with the fast waveform otherwise.
+///
+/// Times are given as the [Duration] since any fixed point, such as boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefreshPolicy {
+    display: Size,
+    min_full_interval: Duration,
+    max_full_interval: Duration,
+    max_partials: u32,
+    large_change_percent: u8,
+    last_full: Option<Duration>,
+    partials: u32,
+}
+
+impl RefreshPolicy {
+    /// The default number of partial refreshes in a row before clearing the ghosting.
+    pub const DEFAULT_MAX_PARTIALS: u32 = 20;
+    /// The default percentage of the display that a change must cover to clear the ghosting.
+    pub const DEFAULT_LARGE_CHANGE_PERCENT: u8 = 50;
+
+    /// Creates a policy for a display of the given size. Use the display module's
+    /// `RECOMMENDED_MIN_FULL_REFRESH_INTERVAL` and `RECOMMENDED_MAX_FULL_REFRESH_INTERVAL` unless
+    /// you have a reason not to.
+    pub const fn new(
+        display: Size,
+        min_full_interval: Duration,
+        max_full_interval: Duration,
+    ) -> Self {
+        RefreshPolicy {
+            display,
+            min_full_interval,
+            max_full_interval,
+            max_partials: Self::DEFAULT_MAX_PARTIALS,
+            large_change_percent: Self::DEFAULT_LARGE_CHANGE_PERCENT,
+            last_full: None,
+            partials: 0,
+        }
+    }
+
+    /// Sets how many partial refreshes can be done in a row.
+    pub const fn with_max_partials(mut self, max_partials: u32) -> Self {
+        self.max_partials = max_partials;
+        self
+    }
+
+    /// Sets the percentage of the display that a change must cover to refresh the whole display.
+    pub const fn with_large_change_percent(mut self, percent: u8) -> Self {
+        self.large_change_percent = percent;
+        self
+    }
+
+    /// Returns how to refresh the display at time `now`, when `changed` is the area that differs
+    /// from what's shown.
+    pub fn decide(&self, now: Duration, changed: Rectangle) -> RefreshKind {
+        let Some(last_full) = self.last_full else {
+            return RefreshKind::Full;
+        };
+        let since_full = now.saturating_sub(last_full);
+        if since_full >= self.max_full_interval {
+            return RefreshKind::Full;
+        }
+
+        let display_area = u64::from(self.display.width) * u64::from(self.display.height);
+        let changed = changed.intersection(&Rectangle::new(Point::zero(), self.display));
+        let changed_area = u64::from(changed.size.width) * u64::from(changed.size.height);
+        let large_change =
+            changed_area * 100 >= display_area * u64::from(self.large_change_percent);
+        if self.partials < self.max_partials && !large_change {
+            RefreshKind::Partial
+        } else {
+            self.clearing_kind(since_full)
+        }
+    }
+
+    /// Like [RefreshPolicy::decide], but also clears the ghosting when any part of `changed` has
+    /// been partially refreshed too often according to `wear`.
+    ///
+    /// As the wear tracker knows where each partial refresh happened, the policy's max partials
+    /// can be raised when it's used, so that small changes spread across the display don't clear
+    /// the ghosting more often than needed.
+    pub fn decide_with_wear<const COLS: usize, const ROWS: usize>(
+        &self,
+        now: Duration,
+        changed: Rectangle,
+        wear: &WearTracker<COLS, ROWS>,
+    ) -> RefreshKind {
+        let kind = self.decide(now, changed);
+        match (kind, self.last_full) {
+            (RefreshKind::Partial, Some(last_full)) if wear.is_worn(changed) => {
+                self.clearing_kind(now.saturating_sub(last_full))
+            }
+            _ => kind,
+        }
+    }
+
+    /// The refresh to clear the ghosting with, `since_full` after the last full refresh.
+    fn clearing_kind(&self, since_full: Duration) -> RefreshKind {
+        if since_full >= self.min_full_interval {
+            RefreshKind::Full
+        } else {
+            RefreshKind::Fast
+        }
+    }
+
+    /// The size of the display that this policy is for.
+    pub const fn display_size(&self) -> Size {
+        self.display
+    }
+
+    /// Forgets the refresh history, so the next refresh is full, e.g. after the display was
+    /// cleared with [deghost].
+    pub fn reset(&mut self) {
+        self.last_full = None;
+        self.partials = 0;
+    }
+
+    /// Records that the display was refreshed at time `now`.
+    pub fn record(&mut self, now: Duration, kind: RefreshKind) {
+        match kind {
+            RefreshKind::Partial => self.partials = self.partials.saturating_add(1),
+            RefreshKind::Fast => self.partials = 0,
+            RefreshKind::Full => {
+                self.partials = 0;
+                self.last_full = Some(now);
+            }
+        }
+    }
+
+    /// Decides how to refresh the display, switches it to that refresh mode, and refreshes it.
+    ///
+    /// The framebuffer should already have been written. Returns the kind of refresh that was
+    /// done.
+    pub async fn refresh<D, SPI, ERROR>(
+        &mut self,
+        epd: &mut D,
+        spi: &mut SPI,
+        now: Duration,
+        changed: Rectangle,
+    ) -> Result<RefreshKind, ERROR>
+    where
+        D: SetRefreshKind<SPI, ERROR>,
+        SPI: SpiDevice,
+    {
+        let kind = self.decide(now, changed);
+        refresh_with(epd, spi, kind).await?;
+        self.record(now, kind);
+        Ok(kind)
+    }
+
+    /// Like [RefreshPolicy::refresh], but decides with [RefreshPolicy::decide_with_wear], and
+    /// records the refresh in `wear` too.
+    pub async fn refresh_with_wear<const COLS: usize, const ROWS: usize, D, SPI, ERROR>(
+        &mut self,
+        epd: &mut D,
+        spi: &mut SPI,
+        now: Duration,
+        changed: Rectangle,
+        wear: &mut WearTracker<COLS, ROWS>,
+    ) -> Result<RefreshKind, ERROR>
+    where
+        D: SetRefreshKind<SPI, ERROR>,
+        SPI: SpiDevice,
+    {
+        let kind = self.decide_with_wear(now, changed, wear);
+        refresh_with(epd, spi, kind).await?;
+        self.record(now, kind);
+        wear.record(changed, kind);
+        Ok(kind)
+    }
+}
+
+async fn refresh_with<D, SPI, ERROR>(
+    epd: &mut D,
+    spi: &mut SPI,
+    kind: RefreshKind,
+) -> Result<(), ERROR>
+where
+    D: SetRefreshKind<SPI, ERROR>,
+    SPI: SpiDevice,
+{
+    debug!("Refreshing with {:?}", kind);
+    epd.set_refresh_kind(spi, kind).await?;
+    epd.update_display(spi).await
+}
+
+/// Counts the partial refreshes of each cell of a coarse `COLS` x `ROWS` grid over the display,
+/// to find areas, such as a clock, that are partially refreshed far more often than the rest.
+///
+/// Each cell keeps two counts: the partial refreshes since the ghosting was last cleared by a fast
+/// or full refresh, which [RefreshPolicy::decide_with_wear] uses to clear the ghosting when a cell
+/// reaches the max partials, and the total, for reporting hot spots with [WearTracker::hottest].
+///
+/// ```
+/// use embedded_graphics::{prelude::*, primitives::Rectangle};
+/// use epd_waveshare_async::refresh::{RefreshKind, WearTracker};
+///
+/// let mut wear = WearTracker::<4, 8>::new(Size::new(128, 296)).with_max_partials(2);
+/// let clock = Rectangle::new(Point::zero(), Size::new(32, 32));
+/// wear.record(clock, RefreshKind::Partial);
+/// assert!(!wear.is_worn(clock));
+/// wear.record(clock, RefreshKind::Partial);
+/// assert!(wear.is_worn(clock));
+///
+/// let (hot_spot, count) = wear.hottest().unwrap();
+/// assert_eq!(hot_spot, Rectangle::new(Point::zero(), Size::new(32, 37)));
+/// assert_eq!(count, 2);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WearTracker<const COLS: usize, const ROWS: usize> {
+    display: Size,
+    max_partials: u32,
+    since_clear: [[u32; COLS]; ROWS],
+    totals: [[u32; COLS]; ROWS],
+}
+
+impl<const COLS: usize, const ROWS: usize> WearTracker<COLS, ROWS> {
+    /// The default number of partial refreshes of one cell before clearing the ghosting.
+    pub const DEFAULT_MAX_PARTIALS: u32 = 50;
+
+    /// Creates a tracker for a display of the given size, with no refreshes recorded.
+    pub const fn new(display: Size) -> Self {
+        WearTracker {
+            display,
+            max_partials: Self::DEFAULT_MAX_PARTIALS,
+            since_clear: [[0; COLS]; ROWS],
+            totals: [[0; COLS]; ROWS],
+        }
+    }
+
+    /// Sets how many partial refreshes one cell can have before the ghosting is cleared.
+    pub const fn with_max_partials(mut self, max_partials: u32) -> Self {
+        self.max_partials = max_partials;
+        self
+    }
+
+    /// The size of each cell. Cells in the last row and column may be cut off by the display's
+    /// edge.
+    pub const fn cell_size(&self) -> Size {
+        Size::new(
+            self.display.width.div_ceil(COLS as u32),
+            self.display.height.div_ceil(ROWS as u32),
+        )
+    }
+
+    /// Records a refresh of `changed`.
+    ///
+    /// Partial refreshes are counted in each cell that `changed` covers. Fast and full refreshes
+    /// reset the counts since the ghosting was cleared, but not the totals.
+    pub fn record(&mut self, changed: Rectangle, kind: RefreshKind) {
+        if kind != RefreshKind::Partial {
+            self.since_clear = [[0; COLS]; ROWS];
+            return;
+        }
+        for (col, row) in self.cells(changed) {
+            self.since_clear[row][col] = self.since_clear[row][col].saturating_add(1);
+            self.totals[row][col] = self.totals[row][col].saturating_add(1);
+        }
+    }
+
+    /// Whether any cell that `changed` covers has reached the max partials since the ghosting
+    /// was last cleared.
+    pub fn is_worn(&self, changed: Rectangle) -> bool {
+        self.cells(changed)
+            .any(|(col, row)| self.since_clear[row][col] >= self.max_partials)
+    }
+
+    /// Returns the area and total partial refreshes of the cell that has been partially refreshed
+    /// the most, or `None` if nothing has been partially refreshed.
+    pub fn hottest(&self) -> Option<(Rectangle, u32)> {
+        self.hot_spots(1).max_by_key(|(_, total)| *total)
+    }
+
+    /// Returns the area and total partial refreshes of each cell with at least `min_total`
+    /// partial refreshes, row by row.
+    pub fn hot_spots(&self, min_total: u32) -> impl Iterator<Item = (Rectangle, u32)> + '_ {
+        let display = Rectangle::new(Point::zero(), self.display);
+        let cell = self.cell_size();
+        (0..ROWS)
+            .flat_map(|row| (0..COLS).map(move |col| (col, row)))
+            .filter(move |(col, row)| self.totals[*row][*col] >= min_total)
+            .map(move |(col, row)| {
+                let top_left = Point::new(
+                    (col as u32 * cell.width) as i32,
+                    (row as u32 * cell.height) as i32,
+                );
+                let area = Rectangle::new(top_left, cell).intersection(&display);
+                (area, self.totals[row][col])
+            })
+    }
+
+    /// Forgets all recorded refreshes, including the totals.
+    pub fn reset(&mut self) {
+        self.since_clear = [[0; COLS]; ROWS];
+        self.totals = [[0; COLS]; ROWS];
+    }
+
+    /// The columns and rows of the cells that `area` covers.
+    fn cells(&self, area: Rectangle) -> impl Iterator<Item = (usize, usize)> {
+        let area = area.intersection(&Rectangle::new(Point::zero(), self.display));
+        let cell = self.cell_size();
+        let (cols, rows) = match area.bottom_right() {
+            Some(bottom_right) if cell.width > 0 && cell.height > 0 => (
+                area.top_left.x as usize / cell.width as usize
+                    ..bottom_right.x as usize / cell.width as usize + 1,
+                area.top_left.y as usize / cell.height as usize
+                    ..bottom_right.y as usize / cell.height as usize + 1,
+            ),
+            // Nothing changed, so no cells are covered.
+            _ => (0..0, 0..0),
+        };
+        rows.flat_map(move |row| cols.clone().map(move |col| (col, row)))
+    }
+}
+
+/// An estimate of how long one refresh mode keeps the display busy, refined from measured
+/// refreshes, e.g. for scheduling around refreshes or setting task timeouts.
+///
+/// It starts from a typical duration, such as from a driver's `estimated_refresh_duration`. The
+/// first measurement replaces that, as it reflects the actual panel, and later measurements are
+/// averaged in with a weight of 1/4, so that the estimate follows slow changes in temperature.
+/// Refreshes can be measured around [WaitIdle::wait_until_idle], or with a
+/// [Benchmark](crate::bench::Benchmark).
+///
+/// ```
+/// use core::time::Duration;
+/// use epd_waveshare_async::refresh::RefreshEstimate;
+///
+/// let mut estimate = RefreshEstimate::new(Duration::from_millis(3000));
+/// estimate.record(Duration::from_millis(2600));
+/// assert_eq!(estimate.estimate(), Duration::from_millis(2600));
+/// estimate.record(Duration::from_millis(3000));
+/// assert_eq!(estimate.estimate(), Duration::from_millis(2700));
+/// // Allow 50% longer before giving up on the refresh.
+/// assert_eq!(estimate.timeout(50), Duration::from_millis(4050));
+/// ```
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefreshEstimate {
+    estimate: Duration,
+    samples: u32,
+}
+
+impl RefreshEstimate {
+    /// Creates an estimate that starts at the `typical` duration.
+    pub const fn new(typical: Duration) -> Self {
+        RefreshEstimate {
+            estimate: typical,
+            samples: 0,
+        }
+    }
+
+    /// The current estimate.
+    pub const fn estimate(&self) -> Duration {
+        self.estimate
+    }
+
+    /// The number of measured refreshes recorded.
+    pub const fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Returns the estimate with `headroom_percent` added, for use as a timeout.
+    pub fn timeout(&self, headroom_percent: u32) -> Duration {
+        self.estimate + self.estimate * headroom_percent / 100
+    }
+
+    /// Records that a refresh took `measured`.
+    pub fn record(&mut self, measured: Duration) {
+        self.estimate = if self.samples == 0 {
+            measured
+        } else {
+            (self.estimate * 3 + measured) / 4
+        };
+        self.samples = self.samples.saturating_add(1);
+    }
+}
+
+/// The number of bytes of a pattern that are written at once.
+pub(crate) const PATTERN_LEN: usize = 256;
+pub(crate) static BLACK: [u8; PATTERN_LEN] = [0x00; PATTERN_LEN];
+pub(crate) static WHITE: [u8; PATTERN_LEN] = [0xFF; PATTERN_LEN];
+
+/// Clears image retention by fully refreshing the display to black, then white, `cycles` times.
+///
+/// `display` is the display's size, whose width must be a multiple of 8. The display's cleanest
+/// waveform, from [RefreshKind::Full], is used, and left set afterwards. The display is left
+/// white, with its framebuffer overwritten, so redraw it with a full refresh.
+///
+/// This takes several seconds per cycle. A few cycles once a day, e.g. overnight, is usually
+/// enough.
+pub async fn deghost<D, SPI, ERROR>(
+    epd: &mut D,
+    spi: &mut SPI,
+    display: Size,
+    cycles: u32,
+) -> Result<(), ERROR>
+where
+    D: SetRefreshKind<SPI, ERROR> + DisplaySimple<1, 1, SPI, ERROR> + WaitIdle<ERROR>,
+    SPI: SpiDevice,
+{
+    debug!("Deghosting with {} cycles", cycles);
+    epd.set_refresh_kind(spi, RefreshKind::Full).await?;
+    for _ in 0..cycles {
+        for solid in [&BLACK, &WHITE] {
+            write_pattern(epd, spi, display, |_| (solid, u32::MAX)).await?;
+            Displayable::update_display(epd, spi).await?;
+            epd.wait_until_idle().await?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a repeating pattern to the whole framebuffer, without needing a buffer for it.
+///
+/// The display is written in bands of rows. `pattern(top)` returns the bytes for the band that
+/// starts at row `top`, and the most rows that the band can have. The band's bytes are taken from
+/// the start of the pattern, so the pattern must repeat every row, or the band must be one row.
+pub(crate) async fn write_pattern<D, SPI, ERROR>(
+    epd: &mut D,
+    spi: &mut SPI,
+    display: Size,
+    pattern: impl Fn(u32) -> (&'static [u8; PATTERN_LEN], u32),
+) -> Result<(), ERROR>
+where
+    D: DisplaySimple<1, 1, SPI, ERROR>,
+    SPI: SpiDevice,
+{
+    let bytes_per_row = (display.width as usize).div_ceil(8);
+    debug_assert!(
+        bytes_per_row <= PATTERN_LEN,
+        "The display is too wide to fill with a pattern."
+    );
+    let rows_per_band = (PATTERN_LEN / bytes_per_row.max(1)).max(1) as u32;
+    let mut top = 0;
+    while top < display.height {
+        let (data, max_rows) = pattern(top);
+        let height = rows_per_band.min(max_rows).min(display.height - top).max(1);
+        let band = Pattern {
+            window: Rectangle::new(Point::new(0, top as i32), Size::new(display.width, height)),
+            data: &data[..bytes_per_row * height as usize],
+        };
+        epd.write_framebuffer(spi, &band).await?;
+        top += height;
+    }
+    Ok(())
+}
+
+/// A band of the display filled with a pattern.
+struct Pattern<'a> {
+    window: Rectangle,
+    data: &'a [u8],
+}
+
+impl BufferView<1, 1> for Pattern<'_> {
+    fn window(&self) -> Rectangle {
+        self.window
+    }
+
+    fn data(&self) -> [&[u8]; 1] {
+        [self.data]
+    }
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use super::*;
+    use crate::{
+        epd2in9_v2::{self, Command, Epd2In9V2, RefreshMode},
+        testing::{block_on, Event, MockHw},
+    };
+
+    const MIN: Duration = Duration::from_secs(180);
+    const MAX: Duration = Duration::from_secs(3600);
+
+    fn policy() -> RefreshPolicy {
+        RefreshPolicy::new(Size::new(100, 100), MIN, MAX).with_max_partials(2)
+    }
+
+    fn small() -> Rectangle {
+        Rectangle::new(Point::zero(), Size::new(10, 10))
+    }
+
+    #[test]
+    fn test_clears_ghosting_after_max_partials() {
+        let mut policy = policy();
+        let mut now = Duration::ZERO;
+        let mut refresh = |policy: &mut RefreshPolicy, seconds| {
+            now += Duration::from_secs(seconds);
+            let kind = policy.decide(now, small());
+            policy.record(now, kind);
+            kind
+        };
+        assert_eq!(refresh(&mut policy, 0), RefreshKind::Full);
+        assert_eq!(refresh(&mut policy, 10), RefreshKind::Partial);
+        assert_eq!(refresh(&mut policy, 10), RefreshKind::Partial);
+        // Too soon for another full refresh.
+        assert_eq!(refresh(&mut policy, 10), RefreshKind::Fast);
+        assert_eq!(refresh(&mut policy, 10), RefreshKind::Partial);
+        assert_eq!(refresh(&mut policy, 10), RefreshKind::Partial);
+        assert_eq!(refresh(&mut policy, 200), RefreshKind::Full);
+    }
+
+    #[test]
+    fn test_full_refresh_for_large_changes_and_long_intervals() {
+        let mut policy = policy();
+        policy.record(Duration::ZERO, RefreshKind::Full);
+
+        let half = Rectangle::new(Point::new(0, 50), Size::new(100, 100));
+        assert_eq!(policy.decide(MIN, half), RefreshKind::Full);
+        assert_eq!(policy.decide(MIN, small()), RefreshKind::Partial);
+        assert_eq!(policy.decide(MAX, small()), RefreshKind::Full);
+    }
+
+    #[test]
+    fn test_wear_counts_partials_per_cell() {
+        let mut wear = WearTracker::<2, 2>::new(Size::new(100, 100)).with_max_partials(3);
+        let corner = Rectangle::new(Point::new(40, 40), Size::new(20, 5));
+        for _ in 0..3 {
+            wear.record(small(), RefreshKind::Partial);
+        }
+        wear.record(corner, RefreshKind::Partial);
+        assert!(wear.is_worn(small()));
+        assert!(!wear.is_worn(Rectangle::new(Point::new(60, 60), Size::new(1, 1))));
+
+        let spots: Vec<_> = wear.hot_spots(1).collect();
+        assert_eq!(
+            spots,
+            [
+                (Rectangle::new(Point::zero(), Size::new(50, 50)), 4),
+                (Rectangle::new(Point::new(50, 0), Size::new(50, 50)), 1),
+            ]
+        );
+
+        // Clearing the ghosting keeps the totals.
+        wear.record(small(), RefreshKind::Fast);
+        assert!(!wear.is_worn(small()));
+        assert_eq!(
+            wear.hottest(),
+            Some((Rectangle::new(Point::zero(), Size::new(50, 50)), 4))
+        );
+        wear.reset();
+        assert_eq!(wear.hottest(), None);
+    }
+
+    #[test]
+    fn test_worn_areas_clear_ghosting() {
+        let mut policy = policy().with_max_partials(100);
+        let mut wear = WearTracker::<2, 2>::new(Size::new(100, 100)).with_max_partials(2);
+        policy.record(Duration::ZERO, RefreshKind::Full);
+        wear.record(small(), RefreshKind::Full);
+        for _ in 0..2 {
+            assert_eq!(
+                policy.decide_with_wear(MIN, small(), &wear),
+                RefreshKind::Partial
+            );
+            policy.record(MIN, RefreshKind::Partial);
+            wear.record(small(), RefreshKind::Partial);
+        }
+        assert_eq!(
+            policy.decide_with_wear(MIN, small(), &wear),
+            RefreshKind::Full
+        );
+        assert_eq!(
+            policy.decide_with_wear(Duration::from_secs(1), small(), &wear),
+            RefreshKind::Fast
+        );
+        // Other cells are fine.
+        let elsewhere = Rectangle::new(Point::new(60, 60), Size::new(10, 10));
+        assert_eq!(
+            policy.decide_with_wear(MIN, elsewhere, &wear),
+            RefreshKind::Partial
+        );
+    }
+
+    #[test]
+    fn test_refresh_switches_mode_before_updating() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        log.take_events();
+
+        let mut policy = policy();
+        let kind = block_on(policy.refresh(&mut epd, &mut spi, Duration::ZERO, small()));
+        assert_eq!(kind, Ok(RefreshKind::Full));
+        let commands = log.commands();
+        assert_eq!(commands.first(), Some(&(Command::SwReset as u8)));
+        assert_eq!(commands.last(), Some(&(Command::MasterActivation as u8)));
+    }
+
+    #[test]
+    fn test_deghost_flashes_black_then_white() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        log.take_events();
+
+        let display = Size::new(
+            epd2in9_v2::DISPLAY_WIDTH.into(),
+            epd2in9_v2::DISPLAY_HEIGHT.into(),
+        );
+        block_on(deghost(&mut epd, &mut spi, display, 2)).unwrap();
+
+        let events = log.take_events();
+        let activations = events
+            .iter()
+            .filter(|e| **e == Event::Command(Command::MasterActivation as u8))
+            .count();
+        assert_eq!(activations, 4);
+        let written: Vec<_> = events
+            .windows(2)
+            .filter(|pair| pair[0] == Event::Command(Command::WriteLowRam as u8))
+            .filter_map(|pair| match &pair[1] {
+                Event::Data(data) => Some(data),
+                _ => None,
+            })
+            .collect();
+        // Each fill covers the display in bands of 16 rows.
+        let bytes = written[..19].iter().map(|d| d.len()).sum::<usize>();
+        assert_eq!(bytes, 16 * 296);
+        assert!(written[..19].iter().all(|d| d.iter().all(|b| *b == 0x00)));
+        assert!(written[19..38].iter().all(|d| d.iter().all(|b| *b == 0xFF)));
+    }
+}
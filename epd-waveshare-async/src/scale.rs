@@ -0,0 +1,137 @@
+//! Scales 1-bit images, such as a [crate::buffer::BinaryBuffer] or a raw [crate::buffer::Bitmap], into an area
+//! of another buffer, so that one asset can be shown at different sizes on different displays.
+//!
+#![cfg_attr(feature = "epd2in9_v2", doc = "```")]
+#![cfg_attr(not(feature = "epd2in9_v2"), doc = "```ignore")]
+//! use embedded_graphics::{prelude::{Point, Size}, primitives::Rectangle};
+//! use epd_waveshare_async::{
+//!     dither::Dither,
+//!     epd2in9_v2,
+//!     buffer::Bitmap,
+//!     scale::{scale, Filter},
+//! };
+//!
+//! // An 8x8 logo, with set bits shown as white.
+//! let data = [0x3C, 0x42, 0x81, 0x81, 0x81, 0x81, 0x42, 0x3C];
+//! let logo = Bitmap::new(&data, Size::new(8, 8)).unwrap();
+//! let mut buffer = epd2in9_v2::new_binary_buffer();
+//! let area = Rectangle::new(Point::new(8, 8), Size::new(32, 32));
+//! scale(&logo, &mut buffer, area, Filter::Nearest).unwrap();
+//! scale(&logo, &mut buffer, area, Filter::Box(Dither::Ordered)).unwrap();
+//! ```
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    pixelcolor::Rgb888,
+    prelude::Size,
+    primitives::{PointsIter, Rectangle},
+};
+
+use crate::{
+    buffer::BufferView,
+    dither::{quantize, Dither, Quantize},
+};
+
+/// How to pick each destination pixel's color.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Copies the closest source pixel. This keeps edges sharp, and suits upscaling.
+    #[default]
+    Nearest,
+    /// Averages all the source pixels covered by each destination pixel, and converts the average
+    /// with the given dithering. This keeps thin lines visible when downscaling.
+    Box(Dither),
+}
+
+/// Scales `source` to exactly fill `area` of `target`, and returns the area that changed.
+///
+/// Set bits in the source are drawn as white, and clear bits as black.
+pub fn scale<D>(
+    source: &dyn BufferView<1, 1>,
+    target: &mut D,
+    area: Rectangle,
+    filter: Filter,
+) -> Result<Rectangle, D::Error>
+where
+    D: DrawTarget,
+    D::Color: Quantize,
+{
+    let source_size = source.window().size;
+    if area.is_zero_sized() || source_size.width == 0 || source_size.height == 0 {
+        return Ok(Rectangle::new(area.top_left, Size::zero()));
+    }
+    let [data] = source.data();
+    let bytes_per_row = data.len() / source_size.height as usize;
+    let is_set =
+        |x: u32, y: u32| data[y as usize * bytes_per_row + x as usize / 8] & (0x80 >> (x % 8)) != 0;
+    // The source pixels, from `start` up to `end`, that a destination offset covers on one axis.
+    let span = |offset: u32, source: u32, destination: u32| {
+        let start = u64::from(offset) * u64::from(source) / u64::from(destination);
+        let end = (u64::from(offset) + 1) * u64::from(source) / u64::from(destination);
+        (start as u32, (end as u32).max(start as u32 + 1))
+    };
+
+    let colors = area.points().map(|point| {
+        let offset = point - area.top_left;
+        let (x0, x1) = span(offset.x as u32, source_size.width, area.size.width);
+        let (y0, y1) = span(offset.y as u32, source_size.height, area.size.height);
+        let (luma, dither) = match filter {
+            Filter::Nearest => (if is_set(x0, y0) { 255 } else { 0 }, Dither::Threshold),
+            Filter::Box(dither) => {
+                let set = (y0..y1)
+                    .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+                    .filter(|(x, y)| is_set(*x, *y))
+                    .count() as u32;
+                let total = (x1 - x0) * (y1 - y0);
+                ((set * 255 / total) as u8, dither)
+            }
+        };
+        quantize(Rgb888::new(luma, luma, luma), point, dither)
+    });
+    target.fill_contiguous(&area, colors)?;
+    Ok(area)
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{
+        pixelcolor::Gray2,
+        prelude::{GrayColor, Point},
+    };
+
+    use super::*;
+    use crate::buffer::{
+        binary_buffer_length, gray2_split_buffer_length, Bitmap, Gray2SplitBuffer,
+    };
+
+    #[test]
+    fn test_nearest_upscales_pixels_into_blocks() {
+        use crate::buffer::BinaryBuffer;
+
+        const SIZE: Size = Size::new(8, 4);
+        // A 2x2 image, with only the top left pixel set.
+        let source = Bitmap::new(&[0x80, 0x00], Size::new(2, 2)).unwrap();
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let area = Rectangle::new(Point::zero(), Size::new(4, 4));
+        let changed = scale(&source, &mut buffer, area, Filter::Nearest).unwrap();
+        assert_eq!(changed, area);
+        assert_eq!(buffer.data(), [0xC0, 0xC0, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_box_averages_covered_pixels() {
+        const SIZE: Size = Size::new(8, 1);
+        // A 4x2 image, where the left 2x2 block is 3/4 set and the right block is clear.
+        let source = Bitmap::new(&[0xC0, 0x40], Size::new(4, 2)).unwrap();
+        let mut buffer = Gray2SplitBuffer::<{ gray2_split_buffer_length(SIZE) }>::new(SIZE);
+        let area = Rectangle::new(Point::zero(), Size::new(2, 1));
+        scale(&source, &mut buffer, area, Filter::Box(Dither::Threshold)).unwrap();
+
+        let level = |x: u32| {
+            let bit = |data: &[u8]| u8::from(data[0] & (0x80 >> x) != 0);
+            Gray2::new(bit(buffer.high.data()) << 1 | bit(buffer.low.data())).luma()
+        };
+        assert_eq!(level(0), 2);
+        assert_eq!(level(1), 0);
+    }
+}
@@ -0,0 +1,186 @@
+//! Shows a new frame at a fixed interval, such as every minute for a clock, sleeping the display
+//! in between.
+//!
+//! ```text
+//! let policy = RefreshPolicy::new(
+//!     Size::new(epd2in9_v2::DISPLAY_WIDTH.into(), epd2in9_v2::DISPLAY_HEIGHT.into()),
+//!     epd2in9_v2::RECOMMENDED_MIN_FULL_REFRESH_INTERVAL,
+//!     epd2in9_v2::RECOMMENDED_MAX_FULL_REFRESH_INTERVAL,
+//! );
+//! let mut scheduler = FrameScheduler::new(epd, spi, Duration::from_secs(60), policy);
+//! loop {
+//!     let time = clock.time_at(scheduler.next_deadline());
+//!     let changed = draw_time(&mut buffer, time)?;
+//!     scheduler.next_frame(&buffer, changed).await?;
+//! }
+//! ```
+use embassy_time::{Duration, Instant, Timer};
+use embedded_graphics::primitives::Rectangle;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::{
+    buffer::BufferView,
+    idle::{DisplayLost, Power, PowerCell},
+    log::debug,
+    refresh::{deghost, RefreshKind, RefreshPolicy, SetRefreshKind},
+    DisplaySimple, Sleep, WaitIdle, Wake,
+};
+
+/// When to clear image retention with [deghost].
+struct Deghost {
+    period: Duration,
+    cycles: u32,
+    next: Instant,
+}
+
+/// Owns a display and its SPI device, and refreshes the display once per period.
+///
+/// Each refresh is started early by however long the last refresh of the same kind took, so that
+/// it finishes at the scheduled time. The display sleeps between frames, and a [RefreshPolicy]
+/// chooses the kind of each refresh to limit both flashing and ghosting.
+///
+/// `D` is the ready state of the display, such as `Epd2In9V2<HW, StateReady>`, and `S` is its
+/// sleeping state. Like [crate::idle::AutoSleep], the display is lost if waking or sleeping it
+/// fails, and later frames return [DisplayLost].
+pub struct FrameScheduler<D, S, SPI> {
+    spi: SPI,
+    power: PowerCell<D, S>,
+    period: Duration,
+    policy: RefreshPolicy,
+    next: Instant,
+    /// How long the last partial, fast and full refreshes took.
+    latency: [Duration; 3],
+    deghost: Option<Deghost>,
+}
+
+impl<D, S, SPI> FrameScheduler<D, S, SPI> {
+    /// Creates a scheduler for the given initialised display. The first frame is shown as soon as
+    /// possible.
+    pub fn new(display: D, spi: SPI, period: Duration, policy: RefreshPolicy) -> Self {
+        FrameScheduler {
+            spi,
+            power: PowerCell::new(display),
+            period,
+            policy,
+            next: Instant::now(),
+            latency: [Duration::from_ticks(0); 3],
+            deghost: None,
+        }
+    }
+
+    /// Clears image retention with [deghost] every `period`, e.g. daily, flashing the display
+    /// `cycles` times.
+    ///
+    /// The cycles run just before the first frame after each period, which is then shown late by
+    /// however long the cycles took, with a full refresh.
+    pub fn with_deghost(mut self, period: Duration, cycles: u32) -> Self {
+        self.deghost = Some(Deghost {
+            period,
+            cycles,
+            next: Instant::now() + period,
+        });
+        self
+    }
+
+    /// Returns the display, awake or asleep, or `None` if it was lost to an error, and the SPI
+    /// device.
+    pub fn into_inner(self) -> (Option<Power<D, S>>, SPI) {
+        (self.power.into_inner(), self.spi)
+    }
+
+    /// The time that the next frame will be shown by. Draw the content for this time before
+    /// calling [FrameScheduler::next_frame].
+    pub fn next_deadline(&self) -> Instant {
+        self.next
+    }
+
+    /// Waits until it's time for the next frame, then wakes the display, writes `buffer` to it,
+    /// refreshes it, and puts it back to sleep.
+    ///
+    /// `changed` is the area that differs from the last frame, which the [RefreshPolicy] uses to
+    /// choose the kind of refresh. Returns the kind of refresh that was done.
+    pub async fn next_frame<const BITS: usize, const FRAMES: usize, B, ERROR>(
+        &mut self,
+        buffer: &B,
+        changed: Rectangle,
+    ) -> Result<RefreshKind, ERROR>
+    where
+        SPI: SpiDevice,
+        B: BufferView<BITS, FRAMES>,
+        D: DisplaySimple<BITS, FRAMES, SPI, ERROR>
+            + DisplaySimple<1, 1, SPI, ERROR>
+            + SetRefreshKind<SPI, ERROR>
+            + WaitIdle<ERROR>
+            + Sleep<SPI, ERROR, DisplayOut = S>,
+        S: Wake<SPI, ERROR, DisplayOut = D>,
+        ERROR: From<DisplayLost>,
+    {
+        let deadline = self.next;
+        let at = policy_time(deadline);
+        let deghost_due = self
+            .deghost
+            .as_ref()
+            .is_some_and(|deghost| deadline >= deghost.next);
+        if deghost_due {
+            self.policy.reset();
+        }
+        let kind = self.policy.decide(at, changed);
+        let start = deadline
+            .checked_sub(self.latency[kind as usize])
+            .unwrap_or(deadline);
+        Timer::at(start).await;
+        debug!("Starting frame for {} ms", deadline.as_millis());
+
+        let started = Instant::now();
+        let display = self.power.wake(&mut self.spi).await?;
+        if let Some(cleaning) = self.deghost.as_mut().filter(|_| deghost_due) {
+            let size = self.policy.display_size();
+            deghost(display, &mut self.spi, size, cleaning.cycles).await?;
+            cleaning.next = next_deadline(cleaning.next, cleaning.period, deadline);
+        }
+        display.write_framebuffer(&mut self.spi, buffer).await?;
+        let kind = self
+            .policy
+            .refresh(display, &mut self.spi, at, changed)
+            .await?;
+        self.power.sleep(&mut self.spi).await?;
+
+        // The deghosting time would skew the estimate for the next refresh of this kind.
+        if !deghost_due {
+            self.latency[kind as usize] = Instant::now() - started;
+        }
+        self.next = next_deadline(deadline, self.period, Instant::now());
+        Ok(kind)
+    }
+}
+
+/// Converts an instant to the time since boot, as used by [RefreshPolicy].
+fn policy_time(instant: Instant) -> core::time::Duration {
+    core::time::Duration::from_micros(instant.as_micros())
+}
+
+/// Returns the first deadline after `now`, continuing from `deadline` in steps of `period`.
+///
+/// Missed frames are skipped, so that the schedule stays aligned to the first deadline.
+fn next_deadline(deadline: Instant, period: Duration, now: Instant) -> Instant {
+    let next = deadline + period;
+    if next > now || period.as_ticks() == 0 {
+        return next;
+    }
+    let missed = (now - next).as_ticks() / period.as_ticks() + 1;
+    next + Duration::from_ticks(missed * period.as_ticks())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_deadline_skips_missed_frames() {
+        let at = |seconds| Instant::from_secs(seconds);
+        let minute = Duration::from_secs(60);
+        assert_eq!(next_deadline(at(60), minute, at(61)), at(120));
+        assert_eq!(next_deadline(at(60), minute, at(120)), at(180));
+        assert_eq!(next_deadline(at(60), minute, at(250)), at(300));
+    }
+}
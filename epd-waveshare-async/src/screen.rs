@@ -0,0 +1,357 @@
+//! A template for screens that are split into named regions, such as the clock, weather and
+//! calendar of a dashboard.
+//!
+//! Each region has a `bind` closure, which picks the data that the region shows out of the app's
+//! state and returns its [fingerprint], and a `render` closure, which draws the region from the
+//! state. [Screen::render_changed] then redraws only the regions whose data changed, and partially
+//! refreshes just their rows.
+//!
+//! ```text
+//! struct State {
+//!     time: (u8, u8),
+//!     temperature: i16,
+//! }
+//!
+//! let draw_clock = |state: &State, target: &mut Cropped<'_, Clipped<'_, _>>| {
+//!     target.clear(BinaryColor::On)?;
+//!     Text::new(&format_time(state.time), Point::new(0, 20), style).draw(target)?;
+//!     Ok(())
+//! };
+//! let mut screen = Screen::<_, _, 4>::new(epd2in9_v2::new_binary_buffer())
+//!     .with_region("clock", clock_area, &|state: &State| fingerprint(&state.time), &draw_clock)
+//!     .with_region("weather", weather_area, &|state: &State| fingerprint(&state.temperature), &draw_weather);
+//!
+//! screen.render_all(&mut epd, &mut spi, &state).await?;
+//! loop {
+//!     state.time = next_minute().await;
+//!     // Only the clock is redrawn and refreshed.
+//!     screen.render_changed(&mut epd, &mut spi, &state).await?;
+//! }
+//! ```
+use core::{
+    convert::Infallible,
+    hash::{Hash, Hasher},
+};
+
+use embedded_graphics::{
+    draw_target::{Clipped, Cropped, DrawTarget, DrawTargetExt},
+    prelude::{Point, Size},
+    primitives::Rectangle,
+};
+use embedded_hal_async::spi::SpiDevice;
+use heapless::Vec;
+
+use crate::{
+    buffer::BufferView,
+    dirty::{Band, DirtyRegions},
+    log::{trace, warning},
+    DisplayPartial, DisplaySimple,
+};
+
+/// Picks a region's data out of the state, and returns its [fingerprint].
+pub type Bind<'a, S> = &'a dyn Fn(&S) -> u64;
+
+/// Draws a region from the state.
+///
+/// The draw target is translated so that `(0, 0)` is the top left of the region, and anything
+/// drawn outside the region is clipped.
+pub type Render<'a, S, B> =
+    &'a dyn Fn(&S, &mut Cropped<'_, Clipped<'_, B>>) -> Result<(), Infallible>;
+
+/// Hashes `value` into a fingerprint, which changes whenever `value` does (barring collisions).
+///
+/// This uses 64-bit FNV-1a, which is fast and stable across builds, but isn't collision resistant.
+pub fn fingerprint<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = Fnv1a(0xCBF2_9CE4_8422_2325);
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+struct Fnv1a(u64);
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01B3);
+        }
+    }
+}
+
+struct Region<'a, S, B: DrawTarget> {
+    name: &'static str,
+    area: Rectangle,
+    bind: Bind<'a, S>,
+    render: Render<'a, S, B>,
+    /// The fingerprint of the data that the region was last drawn with.
+    drawn: Option<u64>,
+}
+
+/// A buffer split into up to `N` named regions, each drawn from the state `S`.
+pub struct Screen<'a, S, B: DrawTarget, const N: usize> {
+    buffer: B,
+    regions: Vec<Region<'a, S, B>, N>,
+}
+
+impl<'a, S, B, const N: usize> Screen<'a, S, B, N>
+where
+    B: DrawTarget<Error = Infallible>,
+{
+    /// Creates a screen that draws to `buffer`, with no regions.
+    pub fn new(buffer: B) -> Self {
+        Self {
+            buffer,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Adds a region called `name`, which covers `area` of the buffer.
+    ///
+    /// Regions are drawn in the order that they're added. Regions beyond the first `N` are
+    /// ignored.
+    pub fn with_region(
+        mut self,
+        name: &'static str,
+        area: Rectangle,
+        bind: Bind<'a, S>,
+        render: Render<'a, S, B>,
+    ) -> Self {
+        let region = Region {
+            name,
+            area,
+            bind,
+            render,
+            drawn: None,
+        };
+        if self.regions.push(region).is_err() {
+            warning!("Screen is full; ignoring region {}", name);
+        }
+        self
+    }
+
+    /// Returns the area of the region called `name`.
+    pub fn area(&self, name: &str) -> Option<Rectangle> {
+        self.regions
+            .iter()
+            .find(|region| region.name == name)
+            .map(|region| region.area)
+    }
+
+    /// Makes the region called `name` redraw on the next [Screen::render_changed], even if its
+    /// data hasn't changed. Returns `false` if there is no such region.
+    pub fn invalidate(&mut self, name: &str) -> bool {
+        match self.regions.iter_mut().find(|region| region.name == name) {
+            Some(region) => {
+                region.drawn = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Makes every region redraw on the next [Screen::render_changed].
+    pub fn invalidate_all(&mut self) {
+        for region in self.regions.iter_mut() {
+            region.drawn = None;
+        }
+    }
+
+    /// Returns the buffer.
+    pub fn buffer(&self) -> &B {
+        &self.buffer
+    }
+
+    /// Returns the buffer.
+    pub fn into_inner(self) -> B {
+        self.buffer
+    }
+
+    /// Draws every region, then writes the whole buffer to the display and refreshes it.
+    ///
+    /// Use this for the first frame, and for periodic full refreshes to clear ghosting.
+    pub async fn render_all<const BITS: usize, const FRAMES: usize, D, SPI, ERROR>(
+        &mut self,
+        epd: &mut D,
+        spi: &mut SPI,
+        state: &S,
+    ) -> Result<(), ERROR>
+    where
+        B: BufferView<BITS, FRAMES>,
+        D: DisplaySimple<BITS, FRAMES, SPI, ERROR>,
+        SPI: SpiDevice,
+    {
+        for region in self.regions.iter_mut() {
+            let fingerprint = (region.bind)(state);
+            draw(&mut self.buffer, region, state, fingerprint);
+        }
+        epd.display_framebuffer(spi, &self.buffer).await
+    }
+
+    /// Redraws only the regions whose data changed since they were last drawn, then writes their
+    /// rows to the display and partially refreshes it. Returns the number of regions redrawn.
+    ///
+    /// The display should be in a partial refresh mode, with its base framebuffer already
+    /// written, such as by [Screen::render_all]. Does nothing if no data has changed.
+    pub async fn render_changed<const BITS: usize, const FRAMES: usize, D, SPI, ERROR>(
+        &mut self,
+        epd: &mut D,
+        spi: &mut SPI,
+        state: &S,
+    ) -> Result<usize, ERROR>
+    where
+        B: BufferView<BITS, FRAMES>,
+        D: DisplayPartial<BITS, FRAMES, SPI, ERROR>,
+        SPI: SpiDevice,
+    {
+        let bounds = self.buffer.bounding_box();
+        let mut bands = DirtyRegions::<4>::new();
+        let mut redrawn = 0;
+        for region in self.regions.iter_mut() {
+            let fingerprint = (region.bind)(state);
+            if region.drawn == Some(fingerprint) {
+                continue;
+            }
+            draw(&mut self.buffer, region, state, fingerprint);
+            redrawn += 1;
+            let rows = region.area.intersection(&bounds).rows();
+            bands.add(Rectangle::new(
+                Point::new(bounds.top_left.x, rows.start),
+                Size::new(bounds.size.width, rows.len() as u32),
+            ));
+        }
+        if bands.is_empty() {
+            return Ok(redrawn);
+        }
+        for rows in bands.iter().map(|band| band.rows()) {
+            let start = (rows.start - bounds.top_left.y) as usize;
+            let end = (rows.end - bounds.top_left.y) as usize;
+            epd.write_framebuffer(spi, &Band::new(&self.buffer, start..end))
+                .await?;
+        }
+        epd.update_display(spi).await?;
+        Ok(redrawn)
+    }
+}
+
+fn draw<S, B: DrawTarget<Error = Infallible>>(
+    buffer: &mut B,
+    region: &mut Region<'_, S, B>,
+    state: &S,
+    fingerprint: u64,
+) {
+    trace!("Drawing screen region {}", region.name);
+    let mut clipped = buffer.clipped(&region.area);
+    // Drawing to the buffer can't fail.
+    let _ = (region.render)(state, &mut clipped.cropped(&region.area));
+    region.drawn = Some(fingerprint);
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use core::cell::Cell;
+
+    use embedded_graphics::pixelcolor::BinaryColor;
+
+    use super::*;
+    use crate::{
+        buffer::BinaryBuffer,
+        epd2in9_v2::{self, Command, Epd2In9V2, RefreshMode},
+        testing::{block_on, Event, MockHw},
+    };
+
+    type Buffer = BinaryBuffer<{ epd2in9_v2::BINARY_BUFFER_LENGTH }>;
+
+    struct State {
+        time: (u8, u8),
+        temperature: i16,
+    }
+
+    fn fill(_: &State, target: &mut Cropped<'_, Clipped<'_, Buffer>>) -> Result<(), Infallible> {
+        // Larger than any region, to check that drawing is clipped.
+        target.fill_solid(
+            &Rectangle::new(Point::new(-8, -8), Size::new(200, 200)),
+            BinaryColor::On,
+        )
+    }
+
+    #[test]
+    fn test_fingerprint_changes_with_value() {
+        assert_eq!(fingerprint(&(12u8, 30u8)), fingerprint(&(12u8, 30u8)));
+        assert_ne!(fingerprint(&(12u8, 30u8)), fingerprint(&(12u8, 31u8)));
+        assert_ne!(fingerprint("a"), fingerprint("b"));
+    }
+
+    #[test]
+    fn test_render_changed_redraws_only_changed_regions() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+
+        let clock_draws = Cell::new(0);
+        let draw_clock = |state: &State, target: &mut Cropped<'_, Clipped<'_, Buffer>>| {
+            clock_draws.set(clock_draws.get() + 1);
+            fill(state, target)
+        };
+        let clock = Rectangle::new(Point::new(8, 4), Size::new(8, 2));
+        let weather = Rectangle::new(Point::new(0, 100), Size::new(128, 20));
+        let mut screen = Screen::<_, _, 2>::new(epd2in9_v2::new_binary_buffer())
+            .with_region(
+                "clock",
+                clock,
+                &|state: &State| fingerprint(&state.time),
+                &draw_clock,
+            )
+            .with_region(
+                "weather",
+                weather,
+                &|state: &State| fingerprint(&state.temperature),
+                &fill,
+            );
+        let mut state = State {
+            time: (12, 30),
+            temperature: 21,
+        };
+
+        block_on(screen.render_all(&mut epd, &mut spi, &state)).unwrap();
+        assert_eq!(clock_draws.get(), 1);
+        let bytes_per_row = epd2in9_v2::DISPLAY_WIDTH as usize / 8;
+        let data = screen.buffer().data();
+        assert_eq!(data[4 * bytes_per_row], 0x00);
+        assert_eq!(data[4 * bytes_per_row + 1], 0xFF);
+        assert_eq!(data[6 * bytes_per_row + 1], 0x00);
+
+        // Nothing changed, so nothing is drawn or sent.
+        log.take_events();
+        let redrawn = block_on(screen.render_changed(&mut epd, &mut spi, &state)).unwrap();
+        assert_eq!(redrawn, 0);
+        assert_eq!(log.take_events(), []);
+
+        state.time = (12, 31);
+        let redrawn = block_on(screen.render_changed(&mut epd, &mut spi, &state)).unwrap();
+        assert_eq!(redrawn, 1);
+        assert_eq!(clock_draws.get(), 2);
+        let writes: std::vec::Vec<_> = log
+            .take_events()
+            .windows(2)
+            .filter_map(|events| match events {
+                [Event::Command(c), Event::Data(data)] if *c == Command::WriteLowRam as u8 => {
+                    Some(data.len())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(writes, [2 * bytes_per_row]);
+
+        // Invalidated regions are redrawn even if their data is the same.
+        assert!(screen.invalidate("weather"));
+        assert!(!screen.invalidate("calendar"));
+        let redrawn = block_on(screen.render_changed(&mut epd, &mut spi, &state)).unwrap();
+        assert_eq!(redrawn, 1);
+        assert_eq!(clock_draws.get(), 2);
+        assert_eq!(screen.area("weather"), Some(weather));
+    }
+}
@@ -0,0 +1,227 @@
+//! A scripted sequence of full refreshes for checking that a panel works, e.g. after assembly.
+//!
+//! Each driver has a `self_test` method that runs the sequence in [SelfTestStep] order, and
+//! returns a [SelfTestReport] of how each step went.
+//!
+//! ```text
+//! let report = epd.self_test(&mut spi, 1000).await?;
+//! if !report.is_healthy() {
+//!     error!("Display failed its self test: {:?}", report);
+//! }
+//! ```
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::Size};
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+use embedded_hal_async::spi::SpiDevice;
+
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+use crate::{
+    hw::Timings,
+    log::{debug, warning},
+    refresh::{write_pattern, RefreshKind, SetRefreshKind, BLACK, PATTERN_LEN, WHITE},
+    DisplaySimple, WaitIdle,
+};
+
+/// The steps of a self test, in the order they're run.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTestStep {
+    /// A white display with a black border.
+    BorderBlack,
+    /// A white display with a white border.
+    BorderWhite,
+    /// A checkerboard of 8x8 pixel squares, starting with white in the top left.
+    Checkerboard,
+    /// The checkerboard with black and white swapped, so every pixel changes.
+    InvertedCheckerboard,
+    /// A black display.
+    Black,
+    /// A white display.
+    White,
+}
+
+impl SelfTestStep {
+    /// Every step, in order.
+    pub const ALL: [SelfTestStep; 6] = [
+        SelfTestStep::BorderBlack,
+        SelfTestStep::BorderWhite,
+        SelfTestStep::Checkerboard,
+        SelfTestStep::InvertedCheckerboard,
+        SelfTestStep::Black,
+        SelfTestStep::White,
+    ];
+}
+
+/// How one step of a self test went.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepResult {
+    pub step: SelfTestStep,
+    /// Whether the display reported that it was busy once the refresh started. A display that
+    /// never goes busy is likely disconnected, or has a faulty busy line.
+    pub went_busy: bool,
+    /// How long the driver waited for the refresh to finish, if the hardware has
+    /// [Timings] enabled.
+    pub busy_us: Option<u64>,
+}
+
+/// The results of a self test.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub steps: [StepResult; 6],
+}
+
+impl SelfTestReport {
+    /// Whether the display went busy for every refresh.
+    ///
+    /// This can't tell whether the image actually appeared, so also check the panel by eye, or
+    /// compare [StepResult::busy_us] with a known good panel.
+    pub fn is_healthy(&self) -> bool {
+        self.steps.iter().all(|step| step.went_busy)
+    }
+}
+
+/// The driver-specific parts of a self test.
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+pub(crate) trait SelfTestTarget<SPI: SpiDevice, ERROR>:
+    SetRefreshKind<SPI, ERROR> + DisplaySimple<1, 1, SPI, ERROR> + WaitIdle<ERROR>
+{
+    /// Sets the border color for the next full refresh.
+    async fn set_test_border(&mut self, spi: &mut SPI, color: BinaryColor) -> Result<(), ERROR>;
+
+    /// Starts a refresh, and returns whether the display went busy.
+    async fn start_test_refresh(&mut self, spi: &mut SPI) -> Result<bool, ERROR>;
+
+    fn test_timings(&mut self) -> Option<&mut Timings>;
+
+    async fn pause(&mut self, ms: u32);
+}
+
+/// The size of each checkerboard square.
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+const SQUARE: u32 = 8;
+
+/// Alternating white and black squares along a row.
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+static CHECKER: [u8; PATTERN_LEN] = {
+    let mut row = [0; PATTERN_LEN];
+    let mut i = 0;
+    while i < PATTERN_LEN {
+        row[i] = if i % 2 == 0 { 0xFF } else { 0x00 };
+        i += 1;
+    }
+    row
+};
+
+/// Alternating black and white squares along a row.
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+static CHECKER_INVERTED: [u8; PATTERN_LEN] = {
+    let mut row = [0; PATTERN_LEN];
+    let mut i = 0;
+    while i < PATTERN_LEN {
+        row[i] = !CHECKER[i];
+        i += 1;
+    }
+    row
+};
+
+/// Runs each [SelfTestStep] with full refreshes, pausing for `pause_ms` after each one.
+#[cfg(any(feature = "epd2in9", feature = "epd2in9_v2"))]
+pub(crate) async fn run<D, SPI, ERROR>(
+    epd: &mut D,
+    spi: &mut SPI,
+    display: Size,
+    pause_ms: u32,
+) -> Result<SelfTestReport, ERROR>
+where
+    D: SelfTestTarget<SPI, ERROR>,
+    SPI: SpiDevice,
+{
+    debug!("Starting self test");
+    epd.set_refresh_kind(spi, RefreshKind::Full).await?;
+    let mut steps = SelfTestStep::ALL.map(|step| StepResult {
+        step,
+        went_busy: false,
+        busy_us: None,
+    });
+    for result in &mut steps {
+        match result.step {
+            SelfTestStep::BorderBlack | SelfTestStep::BorderWhite => {
+                let color = BinaryColor::from(result.step == SelfTestStep::BorderWhite);
+                epd.set_test_border(spi, color).await?;
+                write_pattern(epd, spi, display, |_| (&WHITE, u32::MAX)).await?;
+            }
+            SelfTestStep::Checkerboard | SelfTestStep::InvertedCheckerboard => {
+                let inverted = result.step == SelfTestStep::InvertedCheckerboard;
+                write_pattern(epd, spi, display, |top| {
+                    let odd = (top / SQUARE) % 2 == 1;
+                    let data = if odd != inverted {
+                        &CHECKER_INVERTED
+                    } else {
+                        &CHECKER
+                    };
+                    // Bands mustn't cross into the next row of squares.
+                    (data, SQUARE - top % SQUARE)
+                })
+                .await?;
+            }
+            SelfTestStep::Black => {
+                write_pattern(epd, spi, display, |_| (&BLACK, u32::MAX)).await?;
+            }
+            SelfTestStep::White => {
+                write_pattern(epd, spi, display, |_| (&WHITE, u32::MAX)).await?;
+            }
+        }
+
+        let busy_before = epd.test_timings().map(|t| t.busy_wait_us());
+        result.went_busy = epd.start_test_refresh(spi).await?;
+        epd.wait_until_idle().await?;
+        let busy_after = epd.test_timings().map(|t| t.busy_wait_us());
+        result.busy_us = busy_before
+            .zip(busy_after)
+            .map(|(before, after)| after.saturating_sub(before));
+        if !result.went_busy {
+            warning!(
+                "Display didn't go busy for self test step {:?}",
+                result.step
+            );
+        }
+        epd.pause(pause_ms).await;
+    }
+    Ok(SelfTestReport { steps })
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use super::*;
+    use crate::{
+        epd2in9_v2::{self, Command, Epd2In9V2, RefreshMode},
+        testing::{block_on, MockHw},
+    };
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "epd2in9_v2-full-slow"),
+        ignore = "counts the commands of RefreshMode::FullSlow"
+    )]
+    fn test_reports_each_step() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        log.take_events();
+
+        let report = block_on(epd.self_test(&mut spi, 0)).unwrap();
+        let steps: std::vec::Vec<_> = report.steps.iter().map(|s| s.step).collect();
+        assert_eq!(steps, SelfTestStep::ALL);
+        // The mock display is never busy, which is what a disconnected display looks like.
+        assert!(!report.is_healthy());
+        assert!(report.steps.iter().all(|s| s.busy_us.is_none()));
+
+        let commands = log.commands();
+        let count = |command: Command| commands.iter().filter(|c| **c == command as u8).count();
+        assert_eq!(count(Command::MasterActivation), 6);
+        assert_eq!(count(Command::SetBorderWaveform), 2);
+    }
+}
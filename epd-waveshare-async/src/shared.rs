@@ -0,0 +1,172 @@
+//! A display handle that can be shared between tasks, with each task drawing to its own region.
+//!
+//! ```text
+//! static EPD: StaticCell<SharedEpd<CriticalSectionRawMutex, Epd2In9V2<...>, Epd2In9BinaryBuffer, Spi>> = ...;
+//! let epd = EPD.init(SharedEpd::new(epd, epd2in9_v2::new_binary_buffer(), spi));
+//!
+//! // In the clock task:
+//! let clock = epd.region(Rectangle::new(Point::zero(), Size::new(128, 32)));
+//! clock.draw(|target| Text::new("12:00", Point::new(0, 10), style).draw(target)).await?;
+//! clock.update().await?;
+//! ```
+use embassy_sync::{blocking_mutex::raw::RawMutex, mutex::Mutex};
+use embedded_graphics::{
+    draw_target::{Clipped, Cropped, DrawTarget, DrawTargetExt},
+    primitives::Rectangle,
+};
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::{buffer::BufferView, DisplaySimple};
+
+struct Inner<D, B, SPI> {
+    display: D,
+    buffer: B,
+    spi: SPI,
+}
+
+/// Owns a ready display, its framebuffer and SPI device behind a mutex.
+///
+/// Every operation holds the lock for its whole duration, so updates requested from different tasks
+/// never interleave their commands.
+pub struct SharedEpd<M: RawMutex, D, B, SPI> {
+    inner: Mutex<M, Inner<D, B, SPI>>,
+}
+
+impl<M: RawMutex, D, B, SPI> SharedEpd<M, D, B, SPI> {
+    /// Shares the given display, using `buffer` as its framebuffer.
+    pub const fn new(display: D, buffer: B, spi: SPI) -> Self {
+        SharedEpd {
+            inner: Mutex::new(Inner {
+                display,
+                buffer,
+                spi,
+            }),
+        }
+    }
+
+    /// Returns a handle for drawing to the given area of the framebuffer.
+    pub fn region(&self, area: Rectangle) -> EpdRegion<'_, M, D, B, SPI> {
+        EpdRegion { epd: self, area }
+    }
+
+    /// Gives `f` exclusive access to the whole framebuffer.
+    pub async fn draw<R>(&self, f: impl FnOnce(&mut B) -> R) -> R {
+        let mut inner = self.inner.lock().await;
+        f(&mut inner.buffer)
+    }
+
+    /// Writes the whole framebuffer to the display and refreshes it.
+    pub async fn update<const BITS: usize, const FRAMES: usize, ERROR>(&self) -> Result<(), ERROR>
+    where
+        SPI: SpiDevice,
+        B: BufferView<BITS, FRAMES>,
+        D: DisplaySimple<BITS, FRAMES, SPI, ERROR>,
+    {
+        let mut inner = self.inner.lock().await;
+        let Inner {
+            display,
+            buffer,
+            spi,
+        } = &mut *inner;
+        display.display_framebuffer(spi, buffer).await
+    }
+
+    /// Returns the display, buffer and SPI device.
+    pub fn into_inner(self) -> (D, B, SPI) {
+        let inner = self.inner.into_inner();
+        (inner.display, inner.buffer, inner.spi)
+    }
+}
+
+/// A handle for drawing to one area of a [SharedEpd]'s framebuffer.
+pub struct EpdRegion<'a, M: RawMutex, D, B, SPI> {
+    epd: &'a SharedEpd<M, D, B, SPI>,
+    area: Rectangle,
+}
+
+impl<M: RawMutex, D, B, SPI> EpdRegion<'_, M, D, B, SPI> {
+    /// The area of the framebuffer covered by this region.
+    pub fn area(&self) -> Rectangle {
+        self.area
+    }
+
+    /// Gives `f` exclusive access to this region of the framebuffer.
+    ///
+    /// The draw target is translated so that `(0, 0)` is the top left of the region, and anything
+    /// drawn outside the region is clipped.
+    pub async fn draw<R>(&self, f: impl FnOnce(&mut Cropped<'_, Clipped<'_, B>>) -> R) -> R
+    where
+        B: DrawTarget,
+    {
+        let mut inner = self.epd.inner.lock().await;
+        let mut clipped = inner.buffer.clipped(&self.area);
+        f(&mut clipped.cropped(&self.area))
+    }
+
+    /// Writes the framebuffer to the display and refreshes it.
+    ///
+    /// This writes the whole framebuffer, so changes that other tasks have drawn but not yet
+    /// displayed are shown too. Use a partial refresh mode to only update the changed pixels.
+    pub async fn update<const BITS: usize, const FRAMES: usize, ERROR>(&self) -> Result<(), ERROR>
+    where
+        SPI: SpiDevice,
+        B: BufferView<BITS, FRAMES>,
+        D: DisplaySimple<BITS, FRAMES, SPI, ERROR>,
+    {
+        self.epd.update().await
+    }
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embedded_graphics::{
+        pixelcolor::BinaryColor,
+        prelude::{Point, Size},
+        primitives::{Primitive, PrimitiveStyle},
+        Drawable,
+    };
+
+    use super::*;
+    use crate::epd2in9_v2::{self, Command, Epd2In9V2, RefreshMode};
+    use crate::testing::{block_on, MockHw};
+
+    #[test]
+    fn test_regions_draw_in_local_coordinates() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        log.take_events();
+
+        let shared: SharedEpd<NoopRawMutex, _, _, _> =
+            SharedEpd::new(epd, epd2in9_v2::new_binary_buffer(), spi);
+        let top = shared.region(Rectangle::new(Point::zero(), Size::new(8, 8)));
+        let bottom = shared.region(Rectangle::new(Point::new(0, 8), Size::new(8, 8)));
+
+        // Fill more than the region, to check that it's clipped.
+        block_on(bottom.draw(|target| {
+            Rectangle::new(Point::zero(), Size::new(16, 16))
+                .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+                .draw(target)
+        }))
+        .unwrap();
+        block_on(top.update()).unwrap();
+        assert_eq!(
+            log.commands()
+                .iter()
+                .filter(|c| **c == Command::WriteLowRam as u8)
+                .count(),
+            1
+        );
+
+        let (_, buffer, _) = shared.into_inner();
+        let data = buffer.data();
+        let bytes_per_row = epd2in9_v2::DISPLAY_WIDTH as usize / 8;
+        assert_eq!(data[7 * bytes_per_row], 0x00);
+        assert_eq!(data[8 * bytes_per_row], 0xFF);
+        assert_eq!(data[8 * bytes_per_row + 1], 0x00);
+        assert_eq!(data[15 * bytes_per_row], 0xFF);
+        assert_eq!(data[16 * bytes_per_row], 0x00);
+    }
+}
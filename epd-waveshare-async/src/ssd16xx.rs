@@ -0,0 +1,200 @@
+//! The command layer shared by the drivers for Solomon Systech SSD16xx controllers, such as the
+//! `epd2in9` and `epd2in9_v2`.
+//!
+//! These controllers share their RAM windowing, address counter and update sequencing commands,
+//! so a driver only needs its own initialisation, LUTs and update control values. These commands
+//! assume the driver has set the data entry mode to increment X first, then Y.
+use embedded_graphics::{
+    prelude::{Point, Size},
+    primitives::Rectangle,
+};
+
+use crate::{
+    buffer::{align_window, check_window, split_low_and_high, BufferView, WindowError},
+    command::commands,
+    hw::CommandDataSend,
+    log::warning,
+};
+
+commands! {
+    /// The registers shared by the SSD16xx controllers. The drivers' own `Command` enums reuse
+    /// these values.
+    pub(crate) enum Command {
+        /// Activates the display update sequence set with [Command::DisplayUpdateControl2].
+        MasterActivation = 0x20,
+        /// Configures the display update sequence for [Command::MasterActivation].
+        DisplayUpdateControl2 = 0x22,
+        /// Sets the inclusive start and end bytes of the X axis for the address counter.
+        SetRamXStartEnd = 0x44,
+        /// Sets the inclusive start and end rows of the Y axis for the address counter.
+        SetRamYStartEnd = 0x45,
+        /// Sets the current x byte of the address counter.
+        SetRamX = 0x4E,
+        /// Sets the current y row of the address counter.
+        SetRamY = 0x4F,
+    }
+}
+
+/// Returns the window of `buf`, or why it can't be written to a display of size `display`.
+pub(crate) fn checked_window<const BITS: usize, const FRAMES: usize>(
+    buf: &dyn BufferView<BITS, FRAMES>,
+    display: Size,
+) -> Result<Rectangle, WindowError> {
+    let window = buf.window();
+    check_window(window, display)?;
+    Ok(window)
+}
+
+/// Returns the [Command::SetRamXStartEnd] and [Command::SetRamYStartEnd] data for `shape`.
+///
+/// The x-axis is addressed in whole bytes, so unaligned windows are rounded out to whole bytes,
+/// with a warning.
+pub(crate) fn window_data(shape: Rectangle) -> ([u8; 2], [u8; 4]) {
+    let aligned = align_window(shape, shape).map_or(shape, |aligned| aligned.window);
+    // This is a soft failure; it will just lead to slightly misaligned display content.
+    if aligned != shape {
+        warning!("window's top_left.x and width must be 8-bit aligned");
+    }
+    let x_start = aligned.top_left.x;
+    let x_end = x_start + aligned.size.width as i32 - 1;
+    let x = [((x_start >> 3) & 0xFF) as u8, ((x_end >> 3) & 0xFF) as u8];
+
+    let (y_start_low, y_start_high) = split_low_and_high(shape.top_left.y as u16);
+    let (y_end_low, y_end_high) =
+        split_low_and_high((shape.top_left.y + shape.size.height as i32 - 1) as u16);
+    (x, [y_start_low, y_start_high, y_end_low, y_end_high])
+}
+
+/// Returns the [Command::SetRamX] and [Command::SetRamY] data for `position`.
+///
+/// Unaligned x positions are rounded down to whole bytes, with a warning.
+pub(crate) fn cursor_data(position: Point) -> ([u8; 1], [u8; 2]) {
+    // This is a soft failure; it will just lead to slightly misaligned display content.
+    if position.x % 8 != 0 {
+        warning!("position.x must be 8-bit aligned");
+    }
+    let (y_low, y_high) = split_low_and_high(position.y as u16);
+    ([(position.x >> 3) as u8], [y_low, y_high])
+}
+
+/// Sets the window to which the next image data will be written.
+pub(crate) async fn set_window<HW: CommandDataSend>(
+    hw: &mut HW,
+    spi: &mut HW::Spi,
+    shape: Rectangle,
+) -> Result<(), HW::Error> {
+    let (x, y) = window_data(shape);
+    hw.send(spi, Command::SetRamXStartEnd.register(), &x)
+        .await?;
+    hw.send(spi, Command::SetRamYStartEnd.register(), &y).await
+}
+
+/// Sets the address counter to write the next data to.
+pub(crate) async fn set_cursor<HW: CommandDataSend>(
+    hw: &mut HW,
+    spi: &mut HW::Spi,
+    position: Point,
+) -> Result<(), HW::Error> {
+    let (x, y) = cursor_data(position);
+    hw.send(spi, Command::SetRamX.register(), &x).await?;
+    hw.send(spi, Command::SetRamY.register(), &y).await
+}
+
+/// Writes image `data` to the `ram` register, filling `window` from its top left. The data is
+/// inverted if the hardware's [crate::hw::SpiHw::polarity] asks for it.
+pub(crate) async fn write_ram<HW: CommandDataSend>(
+    hw: &mut HW,
+    spi: &mut HW::Spi,
+    ram: u8,
+    window: Rectangle,
+    data: &[u8],
+) -> Result<(), HW::Error> {
+    set_window(hw, spi, window).await?;
+    set_cursor(hw, spi, window.top_left).await?;
+    hw.send_image(spi, ram, data).await
+}
+
+/// Runs the display update sequence selected by `update_control`.
+pub(crate) async fn activate<HW: CommandDataSend>(
+    hw: &mut HW,
+    spi: &mut HW::Spi,
+    update_control: &[u8],
+) -> Result<(), HW::Error> {
+    hw.send(
+        spi,
+        Command::DisplayUpdateControl2.register(),
+        update_control,
+    )
+    .await?;
+    hw.send(spi, Command::MasterActivation.register(), &[])
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::prelude::Size;
+    use embedded_hal::digital::PinState;
+
+    use super::*;
+    use crate::testing::{block_on, Event, MockHw};
+
+    #[test]
+    fn test_window_data_addresses_bytes_and_rows() {
+        let shape = Rectangle::new(Point::new(16, 260), Size::new(32, 36));
+        assert_eq!(window_data(shape), ([2, 5], [0x04, 0x01, 0x27, 0x01]));
+        assert_eq!(cursor_data(shape.top_left), ([2], [0x04, 0x01]));
+    }
+
+    #[test]
+    fn test_window_data_rounds_unaligned_x_out_to_whole_bytes() {
+        // Pixels 3..13 span the first two bytes.
+        let shape = Rectangle::new(Point::new(3, 1), Size::new(10, 2));
+        assert_eq!(window_data(shape), ([0, 1], [0x01, 0x00, 0x02, 0x00]));
+        assert_eq!(cursor_data(shape.top_left), ([0], [0x01, 0x00]));
+    }
+
+    #[test]
+    fn test_window_data_at_the_origin() {
+        let shape = Rectangle::new(Point::zero(), Size::new(128, 296));
+        assert_eq!(window_data(shape), ([0, 15], [0x00, 0x00, 0x27, 0x01]));
+        assert_eq!(cursor_data(shape.top_left), ([0], [0x00, 0x00]));
+    }
+
+    #[test]
+    fn test_write_ram_sets_the_window_and_cursor_first() {
+        let mut hw = MockHw::new(PinState::High);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let window = Rectangle::new(Point::new(8, 2), Size::new(16, 1));
+
+        block_on(write_ram(&mut hw, &mut spi, 0x24, window, &[0xAA, 0x55])).unwrap();
+
+        assert_eq!(
+            log.events(),
+            vec![
+                Event::Command(0x44),
+                Event::Data(vec![1, 2]),
+                Event::Command(0x45),
+                Event::Data(vec![0x02, 0x00, 0x02, 0x00]),
+                Event::Command(0x4E),
+                Event::Data(vec![1]),
+                Event::Command(0x4F),
+                Event::Data(vec![0x02, 0x00]),
+                Event::Command(0x24),
+                Event::Data(vec![0xAA, 0x55]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_activate_sets_the_update_control_then_activates() {
+        let mut hw = MockHw::new(PinState::High);
+        let log = hw.log();
+        let mut spi = hw.spi();
+
+        block_on(activate(&mut hw, &mut spi, &[0xC7])).unwrap();
+
+        assert_eq!(log.commands(), vec![0x22, 0x20]);
+        assert!(log.events().contains(&Event::Data(vec![0xC7])));
+    }
+}
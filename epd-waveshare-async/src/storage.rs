@@ -0,0 +1,673 @@
+//! Saves display buffers to NOR flash with `embedded-storage-async`, for deep sleep workflows.
+//!
+//! A microcontroller that powers down between refreshes loses its framebuffer, but partial
+//! refreshes need the previous image to diff against. Save the buffer before sleeping, then
+//! [load] it after waking, or [restore_base_framebuffer] to also write it to the display's base
+//! framebuffer, ready for the next partial refresh.
+//!
+//! ```text
+//! // Before powering down.
+//! storage::save(&mut flash, SAVE_OFFSET, &buffer, Compression::Rle).await?;
+//!
+//! // After waking.
+//! storage::restore_base_framebuffer(&mut epd, &mut spi, &mut flash, SAVE_OFFSET, &mut buffer).await?;
+//! draw_changes(&mut buffer)?;
+//! epd.display_framebuffer(&mut spi, &buffer).await?;
+//! ```
+//!
+//! Each save starts with a small header recording the buffer's size and a checksum, so a buffer
+//! is never loaded from erased flash, a different display, or an interrupted save.
+use embedded_graphics::prelude::Size;
+use embedded_hal_async::spi::SpiDevice;
+use embedded_storage_async::nor_flash::{NorFlash, ReadNorFlash};
+
+use crate::{
+    buffer::{BinaryBuffer, BufferView, Gray2SplitBuffer},
+    log::{debug, warning},
+    DisplayPartial,
+};
+
+/// How to encode the buffer data in flash.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// The data is stored as is.
+    None,
+    /// Repeated bytes are run-length encoded. This is much smaller for typical e-paper content,
+    /// which is mostly blank.
+    Rle,
+}
+
+/// Reasons that a buffer can't be saved or loaded.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StorageError<E> {
+    /// The flash returned an error.
+    Flash(E),
+    /// There's no saved buffer at the offset.
+    NotFound,
+    /// The saved buffer has a different size or format to the buffer being loaded.
+    SizeMismatch,
+    /// The saved buffer failed its checksum, e.g. because the save was interrupted.
+    Corrupt,
+    /// The buffer doesn't fit in the flash after the offset.
+    TooLarge,
+    /// The offset isn't aligned to the flash's erase size, or the flash's read or write size
+    /// isn't supported.
+    Unaligned,
+}
+
+impl<E: core::fmt::Debug> core::fmt::Display for StorageError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StorageError::Flash(error) => write!(f, "flash error: {error:?}"),
+            StorageError::NotFound => f.write_str("no saved buffer was found"),
+            StorageError::SizeMismatch => f.write_str("the saved buffer has a different size"),
+            StorageError::Corrupt => f.write_str("the saved buffer is corrupt"),
+            StorageError::TooLarge => f.write_str("the buffer doesn't fit in the flash"),
+            StorageError::Unaligned => f.write_str("the offset or flash sizes aren't aligned"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: core::fmt::Debug> std::error::Error for StorageError<E> {}
+
+/// Reasons that [restore_base_framebuffer] can fail.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreError<E, D> {
+    /// The buffer couldn't be loaded.
+    Storage(StorageError<E>),
+    /// The buffer couldn't be written to the display.
+    Display(D),
+}
+
+impl<E: core::fmt::Debug, D: core::fmt::Debug> core::fmt::Display for RestoreError<E, D> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RestoreError::Storage(error) => write!(f, "couldn't load the buffer: {error}"),
+            RestoreError::Display(error) => write!(f, "couldn't write the buffer: {error:?}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: core::fmt::Debug, D: core::fmt::Debug> std::error::Error for RestoreError<E, D> {}
+
+/// A buffer that can be loaded from flash.
+pub trait StorableBuffer<const BITS: usize, const FRAMES: usize>: BufferView<BITS, FRAMES> {
+    /// Returns the data of each frame, to be overwritten.
+    fn frames_mut(&mut self) -> [&mut [u8]; FRAMES];
+}
+
+impl<const L: usize> StorableBuffer<1, 1> for BinaryBuffer<L> {
+    fn frames_mut(&mut self) -> [&mut [u8]; 1] {
+        [self.data_mut()]
+    }
+}
+
+impl<const L: usize> StorableBuffer<1, 2> for Gray2SplitBuffer<L> {
+    fn frames_mut(&mut self) -> [&mut [u8]; 2] {
+        [self.low.data_mut(), self.high.data_mut()]
+    }
+}
+
+const MAGIC: [u8; 4] = *b"EPDB";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 28;
+/// The size of the reads and writes to flash. Flash read and write sizes must divide this.
+const CHUNK: usize = 256;
+
+/// Describes the saved buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    compression: Compression,
+    bits: u8,
+    frames: u8,
+    size: Size,
+    frame_len: u32,
+    /// Where the data starts, relative to the header.
+    payload_start: u32,
+    payload_len: u32,
+    checksum: u32,
+}
+
+impl Header {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4] = VERSION;
+        bytes[5] = match self.compression {
+            Compression::None => 0,
+            Compression::Rle => 1,
+        };
+        bytes[6] = self.bits;
+        bytes[7] = self.frames;
+        bytes[8..10].copy_from_slice(&(self.size.width as u16).to_le_bytes());
+        bytes[10..12].copy_from_slice(&(self.size.height as u16).to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.frame_len.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.payload_start.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.payload_len.to_le_bytes());
+        bytes[24..28].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; HEADER_LEN]) -> Option<Self> {
+        if bytes[0..4] != MAGIC || bytes[4] != VERSION {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]) as u32;
+        let u32_at =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        Some(Header {
+            compression: match bytes[5] {
+                0 => Compression::None,
+                1 => Compression::Rle,
+                _ => return None,
+            },
+            bits: bytes[6],
+            frames: bytes[7],
+            size: Size::new(u16_at(8), u16_at(10)),
+            frame_len: u32_at(12),
+            payload_start: u32_at(16),
+            payload_len: u32_at(20),
+            checksum: u32_at(24),
+        })
+    }
+}
+
+/// Saves the buffer to flash at `offset`, which must be aligned to the flash's erase size.
+///
+/// The header is written last, so an interrupted save is never loaded. Returns the number of
+/// bytes erased, so the next free offset is `offset` plus this.
+pub async fn save<F, B, const BITS: usize, const FRAMES: usize>(
+    flash: &mut F,
+    offset: u32,
+    buffer: &B,
+    compression: Compression,
+) -> Result<u32, StorageError<F::Error>>
+where
+    F: NorFlash,
+    B: BufferView<BITS, FRAMES> + ?Sized,
+{
+    if !(offset as usize).is_multiple_of(F::ERASE_SIZE) || !CHUNK.is_multiple_of(F::WRITE_SIZE) {
+        return Err(StorageError::Unaligned);
+    }
+    let frames = buffer.data();
+    let frame_len = frames[0].len();
+    let payload_len = match compression {
+        Compression::None => frame_len * FRAMES,
+        Compression::Rle => frames.iter().map(|f| PackBits::new(f).count()).sum(),
+    };
+    let header_space = HEADER_LEN.next_multiple_of(F::WRITE_SIZE);
+    let erase_len = (header_space + payload_len).next_multiple_of(F::ERASE_SIZE);
+    if offset as usize + erase_len > flash.capacity() {
+        return Err(StorageError::TooLarge);
+    }
+    debug!(
+        "Saving buffer to flash at {}, {} bytes of data",
+        offset, payload_len
+    );
+
+    flash
+        .erase(offset, offset + erase_len as u32)
+        .await
+        .map_err(StorageError::Flash)?;
+    let mut writer = Writer::new(flash, offset + header_space as u32);
+    let mut checksum = Checksum::new();
+    for frame in frames {
+        checksum.update(frame);
+        match compression {
+            Compression::None => {
+                for &byte in frame {
+                    writer.push(byte).await?;
+                }
+            }
+            Compression::Rle => {
+                for byte in PackBits::new(frame) {
+                    writer.push(byte).await?;
+                }
+            }
+        }
+    }
+    writer.flush().await?;
+
+    let header = Header {
+        compression,
+        bits: BITS as u8,
+        frames: FRAMES as u8,
+        size: buffer.window().size,
+        frame_len: frame_len as u32,
+        payload_start: header_space as u32,
+        payload_len: payload_len as u32,
+        checksum: checksum.finish(),
+    };
+    let mut writer = Writer::new(flash, offset);
+    for byte in header.to_bytes() {
+        writer.push(byte).await?;
+    }
+    writer.flush().await?;
+    Ok(erase_len as u32)
+}
+
+/// Loads a buffer saved at `offset` into `buffer`.
+///
+/// The saved buffer must have the same size and format as `buffer`. If loading fails part way,
+/// `buffer` may be partially overwritten.
+pub async fn load<F, B, const BITS: usize, const FRAMES: usize>(
+    flash: &mut F,
+    offset: u32,
+    buffer: &mut B,
+) -> Result<(), StorageError<F::Error>>
+where
+    F: ReadNorFlash,
+    B: StorableBuffer<BITS, FRAMES> + ?Sized,
+{
+    if !CHUNK.is_multiple_of(F::READ_SIZE) {
+        return Err(StorageError::Unaligned);
+    }
+    let mut bytes = [0; HEADER_LEN];
+    let mut reader = Reader::new(flash, offset, HEADER_LEN as u32);
+    for byte in &mut bytes {
+        *byte = reader.next().await?;
+    }
+    let header = Header::from_bytes(&bytes).ok_or(StorageError::NotFound)?;
+
+    let size = buffer.window().size;
+    let mut frames = buffer.frames_mut();
+    if header.bits as usize != BITS
+        || header.frames as usize != FRAMES
+        || header.size != size
+        || frames.iter().any(|f| f.len() != header.frame_len as usize)
+    {
+        warning!(
+            "Saved buffer doesn't match: {}x{}",
+            header.size.width,
+            header.size.height
+        );
+        return Err(StorageError::SizeMismatch);
+    }
+    debug!("Loading buffer from flash at {}", offset);
+
+    let mut reader = Reader::new(flash, offset + header.payload_start, header.payload_len);
+    let mut checksum = Checksum::new();
+    for frame in &mut frames {
+        match header.compression {
+            Compression::None => {
+                for byte in frame.iter_mut() {
+                    *byte = reader.next().await?;
+                }
+            }
+            Compression::Rle => {
+                let mut filled = 0;
+                while filled < frame.len() {
+                    let control = reader.next().await?;
+                    // PackBits: 0..=127 is followed by that many plus one literal bytes, 129..=255
+                    // by one byte that repeats 257 minus that many times, and 128 is unused.
+                    let (len, repeated) = match control {
+                        0..=127 => (control as usize + 1, false),
+                        128 => continue,
+                        _ => (257 - control as usize, true),
+                    };
+                    let dest = frame
+                        .get_mut(filled..filled + len)
+                        .ok_or(StorageError::Corrupt)?;
+                    if repeated {
+                        dest.fill(reader.next().await?);
+                    } else {
+                        for byte in dest {
+                            *byte = reader.next().await?;
+                        }
+                    }
+                    filled += len;
+                }
+            }
+        }
+        checksum.update(frame);
+    }
+    if !reader.is_done() || checksum.finish() != header.checksum {
+        return Err(StorageError::Corrupt);
+    }
+    Ok(())
+}
+
+/// Loads a buffer saved at `offset` into `buffer`, and writes it to the display's base
+/// framebuffer, so that the next partial refresh only changes what's been drawn since.
+pub async fn restore_base_framebuffer<D, SPI, ERROR, F, B, const BITS: usize, const FRAMES: usize>(
+    epd: &mut D,
+    spi: &mut SPI,
+    flash: &mut F,
+    offset: u32,
+    buffer: &mut B,
+) -> Result<(), RestoreError<F::Error, ERROR>>
+where
+    D: DisplayPartial<BITS, FRAMES, SPI, ERROR>,
+    SPI: SpiDevice,
+    F: ReadNorFlash,
+    B: StorableBuffer<BITS, FRAMES>,
+{
+    load(flash, offset, buffer)
+        .await
+        .map_err(RestoreError::Storage)?;
+    epd.write_base_framebuffer(spi, buffer)
+        .await
+        .map_err(RestoreError::Display)
+}
+
+/// Run-length encodes bytes with the PackBits scheme.
+struct PackBits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// The number of literal bytes still to yield.
+    literal: usize,
+    /// The byte of a run, still to yield after its control byte.
+    run: Option<u8>,
+}
+
+impl<'a> PackBits<'a> {
+    const MAX_LEN: usize = 128;
+
+    fn new(data: &'a [u8]) -> Self {
+        PackBits {
+            data,
+            pos: 0,
+            literal: 0,
+            run: None,
+        }
+    }
+
+    fn run_len(&self, start: usize) -> usize {
+        self.data[start..]
+            .iter()
+            .take(Self::MAX_LEN)
+            .take_while(|&&byte| byte == self.data[start])
+            .count()
+    }
+}
+
+impl Iterator for PackBits<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.literal > 0 {
+            self.literal -= 1;
+            self.pos += 1;
+            return Some(self.data[self.pos - 1]);
+        }
+        if let Some(byte) = self.run.take() {
+            return Some(byte);
+        }
+        if self.pos >= self.data.len() {
+            return None;
+        }
+
+        let run = self.run_len(self.pos);
+        if run >= 2 {
+            self.run = Some(self.data[self.pos]);
+            self.pos += run;
+            return Some((257 - run) as u8);
+        }
+        let mut end = self.pos + 1;
+        while end < self.data.len() && end - self.pos < Self::MAX_LEN && self.run_len(end) < 2 {
+            end += 1;
+        }
+        self.literal = end - self.pos;
+        Some((self.literal - 1) as u8)
+    }
+}
+
+/// FNV-1a, to catch interrupted saves and bit errors.
+struct Checksum(u32);
+
+impl Checksum {
+    fn new() -> Self {
+        Checksum(0x811c_9dc5)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = (self.0 ^ byte as u32).wrapping_mul(0x0100_0193);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.0
+    }
+}
+
+/// Writes bytes to flash in chunks, padding the last chunk to the flash's write size.
+struct Writer<'a, F> {
+    flash: &'a mut F,
+    offset: u32,
+    chunk: [u8; CHUNK],
+    len: usize,
+}
+
+impl<'a, F: NorFlash> Writer<'a, F> {
+    fn new(flash: &'a mut F, offset: u32) -> Self {
+        Writer {
+            flash,
+            offset,
+            chunk: [0; CHUNK],
+            len: 0,
+        }
+    }
+
+    async fn push(&mut self, byte: u8) -> Result<(), StorageError<F::Error>> {
+        self.chunk[self.len] = byte;
+        self.len += 1;
+        if self.len == CHUNK {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), StorageError<F::Error>> {
+        if self.len == 0 {
+            return Ok(());
+        }
+        let padded = self.len.next_multiple_of(F::WRITE_SIZE);
+        // Erased flash reads as 0xFF, so padding with it leaves the flash as erased.
+        self.chunk[self.len..padded].fill(0xFF);
+        self.flash
+            .write(self.offset, &self.chunk[..padded])
+            .await
+            .map_err(StorageError::Flash)?;
+        self.offset += padded as u32;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+/// Reads `len` bytes from flash in chunks.
+struct Reader<'a, F> {
+    flash: &'a mut F,
+    offset: u32,
+    remaining: u32,
+    chunk: [u8; CHUNK],
+    pos: usize,
+    len: usize,
+}
+
+impl<'a, F: ReadNorFlash> Reader<'a, F> {
+    fn new(flash: &'a mut F, offset: u32, len: u32) -> Self {
+        Reader {
+            flash,
+            offset,
+            remaining: len,
+            chunk: [0; CHUNK],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    async fn next(&mut self) -> Result<u8, StorageError<F::Error>> {
+        if self.pos == self.len {
+            if self.remaining == 0 {
+                return Err(StorageError::Corrupt);
+            }
+            self.len = (self.remaining as usize).min(CHUNK);
+            let read_len = self.len.next_multiple_of(F::READ_SIZE);
+            if self.offset as usize + read_len > self.flash.capacity() {
+                return Err(StorageError::TooLarge);
+            }
+            self.flash
+                .read(self.offset, &mut self.chunk[..read_len])
+                .await
+                .map_err(StorageError::Flash)?;
+            self.offset += read_len as u32;
+            self.remaining -= self.len as u32;
+            self.pos = 0;
+        }
+        self.pos += 1;
+        Ok(self.chunk[self.pos - 1])
+    }
+
+    /// Whether every byte has been read.
+    fn is_done(&self) -> bool {
+        self.remaining == 0 && self.pos == self.len
+    }
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use embedded_graphics::{
+        pixelcolor::BinaryColor,
+        prelude::Point,
+        primitives::{Primitive, PrimitiveStyle, Rectangle},
+        Drawable,
+    };
+    use embedded_storage_async::nor_flash::{ErrorType, NorFlashErrorKind};
+
+    use super::*;
+    use crate::buffer::binary_buffer_length;
+    use crate::epd2in9_v2::{self, Command, Epd2In9V2, RefreshMode};
+    use crate::testing::{block_on, Event, MockHw};
+
+    /// An in-memory flash that checks alignment, and that writes only go to erased bytes.
+    struct MemFlash(std::vec::Vec<u8>);
+
+    impl MemFlash {
+        fn new() -> Self {
+            MemFlash(std::vec![0xFF; 1024])
+        }
+    }
+
+    impl ErrorType for MemFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for MemFlash {
+        const READ_SIZE: usize = 1;
+
+        async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let start = offset as usize;
+            bytes.copy_from_slice(&self.0[start..start + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    impl NorFlash for MemFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 64;
+
+        async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            assert_eq!(from as usize % Self::ERASE_SIZE, 0);
+            assert_eq!(to as usize % Self::ERASE_SIZE, 0);
+            self.0[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+
+        async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            assert_eq!(offset as usize % Self::WRITE_SIZE, 0);
+            assert_eq!(bytes.len() % Self::WRITE_SIZE, 0);
+            let dest = &mut self.0[offset as usize..offset as usize + bytes.len()];
+            assert!(dest.iter().all(|&b| b == 0xFF), "write to unerased flash");
+            dest.copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    const SIZE: Size = Size::new(64, 32);
+    type Buffer = BinaryBuffer<{ binary_buffer_length(SIZE) }>;
+
+    fn drawn_buffer() -> Buffer {
+        let mut buffer = Buffer::new(SIZE);
+        Rectangle::new(Point::new(3, 5), Size::new(20, 9))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(&mut buffer)
+            .unwrap();
+        buffer.data_mut()[100] = 0x5A;
+        buffer
+    }
+
+    #[test]
+    fn test_round_trips_buffers() {
+        let buffer = drawn_buffer();
+        for compression in [Compression::None, Compression::Rle] {
+            let mut flash = MemFlash::new();
+            let mut loaded = Buffer::new(SIZE);
+            assert_eq!(
+                block_on(load(&mut flash, 128, &mut loaded)),
+                Err(StorageError::NotFound)
+            );
+
+            let used = block_on(save(&mut flash, 128, &buffer, compression)).unwrap();
+            block_on(load(&mut flash, 128, &mut loaded)).unwrap();
+            assert_eq!(loaded.data(), buffer.data());
+            match compression {
+                Compression::None => assert_eq!(used, 320),
+                Compression::Rle => assert_eq!(used, 128),
+            }
+
+            let mut other =
+                BinaryBuffer::<{ binary_buffer_length(Size::new(32, 64)) }>::new(Size::new(32, 64));
+            assert_eq!(
+                block_on(load(&mut flash, 128, &mut other)),
+                Err(StorageError::SizeMismatch)
+            );
+
+            flash.0[128 + 40] ^= 0x01;
+            assert_eq!(
+                block_on(load(&mut flash, 128, &mut loaded)),
+                Err(StorageError::Corrupt)
+            );
+        }
+    }
+
+    #[test]
+    fn test_restores_base_framebuffer() {
+        let mut flash = MemFlash::new();
+        let buffer = drawn_buffer();
+        block_on(save(&mut flash, 0, &buffer, Compression::Rle)).unwrap();
+
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let mut epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Partial)).unwrap();
+        log.take_events();
+
+        let mut restored = Buffer::new(SIZE);
+        block_on(restore_base_framebuffer(
+            &mut epd,
+            &mut spi,
+            &mut flash,
+            0,
+            &mut restored,
+        ))
+        .unwrap();
+        assert_eq!(restored.data(), buffer.data());
+        let events = log.take_events();
+        let base = events
+            .iter()
+            .position(|e| *e == Event::Command(Command::WriteHighRam as u8))
+            .unwrap();
+        assert_eq!(events[base + 1], Event::Data(buffer.data().to_vec()));
+    }
+}
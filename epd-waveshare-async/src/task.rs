@@ -0,0 +1,222 @@
+//! An actor that owns a display, for sharing it between embassy tasks.
+//!
+//! The typestate drivers make it awkward to pass a display between tasks, since sleeping and waking
+//! change its type. Instead, an [EpdTask] owns the display and SPI device, and runs in its own task.
+//! Other tasks draw to a shared buffer and send commands through an [EpdChannel].
+//!
+//! ```text
+//! static CHANNEL: EpdChannel<CriticalSectionRawMutex, Epd2In9BinaryBuffer, 4> =
+//!     EpdChannel::new(...);
+//!
+//! #[embassy_executor::task]
+//! async fn display_task(task: EpdTask<'static, ...>) {
+//!     let Err(e) = task.run().await;
+//!     error!("Display failed: {:?}", e);
+//! }
+//!
+//! // Elsewhere:
+//! CHANNEL.draw(|buffer| Text::new("Hello", Point::new(0, 10), style).draw(buffer)).await?;
+//! CHANNEL.display().await;
+//! ```
+use core::convert::Infallible;
+
+use embassy_sync::{blocking_mutex::raw::RawMutex, channel::Channel, mutex::Mutex};
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::{buffer::BufferView, log::debug, DisplaySimple, Sleep, Wake};
+
+/// Commands that can be sent to an [EpdTask].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EpdCommand {
+    /// Displays the current contents of the shared buffer, waking the display if necessary.
+    Display,
+    /// Puts the display to sleep.
+    Sleep,
+}
+
+/// The shared buffer and command queue for communicating with an [EpdTask].
+///
+/// `N` is the number of commands that can be queued.
+pub struct EpdChannel<M: RawMutex, B, const N: usize> {
+    buffer: Mutex<M, B>,
+    commands: Channel<M, EpdCommand, N>,
+}
+
+impl<M: RawMutex, B, const N: usize> EpdChannel<M, B, N> {
+    /// Creates a new channel, which shares the given buffer.
+    pub const fn new(buffer: B) -> Self {
+        EpdChannel {
+            buffer: Mutex::new(buffer),
+            commands: Channel::new(),
+        }
+    }
+
+    /// Gives `f` exclusive access to the buffer. This waits if the buffer is currently being
+    /// written to the display.
+    pub async fn draw<R>(&self, f: impl FnOnce(&mut B) -> R) -> R {
+        let mut buffer = self.buffer.lock().await;
+        f(&mut buffer)
+    }
+
+    /// Asks the display to show the current contents of the buffer.
+    pub async fn display(&self) {
+        self.commands.send(EpdCommand::Display).await
+    }
+
+    /// Asks the display to go to sleep.
+    pub async fn sleep(&self) {
+        self.commands.send(EpdCommand::Sleep).await
+    }
+}
+
+enum Power<D, S> {
+    Awake(D),
+    Asleep(S),
+}
+
+/// Owns a display and its SPI device, and handles the commands sent through an [EpdChannel].
+///
+/// `D` is the ready state of the display, such as `Epd2In9V2<HW, StateReady>`, and `S` is its
+/// sleeping state.
+pub struct EpdTask<'a, M: RawMutex, B, D, S, SPI, const N: usize> {
+    channel: &'a EpdChannel<M, B, N>,
+    spi: SPI,
+    power: Power<D, S>,
+    sleep_when_idle: bool,
+}
+
+impl<'a, M: RawMutex, B, D, S, SPI, const N: usize> EpdTask<'a, M, B, D, S, SPI, N> {
+    /// Creates a new task for the given initialised display.
+    pub fn new(channel: &'a EpdChannel<M, B, N>, display: D, spi: SPI) -> Self {
+        EpdTask {
+            channel,
+            spi,
+            power: Power::Awake(display),
+            sleep_when_idle: false,
+        }
+    }
+
+    /// If `true`, the display is put to sleep whenever there are no more commands queued after
+    /// displaying the buffer. It's woken again for the next [EpdCommand::Display].
+    ///
+    /// This saves power for displays that are updated infrequently. Defaults to `false`.
+    pub fn sleep_when_idle(mut self, sleep_when_idle: bool) -> Self {
+        self.sleep_when_idle = sleep_when_idle;
+        self
+    }
+
+    /// Handles commands forever. This only returns if there's an error, after which the display
+    /// is no longer usable.
+    pub async fn run<const BITS: usize, const FRAMES: usize, ERROR>(
+        self,
+    ) -> Result<Infallible, ERROR>
+    where
+        SPI: SpiDevice,
+        B: BufferView<BITS, FRAMES>,
+        D: DisplaySimple<BITS, FRAMES, SPI, ERROR> + Sleep<SPI, ERROR, DisplayOut = S>,
+        S: Wake<SPI, ERROR, DisplayOut = D>,
+    {
+        let EpdTask {
+            channel,
+            mut spi,
+            mut power,
+            sleep_when_idle,
+        } = self;
+        loop {
+            let command = channel.commands.receive().await;
+            debug!("EPD task received {:?}", command);
+            power = match (command, power) {
+                (EpdCommand::Display, power) => {
+                    let mut display = match power {
+                        Power::Awake(display) => display,
+                        Power::Asleep(display) => display.wake(&mut spi).await?,
+                    };
+                    {
+                        let buffer = channel.buffer.lock().await;
+                        display.display_framebuffer(&mut spi, &*buffer).await?;
+                    }
+                    if sleep_when_idle && channel.commands.is_empty() {
+                        Power::Asleep(display.sleep(&mut spi).await?)
+                    } else {
+                        Power::Awake(display)
+                    }
+                }
+                (EpdCommand::Sleep, Power::Awake(display)) => {
+                    Power::Asleep(display.sleep(&mut spi).await?)
+                }
+                (EpdCommand::Sleep, power @ Power::Asleep(_)) => power,
+            };
+        }
+    }
+}
+
+#[cfg(all(test, feature = "epd2in9_v2"))]
+mod tests {
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use std::future::Future;
+
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+    use super::*;
+    use crate::epd2in9_v2::{self, Command, Epd2In9BinaryBuffer, Epd2In9V2, RefreshMode};
+    use crate::testing::{block_on, MockHw};
+
+    /// Polls the future once, which is enough for it to handle all queued commands with [MockHw].
+    fn poll_once<F: Future>(future: core::pin::Pin<&mut F>) -> Poll<F::Output> {
+        future.poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn test_display_and_sleep_when_idle() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        log.take_events();
+
+        let channel: EpdChannel<NoopRawMutex, Epd2In9BinaryBuffer, 2> =
+            EpdChannel::new(epd2in9_v2::new_binary_buffer());
+        let task = EpdTask::new(&channel, epd, spi).sleep_when_idle(true);
+        let mut run = pin!(task.run());
+
+        block_on(channel.draw(|buffer| buffer.clear(BinaryColor::On))).unwrap();
+        block_on(channel.display());
+        assert!(poll_once(run.as_mut()).is_pending());
+
+        let commands = log.commands();
+        assert!(commands.contains(&(Command::WriteLowRam as u8)));
+        assert_eq!(commands.last(), Some(&(Command::DeepSleepMode as u8)));
+    }
+
+    #[test]
+    fn test_sleep_command() {
+        let hw = MockHw::new(epd2in9_v2::DEFAULT_BUSY_WHEN);
+        let log = hw.log();
+        let mut spi = hw.spi();
+        let epd = block_on(Epd2In9V2::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+        log.take_events();
+
+        let channel: EpdChannel<NoopRawMutex, Epd2In9BinaryBuffer, 2> =
+            EpdChannel::new(epd2in9_v2::new_binary_buffer());
+        let task = EpdTask::new(&channel, epd, spi);
+        let mut run = pin!(task.run());
+
+        block_on(channel.display());
+        assert!(poll_once(run.as_mut()).is_pending());
+        assert_ne!(log.commands().last(), Some(&(Command::DeepSleepMode as u8)));
+
+        block_on(channel.sleep());
+        block_on(channel.sleep());
+        assert!(poll_once(run.as_mut()).is_pending());
+        let commands = log.commands();
+        assert_eq!(commands.last(), Some(&(Command::DeepSleepMode as u8)));
+        let sleeps = commands
+            .iter()
+            .filter(|c| **c == Command::DeepSleepMode as u8)
+            .count();
+        assert_eq!(sleeps, 1);
+    }
+}
@@ -0,0 +1,697 @@
+//! Helpers for exercising the display drivers on a host machine, without any real hardware.
+//!
+//! [MockHw] implements all the `XHw` traits, and records everything sent to the "display" as a
+//! list of [Event]s. This makes it possible to unit test rendering and refresh logic, or to preview
+//! what a driver would do, from a desktop tool. [PoweredHw] adds power hooks and a power profile.
+//! [ScriptedPin] and [MockDelay::new] test busy pin wrappers on their own.
+//!
+#![cfg_attr(feature = "epd2in9", doc = "```")]
+#![cfg_attr(not(feature = "epd2in9"), doc = "```ignore")]
+//! use epd_waveshare_async::epd2in9::{self, Epd2In9, RefreshMode};
+//! use epd_waveshare_async::testing::{block_on, Event, MockHw};
+//!
+//! let hw = MockHw::new(epd2in9::DEFAULT_BUSY_WHEN);
+//! let log = hw.log();
+//! let mut spi = hw.spi();
+//! let _epd = block_on(Epd2In9::new(hw).init(&mut spi, RefreshMode::Full)).unwrap();
+//!
+//! assert!(log.events().contains(&Event::Command(0x12)));
+//! ```
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::{poll_fn, Future},
+    pin::pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    vec::Vec,
+};
+
+use core::convert::Infallible;
+
+use embedded_hal::digital::{ErrorType as PinErrorType, InputPin, OutputPin, PinState};
+use embedded_hal_async::{
+    delay::DelayNs,
+    digital::Wait,
+    spi::{ErrorType as SpiErrorType, Operation, SpiDevice},
+};
+
+use crate::{
+    buffer::{BufferView, WindowError},
+    hw::{BusyHw, DcHw, DelayHw, ErrorHw, PowerProfile, ResetHw, SpiHw},
+    idle::DisplayLost,
+};
+
+/// Something that happened on the mock hardware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A command byte was sent with the DC pin low.
+    Command(u8),
+    /// Data was sent with the DC pin high. Consecutive data writes are merged into one event.
+    Data(Vec<u8>),
+    /// The given number of bytes were read from the display.
+    Read(usize),
+    /// The reset pin was set to the given state.
+    Reset(PinState),
+    /// The driver waited for the given number of nanoseconds.
+    Delay(u32),
+}
+
+#[derive(Debug)]
+struct LogState {
+    dc: PinState,
+    events: Vec<Event>,
+    transactions: usize,
+}
+
+/// A shared handle to the events recorded by a [MockHw] and its [MockSpi].
+#[derive(Debug, Clone)]
+pub struct MockLog(Arc<Mutex<LogState>>);
+
+impl MockLog {
+    fn new() -> Self {
+        MockLog(Arc::new(Mutex::new(LogState {
+            dc: PinState::High,
+            events: Vec::new(),
+            transactions: 0,
+        })))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LogState> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, event: Event) {
+        self.lock().events.push(event);
+    }
+
+    /// Returns a copy of all the events recorded so far.
+    pub fn events(&self) -> Vec<Event> {
+        self.lock().events.clone()
+    }
+
+    /// Returns all the events recorded so far, and clears the log.
+    pub fn take_events(&self) -> Vec<Event> {
+        core::mem::take(&mut self.lock().events)
+    }
+
+    /// Returns the number of SPI transactions (i.e. chip select assertions) so far.
+    pub fn transaction_count(&self) -> usize {
+        self.lock().transactions
+    }
+
+    /// Returns just the delays that have been waited for so far, in nanoseconds.
+    pub fn delays(&self) -> Vec<u32> {
+        self.lock()
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Delay(ns) => Some(*ns),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns just the command bytes that have been sent so far.
+    pub fn commands(&self) -> Vec<u8> {
+        self.lock()
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Command(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PinRole {
+    Dc,
+    Reset,
+    Busy { busy_when: PinState },
+}
+
+/// A mock GPIO pin. Output pins record their changes in the [MockLog], and input pins always report
+/// that the display is idle.
+#[derive(Debug)]
+pub struct MockPin {
+    log: MockLog,
+    role: PinRole,
+}
+
+impl PinErrorType for MockPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for MockPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set_state(PinState::Low)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set_state(PinState::High)
+    }
+
+    fn set_state(&mut self, state: PinState) -> Result<(), Self::Error> {
+        match self.role {
+            PinRole::Dc => self.log.lock().dc = state,
+            PinRole::Reset => self.log.push(Event::Reset(state)),
+            PinRole::Busy { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+impl InputPin for MockPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        match self.role {
+            PinRole::Busy { busy_when } => Ok(busy_when == PinState::Low),
+            _ => Ok(false),
+        }
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.is_high()?)
+    }
+}
+
+impl Wait for MockPin {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A mock SPI device that records writes as [Event::Command] or [Event::Data], depending on the
+/// state of the DC pin. Reads are recorded as [Event::Read], and filled with zeros.
+#[derive(Debug)]
+pub struct MockSpi {
+    log: MockLog,
+}
+
+impl SpiErrorType for MockSpi {
+    type Error = Infallible;
+}
+
+impl SpiDevice for MockSpi {
+    async fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> Result<(), Self::Error> {
+        let mut state = self.log.lock();
+        state.transactions += 1;
+        for op in operations {
+            match op {
+                Operation::Write(data) => match state.dc {
+                    PinState::Low => {
+                        let commands = data.iter().map(|c| Event::Command(*c));
+                        state.events.extend(commands);
+                    }
+                    PinState::High => {
+                        if let Some(Event::Data(previous)) = state.events.last_mut() {
+                            previous.extend_from_slice(data);
+                        } else {
+                            state.events.push(Event::Data(data.to_vec()));
+                        }
+                    }
+                },
+                Operation::Read(buf) => {
+                    buf.fill(0);
+                    state.events.push(Event::Read(buf.len()));
+                }
+                Operation::Transfer(read, _) => read.fill(0),
+                Operation::TransferInPlace(buf) => buf.fill(0),
+                Operation::DelayNs(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An input pin that reads the given levels in turn (`true` for high), for testing busy pin
+/// wrappers such as [crate::hw::DebouncedBusy]. Once the levels run out, it keeps reading the last
+/// one.
+///
+/// Waiting for a level returns immediately, and is counted in [ScriptedPin::waits].
+#[derive(Debug)]
+pub struct ScriptedPin {
+    levels: VecDeque<bool>,
+    last: bool,
+    waits: usize,
+}
+
+impl ScriptedPin {
+    /// Creates a pin that reads `levels` in turn, or low if there are none.
+    pub fn new(levels: impl IntoIterator<Item = bool>) -> Self {
+        ScriptedPin {
+            levels: levels.into_iter().collect(),
+            last: false,
+            waits: 0,
+        }
+    }
+
+    /// Returns how many times the pin was waited on for a level.
+    pub fn waits(&self) -> usize {
+        self.waits
+    }
+
+    /// Returns how many scripted levels haven't been read yet.
+    pub fn remaining(&self) -> usize {
+        self.levels.len()
+    }
+}
+
+impl PinErrorType for ScriptedPin {
+    type Error = Infallible;
+}
+
+impl InputPin for ScriptedPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        if let Some(level) = self.levels.pop_front() {
+            self.last = level;
+        }
+        Ok(self.last)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.is_high()?)
+    }
+}
+
+impl Wait for ScriptedPin {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.waits += 1;
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.waits += 1;
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A mock delay that returns immediately, but records how long it was asked to wait.
+#[derive(Debug)]
+pub struct MockDelay {
+    log: MockLog,
+    yielding: bool,
+}
+
+impl MockDelay {
+    /// Creates a delay with its own log, for testing code that only needs a delay.
+    pub fn new() -> Self {
+        MockDelay {
+            log: MockLog::new(),
+            yielding: false,
+        }
+    }
+
+    /// Makes each delay return [Poll::Pending] once before finishing, like a real timer, so that
+    /// code that would otherwise wait forever can be polled a step at a time.
+    pub fn yielding(self) -> Self {
+        MockDelay {
+            yielding: true,
+            ..self
+        }
+    }
+
+    /// Returns a handle to the log that this delay records into.
+    pub fn log(&self) -> MockLog {
+        self.log.clone()
+    }
+}
+
+impl Default for MockDelay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DelayNs for MockDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.log.push(Event::Delay(ns));
+        let mut pending = self.yielding;
+        poll_fn(|cx| {
+            if !pending {
+                return Poll::Ready(());
+            }
+            pending = false;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// The error type of [MockHw]. The mock hardware never fails, but the drivers still return errors
+/// for requests they can't carry out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockError {
+    /// A buffer's window can't be written to the display.
+    Window(WindowError),
+    /// A wrapper such as [crate::idle::AutoSleep] lost the display.
+    Lost(DisplayLost),
+}
+
+impl From<Infallible> for MockError {
+    fn from(error: Infallible) -> Self {
+        match error {}
+    }
+}
+
+impl From<WindowError> for MockError {
+    fn from(error: WindowError) -> Self {
+        MockError::Window(error)
+    }
+}
+
+impl From<DisplayLost> for MockError {
+    fn from(error: DisplayLost) -> Self {
+        MockError::Lost(error)
+    }
+}
+
+/// Mock hardware that implements all the `XHw` traits.
+///
+/// Use [MockHw::spi] to get the matching SPI device, and [MockHw::log] to inspect what was sent.
+#[derive(Debug)]
+pub struct MockHw {
+    log: MockLog,
+    dc: MockPin,
+    reset: MockPin,
+    busy: MockPin,
+    delay: MockDelay,
+}
+
+impl MockHw {
+    /// Creates new mock hardware. `busy_when` should match what the driver expects, so that the
+    /// mock busy pin always reports that the display is idle.
+    pub fn new(busy_when: PinState) -> Self {
+        let log = MockLog::new();
+        let pin = |role| MockPin {
+            log: log.clone(),
+            role,
+        };
+        MockHw {
+            dc: pin(PinRole::Dc),
+            reset: pin(PinRole::Reset),
+            busy: pin(PinRole::Busy { busy_when }),
+            delay: MockDelay {
+                log: log.clone(),
+                yielding: false,
+            },
+            log,
+        }
+    }
+
+    /// Returns a new SPI device that records into this hardware's log.
+    pub fn spi(&self) -> MockSpi {
+        MockSpi {
+            log: self.log.clone(),
+        }
+    }
+
+    /// Returns a handle to the events recorded by this hardware.
+    pub fn log(&self) -> MockLog {
+        self.log.clone()
+    }
+}
+
+impl ErrorHw for MockHw {
+    type Error = MockError;
+}
+
+impl SpiHw for MockHw {
+    type Spi = MockSpi;
+}
+
+impl DcHw for MockHw {
+    type Dc = MockPin;
+
+    fn dc(&mut self) -> &mut Self::Dc {
+        &mut self.dc
+    }
+}
+
+impl ResetHw for MockHw {
+    type Reset = MockPin;
+
+    fn reset(&mut self) -> &mut Self::Reset {
+        &mut self.reset
+    }
+}
+
+impl BusyHw for MockHw {
+    type Busy = MockPin;
+
+    fn busy(&mut self) -> &mut Self::Busy {
+        &mut self.busy
+    }
+
+    fn busy_when(&self) -> PinState {
+        match self.busy.role {
+            PinRole::Busy { busy_when } => busy_when,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl DelayHw for MockHw {
+    type Delay = MockDelay;
+
+    fn delay(&mut self) -> &mut Self::Delay {
+        &mut self.delay
+    }
+}
+
+/// Wraps [MockHw] with [ResetHw::power_on] and [ResetHw::power_off] hooks that record when they
+/// ran, and a configurable [SpiHw::power_profile].
+#[derive(Debug)]
+pub struct PoweredHw {
+    hw: MockHw,
+    power_ons: Vec<usize>,
+    power_offs: Vec<usize>,
+    power_profile: PowerProfile,
+}
+
+impl PoweredHw {
+    /// Wraps `hw`, with the [PowerProfile::Standard] power profile.
+    pub fn new(hw: MockHw) -> Self {
+        PoweredHw {
+            hw,
+            power_ons: Vec::new(),
+            power_offs: Vec::new(),
+            power_profile: PowerProfile::Standard,
+        }
+    }
+
+    /// Sets the power profile that drivers are given.
+    pub fn with_power_profile(mut self, power_profile: PowerProfile) -> Self {
+        self.power_profile = power_profile;
+        self
+    }
+
+    /// Returns a new SPI device that records into this hardware's log.
+    pub fn spi(&self) -> MockSpi {
+        self.hw.spi()
+    }
+
+    /// Returns a handle to the events recorded by this hardware.
+    pub fn log(&self) -> MockLog {
+        self.hw.log()
+    }
+
+    /// Returns the number of events that had been logged each time the panel was powered on.
+    pub fn power_ons(&self) -> &[usize] {
+        &self.power_ons
+    }
+
+    /// Returns the number of events that had been logged each time the panel was powered off.
+    pub fn power_offs(&self) -> &[usize] {
+        &self.power_offs
+    }
+}
+
+impl ErrorHw for PoweredHw {
+    type Error = MockError;
+}
+
+impl SpiHw for PoweredHw {
+    type Spi = MockSpi;
+
+    fn power_profile(&self) -> PowerProfile {
+        self.power_profile
+    }
+}
+
+impl DcHw for PoweredHw {
+    type Dc = MockPin;
+
+    fn dc(&mut self) -> &mut Self::Dc {
+        self.hw.dc()
+    }
+}
+
+impl ResetHw for PoweredHw {
+    type Reset = MockPin;
+
+    fn reset(&mut self) -> &mut Self::Reset {
+        self.hw.reset()
+    }
+
+    async fn power_on(&mut self) -> Result<(), Self::Error> {
+        self.power_ons.push(self.hw.log.lock().events.len());
+        Ok(())
+    }
+
+    async fn power_off(&mut self) -> Result<(), Self::Error> {
+        self.power_offs.push(self.hw.log.lock().events.len());
+        Ok(())
+    }
+}
+
+impl BusyHw for PoweredHw {
+    type Busy = MockPin;
+
+    fn busy(&mut self) -> &mut Self::Busy {
+        self.hw.busy()
+    }
+
+    fn busy_when(&self) -> PinState {
+        self.hw.busy_when()
+    }
+}
+
+impl DelayHw for PoweredHw {
+    type Delay = MockDelay;
+
+    fn delay(&mut self) -> &mut Self::Delay {
+        self.hw.delay()
+    }
+}
+
+/// Runs a future to completion on the current thread.
+///
+/// This is intended for use with [MockHw], which never actually has to wait. It busy-polls the
+/// future, so a future that waits on something else (such as a real timer) will spin until it's
+/// ready.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+/// Renders a buffer as rows of characters, so that failed buffer assertions and host-side debug
+/// output can be read at a glance.
+///
+/// Set pixels are shown as `#`, and clear pixels as `.`. For 2-bit gray buffers, the levels 0 to 3
+/// are shown as `.`, `-`, `+` and `#`. Two [AsciiArt]s are equal if their windows and data are, so
+/// they can be compared with [assert_eq].
+///
+/// ```
+/// use embedded_graphics::prelude::Size;
+/// use epd_waveshare_async::{buffer::Bitmap, testing::AsciiArt};
+///
+/// let arrow = Bitmap::new(&[0b0110_0000, 0b1111_0000], Size::new(4, 2)).unwrap();
+/// assert_eq!(format!("{}", AsciiArt(&arrow)), ".##.\n####\n");
+/// ```
+pub struct AsciiArt<'a, const FRAMES: usize>(pub &'a dyn BufferView<1, FRAMES>);
+
+impl<const FRAMES: usize> fmt::Display for AsciiArt<'_, FRAMES> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let levels: &[char] = if FRAMES == 1 {
+            &['.', '#']
+        } else {
+            &['.', '-', '+', '#']
+        };
+        let size = self.0.window().size;
+        let frames = self.0.data();
+        let bytes_per_row = (size.width as usize).div_ceil(8);
+        for y in 0..size.height as usize {
+            for x in 0..size.width as usize {
+                let index = y * bytes_per_row + x / 8;
+                let level = frames.iter().enumerate().fold(0, |level, (i, frame)| {
+                    let set = frame
+                        .get(index)
+                        .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0);
+                    level | (usize::from(set) << i)
+                });
+                write!(f, "{}", levels[level.min(levels.len() - 1)])?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<const FRAMES: usize> fmt::Debug for AsciiArt<'_, FRAMES> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Start on a new line, so that the rows line up in assertion messages.
+        writeln!(f)?;
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl<const FRAMES: usize> PartialEq for AsciiArt<'_, FRAMES> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.window() == other.0.window() && self.0.data() == other.0.data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{
+        pixelcolor::Gray2,
+        prelude::{DrawTarget, Point, Size},
+        primitives::Rectangle,
+    };
+
+    use super::*;
+    use crate::buffer::{gray2_split_buffer_length, Gray2SplitBuffer};
+
+    #[test]
+    fn test_ascii_art_shows_gray_levels() {
+        const SIZE: Size = Size::new(8, 2);
+        let mut buffer = Gray2SplitBuffer::<{ gray2_split_buffer_length(SIZE) }>::new(SIZE);
+        for level in 1..4 {
+            let area = Rectangle::new(Point::new(level * 2, 1), Size::new(2, 1));
+            buffer.fill_solid(&area, Gray2::new(level as u8)).unwrap();
+        }
+        assert_eq!(
+            std::format!("{:?}", AsciiArt(&buffer)),
+            "\n........\n..--++##\n"
+        );
+    }
+}
@@ -0,0 +1,526 @@
+//! Drivers for the touch controllers on Waveshare's Touch e-Paper HATs: the GT1151 on the 2.13"
+//! HAT, and the ICNT86 on the 2.9" HAT.
+//!
+//! As with the displays, implement the `XHw` traits for your hardware: [IntHw] from this module,
+//! and [I2cHw], [ResetHw], [DelayHw] and [ErrorHw] from [crate::hw]. Both controllers report
+//! up to [MAX_TOUCHES] points through the same [TouchController] trait, and a [TouchTracker] turns
+//! the reported points into down, move and up [TouchEvent]s.
+//!
+//! ```text
+//! let mut touch = Gt1151::new(touch_hw);
+//! touch.init(&mut i2c).await?;
+//! let mut tracker = TouchTracker::new();
+//!
+//! loop {
+//!     let touches = touch.wait_for_touches(&mut i2c).await?;
+//!     for event in tracker.update(&touches) {
+//!         if let TouchEvent::Up(point) = event {
+//!             handle_tap(point.position);
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Positions are in the touch panel's own coordinates, which may be rotated or mirrored relative
+//! to the display, depending on how it's mounted.
+
+// Drivers run on-device, so they must not panic.
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
+
+use embedded_graphics::prelude::Point;
+use embedded_hal::digital::{ErrorType as PinErrorType, InputPin, OutputPin};
+use embedded_hal_async::{
+    delay::DelayNs,
+    digital::Wait,
+    i2c::{ErrorType as I2cErrorType, I2c},
+};
+use heapless::Vec;
+
+use crate::{
+    hw::{DelayHw, ErrorHw, ErrorSource, ResetHw, WithSource},
+    log::{debug, trace},
+};
+
+/// The most touch points that either controller reports at once.
+pub const MAX_TOUCHES: usize = 5;
+
+pub use crate::hw::I2cHw;
+
+/// Provides access to the touch controller's interrupt pin, which it pulls low when it has new
+/// touch data.
+pub trait IntHw {
+    type Int: InputPin + Wait;
+
+    fn int(&mut self) -> &mut Self::Int;
+}
+
+/// A single point of contact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TouchPoint {
+    /// Identifies the same finger across reads, while it stays down.
+    pub id: u8,
+    /// The position, in the touch panel's coordinates.
+    pub position: Point,
+    /// The contact size or pressure, as reported by the controller.
+    pub size: u16,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for TouchPoint {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "TouchPoint {{ id: {}, position: ({}, {}), size: {} }}",
+            self.id,
+            self.position.x,
+            self.position.y,
+            self.size
+        )
+    }
+}
+
+/// The points of contact from one read of a touch controller.
+pub type Touches = Vec<TouchPoint, MAX_TOUCHES>;
+
+/// Touch controllers that can report the current points of contact.
+pub trait TouchController<I2C: I2c, ERROR> {
+    /// Reads the current points of contact, if the controller has new data. Returns no points
+    /// when nothing is touching the panel, or nothing has changed since the last read.
+    async fn read_touches(&mut self, i2c: &mut I2C) -> Result<Touches, ERROR>;
+
+    /// Waits for the controller to signal new data on its interrupt pin, then reads it.
+    async fn wait_for_touches(&mut self, i2c: &mut I2C) -> Result<Touches, ERROR>;
+}
+
+/// A change to a point of contact, from [TouchTracker::update].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchEvent {
+    /// A new finger touched the panel.
+    Down(TouchPoint),
+    /// A finger that was already down moved.
+    Move(TouchPoint),
+    /// A finger was lifted. The point is where it was last seen.
+    Up(TouchPoint),
+}
+
+/// Turns the points from successive reads into [TouchEvent]s, by matching up their ids.
+#[derive(Debug, Clone, Default)]
+pub struct TouchTracker {
+    down: Touches,
+}
+
+impl TouchTracker {
+    pub const fn new() -> Self {
+        TouchTracker { down: Vec::new() }
+    }
+
+    /// Returns the points that are currently down.
+    pub fn touches(&self) -> &[TouchPoint] {
+        &self.down
+    }
+
+    /// Compares `touches` with the previous points, and returns what changed: first the lifted
+    /// fingers, then the moved ones, then the new ones.
+    pub fn update(&mut self, touches: &[TouchPoint]) -> Vec<TouchEvent, { 2 * MAX_TOUCHES }> {
+        let mut events = Vec::new();
+        let find = |points: &[TouchPoint], id| points.iter().find(|p| p.id == id).copied();
+        for previous in &self.down {
+            if find(touches, previous.id).is_none() {
+                let _ = events.push(TouchEvent::Up(*previous));
+            }
+        }
+        for touch in touches.iter().take(MAX_TOUCHES) {
+            match find(&self.down, touch.id) {
+                Some(previous) if previous.position != touch.position => {
+                    let _ = events.push(TouchEvent::Move(*touch));
+                }
+                Some(_) => {}
+                None => {
+                    let _ = events.push(TouchEvent::Down(*touch));
+                }
+            }
+        }
+        self.down = touches.iter().take(MAX_TOUCHES).copied().collect();
+        events
+    }
+}
+
+/// The GT1151 touch controller, used by the 2.13" Touch e-Paper HAT.
+///
+/// HW should implement [I2cHw], [IntHw], [ResetHw], [DelayHw] and [ErrorHw].
+pub struct Gt1151<HW> {
+    hw: HW,
+}
+
+/// The GT1151's I2C address.
+pub const GT1151_ADDRESS: u8 = 0x14;
+
+const GT1151_PRODUCT_ID: u16 = 0x8140;
+const GT1151_STATUS: u16 = 0x814E;
+const GT1151_POINTS: u16 = 0x814F;
+const GT1151_POINT_LEN: usize = 8;
+
+impl<HW> Gt1151<HW>
+where
+    HW: I2cHw + IntHw + ResetHw + DelayHw + ErrorHw,
+    HW::Error: From<<HW::I2c as I2cErrorType>::Error>
+        + From<<HW::Int as PinErrorType>::Error>
+        + From<<HW::Reset as PinErrorType>::Error>,
+{
+    pub fn new(hw: HW) -> Self {
+        Gt1151 { hw }
+    }
+
+    /// Resets the controller, and returns its 4 byte product id (e.g. `b"1158"`).
+    pub async fn init(&mut self, i2c: &mut HW::I2c) -> Result<[u8; 4], HW::Error> {
+        debug!("Initialising GT1151");
+        reset_touch(&mut self.hw).await?;
+        let mut id = [0; 4];
+        read_register::<HW>(i2c, GT1151_ADDRESS, GT1151_PRODUCT_ID, &mut id).await?;
+        Ok(id)
+    }
+}
+
+impl<HW> TouchController<HW::I2c, HW::Error> for Gt1151<HW>
+where
+    HW: I2cHw + IntHw + ResetHw + DelayHw + ErrorHw,
+    HW::Error: From<<HW::I2c as I2cErrorType>::Error>
+        + From<<HW::Int as PinErrorType>::Error>
+        + From<<HW::Reset as PinErrorType>::Error>,
+{
+    async fn read_touches(&mut self, i2c: &mut HW::I2c) -> Result<Touches, HW::Error> {
+        let mut status = [0];
+        read_register::<HW>(i2c, GT1151_ADDRESS, GT1151_STATUS, &mut status).await?;
+        let mut touches = Vec::new();
+        // The top bit is set when there's new data, and the low bits are the number of points.
+        let count = (status[0] & 0x0F) as usize;
+        if status[0] & 0x80 != 0 && count <= MAX_TOUCHES {
+            let mut data = [0; MAX_TOUCHES * GT1151_POINT_LEN];
+            let data = &mut data[..count * GT1151_POINT_LEN];
+            read_register::<HW>(i2c, GT1151_ADDRESS, GT1151_POINTS, data).await?;
+            touches = data
+                .chunks_exact(GT1151_POINT_LEN)
+                .map(|point| TouchPoint {
+                    id: point[0],
+                    position: Point::new(
+                        i32::from(u16::from_le_bytes([point[1], point[2]])),
+                        i32::from(u16::from_le_bytes([point[3], point[4]])),
+                    ),
+                    size: u16::from_le_bytes([point[5], point[6]]),
+                })
+                .collect();
+        }
+        // Acknowledge the data, so that the controller reports the next touch.
+        write_register::<HW>(i2c, GT1151_ADDRESS, GT1151_STATUS, 0).await?;
+        trace!("GT1151 read {} touches", touches.len());
+        Ok(touches)
+    }
+
+    async fn wait_for_touches(&mut self, i2c: &mut HW::I2c) -> Result<Touches, HW::Error> {
+        wait_for_interrupt(&mut self.hw).await?;
+        self.read_touches(i2c).await
+    }
+}
+
+/// The ICNT86 touch controller, used by the 2.9" Touch e-Paper HAT.
+///
+/// HW should implement [I2cHw], [IntHw], [ResetHw], [DelayHw] and [ErrorHw].
+pub struct Icnt86<HW> {
+    hw: HW,
+}
+
+/// The ICNT86's I2C address.
+pub const ICNT86_ADDRESS: u8 = 0x48;
+
+const ICNT86_VERSION: u16 = 0x000A;
+const ICNT86_COUNT: u16 = 0x1001;
+const ICNT86_POINTS: u16 = 0x1002;
+const ICNT86_POINT_LEN: usize = 7;
+
+impl<HW> Icnt86<HW>
+where
+    HW: I2cHw + IntHw + ResetHw + DelayHw + ErrorHw,
+    HW::Error: From<<HW::I2c as I2cErrorType>::Error>
+        + From<<HW::Int as PinErrorType>::Error>
+        + From<<HW::Reset as PinErrorType>::Error>,
+{
+    pub fn new(hw: HW) -> Self {
+        Icnt86 { hw }
+    }
+
+    /// Resets the controller, and returns its 4 byte firmware version.
+    pub async fn init(&mut self, i2c: &mut HW::I2c) -> Result<[u8; 4], HW::Error> {
+        debug!("Initialising ICNT86");
+        reset_touch(&mut self.hw).await?;
+        let mut version = [0; 4];
+        read_register::<HW>(i2c, ICNT86_ADDRESS, ICNT86_VERSION, &mut version).await?;
+        Ok(version)
+    }
+}
+
+impl<HW> TouchController<HW::I2c, HW::Error> for Icnt86<HW>
+where
+    HW: I2cHw + IntHw + ResetHw + DelayHw + ErrorHw,
+    HW::Error: From<<HW::I2c as I2cErrorType>::Error>
+        + From<<HW::Int as PinErrorType>::Error>
+        + From<<HW::Reset as PinErrorType>::Error>,
+{
+    async fn read_touches(&mut self, i2c: &mut HW::I2c) -> Result<Touches, HW::Error> {
+        let mut count = [0];
+        read_register::<HW>(i2c, ICNT86_ADDRESS, ICNT86_COUNT, &mut count).await?;
+        let mut touches = Vec::new();
+        let count = count[0] as usize;
+        if count <= MAX_TOUCHES {
+            let mut data = [0; MAX_TOUCHES * ICNT86_POINT_LEN];
+            let data = &mut data[..count * ICNT86_POINT_LEN];
+            read_register::<HW>(i2c, ICNT86_ADDRESS, ICNT86_POINTS, data).await?;
+            touches = data
+                .chunks_exact(ICNT86_POINT_LEN)
+                .map(|point| TouchPoint {
+                    id: point[0],
+                    position: Point::new(
+                        i32::from(u16::from_le_bytes([point[1], point[2]])),
+                        i32::from(u16::from_le_bytes([point[3], point[4]])),
+                    ),
+                    size: u16::from(point[5]),
+                })
+                .collect();
+        }
+        // Acknowledge the data, so that the controller reports the next touch.
+        write_register::<HW>(i2c, ICNT86_ADDRESS, ICNT86_COUNT, 0).await?;
+        trace!("ICNT86 read {} touches", touches.len());
+        Ok(touches)
+    }
+
+    async fn wait_for_touches(&mut self, i2c: &mut HW::I2c) -> Result<Touches, HW::Error> {
+        wait_for_interrupt(&mut self.hw).await?;
+        self.read_touches(i2c).await
+    }
+}
+
+async fn reset_touch<HW>(hw: &mut HW) -> Result<(), HW::Error>
+where
+    HW: ResetHw + DelayHw + ErrorHw,
+    HW::Error: From<<HW::Reset as PinErrorType>::Error>,
+{
+    hw.reset()
+        .set_high()
+        .with_source::<HW>(ErrorSource::Reset)?;
+    hw.delay().delay_ms(100).await;
+    hw.reset().set_low().with_source::<HW>(ErrorSource::Reset)?;
+    hw.delay().delay_ms(100).await;
+    hw.reset()
+        .set_high()
+        .with_source::<HW>(ErrorSource::Reset)?;
+    hw.delay().delay_ms(100).await;
+    Ok(())
+}
+
+async fn wait_for_interrupt<HW>(hw: &mut HW) -> Result<(), HW::Error>
+where
+    HW: IntHw + ErrorHw,
+    HW::Error: From<<HW::Int as PinErrorType>::Error>,
+{
+    hw.int()
+        .wait_for_falling_edge()
+        .await
+        .with_source::<HW>(ErrorSource::Int)
+}
+
+/// Reads `buf` from the 16-bit `register`.
+async fn read_register<HW>(
+    i2c: &mut HW::I2c,
+    address: u8,
+    register: u16,
+    buf: &mut [u8],
+) -> Result<(), HW::Error>
+where
+    HW: I2cHw + ErrorHw,
+    HW::Error: From<<HW::I2c as I2cErrorType>::Error>,
+{
+    if buf.is_empty() {
+        return Ok(());
+    }
+    i2c.write_read(address, &register.to_be_bytes(), buf)
+        .await
+        .with_source::<HW>(ErrorSource::I2c)
+}
+
+/// Writes `value` to the 16-bit `register`.
+async fn write_register<HW>(
+    i2c: &mut HW::I2c,
+    address: u8,
+    register: u16,
+    value: u8,
+) -> Result<(), HW::Error>
+where
+    HW: I2cHw + ErrorHw,
+    HW::Error: From<<HW::I2c as I2cErrorType>::Error>,
+{
+    let [high, low] = register.to_be_bytes();
+    i2c.write(address, &[high, low, value])
+        .await
+        .with_source::<HW>(ErrorSource::I2c)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+    use std::{collections::BTreeMap, vec::Vec as StdVec};
+
+    use embedded_hal_async::i2c::{ErrorType, Operation};
+
+    use super::*;
+    use crate::testing::{block_on, MockHw};
+
+    /// An I2C device with a flat map of 16-bit registers, which records every register write.
+    #[derive(Default)]
+    pub struct MockI2c {
+        registers: BTreeMap<u16, u8>,
+        writes: StdVec<(u8, u16, u8)>,
+    }
+
+    impl ErrorType for MockI2c {
+        type Error = Infallible;
+    }
+
+    impl I2c for MockI2c {
+        async fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            let mut register = 0;
+            for op in operations {
+                match op {
+                    Operation::Write(data) => {
+                        register = u16::from_be_bytes([data[0], data[1]]);
+                        for (i, value) in data[2..].iter().enumerate() {
+                            self.writes.push((address, register + i as u16, *value));
+                        }
+                    }
+                    Operation::Read(buf) => {
+                        for (i, byte) in buf.iter_mut().enumerate() {
+                            *byte = self
+                                .registers
+                                .get(&(register + i as u16))
+                                .copied()
+                                .unwrap_or(0);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl I2cHw for MockHw {
+        type I2c = MockI2c;
+    }
+
+    impl IntHw for MockHw {
+        type Int = <MockHw as crate::hw::BusyHw>::Busy;
+
+        fn int(&mut self) -> &mut Self::Int {
+            crate::hw::BusyHw::busy(self)
+        }
+    }
+
+    fn hw() -> MockHw {
+        MockHw::new(embedded_hal::digital::PinState::High)
+    }
+
+    #[test]
+    fn test_gt1151_reads_and_acknowledges_points() {
+        let mut i2c = MockI2c::default();
+        let mut touch = Gt1151::new(hw());
+        // Two points are ready.
+        i2c.registers.insert(GT1151_STATUS, 0x82);
+        let points = [
+            [1, 0x10, 0x00, 0x20, 0x01, 0x05, 0x00, 0],
+            [2, 0x30, 0x00, 0x40, 0x00, 0x06, 0x00, 0],
+        ];
+        for (i, byte) in points.iter().flatten().enumerate() {
+            i2c.registers.insert(GT1151_POINTS + i as u16, *byte);
+        }
+
+        let touches = block_on(touch.read_touches(&mut i2c)).unwrap();
+        assert_eq!(
+            touches,
+            [
+                TouchPoint {
+                    id: 1,
+                    position: Point::new(0x10, 0x120),
+                    size: 5
+                },
+                TouchPoint {
+                    id: 2,
+                    position: Point::new(0x30, 0x40),
+                    size: 6
+                },
+            ]
+        );
+        assert_eq!(i2c.writes, [(GT1151_ADDRESS, GT1151_STATUS, 0)]);
+
+        // No new data.
+        i2c.registers.insert(GT1151_STATUS, 0x02);
+        assert!(block_on(touch.wait_for_touches(&mut i2c))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_icnt86_reads_points() {
+        let mut i2c = MockI2c::default();
+        let mut touch = Icnt86::new(hw());
+        i2c.registers.insert(ICNT86_COUNT, 1);
+        for (i, byte) in [3, 0x01, 0x01, 0x02, 0x00, 0x09, 0].iter().enumerate() {
+            i2c.registers.insert(ICNT86_POINTS + i as u16, *byte);
+        }
+
+        let touches = block_on(touch.read_touches(&mut i2c)).unwrap();
+        assert_eq!(
+            touches,
+            [TouchPoint {
+                id: 3,
+                position: Point::new(0x101, 0x02),
+                size: 9
+            }]
+        );
+        assert_eq!(i2c.writes, [(ICNT86_ADDRESS, ICNT86_COUNT, 0)]);
+    }
+
+    #[test]
+    fn test_tracker_reports_down_move_and_up() {
+        let point = |id, x| TouchPoint {
+            id,
+            position: Point::new(x, 0),
+            size: 0,
+        };
+        let mut tracker = TouchTracker::new();
+        assert_eq!(
+            tracker.update(&[point(1, 0)]),
+            [TouchEvent::Down(point(1, 0))]
+        );
+        assert_eq!(tracker.update(&[point(1, 0)]), []);
+        assert_eq!(
+            tracker.update(&[point(1, 5), point(2, 9)]),
+            [TouchEvent::Move(point(1, 5)), TouchEvent::Down(point(2, 9))]
+        );
+        assert_eq!(
+            tracker.update(&[point(2, 9)]),
+            [TouchEvent::Up(point(1, 5))]
+        );
+        assert_eq!(tracker.touches(), [point(2, 9)]);
+    }
+}
@@ -0,0 +1,1185 @@
+//! Common e-paper UI elements, which draw into any [DrawTarget] (such as the [crate::buffer]s) and
+//! report the area they changed.
+//!
+//! Each [Widget] clears its own bounds before drawing, so redrawing a widget with a new value only
+//! changes its bounds. Collect the changed areas in a [DirtyRect] to find what needs refreshing.
+//!
+//! ```
+//! use embedded_graphics::{
+//!     mono_font::ascii::FONT_6X10,
+//!     pixelcolor::BinaryColor,
+//!     prelude::{Point, Size},
+//!     primitives::Rectangle,
+//! };
+//! use epd_waveshare_async::{
+//!     epd2in9_v2,
+//!     widgets::{DirtyRect, ProgressBar, Readout, Widget, WidgetStyle},
+//! };
+//!
+//! // On e-paper, BinaryColor::On is white.
+//! let style = WidgetStyle::new(&FONT_6X10, BinaryColor::Off, BinaryColor::On);
+//! let mut buffer = epd2in9_v2::new_binary_buffer();
+//! let mut dirty = DirtyRect::new();
+//!
+//! let temperature = Readout::new(Rectangle::new(Point::zero(), Size::new(64, 16)), 21.5, 1, "C");
+//! dirty.add(temperature.draw(&mut buffer, &style).unwrap());
+//! let progress = ProgressBar::new(Rectangle::new(Point::new(0, 16), Size::new(64, 8)), 3, 10);
+//! dirty.add(progress.draw(&mut buffer, &style).unwrap());
+//!
+//! assert_eq!(dirty.take(), Some(Rectangle::new(Point::zero(), Size::new(64, 24))));
+//! ```
+use core::fmt::Write as _;
+
+use embedded_graphics::{
+    draw_target::{DrawTarget, DrawTargetExt},
+    mono_font::{MonoFont, MonoTextStyle},
+    prelude::{PixelColor, Point, Size},
+    primitives::{Primitive, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable,
+};
+
+use crate::{
+    icons::Icon,
+    layout::{align, columns, Align, Length},
+    log::warning,
+};
+
+/// The font and colors that widgets are drawn with.
+#[derive(Debug, Clone, Copy)]
+pub struct WidgetStyle<'a, C> {
+    pub font: &'a MonoFont<'a>,
+    pub foreground: C,
+    pub background: C,
+}
+
+impl<'a, C> WidgetStyle<'a, C> {
+    pub const fn new(font: &'a MonoFont<'a>, foreground: C, background: C) -> Self {
+        WidgetStyle {
+            font,
+            foreground,
+            background,
+        }
+    }
+}
+
+/// A UI element that occupies a fixed area.
+pub trait Widget<C: PixelColor> {
+    /// The area that this widget draws to.
+    fn bounds(&self) -> Rectangle;
+
+    /// Clears this widget's bounds and draws it, clipped to its bounds.
+    ///
+    /// Returns the area that changed, i.e. [Widget::bounds].
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error>;
+}
+
+/// Accumulates the smallest rectangle that covers all the areas changed since it was last taken.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect(Option<Rectangle>);
+
+impl DirtyRect {
+    pub const fn new() -> Self {
+        DirtyRect(None)
+    }
+
+    /// Marks `area` as changed. Empty areas are ignored.
+    pub fn add(&mut self, area: Rectangle) {
+        let Some(bottom_right) = area.bottom_right() else {
+            return;
+        };
+        self.0 = Some(match self.0.and_then(|d| Some((d, d.bottom_right()?))) {
+            Some((dirty, dirty_bottom_right)) => Rectangle::with_corners(
+                dirty.top_left.component_min(area.top_left),
+                dirty_bottom_right.component_max(bottom_right),
+            ),
+            None => area,
+        });
+    }
+
+    /// Returns the changed area, if any.
+    pub fn get(&self) -> Option<Rectangle> {
+        self.0
+    }
+
+    /// Returns the changed area, if any, and resets this to be clean.
+    pub fn take(&mut self) -> Option<Rectangle> {
+        self.0.take()
+    }
+}
+
+/// A single line of text.
+#[derive(Debug, Clone, Copy)]
+pub struct Label<'a> {
+    area: Rectangle,
+    text: &'a str,
+    alignment: Alignment,
+}
+
+impl<'a> Label<'a> {
+    /// Creates a left-aligned label, vertically centered in `area`.
+    pub fn new(area: Rectangle, text: &'a str) -> Self {
+        Label {
+            area,
+            text,
+            alignment: Alignment::Left,
+        }
+    }
+
+    /// Sets the text's horizontal alignment within the label's area.
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+}
+
+impl<C: PixelColor> Widget<C> for Label<'_> {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error> {
+        let mut target = target.clipped(&self.area);
+        target.fill_solid(&self.area, style.background)?;
+
+        let center = self.area.center();
+        let x = match self.alignment {
+            Alignment::Left => self.area.top_left.x,
+            Alignment::Center => center.x,
+            Alignment::Right => self.area.top_left.x + self.area.size.width as i32 - 1,
+        };
+        let text_style = TextStyleBuilder::new()
+            .alignment(self.alignment)
+            .baseline(Baseline::Middle)
+            .build();
+        Text::with_text_style(
+            self.text,
+            Point::new(x, center.y),
+            MonoTextStyle::new(style.font, style.foreground),
+            text_style,
+        )
+        .draw(&mut target)?;
+        Ok(self.area)
+    }
+}
+
+/// A right-aligned number followed by its unit, e.g. "21.5 C".
+#[derive(Debug, Clone, Copy)]
+pub struct Readout<'a> {
+    area: Rectangle,
+    value: f32,
+    decimals: usize,
+    unit: &'a str,
+}
+
+impl<'a> Readout<'a> {
+    /// Creates a readout showing `value` to the given number of decimal places.
+    pub fn new(area: Rectangle, value: f32, decimals: usize, unit: &'a str) -> Self {
+        Readout {
+            area,
+            value,
+            decimals,
+            unit,
+        }
+    }
+}
+
+impl<C: PixelColor> Widget<C> for Readout<'_> {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error> {
+        let mut text = heapless::String::<32>::new();
+        // Text that doesn't fit would be clipped anyway, so it's fine to truncate it.
+        let _ = write!(text, "{:.*} {}", self.decimals, self.value, self.unit);
+        Label::new(self.area, &text)
+            .with_alignment(Alignment::Right)
+            .draw(target, style)
+    }
+}
+
+/// A horizontal bar that fills from the left as `value` approaches `max`.
+#[derive(Debug, Clone, Copy)]
+pub struct ProgressBar {
+    area: Rectangle,
+    value: u32,
+    max: u32,
+}
+
+impl ProgressBar {
+    /// Creates a progress bar. Values greater than `max` show as full.
+    pub fn new(area: Rectangle, value: u32, max: u32) -> Self {
+        ProgressBar { area, value, max }
+    }
+}
+
+impl<C: PixelColor> Widget<C> for ProgressBar {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error> {
+        draw_meter(target, style, self.area, self.value, self.max)?;
+        Ok(self.area)
+    }
+}
+
+/// A battery outline, filled in proportion to its charge.
+#[derive(Debug, Clone, Copy)]
+pub struct BatteryGauge {
+    area: Rectangle,
+    percent: u8,
+}
+
+impl BatteryGauge {
+    /// Creates a battery gauge. Values over 100% show as full.
+    pub fn new(area: Rectangle, percent: u8) -> Self {
+        BatteryGauge { area, percent }
+    }
+}
+
+impl<C: PixelColor> Widget<C> for BatteryGauge {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error> {
+        let mut target = target.clipped(&self.area);
+        target.fill_solid(&self.area, style.background)?;
+
+        // The terminal takes a tenth of the width, and half the height.
+        let size = self.area.size;
+        let terminal_width = (size.width / 10).max(1);
+        let body = Rectangle::new(
+            self.area.top_left,
+            Size::new(size.width.saturating_sub(terminal_width), size.height),
+        );
+        let terminal = Rectangle::new(
+            self.area.top_left + Point::new(body.size.width as i32, size.height as i32 / 4),
+            Size::new(terminal_width, size.height / 2),
+        );
+        target.fill_solid(&terminal, style.foreground)?;
+        draw_meter(&mut target, style, body, self.percent.into(), 100)?;
+        Ok(self.area)
+    }
+}
+
+/// A battery icon, with the charge as optional text, that only redraws when the level it shows
+/// changes.
+///
+/// The text shows the charge to the nearest 10%, and the icon shows empty below 25%, and full from
+/// 75%, so small changes in charge don't cause a refresh.
+#[derive(Debug, Clone, Copy)]
+pub struct BatteryStatus(StatusGlyph);
+
+impl BatteryStatus {
+    /// Creates a battery status, with its icon at the left of `area`, and no text.
+    pub fn new(area: Rectangle) -> Self {
+        BatteryStatus(StatusGlyph::new(area))
+    }
+
+    /// Sets whether to show the charge as text to the right of the icon.
+    pub fn with_text(mut self, show_text: bool) -> Self {
+        self.0.show_text = show_text;
+        self
+    }
+
+    /// The area that this widget draws to.
+    pub fn bounds(&self) -> Rectangle {
+        self.0.area
+    }
+
+    /// Forgets what was last drawn, so the next update redraws everything. Call this after
+    /// clearing the buffer.
+    pub fn invalidate(&mut self) {
+        self.0.shown = None;
+    }
+
+    /// Shows the given charge. Values over 100% show as full.
+    ///
+    /// Only the icon and text that changed are redrawn. Returns the area that changed, or `None`
+    /// if the shown level is the same as last time.
+    pub fn update<D: DrawTarget>(
+        &mut self,
+        target: &mut D,
+        style: &WidgetStyle<'_, D::Color>,
+        percent: u8,
+    ) -> Result<Option<Rectangle>, D::Error> {
+        let tens = (percent.min(100) + 5) / 10;
+        self.0.update(
+            target,
+            style,
+            tens,
+            |tens| match tens {
+                0..=2 => Icon::BatteryEmpty,
+                3..=7 => Icon::BatteryHalf,
+                _ => Icon::BatteryFull,
+            },
+            |tens, text| {
+                let _ = write!(text, "{}%", u32::from(tens) * 10);
+            },
+        )
+    }
+}
+
+/// Signal strength bars, with the strength as optional text, that only redraw when the number of
+/// bars changes.
+#[derive(Debug, Clone, Copy)]
+pub struct SignalStatus(StatusGlyph);
+
+impl SignalStatus {
+    /// Creates a signal status, with its icon at the left of `area`, and no text.
+    pub fn new(area: Rectangle) -> Self {
+        SignalStatus(StatusGlyph::new(area))
+    }
+
+    /// Sets whether to describe the strength as text (e.g. "Good") to the right of the icon.
+    pub fn with_text(mut self, show_text: bool) -> Self {
+        self.0.show_text = show_text;
+        self
+    }
+
+    /// The area that this widget draws to.
+    pub fn bounds(&self) -> Rectangle {
+        self.0.area
+    }
+
+    /// Forgets what was last drawn, so the next update redraws everything. Call this after
+    /// clearing the buffer.
+    pub fn invalidate(&mut self) {
+        self.0.shown = None;
+    }
+
+    /// Shows the strength of a signal received at `rssi` dBm, as 0 to 4 bars.
+    ///
+    /// Only the icon and text that changed are redrawn. Returns the area that changed, or `None`
+    /// if the number of bars is the same as last time.
+    pub fn update<D: DrawTarget>(
+        &mut self,
+        target: &mut D,
+        style: &WidgetStyle<'_, D::Color>,
+        rssi: i16,
+    ) -> Result<Option<Rectangle>, D::Error> {
+        self.0.update(
+            target,
+            style,
+            signal_bars(rssi),
+            |bars| match bars {
+                0 => Icon::SignalNone,
+                1 => Icon::SignalLow,
+                2 => Icon::SignalMedium,
+                3 => Icon::SignalHigh,
+                _ => Icon::SignalFull,
+            },
+            |bars, text| {
+                let description = ["None", "Weak", "Fair", "Good"];
+                let _ = text.push_str(description.get(usize::from(bars)).unwrap_or(&"Strong"));
+            },
+        )
+    }
+}
+
+/// Returns the number of bars, from 0 to 4, to show for a signal received at `rssi` dBm.
+fn signal_bars(rssi: i16) -> u8 {
+    [-89, -78, -67, -55]
+        .iter()
+        .filter(|threshold| rssi >= **threshold)
+        .count() as u8
+}
+
+/// The shared state of the status widgets: an icon and text that both depend on a level.
+#[derive(Debug, Clone, Copy)]
+struct StatusGlyph {
+    area: Rectangle,
+    show_text: bool,
+    /// The level that was last drawn.
+    shown: Option<u8>,
+}
+
+impl StatusGlyph {
+    fn new(area: Rectangle) -> Self {
+        StatusGlyph {
+            area,
+            show_text: false,
+            shown: None,
+        }
+    }
+
+    /// Draws the icon and text for `level`, skipping the icon if it's the same as the one already
+    /// shown. The text is assumed to differ for each level.
+    fn update<D: DrawTarget>(
+        &mut self,
+        target: &mut D,
+        style: &WidgetStyle<'_, D::Color>,
+        level: u8,
+        icon: fn(u8) -> Icon,
+        text: fn(u8, &mut heapless::String<8>),
+    ) -> Result<Option<Rectangle>, D::Error> {
+        let previous = self.shown.replace(level);
+        if previous == Some(level) {
+            return Ok(None);
+        }
+
+        let mut dirty = DirtyRect::new();
+        if previous.map(icon) != Some(icon(level)) {
+            let icon_area = align(self.area, Icon::SIZE, Align::Start, Align::Center);
+            let mut target = target.clipped(&icon_area);
+            target.fill_solid(&icon_area, style.background)?;
+            icon(level).draw_with_color(icon_area.top_left, &mut target, style.foreground)?;
+            dirty.add(icon_area);
+        }
+        if self.show_text {
+            let [_, text_area] = columns(
+                self.area,
+                [Length::Px(Icon::SIZE.width), Length::Fill],
+                STATUS_TEXT_GAP,
+            );
+            let mut buf = heapless::String::new();
+            text(level, &mut buf);
+            dirty.add(Label::new(text_area, &buf).draw(target, style)?);
+        }
+        Ok(dirty.take())
+    }
+}
+
+/// The space between a status widget's icon and its text.
+const STATUS_TEXT_GAP: u32 = 2;
+
+/// A block of text, word wrapped to fit within its area.
+///
+/// Text is broken at spaces where possible, and at explicit newlines. Words that are too long for
+/// one line are broken wherever the line runs out.
+#[derive(Debug, Clone, Copy)]
+pub struct TextBox<'a> {
+    area: Rectangle,
+    text: &'a str,
+    alignment: Alignment,
+    line_spacing: u32,
+    ellipsis: bool,
+}
+
+impl<'a> TextBox<'a> {
+    /// Creates a left-aligned text box, with no extra space between lines.
+    pub fn new(area: Rectangle, text: &'a str) -> Self {
+        TextBox {
+            area,
+            text,
+            alignment: Alignment::Left,
+            line_spacing: 0,
+            ellipsis: false,
+        }
+    }
+
+    /// Sets each line's horizontal alignment within the text box.
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Sets the number of pixels between lines.
+    pub fn with_line_spacing(mut self, line_spacing: u32) -> Self {
+        self.line_spacing = line_spacing;
+        self
+    }
+
+    /// Ends the last line with "..." if the text doesn't fit.
+    pub fn with_ellipsis(mut self, ellipsis: bool) -> Self {
+        self.ellipsis = ellipsis;
+        self
+    }
+}
+
+impl<C: PixelColor> Widget<C> for TextBox<'_> {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error> {
+        let mut target = target.clipped(&self.area);
+        target.fill_solid(&self.area, style.background)?;
+
+        let font = style.font;
+        let advance = font.character_size.width + font.character_spacing;
+        let line_height = font.character_size.height + self.line_spacing;
+        if advance == 0 || line_height == 0 {
+            return Ok(self.area);
+        }
+        // The last character and line don't need the spacing after them.
+        let max_chars = ((self.area.size.width + font.character_spacing) / advance) as usize;
+        let max_lines = ((self.area.size.height + self.line_spacing) / line_height) as usize;
+
+        let character_style = MonoTextStyle::new(font, style.foreground);
+        let mut lines = WrappedLines::new(self.text, max_chars).peekable();
+        let mut y = self.area.top_left.y;
+        for i in 0..max_lines {
+            let Some(mut line) = lines.next() else {
+                break;
+            };
+            let truncated = self.ellipsis && i + 1 == max_lines && lines.peek().is_some();
+            if truncated {
+                line = take_chars(line, max_chars.saturating_sub(ELLIPSIS.len()));
+            }
+
+            let len = line.chars().count() + if truncated { ELLIPSIS.len() } else { 0 };
+            let width = (len as u32 * advance).saturating_sub(font.character_spacing);
+            let free = self.area.size.width.saturating_sub(width);
+            let x = self.area.top_left.x
+                + match self.alignment {
+                    Alignment::Left => 0,
+                    Alignment::Center => (free / 2) as i32,
+                    Alignment::Right => free as i32,
+                };
+
+            let end = Text::with_baseline(line, Point::new(x, y), character_style, Baseline::Top)
+                .draw(&mut target)?;
+            if truncated {
+                Text::with_baseline(ELLIPSIS, end, character_style, Baseline::Top)
+                    .draw(&mut target)?;
+            }
+            y += line_height as i32;
+        }
+        Ok(self.area)
+    }
+}
+
+const ELLIPSIS: &str = "...";
+
+/// Returns the first `n` characters of `s`.
+fn take_chars(s: &str, n: usize) -> &str {
+    match s.char_indices().nth(n) {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
+}
+
+/// Splits text into lines of at most `max_chars` characters.
+struct WrappedLines<'a> {
+    rest: &'a str,
+    max_chars: usize,
+}
+
+impl<'a> WrappedLines<'a> {
+    fn new(text: &'a str, max_chars: usize) -> Self {
+        WrappedLines {
+            rest: text,
+            max_chars,
+        }
+    }
+}
+
+impl<'a> Iterator for WrappedLines<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.rest.is_empty() || self.max_chars == 0 {
+            return None;
+        }
+        let paragraph = match self.rest.find('\n') {
+            Some(newline) => &self.rest[..newline],
+            None => self.rest,
+        };
+
+        let Some((end, next)) = paragraph.char_indices().nth(self.max_chars) else {
+            // The whole paragraph fits, so skip past its newline too.
+            self.rest = self.rest.get(paragraph.len() + 1..).unwrap_or("");
+            return Some(paragraph.trim_end());
+        };
+        let split = if next == ' ' {
+            end
+        } else {
+            // Break after the last space on the line, or mid-word if there isn't one.
+            match paragraph[..end].rfind(' ') {
+                Some(space) if !paragraph[..space].trim_end().is_empty() => space,
+                _ => end,
+            }
+        };
+        let line = &self.rest[..split];
+        self.rest = self.rest[split..].trim_start_matches(' ');
+        Some(line.trim_end())
+    }
+}
+
+/// A scrollable list of items, with the selected item highlighted in inverted colors.
+///
+/// Draw the whole menu with [Widget::draw], or use [Menu::update] to redraw only the rows that
+/// changed since the last update, e.g. after [Menu::select_next].
+#[derive(Debug, Clone, Copy)]
+pub struct Menu<'a> {
+    area: Rectangle,
+    items: &'a [&'a str],
+    selected: usize,
+    /// The index of the first visible item.
+    scroll: usize,
+    /// The scroll position and selection that were last drawn by [Menu::update].
+    shown: Option<(usize, usize)>,
+}
+
+impl<'a> Menu<'a> {
+    /// Creates a menu with the first item selected.
+    pub fn new(area: Rectangle, items: &'a [&'a str]) -> Self {
+        Menu {
+            area,
+            items,
+            selected: 0,
+            scroll: 0,
+            shown: None,
+        }
+    }
+
+    /// The index of the selected item.
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Selects the item at `index`, clamped to the last item.
+    pub fn select(&mut self, index: usize) {
+        self.selected = index.min(self.items.len().saturating_sub(1));
+    }
+
+    /// Selects the next item, unless the last item is already selected.
+    pub fn select_next(&mut self) {
+        self.select(self.selected.saturating_add(1));
+    }
+
+    /// Selects the previous item, unless the first item is already selected.
+    pub fn select_previous(&mut self) {
+        self.select(self.selected.saturating_sub(1));
+    }
+
+    /// Forgets what was last drawn, so the next update redraws everything. Call this after
+    /// clearing the buffer.
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    /// Redraws the rows that changed since the last update: the previously and newly selected
+    /// rows, or every row if the menu scrolled.
+    ///
+    /// Returns the area that changed, or `None` if nothing did.
+    pub fn update<D: DrawTarget>(
+        &mut self,
+        target: &mut D,
+        style: &WidgetStyle<'_, D::Color>,
+    ) -> Result<Option<Rectangle>, D::Error> {
+        self.scroll = self.scroll_for(style);
+        let shown = self.shown.replace((self.scroll, self.selected));
+        match shown {
+            Some((scroll, selected)) if scroll == self.scroll => {
+                if selected == self.selected {
+                    return Ok(None);
+                }
+                let mut dirty = DirtyRect::new();
+                for index in [selected, self.selected] {
+                    dirty.add(self.draw_row(target, style, index - self.scroll)?);
+                }
+                Ok(dirty.take())
+            }
+            _ => self.draw(target, style).map(Some),
+        }
+    }
+
+    fn row_height(style: &WidgetStyle<'_, impl PixelColor>) -> u32 {
+        style.font.character_size.height + 2
+    }
+
+    /// Returns the scroll position that keeps the selected item visible, moving as little as
+    /// possible from the current position.
+    fn scroll_for(&self, style: &WidgetStyle<'_, impl PixelColor>) -> usize {
+        let rows = (self.area.size.height / Self::row_height(style)).max(1) as usize;
+        if self.selected < self.scroll {
+            self.selected
+        } else if self.selected >= self.scroll + rows {
+            self.selected + 1 - rows
+        } else {
+            self.scroll
+        }
+    }
+
+    /// Clears and draws the given visible row, and returns its area.
+    fn draw_row<D: DrawTarget>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, D::Color>,
+        row: usize,
+    ) -> Result<Rectangle, D::Error> {
+        let height = Self::row_height(style);
+        let area = Rectangle::new(
+            self.area.top_left + Point::new(0, (row as u32 * height) as i32),
+            Size::new(self.area.size.width, height),
+        )
+        .intersection(&self.area);
+        let index = self.scroll_for(style) + row;
+        let text = self.items.get(index).copied().unwrap_or("");
+        let style = if index == self.selected && index < self.items.len() {
+            WidgetStyle::new(style.font, style.background, style.foreground)
+        } else {
+            *style
+        };
+        // The label is inset to leave a 1 pixel margin around the text.
+        target.clipped(&area).fill_solid(&area, style.background)?;
+        Label::new(area.offset(-1), text).draw(target, &style)?;
+        Ok(area)
+    }
+}
+
+impl<C: PixelColor> Widget<C> for Menu<'_> {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error> {
+        let mut target = target.clipped(&self.area);
+        target.fill_solid(&self.area, style.background)?;
+        let height = Self::row_height(style);
+        let rows = self.area.size.height.div_ceil(height) as usize;
+        for row in 0..rows {
+            self.draw_row(&mut target, style, row)?;
+        }
+        Ok(self.area)
+    }
+}
+
+/// A bordered area, with an optional title bar.
+#[derive(Debug, Clone, Copy)]
+pub struct Panel<'a> {
+    area: Rectangle,
+    title: Option<&'a str>,
+}
+
+impl<'a> Panel<'a> {
+    pub fn new(area: Rectangle) -> Self {
+        Panel { area, title: None }
+    }
+
+    /// Adds a title, shown in inverted colors at the top of the panel.
+    pub fn with_title(mut self, title: &'a str) -> Self {
+        self.title = Some(title);
+        self
+    }
+
+    /// Returns the area inside the border and below the title, where the panel's contents go.
+    pub fn content(&self, style: &WidgetStyle<'_, impl PixelColor>) -> Rectangle {
+        let title_height = self.title_height(style);
+        let inner = self.area.offset(-2);
+        Rectangle::new(
+            inner.top_left + Point::new(0, title_height as i32),
+            Size::new(
+                inner.size.width,
+                inner.size.height.saturating_sub(title_height),
+            ),
+        )
+    }
+
+    fn title_height(&self, style: &WidgetStyle<'_, impl PixelColor>) -> u32 {
+        match self.title {
+            Some(_) => style.font.character_size.height + 2,
+            None => 0,
+        }
+    }
+}
+
+impl<C: PixelColor> Widget<C> for Panel<'_> {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error> {
+        let mut target = target.clipped(&self.area);
+        target.fill_solid(&self.area, style.background)?;
+        self.area
+            .into_styled(PrimitiveStyle::with_stroke(style.foreground, 1))
+            .draw(&mut target)?;
+
+        if let Some(title) = self.title {
+            let title_area = Rectangle::new(
+                self.area.top_left,
+                Size::new(self.area.size.width, self.title_height(style)),
+            );
+            let inverted = WidgetStyle::new(style.font, style.background, style.foreground);
+            Label::new(title_area.offset(-1), title)
+                .with_alignment(Alignment::Center)
+                .draw(&mut target, &inverted)?;
+        }
+        Ok(self.area)
+    }
+}
+
+/// The modules (i.e. squares) of an encoded QR code.
+///
+/// Implement this for your QR encoder's output, such as `qrcodegen_no_heap::QrCode`:
+///
+/// ```text
+/// struct Modules<'a>(qrcodegen_no_heap::QrCode<'a>);
+///
+/// impl QrModules for Modules<'_> {
+///     fn size(&self) -> u32 {
+///         self.0.size() as u32
+///     }
+///
+///     fn is_dark(&self, x: u32, y: u32) -> bool {
+///         self.0.get_module(x as i32, y as i32)
+///     }
+/// }
+/// ```
+pub trait QrModules {
+    /// The number of modules along each side, not including the quiet zone.
+    fn size(&self) -> u32;
+
+    /// Whether the module at `(x, y)` is dark.
+    fn is_dark(&self, x: u32, y: u32) -> bool;
+}
+
+/// A QR code, scaled up by a whole number of pixels per module, and centered in its area.
+///
+/// Dark modules are drawn in the style's foreground color, and light modules and the quiet zone in
+/// its background color. Most scanners need dark modules on a light background, so with the
+/// crate's binary buffers, the foreground should be `BinaryColor::Off` (black).
+#[derive(Debug, Clone, Copy)]
+pub struct QrCode<'a, Q> {
+    area: Rectangle,
+    modules: &'a Q,
+    quiet_zone: u32,
+}
+
+impl<'a, Q: QrModules> QrCode<'a, Q> {
+    /// Creates a QR code with the standard quiet zone of 4 modules.
+    pub fn new(area: Rectangle, modules: &'a Q) -> Self {
+        QrCode {
+            area,
+            modules,
+            quiet_zone: 4,
+        }
+    }
+
+    /// Sets the width of the blank border around the code, in modules.
+    ///
+    /// A smaller quiet zone allows a larger code, but may not scan if the code is placed next to
+    /// other content.
+    pub fn with_quiet_zone(mut self, quiet_zone: u32) -> Self {
+        self.quiet_zone = quiet_zone;
+        self
+    }
+}
+
+impl<C: PixelColor, Q: QrModules> Widget<C> for QrCode<'_, Q> {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        style: &WidgetStyle<'_, C>,
+    ) -> Result<Rectangle, D::Error> {
+        let mut target = target.clipped(&self.area);
+        target.fill_solid(&self.area, style.background)?;
+
+        let size = self.modules.size();
+        let modules = size.saturating_add(self.quiet_zone.saturating_mul(2));
+        let scale = self.area.size.width.min(self.area.size.height) / modules.max(1);
+        if scale == 0 {
+            warning!(
+                "QR code doesn't fit in {}x{}",
+                self.area.size.width,
+                self.area.size.height
+            );
+            return Ok(self.area);
+        }
+
+        let quiet = (self.quiet_zone * scale) as i32;
+        let top_left = self.area.top_left
+            + (self.area.size - Size::new_equal(modules * scale)) / 2
+            + Point::new(quiet, quiet);
+        let module_size = Size::new_equal(scale);
+        for y in 0..size {
+            for x in 0..size {
+                if self.modules.is_dark(x, y) {
+                    let offset = Point::new((x * scale) as i32, (y * scale) as i32);
+                    target.fill_solid(
+                        &Rectangle::new(top_left + offset, module_size),
+                        style.foreground,
+                    )?;
+                }
+            }
+        }
+        Ok(self.area)
+    }
+}
+
+/// Draws an outline around `area`, filled from the left in proportion to `value / max`, with a
+/// 1 pixel gap between the outline and the fill.
+fn draw_meter<D: DrawTarget>(
+    target: &mut D,
+    style: &WidgetStyle<'_, D::Color>,
+    area: Rectangle,
+    value: u32,
+    max: u32,
+) -> Result<(), D::Error> {
+    target.fill_solid(&area, style.background)?;
+    area.into_styled(PrimitiveStyle::with_stroke(style.foreground, 1))
+        .draw(target)?;
+
+    let inner = area.offset(-2);
+    let filled = if max == 0 {
+        inner.size.width
+    } else {
+        (u64::from(inner.size.width) * u64::from(value.min(max)) / u64::from(max)) as u32
+    };
+    target.fill_solid(
+        &Rectangle::new(inner.top_left, Size::new(filled, inner.size.height)),
+        style.foreground,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{mono_font::ascii::FONT_6X10, pixelcolor::BinaryColor};
+
+    use super::*;
+    use crate::buffer::{binary_buffer_length, BinaryBuffer};
+
+    const SIZE: Size = Size::new(16, 8);
+
+    fn style() -> WidgetStyle<'static, BinaryColor> {
+        WidgetStyle::new(&FONT_6X10, BinaryColor::On, BinaryColor::Off)
+    }
+
+    #[test]
+    fn test_dirty_rect_covers_added_areas() {
+        let mut dirty = DirtyRect::new();
+        dirty.add(Rectangle::new(Point::new(8, 0), Size::new(8, 4)));
+        dirty.add(Rectangle::new(Point::new(0, 10), Size::zero()));
+        dirty.add(Rectangle::new(Point::new(0, 4), Size::new(4, 4)));
+        assert_eq!(
+            dirty.take(),
+            Some(Rectangle::new(Point::zero(), Size::new(16, 8)))
+        );
+        assert_eq!(dirty.get(), None);
+    }
+
+    #[test]
+    fn test_progress_bar_fills_proportionally() {
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let bar = ProgressBar::new(Rectangle::new(Point::zero(), SIZE), 1, 2);
+        assert_eq!(
+            bar.draw(&mut buffer, &style()),
+            Ok(Rectangle::new(Point::zero(), SIZE))
+        );
+
+        // The 12 pixel wide inner area is half filled.
+        let row = &buffer.data()[2 * 2..3 * 2];
+        assert_eq!(row, [0b1011_1111, 0b0000_0001]);
+    }
+
+    #[test]
+    fn test_widgets_stay_in_bounds() {
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let area = Rectangle::new(Point::zero(), Size::new(8, 8));
+        Label::new(area, "Long text")
+            .draw(&mut buffer, &style())
+            .unwrap();
+        Panel::new(area)
+            .with_title("Title")
+            .draw(&mut buffer, &style())
+            .unwrap();
+        BatteryGauge::new(area, 50)
+            .draw(&mut buffer, &style())
+            .unwrap();
+
+        TextBox::new(area, "Long wrapped text")
+            .with_ellipsis(true)
+            .draw(&mut buffer, &style())
+            .unwrap();
+
+        for row in buffer.data().chunks(2) {
+            assert_eq!(row[1], 0);
+        }
+    }
+
+    #[test]
+    fn test_battery_status_redraws_only_changes() {
+        const SIZE: Size = Size::new(40, 16);
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let area = Rectangle::new(Point::zero(), SIZE);
+        let text_area = Rectangle::new(Point::new(18, 0), Size::new(22, 16));
+        let mut battery = BatteryStatus::new(area).with_text(true);
+
+        assert_eq!(battery.update(&mut buffer, &style(), 100), Ok(Some(area)));
+        // Still shows 100%.
+        assert_eq!(battery.update(&mut buffer, &style(), 96), Ok(None));
+        // Still a full icon, so only the text changes.
+        assert_eq!(
+            battery.update(&mut buffer, &style(), 80),
+            Ok(Some(text_area))
+        );
+        assert_eq!(battery.update(&mut buffer, &style(), 50), Ok(Some(area)));
+
+        battery.invalidate();
+        assert_eq!(battery.update(&mut buffer, &style(), 50), Ok(Some(area)));
+    }
+
+    #[test]
+    fn test_signal_status_tracks_bars() {
+        assert_eq!(signal_bars(-40), 4);
+        assert_eq!(signal_bars(-67), 3);
+        assert_eq!(signal_bars(-80), 1);
+        assert_eq!(signal_bars(-120), 0);
+
+        const SIZE: Size = Size::new(24, 16);
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let mut signal = SignalStatus::new(Rectangle::new(Point::zero(), SIZE));
+        let icon_area = Rectangle::new(Point::zero(), Icon::SIZE);
+        assert_eq!(
+            signal.update(&mut buffer, &style(), -50),
+            Ok(Some(icon_area))
+        );
+        assert_eq!(signal.update(&mut buffer, &style(), -54), Ok(None));
+        assert_eq!(
+            signal.update(&mut buffer, &style(), -100),
+            Ok(Some(icon_area))
+        );
+        // The bottom row of each empty bar is drawn.
+        assert_eq!(
+            buffer.data()[14 * 3..14 * 3 + 2],
+            [0b1110_1110, 0b1110_1110]
+        );
+    }
+
+    #[test]
+    fn test_wrapped_lines() {
+        let lines = |text, max_chars| WrappedLines::new(text, max_chars).collect::<Vec<_>>();
+        assert_eq!(lines("The quick brown fox", 10), ["The quick", "brown fox"]);
+        assert_eq!(lines("Unbreakable word", 6), ["Unbrea", "kable", "word"]);
+        assert_eq!(lines("One\n\nTwo  three", 5), ["One", "", "Two", "three"]);
+        assert_eq!(lines("Anything", 0), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn test_menu_redraws_changed_rows() {
+        // 3 rows of 12 pixels each.
+        const SIZE: Size = Size::new(32, 36);
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let area = Rectangle::new(Point::zero(), SIZE);
+        let items = ["One", "Two", "Three", "Four", "Five"];
+        let mut menu = Menu::new(area, &items);
+
+        assert_eq!(menu.update(&mut buffer, &style()), Ok(Some(area)));
+        assert_eq!(menu.update(&mut buffer, &style()), Ok(None));
+        // The selection moves from the first row to the second.
+        menu.select_next();
+        assert_eq!(
+            menu.update(&mut buffer, &style()),
+            Ok(Some(Rectangle::new(Point::zero(), Size::new(32, 24))))
+        );
+        // The selected row is inverted.
+        assert_eq!(buffer.data()[12 * 4], 0xFF);
+        assert_eq!(buffer.data()[0], 0x00);
+
+        menu.select(2);
+        assert_eq!(
+            menu.update(&mut buffer, &style()),
+            Ok(Some(Rectangle::new(Point::new(0, 12), Size::new(32, 24))))
+        );
+        // Selecting past the last visible row scrolls the menu.
+        menu.select_next();
+        assert_eq!(menu.update(&mut buffer, &style()), Ok(Some(area)));
+        menu.select(10);
+        assert_eq!(menu.selected(), 4);
+    }
+
+    /// A 2x2 checkerboard.
+    struct Checkerboard;
+
+    impl QrModules for Checkerboard {
+        fn size(&self) -> u32 {
+            2
+        }
+
+        fn is_dark(&self, x: u32, y: u32) -> bool {
+            x == y
+        }
+    }
+
+    #[test]
+    fn test_qr_code_scales_and_centers() {
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let qr = QrCode::new(Rectangle::new(Point::zero(), SIZE), &Checkerboard).with_quiet_zone(1);
+        qr.draw(&mut buffer, &style()).unwrap();
+
+        // Each module is 2x2 pixels, and the 8x8 code is centered horizontally.
+        assert_eq!(
+            buffer.data(),
+            [
+                0,
+                0,
+                0,
+                0, //
+                0b0000_0011,
+                0,
+                0b0000_0011,
+                0, //
+                0,
+                0b1100_0000,
+                0,
+                0b1100_0000, //
+                0,
+                0,
+                0,
+                0,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_text_box_adds_ellipsis() {
+        // 6 characters per line, and 2 lines.
+        const SIZE: Size = Size::new(40, 24);
+        let mut buffer = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let mut expected = BinaryBuffer::<{ binary_buffer_length(SIZE) }>::new(SIZE);
+        let area = Rectangle::new(Point::zero(), SIZE);
+
+        TextBox::new(area, "Hello there, world")
+            .with_line_spacing(2)
+            .with_ellipsis(true)
+            .draw(&mut buffer, &style())
+            .unwrap();
+        TextBox::new(area, "Hello\nthe...")
+            .with_line_spacing(2)
+            .draw(&mut expected, &style())
+            .unwrap();
+        assert_eq!(buffer.data(), expected.data());
+    }
+}
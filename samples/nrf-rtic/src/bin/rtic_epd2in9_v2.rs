@@ -0,0 +1,123 @@
+//! This example drives the EPD Waveshare 2.9" v2 display from an RTIC 2.x software task on an
+//! nRF52832 board.
+//!
+//! The display task runs at a low priority, while a higher priority task keeps ticking. This works
+//! because the driver futures don't rely on a single-threaded executor.
+
+#![no_std]
+#![no_main]
+
+use embassy_nrf::{bind_interrupts, peripherals, spim};
+use rtic_monotonics::nrf::rtc::prelude::*;
+use {defmt_rtt as _, panic_probe as _};
+
+nrf_rtc0_monotonic!(Mono);
+
+bind_interrupts!(struct Irqs {
+    SPIM2_SPIS2_SPI2 => spim::InterruptHandler<peripherals::SPI2>;
+});
+
+#[rtic::app(device = embassy_nrf, peripherals = false, dispatchers = [SWI0_EGU0, SWI1_EGU1])]
+mod app {
+    use defmt::{expect, info};
+    use embassy_nrf::gpio::{Level, Output, OutputDrive};
+    use embassy_nrf::spim;
+    use embedded_graphics::mono_font::ascii::FONT_6X10;
+    use embedded_graphics::mono_font::MonoTextStyle;
+    use embedded_graphics::pixelcolor::BinaryColor;
+    use embedded_graphics::prelude::*;
+    use embedded_graphics::text::{Baseline, Text};
+    use embedded_hal_bus::spi::ExclusiveDevice;
+    use epd_waveshare_async::{
+        epd2in9_v2::{self, Epd2In9V2, RefreshMode},
+        *,
+    };
+    use nrf_rtic_samples::DisplayHw;
+
+    use super::{Irqs, Mono};
+
+    #[shared]
+    struct Shared {}
+
+    #[local]
+    struct Local {}
+
+    #[init]
+    fn init(_cx: init::Context) -> (Shared, Local) {
+        let p = embassy_nrf::init(Default::default());
+        // SAFETY: RTC0 is only used by the monotonic.
+        Mono::start(unsafe { nrf52832_pac::Peripherals::steal() }.RTC0);
+
+        expect!(heartbeat::spawn(), "Failed to spawn heartbeat");
+        expect!(display::spawn(p), "Failed to spawn display");
+        (Shared {}, Local {})
+    }
+
+    /// A higher priority task, to show that the display task can be pre-empted.
+    #[task(priority = 2)]
+    async fn heartbeat(_cx: heartbeat::Context) {
+        loop {
+            info!("Tick");
+            Mono::delay(1.secs()).await;
+        }
+    }
+
+    #[task(priority = 1)]
+    async fn display(_cx: display::Context, p: embassy_nrf::Peripherals) {
+        let mut config = spim::Config::default();
+        config.frequency = spim::Frequency::M4;
+        config.mode = spim::Mode {
+            polarity: epd2in9_v2::RECOMMENDED_SPI_POLARITY,
+            phase: epd2in9_v2::RECOMMENDED_SPI_PHASE,
+        };
+        let spim = spim::Spim::new_txonly(p.SPI2, Irqs, p.P0_25, p.P0_23, config);
+        // CS is active low.
+        let cs_pin = Output::new(p.P0_22, Level::High, OutputDrive::Standard);
+        let mut spi = expect!(
+            ExclusiveDevice::new_no_delay(spim, cs_pin),
+            "Failed to create SPI device"
+        );
+
+        let epd = Epd2In9V2::new(DisplayHw::new(
+            p.P0_20,
+            p.P0_19,
+            p.P0_18,
+            epd2in9_v2::DEFAULT_BUSY_WHEN,
+            Mono,
+        ));
+
+        info!("Initializing EPD");
+        let mut epd = expect!(
+            epd.init(&mut spi, RefreshMode::Full).await,
+            "Failed to initialize EPD"
+        );
+
+        let mut buffer = epd2in9_v2::new_binary_buffer();
+        buffer.clear(BinaryColor::On).unwrap();
+        let character_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::Off);
+        Text::with_baseline(
+            "Hello, RTIC!",
+            Point::new(10, 10),
+            character_style,
+            Baseline::Top,
+        )
+        .draw(&mut buffer)
+        .unwrap();
+        info!("Displaying text");
+        expect!(
+            epd.display_framebuffer(&mut spi, &buffer).await,
+            "Failed to display text buffer"
+        );
+        Mono::delay(5.secs()).await;
+
+        info!("Final clear");
+        buffer.clear(BinaryColor::On).unwrap();
+        expect!(
+            epd.display_framebuffer(&mut spi, &buffer).await,
+            "Failed to clear display"
+        );
+
+        let _epd = expect!(epd.sleep(&mut spi).await, "Failed to put EPD to sleep");
+        info!("Done");
+    }
+}
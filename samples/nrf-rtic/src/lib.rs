@@ -0,0 +1,129 @@
+#![no_std]
+
+use core::convert::Infallible;
+use core::marker::PhantomData;
+
+use embassy_nrf::gpio::{Input, Level, Output, OutputDrive, Pin, Pull};
+use embassy_nrf::spim;
+use embassy_nrf::Peri;
+use embedded_hal::digital::PinState;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+use embedded_hal_bus::spi::DeviceError;
+use epd_waveshare_async::buffer::WindowError;
+use epd_waveshare_async::hw::{BusyHw, DcHw, DelayHw, ErrorHw, ResetHw, SpiHw};
+use thiserror::Error as ThisError;
+use {defmt_rtt as _, panic_probe as _};
+
+/// The largest transfer that the nRF52832's SPIM EasyDMA can perform in one go.
+pub const EASY_DMA_MAX_LEN: usize = 255;
+
+/// Defines the hardware to use for connecting to the display.
+///
+/// Unlike the embassy samples, the delay is generic so that it can be provided by an RTIC
+/// monotonic.
+pub struct DisplayHw<'a, SPI, DELAY> {
+    dc: Output<'a>,
+    reset: Output<'a>,
+    busy: Input<'a>,
+    busy_when: PinState,
+    delay: DELAY,
+    _spi_type: PhantomData<SPI>,
+}
+
+impl<'a, SPI, DELAY: DelayNs> DisplayHw<'a, SPI, DELAY> {
+    pub fn new<DC: Pin, RESET: Pin, BUSY: Pin>(
+        dc: Peri<'a, DC>,
+        reset: Peri<'a, RESET>,
+        busy: Peri<'a, BUSY>,
+        busy_when: PinState,
+        delay: DELAY,
+    ) -> Self {
+        let dc = Output::new(dc, Level::High, OutputDrive::Standard);
+        let reset = Output::new(reset, Level::High, OutputDrive::Standard);
+        let busy = Input::new(busy, Pull::Up);
+
+        Self {
+            dc,
+            reset,
+            busy,
+            busy_when,
+            delay,
+            _spi_type: PhantomData,
+        }
+    }
+}
+
+pub type RawSpiError = DeviceError<spim::Error, Infallible>;
+
+impl<'a, SPI, DELAY> ErrorHw for DisplayHw<'a, SPI, DELAY> {
+    type Error = Error;
+}
+
+impl<'a, SPI, DELAY> DcHw for DisplayHw<'a, SPI, DELAY> {
+    type Dc = Output<'a>;
+
+    fn dc(&mut self) -> &mut Self::Dc {
+        &mut self.dc
+    }
+}
+
+impl<'a, SPI, DELAY> ResetHw for DisplayHw<'a, SPI, DELAY> {
+    type Reset = Output<'a>;
+
+    fn reset(&mut self) -> &mut Self::Reset {
+        &mut self.reset
+    }
+}
+
+impl<'a, SPI, DELAY> BusyHw for DisplayHw<'a, SPI, DELAY> {
+    type Busy = Input<'a>;
+
+    fn busy(&mut self) -> &mut Self::Busy {
+        &mut self.busy
+    }
+
+    fn busy_when(&self) -> embedded_hal::digital::PinState {
+        self.busy_when
+    }
+}
+
+impl<'a, SPI, DELAY: DelayNs> DelayHw for DisplayHw<'a, SPI, DELAY> {
+    type Delay = DELAY;
+
+    fn delay(&mut self) -> &mut Self::Delay {
+        &mut self.delay
+    }
+}
+
+impl<'a, SPI: SpiDevice, DELAY> SpiHw for DisplayHw<'a, SPI, DELAY> {
+    type Spi = SPI;
+
+    const MAX_WRITE_LEN: usize = EASY_DMA_MAX_LEN;
+}
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("SPI error: {0:?}")]
+    SpiError(RawSpiError),
+    #[error("window error: {0}")]
+    WindowError(WindowError),
+}
+
+impl From<Infallible> for Error {
+    fn from(_: Infallible) -> Self {
+        unreachable!()
+    }
+}
+
+impl From<RawSpiError> for Error {
+    fn from(e: RawSpiError) -> Self {
+        Error::SpiError(e)
+    }
+}
+
+impl From<WindowError> for Error {
+    fn from(e: WindowError) -> Self {
+        Error::WindowError(e)
+    }
+}
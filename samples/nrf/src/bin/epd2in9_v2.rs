@@ -0,0 +1,134 @@
+//! This example tests the EPD Waveshare 2.9" v2 display driver using an nRF52832 board.
+//!
+//! The nRF52832's SPIM EasyDMA can only transfer 255 bytes at a time, so this also checks that
+//! framebuffer writes are split up correctly.
+
+#![no_std]
+#![no_main]
+
+use defmt::{expect, info};
+use embassy_embedded_hal::shared_bus::asynch::spi::SpiDevice;
+use embassy_executor::Spawner;
+use embassy_nrf::gpio::{Level, Output, OutputDrive};
+use embassy_nrf::{bind_interrupts, peripherals, spim};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::Timer;
+use embedded_graphics::mono_font::ascii::FONT_6X10;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::{BinaryColor, Gray2};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::{Alignment, Baseline, Text, TextStyle};
+use epd_waveshare_async::epd2in9_v2::new_gray2_buffer;
+use epd_waveshare_async::{
+    epd2in9_v2::{Epd2In9V2, RefreshMode},
+    *,
+};
+use nrf_samples::*;
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    SPIM2_SPIS2_SPI2 => spim::InterruptHandler<peripherals::SPI2>;
+});
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+
+    let mut config = spim::Config::default();
+    config.frequency = spim::Frequency::M4;
+    // embassy-nrf uses the embedded-hal SPI mode types, so these can be used directly.
+    config.mode = spim::Mode {
+        polarity: epd2in9_v2::RECOMMENDED_SPI_POLARITY,
+        phase: epd2in9_v2::RECOMMENDED_SPI_PHASE,
+    };
+
+    let raw_spi: Mutex<NoopRawMutex, _> = Mutex::new(spim::Spim::new_txonly(
+        p.SPI2, Irqs, p.P0_25, p.P0_23, config,
+    ));
+    // CS is active low.
+    let cs_pin = Output::new(p.P0_22, Level::High, OutputDrive::Standard);
+    let mut spi = SpiDevice::new(&raw_spi, cs_pin);
+    let epd = Epd2In9V2::new(DisplayHw::new(
+        p.P0_20,
+        p.P0_19,
+        p.P0_18,
+        epd2in9_v2::DEFAULT_BUSY_WHEN,
+    ));
+
+    info!("Initializing EPD");
+    let mut epd = expect!(
+        epd.init(&mut spi, RefreshMode::Full).await,
+        "Failed to initialize EPD"
+    );
+
+    // A full buffer is 4736 bytes, which must be split into several EasyDMA transfers.
+    let mut buffer = epd2in9_v2::new_binary_buffer();
+    buffer.clear(BinaryColor::On).unwrap();
+    info!("Displaying white buffer");
+    expect!(
+        epd.display_framebuffer(&mut spi, &buffer).await,
+        "Failed to display buffer"
+    );
+    epd.write_base_framebuffer(&mut spi, &buffer).await.unwrap();
+    Timer::after_secs(2).await;
+
+    info!("Displaying text with a partial refresh");
+    expect!(
+        epd.set_refresh_mode(&mut spi, RefreshMode::Partial).await,
+        "Failed to set refresh mode"
+    );
+    let mut text_style = TextStyle::default();
+    text_style.alignment = Alignment::Left;
+    text_style.baseline = Baseline::Top;
+    let character_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::Off);
+    Text::with_text_style(
+        "Hello, nRF!",
+        Point::new(10, 10),
+        character_style,
+        text_style,
+    )
+    .draw(&mut buffer)
+    .unwrap();
+    expect!(
+        epd.display_framebuffer(&mut spi, &buffer).await,
+        "Failed to display text buffer"
+    );
+    Timer::after_secs(3).await;
+
+    info!("Display 4-color grayscale");
+    let mut gray_buffer = new_gray2_buffer();
+    let bar_size = Size::new(
+        epd2in9_v2::DISPLAY_WIDTH as u32,
+        epd2in9_v2::DISPLAY_HEIGHT as u32 / 4,
+    );
+    for luma in 0..4 {
+        let top_left = Point::new(0, (luma * bar_size.height) as i32);
+        gray_buffer
+            .fill_solid(&Rectangle::new(top_left, bar_size), Gray2::new(luma as u8))
+            .unwrap();
+    }
+    expect!(
+        epd.set_refresh_mode(&mut spi, RefreshMode::Gray2).await,
+        "Failed to set Gray2 refresh mode"
+    );
+    expect!(
+        epd.display_framebuffer(&mut spi, &gray_buffer).await,
+        "Failed to draw Gray2 buffer"
+    );
+    Timer::after_secs(5).await;
+
+    info!("Final clear");
+    epd.set_refresh_mode(&mut spi, RefreshMode::Full)
+        .await
+        .unwrap();
+    buffer.clear(BinaryColor::On).unwrap();
+    expect!(
+        epd.display_framebuffer(&mut spi, &buffer).await,
+        "Failed to clear display"
+    );
+
+    let _epd = expect!(epd.sleep(&mut spi).await, "Failed to put EPD to sleep");
+    info!("Done");
+}
@@ -0,0 +1,128 @@
+#![no_std]
+
+use core::convert::Infallible;
+
+use embassy_embedded_hal::shared_bus::SpiDeviceError;
+use embassy_nrf::gpio::{Input, Level, Output, OutputDrive, Pin, Pull};
+use embassy_nrf::spim;
+use embassy_nrf::Peri;
+use embassy_time::Delay;
+use embedded_hal::digital::PinState;
+use embedded_hal_async::spi::SpiDevice;
+use epd_waveshare_async::buffer::WindowError;
+use epd_waveshare_async::hw::{BusyHw, DcHw, DelayHw, ErrorHw, ResetHw, SpiHw};
+use thiserror::Error as ThisError;
+use {defmt_rtt as _, panic_probe as _};
+
+/// The largest transfer that the nRF52832's SPIM EasyDMA can perform in one go.
+///
+/// The nRF52840's SPIM3 supports up to 65535 bytes, but the other SPIM instances are limited to
+/// 255 bytes on all nRF52 chips.
+pub const EASY_DMA_MAX_LEN: usize = 255;
+
+/// Defines the hardware to use for connecting to the display.
+pub struct DisplayHw<'a, SPI> {
+    dc: Output<'a>,
+    reset: Output<'a>,
+    busy: Input<'a>,
+    busy_when: PinState,
+    delay: Delay,
+    _spi_type: core::marker::PhantomData<SPI>,
+}
+
+impl<'a, SPI> DisplayHw<'a, SPI> {
+    pub fn new<DC: Pin, RESET: Pin, BUSY: Pin>(
+        dc: Peri<'a, DC>,
+        reset: Peri<'a, RESET>,
+        busy: Peri<'a, BUSY>,
+        busy_when: PinState,
+    ) -> Self {
+        let dc = Output::new(dc, Level::High, OutputDrive::Standard);
+        let reset = Output::new(reset, Level::High, OutputDrive::Standard);
+        let busy = Input::new(busy, Pull::Up);
+
+        Self {
+            dc,
+            reset,
+            busy,
+            busy_when,
+            delay: Delay,
+            _spi_type: core::marker::PhantomData,
+        }
+    }
+}
+
+pub type RawSpiError = SpiDeviceError<spim::Error, Infallible>;
+
+impl<'a, SPI> ErrorHw for DisplayHw<'a, SPI> {
+    type Error = Error;
+}
+
+impl<'a, SPI> DcHw for DisplayHw<'a, SPI> {
+    type Dc = Output<'a>;
+
+    fn dc(&mut self) -> &mut Self::Dc {
+        &mut self.dc
+    }
+}
+
+impl<'a, SPI> ResetHw for DisplayHw<'a, SPI> {
+    type Reset = Output<'a>;
+
+    fn reset(&mut self) -> &mut Self::Reset {
+        &mut self.reset
+    }
+}
+
+impl<'a, SPI> BusyHw for DisplayHw<'a, SPI> {
+    type Busy = Input<'a>;
+
+    fn busy(&mut self) -> &mut Self::Busy {
+        &mut self.busy
+    }
+
+    fn busy_when(&self) -> embedded_hal::digital::PinState {
+        self.busy_when
+    }
+}
+
+impl<'a, SPI> DelayHw for DisplayHw<'a, SPI> {
+    type Delay = embassy_time::Delay;
+
+    fn delay(&mut self) -> &mut Self::Delay {
+        &mut self.delay
+    }
+}
+
+impl<'a, SPI: SpiDevice> SpiHw for DisplayHw<'a, SPI> {
+    type Spi = SPI;
+
+    // Framebuffers are much larger than this, so the driver must split them up.
+    const MAX_WRITE_LEN: usize = EASY_DMA_MAX_LEN;
+}
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("SPI error: {0:?}")]
+    SpiError(RawSpiError),
+    #[error("window error: {0}")]
+    WindowError(WindowError),
+}
+
+impl From<Infallible> for Error {
+    fn from(_: Infallible) -> Self {
+        unreachable!()
+    }
+}
+
+impl From<RawSpiError> for Error {
+    fn from(e: RawSpiError) -> Self {
+        Error::SpiError(e)
+    }
+}
+
+impl From<WindowError> for Error {
+    fn from(e: WindowError) -> Self {
+        Error::WindowError(e)
+    }
+}
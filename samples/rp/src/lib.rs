@@ -12,6 +12,7 @@ use embassy_rp::Peri;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_time::Delay;
 use embedded_hal::digital::PinState;
+use epd_waveshare_async::buffer::WindowError;
 use epd_waveshare_async::hw::{BusyHw, DcHw, DelayHw, ErrorHw, ResetHw, SpiHw};
 use thiserror::Error as ThisError;
 use {defmt_rtt as _, panic_probe as _};
@@ -98,6 +99,8 @@ impl<'a, SPI: spi::Instance + 'a> SpiHw for DisplayHw<'a, SPI> {
 pub enum Error {
     #[error("SPI error: {0:?}")]
     SpiError(RawSpiError),
+    #[error("window error: {0}")]
+    WindowError(WindowError),
 }
 
 impl From<Infallible> for Error {
@@ -111,3 +114,9 @@ impl From<RawSpiError> for Error {
         Error::SpiError(e)
     }
 }
+
+impl From<WindowError> for Error {
+    fn from(e: WindowError) -> Self {
+        Error::WindowError(e)
+    }
+}